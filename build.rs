@@ -0,0 +1,26 @@
+use std::process::Command;
+
+/// Embed the commit crabby is built from as `CRABBY_GIT_COMMIT`, for
+/// `crabby env`. Builds outside a git checkout can set it themselves.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CRABBY_GIT_COMMIT");
+    let commit = std::env::var("CRABBY_GIT_COMMIT").ok().or_else(git_commit).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=CRABBY_GIT_COMMIT={}", commit);
+
+    // Build again when HEAD moves to another branch or commit
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, head_ref);
+        }
+    }
+}
+
+fn git_commit() -> Option<String> {
+    git(&["rev-parse", "--short=12", "HEAD"])
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -44,21 +44,33 @@ struct OsvVulnerability {
     database_specific: HashMap<String, serde_json::Value>,
 }
 
-pub async fn check_vulnerabilities() -> Result<()> {
-    println!("{} {} scanning dependencies via OSV.dev...", style("🦀").bold().cyan(), style("🛡️").bold().blue());
+/// OSV.dev's endpoint for looking up many packages at once
+const OSV_BATCH_URL: &str = "https://api.osv.dev/v1/querybatch";
 
-    let client = registry::get_client()?;
-    let lockfile = manifest::CrabbyLock::load().unwrap_or_default();
+/// The vulnerabilities of one locked package
+struct Finding {
+    name: String,
+    version: String,
+    vulns: Vec<OsvVulnerability>,
+}
 
-    if lockfile.dependencies.is_empty() {
-        println!("{} No packages found in lockfile.", style("ℹ").blue());
-        return Ok(());
+impl OsvVulnerability {
+    fn severity(&self) -> &str {
+        self.database_specific.get("severity")
+            .and_then(|v| v.as_str())
+            .unwrap_or("UNKNOWN")
     }
+}
 
+/// Look up every package in `lockfile` at `endpoint`. `Ok(None)` when the
+/// service answered with an error status.
+async fn query_vulnerabilities(client: &reqwest::Client, endpoint: &str, lockfile: &manifest::CrabbyLock) -> Result<Option<Vec<Finding>>> {
     let mut queries = Vec::new();
     let mut name_map = Vec::new(); 
 
-    for (name, dep) in &lockfile.dependencies {
+    let mut deps: Vec<_> = lockfile.dependencies.iter().collect();
+    deps.sort_by_key(|(name, _)| name.as_str());
+    for (name, dep) in deps {
         queries.push(OsvQuery {
             package: OsvPackage {
                 name: name.clone(),
@@ -71,44 +83,57 @@ pub async fn check_vulnerabilities() -> Result<()> {
 
     let batch_request = OsvBatchRequest { queries };
 
-    let resp = client.post("https://api.osv.dev/v1/querybatch")
+    let resp = client.post(endpoint)
         .json(&batch_request)
         .send()
         .await
         .context("Failed to contact OSV.dev API")?;
 
     if !resp.status().is_success() {
-         println!("{} Security audit failed: OSV API returned {}", style("⚠️").yellow(), resp.status());
-         return Ok(());
+         println!("{} Security audit failed: OSV API returned {}", style(crate::ui::icon("⚠️")).yellow(), resp.status());
+         return Ok(None);
     }
 
     let batch_resp: OsvBatchResponse = resp.json()
         .await
         .context("Failed to parse OSV response")?;
 
+    Ok(Some(batch_resp.results.into_iter()
+        .zip(name_map)
+        .filter(|(result, _)| !result.vulns.is_empty())
+        .map(|(result, (name, version))| Finding { name, version, vulns: result.vulns })
+        .collect()))
+}
+
+pub async fn check_vulnerabilities() -> Result<()> {
+    println!("{} {} scanning dependencies via OSV.dev...", style(crate::ui::icon("🦀")).bold().cyan(), style(crate::ui::icon("🛡️")).bold().blue());
+
+    let client = registry::get_client()?;
+    let lockfile = manifest::CrabbyLock::load()?;
+
+    if lockfile.dependencies.is_empty() {
+        println!("{} No packages found in lockfile.", style("ℹ").blue());
+        return Ok(());
+    }
+
+    let Some(findings) = query_vulnerabilities(&client, OSV_BATCH_URL, &lockfile).await? else {
+        return Ok(());
+    };
+
     let mut total_vulns = 0;
     let mut found_any = false;
 
-    for (i, result) in batch_resp.results.iter().enumerate() {
-        if result.vulns.is_empty() {
-            continue;
-        }
-
+    for finding in &findings {
         if !found_any {
             println!("\n{}", style("Vulnerability Report:").bold().underlined());
             found_any = true;
         }
 
-        let (pkg_name, pkg_version) = &name_map[i];
-        total_vulns += result.vulns.len();
+        total_vulns += finding.vulns.len();
 
-        for vuln in &result.vulns {
-            let severity = vuln.database_specific.get("severity")
-                .and_then(|v| v.as_str())
-                .unwrap_or("UNKNOWN");
-
-            println!("\n{}", style(format!("severity: {}", severity)).bold().red());
-            println!("  Package: {}@{}", style(pkg_name).bold(), pkg_version);
+        for vuln in &finding.vulns {
+            println!("\n{}", style(format!("severity: {}", vuln.severity())).bold().red());
+            println!("  Package: {}@{}", style(&finding.name).bold(), finding.version);
             println!("  ID:      {}", style(&vuln.id).cyan());
             println!("  Summary: {}", if vuln.summary.is_empty() { &vuln.details } else { &vuln.summary });
             println!("  More:    {}", style(format!("https://osv.dev/vulnerability/{}", vuln.id)).dim());
@@ -116,11 +141,89 @@ pub async fn check_vulnerabilities() -> Result<()> {
     }
 
     if !found_any {
-        println!("\n{} No known vulnerabilities found across {} dependencies.", style("✅").bold().green(), lockfile.dependencies.len());
+        println!("\n{} No known vulnerabilities found across {} dependencies.", style(crate::ui::icon("✅")).bold().green(), lockfile.dependencies.len());
     } else {
-        println!("\n{} Found {} vulnerabilities across {} dependencies.", style("🚨").bold().red(), total_vulns, lockfile.dependencies.len());
+        println!("\n{} Found {} vulnerabilities across {} dependencies.", style(crate::ui::icon("🚨")).bold().red(), total_vulns, lockfile.dependencies.len());
         println!("\nRun `crabby update <package>` to fix or research better alternatives.");
     }
 
     Ok(())
 }
+
+/// One line on what `findings` contain, worst severity first:
+/// `3 vulnerabilities: 1 high, 2 moderate`. `None` when there are none.
+fn summarize(findings: &[Finding]) -> Option<String> {
+    const ORDER: [&str; 5] = ["critical", "high", "moderate", "low", "unknown"];
+    let mut counts = [0usize; ORDER.len()];
+    for vuln in findings.iter().flat_map(|f| &f.vulns) {
+        let severity = match vuln.severity().to_lowercase().as_str() {
+            // GitHub advisories say "moderate", other sources "medium"
+            "medium" => "moderate".to_string(),
+            other => other.to_string(),
+        };
+        let slot = ORDER.iter().position(|s| *s == severity).unwrap_or(ORDER.len() - 1);
+        counts[slot] += 1;
+    }
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let parts: Vec<String> = ORDER.iter().zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(severity, count)| format!("{} {}", count, severity))
+        .collect();
+    Some(format!("{} vulnerabilit{}: {}", total, if total == 1 { "y" } else { "ies" }, parts.join(", ")))
+}
+
+/// After `crabby install --audit`: a one-line summary of known vulnerabilities
+/// in `lockfile`. Failing to reach OSV.dev only warns; the install already succeeded.
+pub async fn print_install_summary(lockfile: &manifest::CrabbyLock) {
+    if lockfile.dependencies.is_empty() {
+        return;
+    }
+    let summary = match registry::get_client() {
+        Ok(client) => query_vulnerabilities(&client, OSV_BATCH_URL, lockfile).await,
+        Err(e) => Err(e),
+    };
+    match summary {
+        Ok(Some(findings)) => match summarize(&findings) {
+            Some(line) => println!("{} {} — run {}", style(crate::ui::icon("🚨")).red(), style(line).bold(), style("crabby audit").cyan()),
+            None => println!("{} No known vulnerabilities", style(crate::ui::icon("🛡️")).green()),
+        },
+        Ok(None) => {}
+        Err(e) => crate::ui::print_warning(&format!("Could not audit dependencies: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_summary_counts_by_severity() {
+        // Results come back in query order, which is by name: a, b, c
+        let url = crate::test_utils::serve(|_| Some(br#"{"results": [
+            {"vulns": [{"id": "GHSA-1", "database_specific": {"severity": "HIGH"}},
+                       {"id": "GHSA-2", "database_specific": {"severity": "MODERATE"}}]},
+            {},
+            {"vulns": [{"id": "GHSA-3", "database_specific": {"severity": "medium"}}]}
+        ]}"#.to_vec()));
+        let url = format!("{}/v1/querybatch", url);
+
+        let mut lockfile = manifest::CrabbyLock::default();
+        for name in ["a", "b", "c"] {
+            lockfile.dependencies.insert(name.to_string(), manifest::LockDependency {
+                version: "1.0.0".to_string(),
+                tarball: String::new(),
+                integrity: None,
+                dependencies: HashMap::new(),
+                registry: None,
+                committish: None,
+            });
+        }
+        let findings = query_vulnerabilities(&reqwest::Client::new(), &url, &lockfile).await.unwrap().unwrap();
+        assert_eq!(findings.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["a", "c"]);
+        assert_eq!(summarize(&findings).unwrap(), "3 vulnerabilities: 1 high, 2 moderate");
+        assert_eq!(summarize(&[]), None);
+    }
+}
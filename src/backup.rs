@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The project files worth keeping a copy of before changing them
+const FILES: &[&str] = &["package.json", "crabby.lock"];
+
+/// How many backups are kept when `backupLimit` isn't set
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// What `backup.json` in each backup records
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupInfo {
+    /// Seconds since the Unix epoch
+    created: u64,
+    /// The crabby command that made the backup, e.g. `remove lodash`
+    command: String,
+}
+
+/// A backup in `.crabby/backups/`
+#[derive(Debug)]
+pub struct Backup {
+    pub id: String,
+    pub path: PathBuf,
+    pub created: u64,
+    pub command: String,
+    pub files: Vec<String>,
+}
+
+/// `.crabby/backups/` of the project at `root`
+pub fn backup_dir(root: &Path) -> PathBuf {
+    root.join(".crabby").join("backups")
+}
+
+/// The project root for the current directory
+fn project_root() -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    Ok(crate::manifest::find_project_root(&cwd).unwrap_or(cwd))
+}
+
+/// Copy package.json and crabby.lock of the project in the current directory
+/// before `command` changes them, keeping at most `limit` backups.
+pub fn create(command: &str, limit: usize) -> Result<Option<Backup>> {
+    create_in(&project_root()?, command, limit)
+}
+
+/// `create` for the project at `root`. Returns `None` when there is nothing to back up.
+pub fn create_in(root: &Path, command: &str, limit: usize) -> Result<Option<Backup>> {
+    let files: Vec<&str> = FILES.iter().copied().filter(|f| root.join(f).is_file()).collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let dir = backup_dir(root);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Backups are local state, never something to commit
+    let ignore = dir.join(".gitignore");
+    if !ignore.exists() {
+        fs::write(&ignore, "*\n")?;
+    }
+
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+    let mut id = now.as_millis().to_string();
+    while dir.join(&id).exists() {
+        id = (id.parse::<u128>()? + 1).to_string();
+    }
+    let path = dir.join(&id);
+    fs::create_dir(&path)?;
+    for file in &files {
+        fs::copy(root.join(file), path.join(file))
+            .with_context(|| format!("Failed to back up {}", file))?;
+    }
+    let info = BackupInfo { created: now.as_secs(), command: command.to_string() };
+    fs::write(path.join("backup.json"), serde_json::to_string_pretty(&info)?)?;
+
+    rotate(root, limit)?;
+    Ok(Some(Backup {
+        id,
+        path,
+        created: info.created,
+        command: info.command,
+        files: files.iter().map(|f| f.to_string()).collect(),
+    }))
+}
+
+/// The backups of the project at `root`, newest first
+pub fn list_in(root: &Path) -> Result<Vec<Backup>> {
+    let Ok(entries) = fs::read_dir(backup_dir(root)) else {
+        return Ok(Vec::new());
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Ok(content) = fs::read_to_string(path.join("backup.json")) else {
+            continue;
+        };
+        let Ok(info) = serde_json::from_str::<BackupInfo>(&content) else {
+            continue;
+        };
+        backups.push(Backup {
+            id: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            files: FILES.iter().filter(|f| path.join(f).is_file()).map(|f| f.to_string()).collect(),
+            path,
+            created: info.created,
+            command: info.command,
+        });
+    }
+    // Ids are creation times in milliseconds
+    backups.sort_by_key(|b| std::cmp::Reverse(b.id.parse::<u128>().unwrap_or_default()));
+    Ok(backups)
+}
+
+/// Delete all but the newest `limit` backups of the project at `root`
+fn rotate(root: &Path, limit: usize) -> Result<()> {
+    for old in list_in(root)?.into_iter().skip(limit.max(1)) {
+        fs::remove_dir_all(&old.path)
+            .with_context(|| format!("Failed to remove old backup {}", old.path.display()))?;
+    }
+    Ok(())
+}
+
+/// Put the files of `backup` back into the project at `root`
+pub fn restore_in(root: &Path, backup: &Backup) -> Result<()> {
+    for file in &backup.files {
+        fs::copy(backup.path.join(file), root.join(file))
+            .with_context(|| format!("Failed to restore {}", file))?;
+    }
+    Ok(())
+}
+
+/// `created` as `YYYY-MM-DD HH:MM UTC`
+pub fn format_time(created: u64) -> String {
+    let days = (created / 86_400) as i64;
+    let minutes = (created % 86_400) / 60;
+    // Howard Hinnant's days-to-civil
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60, minutes % 60)
+}
+
+/// `crabby restore`: list backups, or restore the one picked by `id` (or from a
+/// menu) after confirmation
+pub fn run_restore(id: Option<&str>, list: bool, yes: bool, limit: usize) -> Result<()> {
+    use std::io::IsTerminal;
+
+    let root = project_root()?;
+    let backups = list_in(&root)?;
+    if backups.is_empty() {
+        crate::ui::print_info("No backups yet. crabby makes one before remove, update and clean.");
+        return Ok(());
+    }
+    let describe = |b: &Backup| format!("{}  {}  crabby {}  ({})", b.id, format_time(b.created), b.command, b.files.join(", "));
+
+    if list {
+        crate::ui::print_header("Backups");
+        for backup in &backups {
+            println!("  {}", describe(backup));
+        }
+        println!("\n{} Restore one with {}", style(crate::ui::icon("💡")).cyan(), style("crabby restore <id>").bold());
+        return Ok(());
+    }
+
+    let backup = match id {
+        Some(id) => backups.iter().find(|b| b.id == id)
+            .with_context(|| format!("No backup '{}'. See `crabby restore --list`", id))?,
+        None if std::io::stdin().is_terminal() => {
+            let items: Vec<String> = backups.iter().map(describe).collect();
+            match crate::ui::prompt_selection(&items, "Restore which backup?")? {
+                Some(index) => &backups[index],
+                None => return Ok(()),
+            }
+        }
+        None => anyhow::bail!("Pass the id of the backup to restore; see `crabby restore --list`"),
+    };
+
+    if !yes {
+        print!("{} Overwrite {} with the copy from {}? (y/n): ",
+            style(crate::ui::icon("❓")).bold().yellow(), backup.files.join(" and "), format_time(backup.created));
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            crate::ui::print_info("Cancelled");
+            return Ok(());
+        }
+    }
+
+    // The restore can be undone like anything else
+    create_in(&root, &format!("restore {}", backup.id), limit + 1)?;
+    restore_in(&root, backup)?;
+    crate::ui::print_success(&format!("Restored {} from backup {}", backup.files.join(" and "), backup.id));
+    crate::ui::print_info("Run `crabby install` to bring node_modules in line");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_rotate_and_restore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        assert!(create_in(root, "remove lodash", 3).unwrap().is_none());
+
+        fs::write(root.join("package.json"), r#"{"name": "app", "version": "1.0.0"}"#).unwrap();
+        let first = create_in(root, "remove lodash", 3).unwrap().unwrap();
+        assert_eq!(first.files, ["package.json"]);
+        assert_eq!(fs::read_to_string(backup_dir(root).join(".gitignore")).unwrap(), "*\n");
+
+        for n in 0..4 {
+            fs::write(root.join("crabby.lock"), format!("{{\"n\": {}}}", n)).unwrap();
+            create_in(root, &format!("update pkg{}", n), 3).unwrap();
+        }
+        let backups = list_in(root).unwrap();
+        assert_eq!(backups.iter().map(|b| b.command.as_str()).collect::<Vec<_>>(), ["update pkg3", "update pkg2", "update pkg1"]);
+        assert!(!first.path.exists());
+
+        fs::write(root.join("crabby.lock"), "changed").unwrap();
+        restore_in(root, &backups[2]).unwrap();
+        assert_eq!(fs::read_to_string(root.join("crabby.lock")).unwrap(), "{\"n\": 1}");
+        // Nothing is left next to package.json
+        let mut names: Vec<_> = fs::read_dir(root).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, [".crabby", "crabby.lock", "package.json"]);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_time(1_709_210_096), "2024-02-29 12:34 UTC");
+    }
+}
@@ -49,14 +49,14 @@ pub fn save_to_cache(name: &str, version: &str, data: &[u8]) -> Result<PathBuf>
     fs::write(&cache_path, data)
         .context("Failed to write package to cache")?;
     
-    println!("{} Cached {}", console::style("💾").dim(), cache_path.display());
+    println!("{} Cached {}", console::style(crate::ui::icon("💾")).dim(), cache_path.display());
     Ok(cache_path)
 }
 
 #[allow(dead_code)]
 pub fn load_from_cache(name: &str, version: &str) -> Result<Vec<u8>> {
     let cache_path = get_package_cache_path(name, version)?;
-    println!("{} Loading from cache", console::style("⚡").cyan());
+    println!("{} Loading from cache", console::style(crate::ui::icon("⚡")).cyan());
     
     fs::read(&cache_path)
         .context("Failed to read package from cache")
@@ -77,12 +77,34 @@ pub fn clear_cache() -> Result<()> {
     if cache_dir.exists() {
         fs::remove_dir_all(&cache_dir)?;
         fs::create_dir_all(&cache_dir)?;
-        println!("{} Cache cleared", console::style("🗑️").green());
+        println!("{} Cache cleared", console::style(crate::ui::icon("🗑️")).green());
     }
     
     Ok(())
 }
 
+/// Total size in bytes of all files below `path` (0 if it doesn't exist)
+pub fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(e) => e,
+        Err(_) => return 0,
+    };
+
+    let mut total = 0;
+    for entry in entries.flatten() {
+        let file_type = match entry.file_type() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            total += dir_size(&entry.path());
+        } else if file_type.is_file() {
+            total += entry.metadata().map(|m| m.len()).unwrap_or(0);
+        }
+    }
+    total
+}
+
 #[allow(dead_code)]
 pub fn get_cache_stats() -> Result<(usize, u64)> {
     let cache_dir = get_cache_dir()?;
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use console::style;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::{config, explorer, manifest, package_utils, registry, ui};
+
+#[derive(Debug, Serialize)]
+pub struct VersionMismatch {
+    pub name: String,
+    pub locked: String,
+    pub installed: String,
+}
+
+/// Discrepancies between crabby.lock, package.json and node_modules
+#[derive(Debug, Default, Serialize)]
+pub struct CheckReport {
+    /// Locked packages with no directory in node_modules
+    pub missing: Vec<String>,
+    /// Locked packages whose installed package.json has a different version
+    pub mismatched: Vec<VersionMismatch>,
+    /// Packages in node_modules that the lockfile doesn't know about
+    pub extraneous: Vec<String>,
+    /// package.json dependencies with no lockfile entry at all
+    pub unlocked: Vec<String>,
+    /// Registries locked packages came from other than the configured one,
+    /// with how many packages each. Only a warning: node_modules can still match.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_registries: Vec<(String, usize)>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty()
+            && self.mismatched.is_empty()
+            && self.extraneous.is_empty()
+            && self.unlocked.is_empty()
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct InstalledVersion {
+    #[serde(default)]
+    version: String,
+}
+
+/// Compare the lockfile against what is on disk under `root`, and against
+/// `registry`, the one entries should come from (`None` when their tarball
+/// hosts are replaced anyway). Never touches the network.
+pub fn compare(root: &Path, pkg: &manifest::PackageJson, lock: &manifest::CrabbyLock, registry: Option<&str>) -> Result<CheckReport> {
+    let node_modules = root.join("node_modules");
+    let mut report = CheckReport::default();
+
+    for (name, dep) in &lock.dependencies {
+        // Sibling workspaces resolve through the link in the root node_modules
+        if dep.is_workspace_link() {
+            continue;
+        }
+        let pkg_json_path = node_modules.join(name).join("package.json");
+        if !pkg_json_path.exists() {
+            report.missing.push(name.clone());
+            continue;
+        }
+
+        let content = manifest::clean_json_content(fs::read_to_string(&pkg_json_path)?);
+        let installed = serde_json::from_str::<InstalledVersion>(&content)
+            .map(|p| p.version)
+            .unwrap_or_default();
+        if installed != dep.version {
+            report.mismatched.push(VersionMismatch {
+                name: name.clone(),
+                locked: dep.version.clone(),
+                installed,
+            });
+        }
+    }
+
+    for (name, path) in explorer::installed_packages(&node_modules)? {
+        // Workspace links are managed outside the lockfile
+        let is_link = fs::symlink_metadata(&path).map(|m| m.file_type().is_symlink()).unwrap_or(false);
+        if !is_link && !lock.dependencies.contains_key(&name) {
+            report.extraneous.push(name);
+        }
+    }
+
+    for name in pkg.get_all_dependencies().keys() {
+        if !lock.dependencies.contains_key(name) {
+            report.unlocked.push(name.clone());
+        }
+    }
+
+    report.missing.sort();
+    report.mismatched.sort_by(|a, b| a.name.cmp(&b.name));
+    report.extraneous.sort();
+    report.unlocked.sort();
+
+    if let Some(registry) = registry {
+        report.other_registries = lock.foreign_registries(registry);
+    }
+    Ok(report)
+}
+
+/// What `--fix` has to install: missing and mismatched packages at their
+/// locked version, and unlocked package.json dependencies at their declared range
+pub fn reinstall_targets(report: &CheckReport, pkg: &manifest::PackageJson, lock: &manifest::CrabbyLock) -> HashMap<String, String> {
+    let declared = pkg.get_all_dependencies();
+    let locked = report.missing.iter().chain(report.mismatched.iter().map(|m| &m.name))
+        .filter_map(|name| {
+            let dep = lock.dependencies.get(name)?;
+            // Directories and git repositories are fetched again from where they came from
+            Some((name.clone(), if dep.is_source() { dep.tarball.clone() } else { format!("={}", dep.version) }))
+        });
+    let unlocked = report.unlocked.iter()
+        .filter_map(|name| Some((name.clone(), declared.get(name)?.clone())));
+    locked.chain(unlocked).collect()
+}
+
+/// Bring node_modules under `root` back in line with the lockfile: delete
+/// extraneous packages and reinstall the rest. Returns the updated lockfile.
+pub async fn fix(root: &Path, report: &CheckReport, pkg: &manifest::PackageJson, lock: manifest::CrabbyLock) -> Result<manifest::CrabbyLock> {
+    let node_modules = root.join("node_modules");
+    for name in &report.extraneous {
+        println!("  {} Removing {}", style(ui::icon(ui::Icons::REMOVE)).red(), style(name).cyan());
+        explorer::remove_installed(&node_modules, name)?;
+    }
+
+    let targets = reinstall_targets(report, pkg, &lock);
+    if targets.is_empty() {
+        return Ok(lock);
+    }
+    // The reinstalled directories replace whatever is on disk, so drop the stale copies first
+    for m in &report.mismatched {
+        explorer::remove_installed(&node_modules, &m.name)?;
+    }
+    let registry_url = config::load_config()?.registry;
+    let client = registry::get_client()?;
+    package_utils::install_all_packages(root, &targets, &registry_url, &client, lock, &package_utils::InstallStats::new()).await
+}
+
+pub fn print_report(report: &CheckReport) {
+    for name in &report.missing {
+        println!("  {} {} {}", style(ui::icon(ui::Icons::ERROR)).red(), style(name).cyan(), style("missing from node_modules").dim());
+    }
+    for m in &report.mismatched {
+        println!("  {} {} {} {} {}",
+            style(ui::icon(ui::Icons::WARNING)).yellow(),
+            style(&m.name).cyan(),
+            style(format!("locked {}", m.locked)).dim(),
+            style("but installed").dim(),
+            style(if m.installed.is_empty() { "unknown" } else { &m.installed }).yellow()
+        );
+    }
+    for name in &report.extraneous {
+        println!("  {} {} {}", style(ui::icon(ui::Icons::WARNING)).yellow(), style(name).cyan(), style("extraneous (not in crabby.lock)").dim());
+    }
+    for name in &report.unlocked {
+        println!("  {} {} {}", style(ui::icon(ui::Icons::ERROR)).red(), style(name).cyan(), style("in package.json but not in crabby.lock").dim());
+    }
+
+    for (registry, count) in &report.other_registries {
+        println!("  {} {} {}", style(ui::icon(ui::Icons::WARNING)).yellow(), style(registry).cyan(),
+            style(format!("{} locked package{} from a registry other than the configured one", count, if *count == 1 { "" } else { "s" })).dim());
+    }
+    if !report.other_registries.is_empty() {
+        ui::print_info("Set replaceRegistryHost to true to download them from the configured registry");
+    }
+
+    if report.is_consistent() {
+        ui::print_success("node_modules matches crabby.lock");
+    } else {
+        println!();
+        ui::print_error("node_modules is out of sync with crabby.lock");
+        ui::print_info("Run `crabby check --fix` to fix it");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reinstall_targets_pin_locked_versions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for (name, version) in [("express", "4.17.0"), ("stray", "1.0.0")] {
+            let dir = root.join("node_modules").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("package.json"), format!(r#"{{"name": "{}", "version": "{}"}}"#, name, version)).unwrap();
+        }
+
+        let mut lock = manifest::CrabbyLock::default();
+        lock.add_package("express".into(), "4.18.2".into(), String::new(), HashMap::new());
+        lock.add_package("debug".into(), "2.6.9".into(), String::new(), HashMap::new());
+        let pkg = manifest::PackageJson {
+            dependencies: HashMap::from([
+                ("express".to_string(), "^4.18.0".to_string()),
+                ("lodash".to_string(), "^4.17.0".to_string()),
+            ]),
+            ..Default::default()
+        };
+
+        let report = compare(root, &pkg, &lock, Some("https://registry.npmjs.org/")).unwrap();
+        assert_eq!(report.missing, ["debug"]);
+        assert_eq!(report.extraneous, ["stray"]);
+
+        let targets = reinstall_targets(&report, &pkg, &lock);
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets["express"], "=4.18.2");
+        assert_eq!(targets["debug"], "=2.6.9");
+        assert_eq!(targets["lodash"], "^4.17.0");
+    }
+}
@@ -0,0 +1,185 @@
+use clap_complete::Shell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use crate::manifest;
+
+/// What the hidden `crabby __complete` subcommand lists, one candidate per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompleteKind {
+    /// Script names from package.json (for `cook` / `run`)
+    Scripts,
+    /// Declared and installed package names (for `remove`, `why`, `update`)
+    Packages,
+}
+
+const BASH_HOOK: &str = r#"
+_crabby_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ $COMP_CWORD -ge 2 && "$cur" != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            cook|run)
+                if [[ $COMP_CWORD -eq 2 ]]; then
+                    COMPREPLY=($(compgen -W "$(crabby __complete scripts 2>/dev/null)" -- "$cur"))
+                    return 0
+                fi
+                ;;
+            remove|rm|why|update)
+                COMPREPLY=($(compgen -W "$(crabby __complete packages 2>/dev/null)" -- "$cur"))
+                return 0
+                ;;
+        esac
+    fi
+    _crabby "$@"
+}
+complete -F _crabby_dynamic -o nosort -o bashdefault -o default crabby
+"#;
+
+const ZSH_HOOK: &str = r#"
+_crabby_dynamic() {
+    if (( CURRENT > 2 )) && [[ $words[CURRENT] != -* ]]; then
+        case $words[2] in
+            cook|run)
+                if (( CURRENT == 3 )); then
+                    compadd -- ${(f)"$(crabby __complete scripts 2>/dev/null)"}
+                    return
+                fi
+                ;;
+            remove|rm|why|update)
+                compadd -- ${(f)"$(crabby __complete packages 2>/dev/null)"}
+                return
+                ;;
+        esac
+    fi
+    _crabby "$@"
+}
+compdef _crabby_dynamic crabby
+"#;
+
+const FISH_HOOK: &str = r#"
+complete -c crabby -n "__fish_seen_subcommand_from cook run" -f -a "(crabby __complete scripts 2>/dev/null)"
+complete -c crabby -n "__fish_seen_subcommand_from remove rm why update" -f -a "(crabby __complete packages 2>/dev/null)"
+"#;
+
+/// PowerShell allows one completer per command, so this goes inside the generated one
+const POWERSHELL_HOOK: &str = r#"    $dynamic = if ($wordToComplete.StartsWith('-')) { $null }
+        elseif ($command -match '^crabby;(cook|run)$') { 'scripts' }
+        elseif ($command -match '^crabby;(remove|rm|why|update)(;|$)') { 'packages' }
+    if ($dynamic) {
+        crabby __complete $dynamic 2>$null | Where-Object { $_ -like "$wordToComplete*" } | ForEach-Object {
+            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)
+        }
+        return
+    }
+
+"#;
+
+const POWERSHELL_ANCHOR: &str = "    $completions = @(switch ($command) {";
+
+/// The completion script for `shell`: clap's static completions plus hooks
+/// that ask `crabby __complete` for script and package names
+pub fn script(shell: Shell, cmd: &mut clap::Command) -> String {
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, cmd, "crabby", &mut buf);
+    let generated = String::from_utf8_lossy(&buf).into_owned();
+
+    match shell {
+        Shell::Bash => generated + BASH_HOOK,
+        Shell::Zsh => generated + ZSH_HOOK,
+        Shell::Fish => generated + FISH_HOOK,
+        Shell::PowerShell => generated.replacen(POWERSHELL_ANCHOR, &format!("{}{}", POWERSHELL_HOOK, POWERSHELL_ANCHOR), 1),
+        _ => generated,
+    }
+}
+
+/// Candidates for `kind` in the project at `root`. Never fails: a missing or
+/// broken package.json just means nothing to suggest.
+pub fn candidates(root: &Path, kind: CompleteKind) -> Vec<String> {
+    let pkg = fs::read_to_string(root.join("package.json")).ok()
+        .and_then(|content| serde_json::from_str::<manifest::PackageJson>(&manifest::clean_json_content(content)).ok());
+
+    let mut names = BTreeSet::new();
+    match kind {
+        CompleteKind::Scripts => {
+            if let Some(pkg) = pkg {
+                names.extend(pkg.scripts.into_keys());
+            }
+        }
+        CompleteKind::Packages => {
+            if let Some(pkg) = pkg {
+                names.extend(pkg.get_all_dependencies().into_keys());
+                names.extend(pkg.peer_dependencies.into_keys());
+            }
+            names.extend(installed_packages(&root.join("node_modules")));
+        }
+    }
+    names.into_iter().collect()
+}
+
+/// Top-level package names in node_modules, including `@scope/name`
+fn installed_packages(node_modules: &Path) -> Vec<String> {
+    let mut names = Vec::new();
+    let Ok(entries) = fs::read_dir(node_modules) else {
+        return names;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        if name.starts_with('@') {
+            if let Ok(scoped) = fs::read_dir(entry.path()) {
+                names.extend(scoped.flatten().map(|e| format!("{}/{}", name, e.file_name().to_string_lossy())));
+            }
+        } else {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// `crabby __complete <kind>`
+pub fn run_complete(kind: CompleteKind) {
+    if let Ok(cwd) = std::env::current_dir() {
+        for name in candidates(&cwd, kind) {
+            println!("{}", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_from_package_json_and_node_modules() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("package.json"), r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "scripts": {"build": "tsc", "test": "jest"},
+            "dependencies": {"express": "^4.0.0"},
+            "devDependencies": {"typescript": "^5.0.0"}
+        }"#).unwrap();
+        fs::create_dir_all(root.join("node_modules/express")).unwrap();
+        fs::create_dir_all(root.join("node_modules/@types/node")).unwrap();
+        fs::create_dir_all(root.join("node_modules/.bin")).unwrap();
+
+        assert_eq!(candidates(root, CompleteKind::Scripts), vec!["build", "test"]);
+        assert_eq!(candidates(root, CompleteKind::Packages), vec!["@types/node", "express", "typescript"]);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(candidates(empty.path(), CompleteKind::Packages).is_empty());
+    }
+
+    #[test]
+    fn test_scripts_include_dynamic_hooks() {
+        let mut cmd = clap::Command::new("crabby")
+            .subcommand(clap::Command::new("cook").arg(clap::Arg::new("script")));
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script = script(shell, &mut cmd);
+            assert!(script.contains("crabby __complete"), "{:?} has no dynamic hook", shell);
+        }
+    }
+}
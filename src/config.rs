@@ -1,11 +1,56 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
+use console::style;
+use crate::error::{failure, ErrorKind};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CrabbyConfig {
     #[serde(default = "default_registry")]
     pub registry: String,
+    /// Token sent to the registry when publishing
+    #[serde(default, rename = "authToken", skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+    /// Hard-link packages from a shared content-addressable store instead of
+    /// extracting a copy into every project
+    #[serde(default, rename = "useStore")]
+    pub use_store: bool,
+    /// What `crabby init` creates when neither --ts nor --js is given
+    #[serde(default, rename = "defaultProjectType", alias = "default_project_type", skip_serializing_if = "Option::is_none")]
+    pub default_project_type: Option<crate::init::ProjectType>,
+    /// Node.js version this project runs on (`20`, `lts`, ...), see `crabby node`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// Set to false to never look for a newer crabby in the background
+    #[serde(default, rename = "updateCheck", alias = "update_check", skip_serializing_if = "Option::is_none")]
+    pub update_check: Option<bool>,
+    /// Refuse to install, rather than warn, when package.json's packageManager
+    /// names another package manager or crabby version
+    #[serde(default, rename = "enforcePackageManager", alias = "enforce_package_manager", skip_serializing_if = "std::ops::Not::not")]
+    pub enforce_package_manager: bool,
+    /// How many backups of package.json and crabby.lock to keep in .crabby/backups
+    #[serde(default, rename = "backupLimit", alias = "backup_limit", skip_serializing_if = "Option::is_none")]
+    pub backup_limit: Option<usize>,
+    /// Check installed packages for known vulnerabilities after every install
+    #[serde(default, rename = "auditOnInstall", alias = "audit_on_install", skip_serializing_if = "std::ops::Not::not")]
+    pub audit_on_install: bool,
+    /// ASCII instead of emoji and box-drawing characters, like `--ascii`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ascii: bool,
+    /// Download locked tarballs from `registry`, whichever registry the
+    /// lockfile recorded them from (for mirrors and air-gapped setups)
+    #[serde(default, rename = "replaceRegistryHost", alias = "replace_registry_host", skip_serializing_if = "std::ops::Not::not")]
+    pub replace_registry_host: bool,
+    /// Seconds a registry request may take (default 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Seconds to wait for a connection to the registry (default 10)
+    #[serde(default, rename = "connectTimeout", alias = "connect_timeout", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Idle connections kept open per host; 0 disables keep-alive
+    #[serde(default, rename = "poolMaxIdle", alias = "pool_max_idle", skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle: Option<usize>,
 }
 
 fn default_registry() -> String {
@@ -16,37 +61,233 @@ impl Default for CrabbyConfig {
     fn default() -> Self {
         Self {
             registry: default_registry(),
+            auth_token: None,
+            use_store: false,
+            default_project_type: None,
+            node: None,
+            update_check: None,
+            enforce_package_manager: false,
+            backup_limit: None,
+            audit_on_install: false,
+            ascii: false,
+            replace_registry_host: false,
+            timeout: None,
+            connect_timeout: None,
+            pool_max_idle: None,
         }
     }
 }
 
 impl CrabbyConfig {
+    /// The project's crabby.config.json on top of the user's ~/.crabby/config.json
     pub fn load() -> Result<Self> {
+        let layers: Vec<PathBuf> = [user_config_path().ok(), Some(PathBuf::from("crabby.config.json"))]
+            .into_iter()
+            .flatten()
+            .collect();
+        Self::load_layers(&layers)
+    }
+
+    /// Merge the config files in `layers` key by key, later files winning.
+    /// Missing files are skipped; a value of the wrong type is an error.
+    pub fn load_layers(layers: &[PathBuf]) -> Result<Self> {
+        let mut merged = serde_json::Map::new();
+        for path in layers {
+            for (key, value) in read_object(path)? {
+                let key = canonical_key(&key).map(str::to_string).unwrap_or(key);
+                // Check each key on its own so the error can say which one is wrong
+                let single = serde_json::Map::from_iter([(key.clone(), value)]);
+                if let Err(e) = serde_json::from_value::<Self>(serde_json::Value::Object(single.clone())) {
+                    return Err(failure(ErrorKind::Config, format!("Invalid '{}' in {}: {}", key, path.display(), e)));
+                }
+                merged.extend(single);
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(merged))
+            .map_err(|e| failure(ErrorKind::Config, format!("Invalid config: {}", e)))
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
         // If config doesn't exist, return default without error
-        if !std::path::Path::new("crabby.config.json").exists() {
+        if !path.exists() {
             return Ok(Self::default());
         }
 
-        let content = fs::read_to_string("crabby.config.json")
-            .context("Could not read crabby.config.json")?;
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
         
         // Try parsing. If it fails (maybe it's the old format with "scripts"), 
         // fallback to default to avoid breaking.
-        let config: CrabbyConfig = match serde_json::from_str(&content) {
-            Ok(c) => c,
-            Err(_) => {
-                // Potential future improvement: warn user if format is invalid
-                Self::default()
-            }
-        };
+        // Potential future improvement: warn user if format is invalid
+        let config: CrabbyConfig = serde_json::from_str(&content).unwrap_or_default();
         Ok(config)
     }
+
+    /// How many backups `crabby restore` can choose from
+    pub fn backup_limit(&self) -> usize {
+        self.backup_limit.unwrap_or(crate::backup::DEFAULT_LIMIT)
+    }
+
+    /// Registry timeouts and pooling set in the config file
+    pub fn network_options(&self) -> crate::registry::NetworkOptions {
+        crate::registry::NetworkOptions {
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            pool_max_idle: self.pool_max_idle,
+        }
+    }
+
+    /// The configured auth token, falling back to the NPM_TOKEN environment variable
+    pub fn auth_token(&self) -> Option<String> {
+        self.auth_token.clone()
+            .or_else(|| std::env::var("NPM_TOKEN").ok())
+            .filter(|t| !t.is_empty())
+    }
 }
 
 pub fn load_config() -> Result<CrabbyConfig> {
     CrabbyConfig::load()
 }
 
+/// ~/.crabby/config.json: settings that apply to every project of the user
+pub fn user_config_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not find your home directory")?;
+    Ok(home.join(".crabby").join("config.json"))
+}
+
+/// The keys `crabby config` knows, as written in the file
+pub const KEYS: &[&str] = &["registry", "authToken", "useStore", "defaultProjectType", "node", "updateCheck", "enforcePackageManager", "backupLimit", "auditOnInstall", "ascii", "replaceRegistryHost", "timeout", "connectTimeout", "poolMaxIdle"];
+
+/// The file spelling of `key`, which may also be given in snake_case
+fn canonical_key(key: &str) -> Option<&'static str> {
+    match key {
+        "registry" => Some("registry"),
+        "authToken" | "auth_token" => Some("authToken"),
+        "useStore" | "use_store" => Some("useStore"),
+        "defaultProjectType" | "default_project_type" => Some("defaultProjectType"),
+        "node" | "node_version" | "nodeVersion" => Some("node"),
+        "updateCheck" | "update_check" => Some("updateCheck"),
+        "enforcePackageManager" | "enforce_package_manager" => Some("enforcePackageManager"),
+        "backupLimit" | "backup_limit" => Some("backupLimit"),
+        "auditOnInstall" | "audit_on_install" => Some("auditOnInstall"),
+        "ascii" => Some("ascii"),
+        "replaceRegistryHost" | "replace_registry_host" => Some("replaceRegistryHost"),
+        "timeout" => Some("timeout"),
+        "connectTimeout" | "connect_timeout" => Some("connectTimeout"),
+        "poolMaxIdle" | "pool_max_idle" => Some("poolMaxIdle"),
+        _ => None,
+    }
+}
+
+fn unknown_key(key: &str) -> anyhow::Error {
+    failure(ErrorKind::Config, format!("Unknown config key '{}'. Known keys: {}", key, KEYS.join(", ")))
+}
+
+/// The JSON object in `path`, empty if there is no such file
+fn read_object(path: &Path) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(serde_json::Map::new());
+    };
+    match serde_json::from_str(&content) {
+        Ok(serde_json::Value::Object(map)) => Ok(map),
+        _ => Err(failure(ErrorKind::Config, format!("{} is not a JSON object", path.display()))),
+    }
+}
+
+/// The value `input` means for `key`, or why it can't be used
+fn parse_value(key: &str, input: &str) -> Result<serde_json::Value> {
+    let input = input.trim();
+    let bool_value = || match input {
+        "true" | "yes" | "1" => Ok(serde_json::Value::Bool(true)),
+        "false" | "no" | "0" => Ok(serde_json::Value::Bool(false)),
+        _ => anyhow::bail!("{} must be true or false", key),
+    };
+    match key {
+        "registry" => {
+            let url = reqwest::Url::parse(input).with_context(|| format!("'{}' is not a URL", input))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                anyhow::bail!("The registry must be an http(s) URL");
+            }
+            Ok(input.trim_end_matches('/').into())
+        }
+        "useStore" | "updateCheck" | "enforcePackageManager" | "auditOnInstall" | "ascii" | "replaceRegistryHost" => bool_value(),
+        "backupLimit" => match input.parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(limit.into()),
+            _ => anyhow::bail!("backupLimit must be a whole number above 0"),
+        },
+        "timeout" | "connectTimeout" => match input.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(secs.into()),
+            _ => anyhow::bail!("{} must be a whole number of seconds above 0", key),
+        },
+        "poolMaxIdle" => match input.parse::<usize>() {
+            Ok(idle) => Ok(idle.into()),
+            _ => anyhow::bail!("poolMaxIdle must be a whole number"),
+        },
+        "defaultProjectType" => {
+            let project_type: crate::init::ProjectType = serde_json::from_value(input.to_lowercase().into())
+                .map_err(|_| anyhow::anyhow!("defaultProjectType must be ts or js"))?;
+            Ok(serde_json::to_value(project_type)?)
+        }
+        _ if input.is_empty() => anyhow::bail!("{} can't be empty", key),
+        _ => Ok(input.into()),
+    }
+}
+
+/// `crabby config set`: write `key` = `input` into the config file at `path`
+pub fn set_value(path: &Path, key: &str, input: &str) -> Result<()> {
+    let key = canonical_key(key).ok_or_else(|| unknown_key(key))?;
+    let value = parse_value(key, input).map_err(|e| failure(ErrorKind::Config, e.to_string()))?;
+
+    let mut object = read_object(path)?;
+    // Drop other spellings of the key so it isn't there twice
+    object.retain(|existing, _| canonical_key(existing) != Some(key));
+    object.insert(key.to_string(), value);
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&object)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// `crabby config get`: the value of `key` in `config`, if set
+pub fn get_value(config: &CrabbyConfig, key: &str) -> Result<Option<serde_json::Value>> {
+    let key = canonical_key(key).ok_or_else(|| unknown_key(key))?;
+    Ok(serde_json::to_value(config)?.get(key).cloned())
+}
+
+/// A config value the way a user typed it: strings without quotes
+pub fn display_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The config file `crabby config` works on
+pub fn config_path(global: bool) -> Result<PathBuf> {
+    if global {
+        user_config_path()
+    } else {
+        Ok(PathBuf::from("crabby.config.json"))
+    }
+}
+
+/// `crabby config list`: every key with its value, with the token masked
+pub fn print_list(config: &CrabbyConfig) -> Result<()> {
+    let values = serde_json::to_value(config)?;
+    for key in KEYS {
+        let shown = match values.get(*key) {
+            Some(_) if *key == "authToken" => "********".to_string(),
+            Some(value) => display_value(value),
+            None => style("(not set)").dim().to_string(),
+        };
+        println!("{} = {}", style(key).cyan(), shown);
+    }
+    Ok(())
+}
+
 pub fn get_cache_dir() -> Result<std::path::PathBuf> {
     let cache_dir = if cfg!(target_os = "windows") {
         let local_app_data = std::env::var("LOCALAPPDATA")
@@ -65,3 +306,70 @@ pub fn get_cache_dir() -> Result<std::path::PathBuf> {
     
     Ok(cache_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_set_get_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("crabby.config.json");
+        fs::write(&path, r#"{"log_level": "info", "default_project_type": "ts"}"#).unwrap();
+
+        set_value(&path, "registry", "https://registry.example.com/").unwrap();
+        set_value(&path, "use_store", "true").unwrap();
+        set_value(&path, "defaultProjectType", "JavaScript").unwrap();
+        set_value(&path, "node_version", "20").unwrap();
+
+        let config = CrabbyConfig::load_from(&path).unwrap();
+        assert_eq!(get_value(&config, "registry").unwrap().unwrap(), "https://registry.example.com");
+        assert_eq!(get_value(&config, "useStore").unwrap().unwrap(), true);
+        assert_eq!(get_value(&config, "default_project_type").unwrap().unwrap(), "js");
+        assert_eq!(get_value(&config, "node").unwrap().unwrap(), "20");
+        assert_eq!(get_value(&config, "authToken").unwrap(), None);
+
+        // Keys crabby doesn't manage are kept, and an old spelling is replaced
+        let raw = read_object(&path).unwrap();
+        assert_eq!(raw["log_level"], "info");
+        assert!(!raw.contains_key("default_project_type"));
+
+        assert!(set_value(&path, "registry", "not a url").is_err());
+        assert!(set_value(&path, "registry", "ftp://example.com").is_err());
+        assert!(set_value(&path, "useStore", "maybe").is_err());
+        assert!(set_value(&path, "colour", "blue").is_err());
+        assert_eq!(CrabbyConfig::load_from(&path).unwrap().registry, "https://registry.example.com");
+    }
+
+    #[test]
+    fn test_project_config_overrides_user_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let user = tmp.path().join("config.json");
+        let project = tmp.path().join("crabby.config.json");
+        fs::write(&user, r#"{"registry": "https://user.example.com", "authToken": "secret", "use_store": true}"#).unwrap();
+        fs::write(&project, r#"{"registry": "https://project.example.com", "useStore": false}"#).unwrap();
+
+        let config = CrabbyConfig::load_layers(&[user.clone(), project]).unwrap();
+        assert_eq!(config.registry, "https://project.example.com");
+        // Either spelling of a key overrides the other
+        assert!(!config.use_store);
+        // What the project doesn't set comes from the user config
+        assert_eq!(config.auth_token.as_deref(), Some("secret"));
+
+        let config = CrabbyConfig::load_layers(&[user, tmp.path().join("missing.json")]).unwrap();
+        assert_eq!(config.registry, "https://user.example.com");
+        assert!(config.use_store);
+    }
+
+    #[test]
+    fn test_mistyped_key_is_reported() {
+        let tmp = tempfile::tempdir().unwrap();
+        let user = tmp.path().join("config.json");
+        fs::write(&user, r#"{"registry": "https://user.example.com", "authToken": "secret", "backupLimit": "5"}"#).unwrap();
+
+        let err = CrabbyConfig::load_layers(std::slice::from_ref(&user)).unwrap_err();
+        assert_eq!(crate::error::classify(&err), ErrorKind::Config);
+        let message = err.to_string();
+        assert!(message.contains("'backupLimit'") && message.contains(&user.display().to_string()), "{}", message);
+    }
+}
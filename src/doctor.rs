@@ -0,0 +1,333 @@
+use anyhow::Result;
+use console::style;
+use std::path::Path;
+use std::process::Command;
+use crate::{cache, config, global, manifest, setup_path, ui};
+
+/// Minimum Node.js major version we consider healthy
+const MIN_NODE_MAJOR: u64 = 18;
+/// Cache size above which we suggest a cleanup (2 GB)
+const CACHE_SIZE_LIMIT: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Pass, detail: detail.into(), fix: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Warn, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Fail, detail: detail.into(), fix: Some(fix.into()) }
+    }
+}
+
+/// Run every environment check and print the report.
+/// Returns `true` when no check failed.
+pub fn run() -> Result<bool> {
+    ui::print_header(&format!("{} Crabby Doctor", ui::Icons::CONFIG));
+    println!();
+
+    let results = vec![
+        check_node(),
+        check_tool("npm", "npm"),
+        check_tool("corepack", "corepack"),
+        check_tsx(),
+        check_global_bin_on_path(),
+        check_cache(),
+        check_package_json(),
+        check_lockfile(),
+        check_symlinks(),
+    ];
+
+    print_report(&results);
+    offer_strict_rewrite()?;
+
+    Ok(!results.iter().any(|r| r.status == CheckStatus::Fail))
+}
+
+fn print_report(results: &[CheckResult]) {
+    for result in results {
+        let icon = match result.status {
+            CheckStatus::Pass => style(ui::icon(ui::Icons::SUCCESS)).green(),
+            CheckStatus::Warn => style(ui::icon(ui::Icons::WARNING)).yellow(),
+            CheckStatus::Fail => style(ui::icon(ui::Icons::ERROR)).red(),
+        };
+        println!("  {} {:<14} {}", icon, style(result.name).bold(), style(&result.detail).dim());
+        if let Some(fix) = &result.fix {
+            println!("     {} {}", style(ui::icon(ui::Icons::ARROW_RIGHT)).dim(), style(fix).cyan());
+        }
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    println!(
+        "\n{} passed, {} warnings, {} failed",
+        style(count(CheckStatus::Pass)).green().bold(),
+        style(count(CheckStatus::Warn)).yellow().bold(),
+        style(count(CheckStatus::Fail)).red().bold()
+    );
+}
+
+fn check_node() -> CheckResult {
+    let path = match which::which("node") {
+        Ok(p) => p,
+        Err(_) => {
+            return CheckResult::warn(
+                "Node.js",
+                "not found on PATH, the portable runtime will be used",
+                "Install Node.js from https://nodejs.org or run `crabby run` once to download the portable runtime",
+            );
+        }
+    };
+
+    let version = Command::new(&path)
+        .arg("--version")
+        .output()
+        .ok()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    match semver::Version::parse(version.trim_start_matches('v')) {
+        Ok(v) if v.major >= MIN_NODE_MAJOR => {
+            CheckResult::pass("Node.js", format!("{} ({})", version, path.display()))
+        }
+        Ok(_) => CheckResult::fail(
+            "Node.js",
+            format!("{} is too old (need v{}+)", version, MIN_NODE_MAJOR),
+            format!("Install Node.js {} or newer from https://nodejs.org", MIN_NODE_MAJOR),
+        ),
+        Err(_) => CheckResult::fail(
+            "Node.js",
+            format!("could not determine version of {}", path.display()),
+            "Reinstall Node.js from https://nodejs.org",
+        ),
+    }
+}
+
+fn check_tool(name: &'static str, binary: &str) -> CheckResult {
+    match which::which(binary) {
+        Ok(p) => CheckResult::pass(name, p.display().to_string()),
+        Err(_) => CheckResult::warn(
+            name,
+            "not found (optional)",
+            format!("Only needed for projects that expect {}; ships with Node.js", binary),
+        ),
+    }
+}
+
+fn check_tsx() -> CheckResult {
+    match crate::tsx_utils::get_tsx_command() {
+        Ok(crate::tsx_utils::TsxCommand::NodeMjs(p)) | Ok(crate::tsx_utils::TsxCommand::Executable(p)) => {
+            CheckResult::pass("tsx", p.display().to_string())
+        }
+        Err(_) => CheckResult::warn(
+            "tsx",
+            "not installed, TypeScript files cannot be run",
+            "crabby install tsx -D",
+        ),
+    }
+}
+
+fn check_global_bin_on_path() -> CheckResult {
+    let bin_dir = match global::get_global_bin_dir() {
+        Ok(d) => d,
+        Err(e) => return CheckResult::fail("Global bin", e.to_string(), "Make sure your home directory is writable"),
+    };
+
+    if setup_path::is_on_path(&bin_dir) {
+        CheckResult::pass("Global bin", bin_dir.display().to_string())
+    } else {
+        CheckResult::warn("Global bin", format!("{} is not on PATH", bin_dir.display()), "crabby setup-path")
+    }
+}
+
+fn check_cache() -> CheckResult {
+    let cache_dir = match config::get_cache_dir() {
+        Ok(d) => d,
+        Err(e) => return CheckResult::fail("Cache", e.to_string(), "Set HOME (or LOCALAPPDATA on Windows)"),
+    };
+
+    let probe = cache_dir.join(".crabby-doctor-probe");
+    if std::fs::write(&probe, b"ok").is_err() {
+        return CheckResult::fail(
+            "Cache",
+            format!("{} is not writable", cache_dir.display()),
+            format!("Fix the permissions of {}", cache_dir.display()),
+        );
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    let size = cache::dir_size(&cache_dir);
+    if size > CACHE_SIZE_LIMIT {
+        CheckResult::warn(
+            "Cache",
+            format!("{} uses {} (limit {})", cache_dir.display(), ui::format_size(size), ui::format_size(CACHE_SIZE_LIMIT)),
+            "crabby clean --cache",
+        )
+    } else {
+        CheckResult::pass("Cache", format!("{} ({})", cache_dir.display(), ui::format_size(size)))
+    }
+}
+
+fn check_package_json() -> CheckResult {
+    let path = Path::new("package.json");
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return CheckResult::pass("package.json", "not in a project directory, skipped");
+    };
+
+    let relaxed = manifest::relax_json(&content);
+    match manifest::parse_relaxed::<manifest::PackageJson>(&relaxed, path) {
+        Ok(pkg) if !relaxed.fixes.is_empty() => CheckResult::warn(
+            "package.json",
+            format!("{}@{}, but not strict JSON: {}", pkg.name, pkg.version, relaxed.fixes.join(", ")),
+            "Other tools may refuse it; run `crabby doctor` in a terminal to rewrite it",
+        ),
+        Ok(pkg) => CheckResult::pass("package.json", format!("{}@{}", pkg.name, pkg.version)),
+        Err(e) => CheckResult::fail("package.json", format!("{:#}", e), "Fix the JSON syntax in package.json"),
+    }
+}
+
+/// Offer to drop the comments and trailing commas from a package.json that
+/// only parses leniently. Returns whether it was rewritten.
+fn offer_strict_rewrite() -> Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    let path = Path::new("package.json");
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Ok(false);
+    };
+    let relaxed = manifest::relax_json(&content);
+    if relaxed.fixes.is_empty() || manifest::parse_relaxed::<serde_json::Value>(&relaxed, path).is_err() {
+        return Ok(false);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    print!("\n{} Rewrite package.json as strict JSON, removing comments and trailing commas? (y/n): ", style(ui::icon("❓")).bold().yellow());
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    if !input.trim().eq_ignore_ascii_case("y") {
+        return Ok(false);
+    }
+    std::fs::write(path, manifest::strict_json(&content))?;
+    ui::print_success("Rewrote package.json");
+    Ok(true)
+}
+
+fn check_lockfile() -> CheckResult {
+    if !Path::new("crabby.lock").exists() {
+        if Path::new("package.json").exists() {
+            return CheckResult::warn("crabby.lock", "missing", "crabby install");
+        }
+        return CheckResult::pass("crabby.lock", "not in a project directory, skipped");
+    }
+
+    let content = match std::fs::read_to_string("crabby.lock") {
+        Ok(c) => c,
+        Err(e) => return CheckResult::fail("crabby.lock", e.to_string(), "Check the file permissions of crabby.lock"),
+    };
+    if let Some(line) = manifest::conflict_marker_line(&content) {
+        return CheckResult::fail("crabby.lock", format!("merge conflict markers at line {}", line), "crabby install --fix-lockfile");
+    }
+    let lock = match manifest::CrabbyLock::parse(&content) {
+        Ok(l) => l,
+        Err(e) => {
+            return CheckResult::fail(
+                "crabby.lock",
+                format!("{:#}", e),
+                "crabby install --fix-lockfile",
+            );
+        }
+    };
+
+    let pkg = match manifest::PackageJson::load() {
+        Ok(p) => p,
+        // Parse errors are reported by the package.json check
+        Err(_) => return CheckResult::pass("crabby.lock", format!("{} entries", lock.dependencies.len())),
+    };
+
+    let mut missing_from_lock: Vec<String> = pkg.get_all_dependencies()
+        .into_keys()
+        .filter(|name| !lock.dependencies.contains_key(name))
+        .collect();
+    missing_from_lock.sort();
+
+    if !missing_from_lock.is_empty() {
+        return CheckResult::fail(
+            "crabby.lock",
+            format!("out of sync with package.json, missing: {}", missing_from_lock.join(", ")),
+            "crabby install",
+        );
+    }
+
+    let node_modules = Path::new("node_modules");
+    let mut missing_on_disk: Vec<&String> = lock.dependencies.keys()
+        .filter(|name| !node_modules.join(name).exists())
+        .collect();
+    missing_on_disk.sort();
+
+    if !missing_on_disk.is_empty() {
+        let names: Vec<&str> = missing_on_disk.iter().map(|s| s.as_str()).collect();
+        return CheckResult::warn(
+            "crabby.lock",
+            format!("{} locked packages missing from node_modules ({})", names.len(), names.join(", ")),
+            "crabby install",
+        );
+    }
+
+    let config = config::load_config().unwrap_or_default();
+    let other_registries = lock.foreign_registries(&config.registry);
+    if !config.replace_registry_host && !other_registries.is_empty() {
+        let registries: Vec<String> = other_registries.iter().map(|(registry, count)| format!("{} ({})", registry, count)).collect();
+        return CheckResult::warn(
+            "crabby.lock",
+            format!("packages locked from {}, but the registry is {}", registries.join(", "), config.registry),
+            "crabby config set replaceRegistryHost true",
+        );
+    }
+
+    CheckResult::pass("crabby.lock", format!("{} entries, consistent with node_modules", lock.dependencies.len()))
+}
+
+#[cfg(target_os = "windows")]
+fn check_symlinks() -> CheckResult {
+    let base = std::env::temp_dir().join(format!("crabby-doctor-{}", std::process::id()));
+    let target = base.join("target");
+    let link = base.join("link");
+    let _ = std::fs::create_dir_all(&target);
+
+    let result = std::os::windows::fs::symlink_dir(&target, &link);
+    let _ = std::fs::remove_dir_all(&base);
+
+    match result {
+        Ok(_) => CheckResult::pass("Symlinks", "directory symlinks supported"),
+        Err(_) => CheckResult::warn(
+            "Symlinks",
+            "directory symlinks not permitted, workspaces will use junctions",
+            "Enable Developer Mode (Settings > For developers) or run as administrator",
+        ),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn check_symlinks() -> CheckResult {
+    CheckResult::pass("Symlinks", "supported")
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::{cache, config, global, node_runtime, ui};
+
+/// The commit this build was made from, embedded by build.rs
+pub const COMMIT: &str = env!("CRABBY_GIT_COMMIT");
+
+/// What `crabby env` reports, for bug reports
+#[derive(Debug, Serialize)]
+pub struct EnvInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// The node scripts of this project run on; `None` when there is none yet
+    pub node: Option<NodeInfo>,
+    pub registry: String,
+    pub cache_dir: PathBuf,
+    pub cache_size: u64,
+    pub global_dir: PathBuf,
+    pub color: bool,
+    pub tty: bool,
+    pub ascii: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodeInfo {
+    pub version: Option<String>,
+    pub path: PathBuf,
+    /// What the project asks for and where that comes from, e.g. `20 (.nvmrc)`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested: Option<String>,
+}
+
+/// Gather the environment of a command run in `dir`. Never downloads anything.
+pub fn collect(dir: &Path) -> Result<EnvInfo> {
+    let node = node_runtime::which_node(dir).ok().map(|path| NodeInfo {
+        version: node_runtime::node_version(&path).map(|v| format!("v{}", v)),
+        path: which::which(&path).unwrap_or(path),
+        requested: node_runtime::requested_version(dir).ok().flatten()
+            .map(|selection| format!("{} ({})", selection.spec, selection.source)),
+    });
+    let cache_dir = config::get_cache_dir()?;
+    Ok(EnvInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: COMMIT,
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        node,
+        registry: config::load_config()?.registry,
+        cache_size: cache::dir_size(&cache_dir),
+        cache_dir,
+        global_dir: global::get_global_dir()?,
+        color: console::colors_enabled(),
+        tty: console::Term::stdout().is_term(),
+        ascii: ui::ascii(),
+    })
+}
+
+/// `info` as plain lines, ready to paste into an issue
+pub fn render(info: &EnvInfo) -> String {
+    let yes_no = |on: bool| if on { "yes" } else { "no" };
+    let node = match &info.node {
+        Some(node) => {
+            let mut line = format!("{} {}", node.version.as_deref().unwrap_or("unknown version"), node.path.display());
+            if let Some(requested) = &node.requested {
+                line.push_str(&format!(", wants {}", requested));
+            }
+            line
+        }
+        None => "not found".to_string(),
+    };
+    [
+        ("crabby", format!("{} ({})", info.version, info.commit)),
+        ("os", format!("{} {}", info.os, info.arch)),
+        ("node", node),
+        ("registry", info.registry.clone()),
+        ("cache", format!("{} ({})", info.cache_dir.display(), ui::format_size(info.cache_size))),
+        ("global", info.global_dir.display().to_string()),
+        ("terminal", format!("color: {}, tty: {}, ascii: {}", yes_no(info.color), yes_no(info.tty), yes_no(info.ascii))),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{:<9} {}\n", key, value))
+    .collect()
+}
+
+/// `crabby env`: the environment as a block to copy into a bug report, or JSON
+pub fn run(json: bool) -> Result<()> {
+    let info = collect(&std::env::current_dir()?)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        print!("```\n{}```\n", render(&info));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_is_one_line_per_field() {
+        let info = EnvInfo {
+            version: "2.9.7",
+            commit: "15bc1449b2e0",
+            os: "linux",
+            arch: "x86_64",
+            node: Some(NodeInfo { version: Some("v20.11.0".into()), path: "/usr/bin/node".into(), requested: Some("20 (.nvmrc)".into()) }),
+            registry: "https://registry.npmjs.org".into(),
+            cache_dir: "/home/me/.cache/crabby".into(),
+            cache_size: 2048,
+            global_dir: "/home/me/.crabby/global".into(),
+            color: false,
+            tty: true,
+            ascii: true,
+        };
+        let text = render(&info);
+        assert_eq!(text.lines().count(), 7);
+        assert!(text.starts_with("crabby    2.9.7 (15bc1449b2e0)\n"), "{}", text);
+        assert!(text.contains("node      v20.11.0 /usr/bin/node, wants 20 (.nvmrc)\n"), "{}", text);
+        assert!(text.contains("terminal  color: no, tty: yes, ascii: yes\n"), "{}", text);
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["commit"], "15bc1449b2e0");
+        assert_eq!(json["node"]["version"], "v20.11.0");
+        assert_ne!(COMMIT, "");
+    }
+}
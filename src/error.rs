@@ -0,0 +1,140 @@
+use console::style;
+use serde::Serialize;
+
+/// What kind of failure ended a command, for the exit code and a hint on what to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    /// The registry or another server couldn't be reached, or answered 5xx
+    Network,
+    /// The registry refused the request (4xx), or has no such package or version
+    Registry,
+    /// A download didn't match its checksum
+    Integrity,
+    /// A package.json script or lifecycle script failed
+    Script,
+    /// Reading or writing files failed
+    Filesystem,
+    /// A config file or setting is invalid
+    Config,
+    Other,
+}
+
+/// Exit codes, as listed in `crabby --help`
+pub const EXIT_CODES: &str = "\
+Exit codes:
+  1  Other failure
+  2  Invalid command-line usage
+  3  Network error
+  4  Registry refused the request (4xx, unknown package or version)
+  5  Integrity check failed
+  6  Script failed
+  7  Filesystem error
+  8  Invalid configuration";
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Network => 3,
+            ErrorKind::Registry => 4,
+            ErrorKind::Integrity => 5,
+            ErrorKind::Script => 6,
+            ErrorKind::Filesystem => 7,
+            ErrorKind::Config => 8,
+        }
+    }
+
+    /// What to try next
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::Network => Some("Check your connection and the `registry` setting, or raise --registry-timeout"),
+            ErrorKind::Registry => Some("Check the package name and version, and `authToken` for private packages"),
+            ErrorKind::Integrity => Some("Run `crabby clean --cache` and install again"),
+            ErrorKind::Script => Some("The script's own output above says what went wrong"),
+            ErrorKind::Filesystem => Some("Check permissions and free disk space"),
+            ErrorKind::Config => Some("Run `crabby config list` to see the settings in effect"),
+            ErrorKind::Other => None,
+        }
+    }
+}
+
+/// An error that knows its [`ErrorKind`]. Wrap it with `.context()` as usual;
+/// [`classify`] finds it anywhere in the chain.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: ErrorKind,
+    message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// An `anyhow::Error` of class `kind`
+pub fn failure(kind: ErrorKind, message: impl Into<String>) -> anyhow::Error {
+    Failure { kind, message: message.into() }.into()
+}
+
+/// The class of `error`: the outermost [`Failure`], HTTP or I/O error in its chain
+pub fn classify(error: &anyhow::Error) -> ErrorKind {
+    for cause in error.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return failure.kind;
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return match e.status() {
+                Some(status) if status.is_client_error() => ErrorKind::Registry,
+                _ => ErrorKind::Network,
+            };
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorKind::Filesystem;
+        }
+    }
+    ErrorKind::Other
+}
+
+/// Print `error` for people: a red header, its causes indented below and a hint
+pub fn report(error: &anyhow::Error) {
+    let kind = classify(error);
+    let mut lines = error.chain().map(|cause| cause.to_string());
+    let header = lines.next().unwrap_or_default();
+    eprintln!("{} {}", style(crate::ui::icon(crate::ui::Icons::ERROR)).red(), style(header).red().bold());
+    for cause in lines {
+        for line in cause.lines() {
+            eprintln!("    {}", style(line).dim());
+        }
+    }
+    if let Some(hint) = kind.hint() {
+        eprintln!("{} {}", style(crate::ui::icon(crate::ui::Icons::TIP)).dim(), hint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_classify_finds_the_kind_in_the_chain() {
+        let script: anyhow::Result<()> = Err(failure(ErrorKind::Script, "Command failed with status: 1"));
+        let error = script.context("postinstall of esbuild failed").unwrap_err();
+        assert_eq!(classify(&error), ErrorKind::Script);
+        assert_eq!(classify(&error).exit_code(), 6);
+
+        let io = std::fs::read("/definitely/not/here").context("Could not read package.json").unwrap_err();
+        assert_eq!(classify(&io), ErrorKind::Filesystem);
+        assert_eq!(classify(&anyhow::anyhow!("something else")), ErrorKind::Other);
+
+        let codes: std::collections::HashSet<i32> = [ErrorKind::Network, ErrorKind::Registry, ErrorKind::Integrity, ErrorKind::Script, ErrorKind::Filesystem, ErrorKind::Config, ErrorKind::Other]
+            .iter()
+            .map(|kind| kind.exit_code())
+            .collect();
+        assert_eq!(codes.len(), 7);
+    }
+}
@@ -1,5 +1,143 @@
 use crate::manifest::{CrabbyLock, PackageJson};
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+/// List the packages installed directly under `node_modules`, descending into
+/// `@scope` directories. Hidden entries like `.bin` are skipped.
+pub fn installed_packages(node_modules: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut packages = Vec::new();
+    if node_modules.exists() {
+        visit_dirs(node_modules, node_modules, &mut packages)?;
+    }
+    packages.sort();
+    Ok(packages)
+}
+
+fn visit_dirs(dir: &Path, base: &Path, packages: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_dir() { continue; }
+
+        let relative = path.strip_prefix(base)?;
+        let pkg_name = relative.to_string_lossy().replace("\\", "/");
+
+        if pkg_name.starts_with('.') { continue; } // Skip .bin, .cache etc
+
+        if pkg_name.starts_with('@') && !pkg_name.contains('/') {
+            // It's a scope, look inside
+            visit_dirs(&path, base, packages)?;
+        } else {
+            packages.push((pkg_name, path));
+        }
+    }
+    Ok(())
+}
+
+/// Version of `name` installed in `node_modules`, if it is installed
+pub fn installed_version(node_modules: &Path, name: &str) -> Option<String> {
+    let content = fs::read_to_string(node_modules.join(name).join("package.json")).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&crate::manifest::clean_json_content(content)).ok()?;
+    json.get("version")?.as_str().map(str::to_string)
+}
+
+/// Every locked package reachable from the dependencies declared in package.json
+pub fn reachable_packages(pkg: &PackageJson, lock: &CrabbyLock) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    for name in pkg.get_all_dependencies().keys().chain(pkg.peer_dependencies.keys()) {
+        collect_reachable(name, lock, &mut reachable);
+    }
+    reachable
+}
+
+/// Like `reachable_packages`, but devDependencies don't count, so anything only
+/// they pull in is left out (`npm prune --production`)
+pub fn production_packages(pkg: &PackageJson, lock: &CrabbyLock) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let roots = pkg.dependencies.keys()
+        .chain(pkg.optional_dependencies.keys())
+        .chain(pkg.peer_dependencies.keys());
+    for name in roots {
+        collect_reachable(name, lock, &mut reachable);
+    }
+    reachable
+}
+
+fn collect_reachable(name: &str, lock: &CrabbyLock, reachable: &mut HashSet<String>) {
+    if reachable.contains(name) { return; }
+    reachable.insert(name.to_string());
+
+    if let Some(dep_info) = lock.dependencies.get(name) {
+        for sub_dep in dep_info.dependencies.keys() {
+            collect_reachable(sub_dep, lock, reachable);
+        }
+    }
+}
+
+/// `crabby remove`: drop each of `names` from `pkg`, `lock` and the
+/// node_modules under `root`, then the packages nothing needs any more.
+/// Returns those pruned from node_modules.
+pub fn remove_packages(root: &Path, names: &[&str], pkg: &mut PackageJson, lock: &mut CrabbyLock) -> Result<Vec<String>> {
+    let node_modules = root.join("node_modules");
+    for name in names {
+        pkg.remove_dependency(name);
+        remove_installed(&node_modules, name)?;
+        lock.dependencies.remove(*name);
+    }
+    let reachable = reachable_packages(pkg, lock);
+    lock.dependencies.retain(|name, _| reachable.contains(name));
+    prune_orphans(&node_modules, &reachable, false)
+}
+
+/// Delete an installed package together with the shims it linked into `.bin`
+pub fn remove_installed(node_modules: &Path, name: &str) -> Result<()> {
+    let package_dir = node_modules.join(name);
+    let bin_dir = node_modules.join(".bin");
+
+    let bin_names: Vec<String> = fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|c| serde_json::from_str::<crate::package_utils::InstalledPackageJson>(&crate::manifest::clean_json_content(c)).ok())
+        .map(|p| match p.bin {
+            crate::package_utils::PackageBin::String(_) => vec![name.to_string()],
+            crate::package_utils::PackageBin::Map(map) => map.into_keys().collect(),
+            crate::package_utils::PackageBin::None => Vec::new(),
+        })
+        .unwrap_or_default();
+
+    for bin_name in bin_names {
+        for shim in [bin_dir.join(&bin_name), bin_dir.join(&bin_name).with_extension("cmd")] {
+            if shim.exists() {
+                fs::remove_file(&shim)?;
+            }
+        }
+    }
+
+    if package_dir.exists() {
+        crate::fs_utils::remove_dir_all(&package_dir)?;
+    }
+    Ok(())
+}
+
+/// Remove installed packages that aren't in `reachable`, returning their names.
+/// With `dry_run` nothing is deleted.
+pub fn prune_orphans(node_modules: &Path, reachable: &HashSet<String>, dry_run: bool) -> Result<Vec<String>> {
+    let mut pruned = Vec::new();
+    for (name, path) in installed_packages(node_modules)? {
+        // Workspace links are managed by `crabby install`, not the lockfile
+        if fs::symlink_metadata(&path).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+            continue;
+        }
+        if !reachable.contains(&name) {
+            if !dry_run {
+                remove_installed(node_modules, &name)?;
+            }
+            pruned.push(name);
+        }
+    }
+    Ok(pruned)
+}
 
 pub fn find_dependency_paths(lock: &CrabbyLock, pkg: &PackageJson, target: &str) -> Vec<Vec<String>> {
     let mut paths = Vec::new();
@@ -46,3 +184,131 @@ fn search_recursive(lock: &CrabbyLock, current: &str, target: &str, path: &mut V
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn write_package(node_modules: &Path, name: &str, manifest: &str) {
+        let dir = node_modules.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.json"), manifest).unwrap();
+    }
+
+    #[test]
+    fn test_remove_cleans_shims_and_orphans() {
+        let tmp = tempfile::tempdir().unwrap();
+        let node_modules = tmp.path().join("node_modules");
+        write_package(&node_modules, "cli-tool", r#"{"name": "cli-tool", "bin": {"tool": "bin/tool.js"}}"#);
+        write_package(&node_modules, "only-for-cli", r#"{"name": "only-for-cli"}"#);
+        write_package(&node_modules, "shared", r#"{"name": "shared"}"#);
+        write_package(&node_modules, "app-dep", r#"{"name": "app-dep"}"#);
+        fs::create_dir_all(node_modules.join(".bin")).unwrap();
+        fs::write(node_modules.join(".bin").join("tool"), "#!/bin/sh").unwrap();
+
+        let mut lock = CrabbyLock::default();
+        lock.add_package("cli-tool".into(), "1.0.0".into(), String::new(), HashMap::from([
+            ("only-for-cli".to_string(), "^1.0.0".to_string()),
+            ("shared".to_string(), "^1.0.0".to_string()),
+        ]));
+        lock.add_package("only-for-cli".into(), "1.0.0".into(), String::new(), HashMap::new());
+        lock.add_package("app-dep".into(), "1.0.0".into(), String::new(), HashMap::from([
+            ("shared".to_string(), "^1.0.0".to_string()),
+        ]));
+        lock.add_package("shared".into(), "1.0.0".into(), String::new(), HashMap::new());
+
+        // package.json after `cli-tool` was removed from it
+        let pkg = PackageJson {
+            dependencies: HashMap::from([("app-dep".to_string(), "^1.0.0".to_string())]),
+            ..Default::default()
+        };
+
+        remove_installed(&node_modules, "cli-tool").unwrap();
+        assert!(!node_modules.join("cli-tool").exists());
+        assert!(!node_modules.join(".bin").join("tool").exists());
+
+        lock.dependencies.remove("cli-tool");
+        let pruned = prune_orphans(&node_modules, &reachable_packages(&pkg, &lock), false).unwrap();
+        assert_eq!(pruned, vec!["only-for-cli".to_string()]);
+        assert!(!node_modules.join("only-for-cli").exists());
+        assert!(node_modules.join("shared").exists());
+        assert!(node_modules.join("app-dep").exists());
+    }
+
+    #[test]
+    fn test_remove_several_packages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let node_modules = tmp.path().join("node_modules");
+        for name in ["left-pad", "@scope/util", "util-dep", "kept"] {
+            write_package(&node_modules, name, &format!(r#"{{"name": "{}"}}"#, name));
+        }
+
+        let mut lock = CrabbyLock::default();
+        lock.add_package("left-pad".into(), "1.0.0".into(), String::new(), HashMap::new());
+        lock.add_package("@scope/util".into(), "1.0.0".into(), String::new(), HashMap::from([
+            ("util-dep".to_string(), "^1.0.0".to_string()),
+        ]));
+        lock.add_package("util-dep".into(), "1.0.0".into(), String::new(), HashMap::new());
+        lock.add_package("kept".into(), "1.0.0".into(), String::new(), HashMap::new());
+        let mut pkg = PackageJson {
+            dependencies: HashMap::from([("left-pad".to_string(), "^1.0.0".to_string()), ("kept".to_string(), "^1.0.0".to_string())]),
+            dev_dependencies: HashMap::from([("@scope/util".to_string(), "^1.0.0".to_string())]),
+            ..Default::default()
+        };
+
+        let pruned = remove_packages(tmp.path(), &["left-pad", "@scope/util"], &mut pkg, &mut lock).unwrap();
+        assert_eq!(pruned, ["util-dep"]);
+        assert!(!pkg.has_dependency("left-pad") && !pkg.has_dependency("@scope/util"));
+        let mut locked: Vec<&String> = lock.dependencies.keys().collect();
+        locked.sort();
+        assert_eq!(locked, ["kept"]);
+        assert_eq!(installed_packages(&node_modules).unwrap().into_iter().map(|(name, _)| name).collect::<Vec<_>>(), ["kept"]);
+    }
+
+    #[test]
+    fn test_installed_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let node_modules = tmp.path().join("node_modules");
+        write_package(&node_modules, "unversioned", r#"{"name": "unversioned"}"#);
+        write_package(&node_modules, "@scope/versioned", r#"{"name": "@scope/versioned", "version": "2.1.0"}"#);
+
+        assert_eq!(installed_version(&node_modules, "@scope/versioned").as_deref(), Some("2.1.0"));
+        assert_eq!(installed_version(&node_modules, "unversioned"), None);
+        assert_eq!(installed_version(&node_modules, "absent"), None);
+    }
+
+    #[test]
+    fn test_production_prune_drops_dev_subtree() {
+        let tmp = tempfile::tempdir().unwrap();
+        let node_modules = tmp.path().join("node_modules");
+        for name in ["express", "shared", "jest", "jest-util"] {
+            write_package(&node_modules, name, &format!(r#"{{"name": "{}"}}"#, name));
+        }
+
+        let mut lock = CrabbyLock::default();
+        lock.add_package("express".into(), "4.0.0".into(), String::new(), HashMap::from([
+            ("shared".to_string(), "^1.0.0".to_string()),
+        ]));
+        lock.add_package("jest".into(), "29.0.0".into(), String::new(), HashMap::from([
+            ("jest-util".to_string(), "^29.0.0".to_string()),
+            ("shared".to_string(), "^1.0.0".to_string()),
+        ]));
+        lock.add_package("jest-util".into(), "29.0.0".into(), String::new(), HashMap::new());
+        lock.add_package("shared".into(), "1.0.0".into(), String::new(), HashMap::new());
+
+        let pkg = PackageJson {
+            dependencies: HashMap::from([("express".to_string(), "^4.0.0".to_string())]),
+            dev_dependencies: HashMap::from([("jest".to_string(), "^29.0.0".to_string())]),
+            ..Default::default()
+        };
+
+        assert!(prune_orphans(&node_modules, &reachable_packages(&pkg, &lock), true).unwrap().is_empty());
+
+        let mut pruned = prune_orphans(&node_modules, &production_packages(&pkg, &lock), false).unwrap();
+        pruned.sort();
+        assert_eq!(pruned, vec!["jest".to_string(), "jest-util".to_string()]);
+        assert!(node_modules.join("express").exists());
+        assert!(node_modules.join("shared").exists());
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Directories that couldn't be removed and were moved aside instead
+static LEFTOVERS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// How long to wait before each retry of a removal that hit a locked file
+const BACKOFF_MS: [u64; 5] = [50, 100, 200, 400, 800];
+
+/// `path` in the form the OS handles best. On Windows that's an absolute
+/// `\\?\` path, which isn't limited to 260 characters; deep node_modules
+/// trees go past that easily. Elsewhere `path` is returned as is.
+pub fn extended_path(path: &Path) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    {
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            match std::env::current_dir() {
+                Ok(cwd) => cwd.join(path),
+                Err(_) => return path.to_path_buf(),
+            }
+        };
+        // `\\?\` paths aren't normalized by Windows, so `.`, `..` and `/` must go first
+        let mut clean = PathBuf::new();
+        for component in absolute.components() {
+            match component {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => {
+                    clean.pop();
+                }
+                other => clean.push(other),
+            }
+        }
+        let raw = clean.to_string_lossy().into_owned();
+        if raw.starts_with(r"\\?\") {
+            clean
+        } else if let Some(unc) = raw.strip_prefix(r"\\") {
+            PathBuf::from(format!(r"\\?\UNC\{}", unc))
+        } else {
+            PathBuf::from(format!(r"\\?\{}", raw))
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// Whether `err` may go away on its own: another process (an antivirus
+/// scanner, an editor, a file watcher) briefly holding a file open
+fn is_transient(err: &io::Error) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        // ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION, ERROR_DIR_NOT_EMPTY
+        matches!(err.raw_os_error(), Some(5 | 32 | 33 | 145))
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        // Something still writing into the tree while it's being removed
+        err.kind() == io::ErrorKind::DirectoryNotEmpty
+    }
+}
+
+/// Remove the directory at `path`, retrying while files in it are locked.
+/// If it still can't be removed, it's renamed to a `.crabby-trash-*` sibling so
+/// that the path is free again, and listed by `report_leftovers`.
+pub fn remove_dir_all(path: &Path) -> Result<()> {
+    let target = extended_path(path);
+    let mut attempts = BACKOFF_MS.iter();
+    let err = loop {
+        match std::fs::remove_dir_all(&target) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) if is_transient(&e) => match attempts.next() {
+                Some(ms) => std::thread::sleep(Duration::from_millis(*ms)),
+                None => break e,
+            },
+            Err(e) => break e,
+        }
+    };
+
+    let trash = trash_path(&target);
+    std::fs::rename(&target, &trash)
+        .map_err(|_| err)
+        .with_context(|| format!("Failed to remove {}", path.display()))?;
+    // What went away while retrying doesn't have to be left behind
+    let _ = std::fs::remove_dir_all(&trash);
+    if trash.exists() {
+        LEFTOVERS.lock().unwrap().push(trash);
+    }
+    Ok(())
+}
+
+/// A sibling of `path` that no other run picks
+fn trash_path(path: &Path) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    let name = format!(".crabby-trash-{:x}{:08x}", std::process::id(), nanos);
+    path.parent().unwrap_or(Path::new(".")).join(name)
+}
+
+/// Tell the user about directories `remove_dir_all` had to leave behind
+pub fn report_leftovers() {
+    let leftovers = std::mem::take(&mut *LEFTOVERS.lock().unwrap());
+    if leftovers.is_empty() {
+        return;
+    }
+    crate::ui::print_warning(&format!(
+        "Files in use kept {} from being removed; delete {} once they're closed:",
+        if leftovers.len() == 1 { "a directory" } else { "some directories" },
+        if leftovers.len() == 1 { "it" } else { "them" },
+    ));
+    for path in leftovers {
+        let shown = path.to_string_lossy().trim_start_matches(r"\\?\").to_string();
+        eprintln!("  {}", shown);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_dir_all_removes_deep_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("node_modules");
+        // Well past Windows' 260 character limit
+        let mut deep = root.clone();
+        for _ in 0..30 {
+            deep = deep.join("node_modules").join("some-package");
+        }
+        std::fs::create_dir_all(extended_path(&deep)).unwrap();
+        std::fs::write(extended_path(&deep.join("index.js")), "").unwrap();
+
+        remove_dir_all(&root).unwrap();
+        assert!(!root.exists());
+        // Nothing there is fine too
+        remove_dir_all(&root).unwrap();
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_trash_path_is_a_hidden_sibling() {
+        let trash = trash_path(Path::new("/project/node_modules/left-pad"));
+        assert_eq!(trash.parent(), Some(Path::new("/project/node_modules")));
+        assert!(trash.file_name().unwrap().to_string_lossy().starts_with(".crabby-trash-"));
+    }
+}
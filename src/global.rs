@@ -1,8 +1,10 @@
 use anyhow::{Context, Result};
 use console::style;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::{manifest, package_utils, registry, config};
+use crate::{manifest, package_utils, registry, config, ui};
 
 /// Get global installation directory (~/.crabby/global)
 pub fn get_global_dir() -> Result<PathBuf> {
@@ -36,98 +38,409 @@ pub fn get_global_bin_dir() -> Result<PathBuf> {
     Ok(bin_dir)
 }
 
-/// Install a package globally
-pub async fn install_global(package: &str) -> Result<()> {
+/// Where a global package lives: its own project with its own node_modules
+/// and crabby.lock (~/.crabby/global/<pkg>), so unrelated tools never share
+/// transitive dependencies
+pub fn package_prefix(global_dir: &Path, package: &str) -> PathBuf {
+    global_dir.join(package)
+}
+
+/// Install a package globally, returning the installed version
+pub async fn install_global(package: &str) -> Result<String> {
+    crate::safety::validate_package_name(package)?;
     let global_dir = get_global_dir()?;
+    migrate_flat_layout(&global_dir).await?;
+
+    println!("{} Installing {} globally...", style(ui::icon("🌍")).bold().blue(), package);
+    let lock_path = package_prefix(&global_dir, package).join("crabby.lock");
+    let lockfile = manifest::CrabbyLock::load_from(&lock_path)?;
+    let registry = config::load_config()?.registry;
+    let version = install_isolated(&global_dir, &get_global_bin_dir()?, &registry, package, lockfile, &HashMap::new()).await?;
+    println!("{} Installed {} v{}", style(ui::icon("✅")).green(), style(package).bold(), style(&version).dim());
+    Ok(version)
+}
+
+/// Install `package` from `registry` into its prefix starting from
+/// `lockfile`, record it and link its bins into `bin_dir`. `pinned` are
+/// installed alongside at exactly their versions.
+async fn install_isolated(global_dir: &Path, bin_dir: &Path, registry: &str, package: &str, lockfile: manifest::CrabbyLock, pinned: &HashMap<String, String>) -> Result<String> {
+    let prefix = package_prefix(global_dir, package);
+    fs::create_dir_all(prefix.join("node_modules"))?;
+    println!("   Target: {}", style(prefix.display()).dim());
+
+    let client = registry::get_client()?;
+    let stats = package_utils::InstallStats::new();
+    let (version, updated_lock) = if pinned.is_empty() {
+        let (version, _tarball, lock) = package_utils::install_package(&prefix, package, registry, &client, lockfile, &stats).await?;
+        (version, lock)
+    } else {
+        let lock = package_utils::install_all_packages(&prefix, pinned, registry, &client, lockfile, &stats).await?;
+        let version = lock.dependencies.get(package).map(|d| d.version.clone()).unwrap_or_default();
+        (version, lock)
+    };
+    updated_lock.save_to(&prefix.join("crabby.lock"))?;
+
+    record_global_package(global_dir, package, &version)?;
+    link_global_binaries(package, &prefix.join("node_modules").join(package), &shim_root(package), bin_dir)?;
+    Ok(version)
+}
+
+/// Where the shims of `package` find it, relative to ~/.crabby
+fn shim_root(package: &str) -> String {
+    format!("global/{}/node_modules", package)
+}
+
+/// Installs made before each global package had its own tree share one flat
+/// ~/.crabby/global/node_modules. Reinstall each top-level package into its
+/// own prefix at the versions it had, then delete the flat tree.
+async fn migrate_flat_layout(global_dir: &Path) -> Result<()> {
+    let flat = global_dir.join("node_modules");
+    if !flat.exists() {
+        return Ok(());
+    }
+    let flat_lock = load_global_lock(global_dir);
+    let registry = config::load_config()?.registry;
     let bin_dir = get_global_bin_dir()?;
-    let config = config::load_config()?;
-    
-    // We treat the global dir like a project with its own node_modules
-    let node_modules = global_dir.join("node_modules");
-    if !node_modules.exists() {
-        fs::create_dir_all(&node_modules)?;
+    let packages = list_global_packages()?;
+    if !packages.is_empty() {
+        ui::print_info(&format!("Moving {} global package(s) into separate trees...", packages.len()));
     }
 
-    println!("{} Installing {} globally...", style("🌍").bold().blue(), package);
-    println!("   Target: {}", style(global_dir.display()).dim());
-    
-    // Reuse package_utils::install_package logic but pointing to global dir
-    let client = registry::get_client()?;
-    
-    // Strategy: Change Directory. since CLI is single-process, this is fine.
-    let original_cwd = std::env::current_dir()?;
-    std::env::set_current_dir(&global_dir)?;
-    
-    // Install package
-    let lockfile = manifest::CrabbyLock::load().unwrap_or_default();
-    let result = package_utils::install_package(package, &config.registry, &client, lockfile).await;
-    
-    // Restore CWD
-    // We attempt to restore even if install failed, but trigger error if restore fails methods
-    let restore_res = std::env::set_current_dir(original_cwd);
-    
-    match result {
-        Ok((version, _tarball, updated_lock)) => {
-            restore_res?;
-            
-            // Save lockfile (conceptually in global dir, but we changed back, so we need to be careful)
-            // Wait, we are back in original CWD. 
-            // We should save lockfile in global dir.
-            // Actually install_package returns the updated lock struct. 
-            // We should save it to global_dir/crabby.lock
-            
-            let lock_path = global_dir.join("crabby.lock");
-            let content = serde_json::to_string_pretty(&updated_lock)?;
-            fs::write(lock_path, content)?;
-
-            // Link binaries to global bin
-            link_global_binaries(package, &global_dir, &bin_dir)?;
-            
-            println!("{} Installed {} v{}", style("✅").green(), style(package).bold(), style(&version).dim());
-            Ok(())
-        },
-        Err(e) => {
-            let _ = restore_res; // Best effort restore
-            Err(e)
+    let mut failed = 0;
+    for package in &packages {
+        // Pin the package and everything it pulled in to the flat tree's versions
+        let root = manifest::PackageJson {
+            dependencies: [(package.clone(), "*".to_string())].into(),
+            ..Default::default()
+        };
+        let reachable = crate::explorer::reachable_packages(&root, &flat_lock);
+        let lock = manifest::CrabbyLock {
+            dependencies: flat_lock.dependencies.iter()
+                .filter(|(name, _)| reachable.contains(*name))
+                .map(|(name, dep)| (name.clone(), dep.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        // Locked entries are not followed into their own dependencies, so name them all
+        let mut pinned: HashMap<String, String> = lock.dependencies.iter()
+            .map(|(name, dep)| (name.clone(), dep.version.clone()))
+            .collect();
+        pinned.entry(package.clone()).or_insert_with(|| "latest".to_string());
+
+        if let Err(e) = install_isolated(global_dir, &bin_dir, &registry, package, lock, &pinned).await {
+            ui::print_error(&format!("Could not move {}: {}", package, e));
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} global package(s) could not be moved out of {}", failed, flat.display());
+    }
+
+    crate::fs_utils::remove_dir_all(&flat)?;
+    let _ = fs::remove_file(global_dir.join("crabby.lock"));
+    Ok(())
+}
+
+/// Uninstall a global package: its shims, its tree and its record
+pub async fn remove_global(package: &str) -> Result<()> {
+    crate::safety::validate_package_name(package)?;
+    let global_dir = get_global_dir()?;
+    migrate_flat_layout(&global_dir).await?;
+
+    let prefix = package_prefix(&global_dir, package);
+    if !prefix.join("node_modules").join(package).exists() {
+        anyhow::bail!("{} is not installed globally", package);
+    }
+    unlink_global_binaries(package, &prefix.join("node_modules").join(package), &get_global_bin_dir()?)?;
+    crate::fs_utils::remove_dir_all(&prefix)?;
+    // Drop the empty @scope directory a scoped package leaves behind
+    if let Some(scope) = prefix.parent().filter(|p| *p != global_dir) {
+        let _ = fs::remove_dir(scope);
+    }
+
+    let pkg_path = global_dir.join("package.json");
+    if let Ok(content) = fs::read_to_string(&pkg_path) {
+        let mut json: serde_json::Value = serde_json::from_str(&manifest::clean_json_content(content))?;
+        if let Some(deps) = json.get_mut("dependencies").and_then(|d| d.as_object_mut()) {
+            deps.remove(package);
+        }
+        fs::write(&pkg_path, serde_json::to_string_pretty(&json)?)?;
+    }
+    Ok(())
+}
+
+/// The installed version of a global package, from its own tree or the old flat one
+pub fn installed_global_version(package: &str) -> Result<Option<String>> {
+    let global_dir = get_global_dir()?;
+    let isolated = package_prefix(&global_dir, package).join("node_modules");
+    Ok(crate::explorer::installed_version(&isolated, package)
+        .or_else(|| crate::explorer::installed_version(&global_dir.join("node_modules"), package)))
+}
+
+/// Remember a globally installed package in the global package.json so that
+/// `update -g --all` can tell it apart from its dependencies
+fn record_global_package(global_dir: &Path, package: &str, version: &str) -> Result<()> {
+    let pkg_path = global_dir.join("package.json");
+    let mut json: serde_json::Value = fs::read_to_string(&pkg_path)
+        .ok()
+        .and_then(|c| serde_json::from_str(&manifest::clean_json_content(c)).ok())
+        .unwrap_or_else(|| serde_json::json!({ "private": true }));
+
+    if !json.get("dependencies").is_some_and(|d| d.is_object()) {
+        json["dependencies"] = serde_json::json!({});
+    }
+    json["dependencies"][package] = format!("^{}", version).into();
+    fs::write(&pkg_path, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
+}
+
+fn load_global_lock(global_dir: &Path) -> manifest::CrabbyLock {
+    fs::read_to_string(global_dir.join("crabby.lock"))
+        .ok()
+        .and_then(|c| manifest::CrabbyLock::parse(&c).ok())
+        .unwrap_or_default()
+}
+
+/// Names of the packages the user installed globally. Installs made before
+/// they were recorded in the global package.json are recovered from the
+/// lockfile: every entry no other entry depends on.
+pub fn list_global_packages() -> Result<Vec<String>> {
+    Ok(global_package_names(&get_global_dir()?))
+}
+
+fn global_package_names(global_dir: &Path) -> Vec<String> {
+    let recorded: Vec<String> = fs::read_to_string(global_dir.join("package.json"))
+        .ok()
+        .and_then(|c| serde_json::from_str::<serde_json::Value>(&manifest::clean_json_content(c)).ok())
+        .and_then(|json| json.get("dependencies")?.as_object().map(|deps| deps.keys().cloned().collect()))
+        .unwrap_or_default();
+
+    let lockfile = load_global_lock(global_dir);
+    let mut packages: Vec<String> = if recorded.is_empty() {
+        let depended_on: std::collections::HashSet<&String> = lockfile.dependencies.values()
+            .flat_map(|d| d.dependencies.keys())
+            .collect();
+        lockfile.dependencies.keys()
+            .filter(|name| !depended_on.contains(name))
+            .cloned()
+            .collect()
+    } else {
+        recorded
+    };
+    packages.sort();
+    packages
+}
+
+/// A global package as `crabby list -g` shows it
+#[derive(Debug, Serialize)]
+pub struct GlobalPackage {
+    pub name: String,
+    pub version: Option<String>,
+    /// Commands it put in the global bin dir
+    pub bins: Vec<String>,
+    /// Its own crabby.lock, or the shared one of the old flat layout
+    #[serde(skip)]
+    pub lockfile: manifest::CrabbyLock,
+}
+
+/// Every package installed globally into `global_dir`, by name
+pub fn global_packages(global_dir: &Path) -> Result<Vec<GlobalPackage>> {
+    global_package_names(global_dir).into_iter().map(|name| {
+        let prefix = package_prefix(global_dir, &name);
+        let lock_path = prefix.join("crabby.lock");
+        let (root, lockfile) = if lock_path.is_file() {
+            (prefix, manifest::CrabbyLock::load_from(&lock_path)?)
+        } else {
+            (global_dir.to_path_buf(), load_global_lock(global_dir))
+        };
+        let node_modules = root.join("node_modules");
+        let mut bins: Vec<String> = package_bins(&name, &node_modules.join(&name))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(bin, _)| bin)
+            .collect();
+        bins.sort();
+        Ok(GlobalPackage {
+            version: crate::explorer::installed_version(&node_modules, &name)
+                .or_else(|| lockfile.dependencies.get(&name).map(|dep| dep.version.clone())),
+            name,
+            bins,
+            lockfile,
+        })
+    }).collect()
+}
+
+/// Update a global package to its latest version.
+/// Returns the previously installed version (if any) and the new one.
+pub async fn update_global(package: &str) -> Result<(Option<String>, String)> {
+    crate::safety::validate_package_name(package)?;
+    println!("{} Updating global package {}...", style(ui::icon("🌍")).bold().blue(), package);
+
+    let global_dir = get_global_dir()?;
+    migrate_flat_layout(&global_dir).await?;
+
+    let registry = config::load_config()?.registry;
+    let (previous, version) = update_isolated(&global_dir, &get_global_bin_dir()?, &registry, package).await?;
+    println!("{} Installed {} v{}", style(ui::icon("✅")).green(), style(package).bold(), style(&version).dim());
+    Ok((previous, version))
+}
+
+/// Reinstall `package` in its prefix at the latest version. Nothing changes
+/// if that fails; once it succeeds, the shims of bins the new version no
+/// longer ships are removed.
+async fn update_isolated(global_dir: &Path, bin_dir: &Path, registry: &str, package: &str) -> Result<(Option<String>, String)> {
+    // Drop the pin so the install resolves the latest version instead of reusing
+    // the lock; the lock on disk only changes once that install succeeds
+    let lock_path = package_prefix(global_dir, package).join("crabby.lock");
+    let mut lockfile = manifest::CrabbyLock::load_from(&lock_path)?;
+    let previous = lockfile.dependencies.remove(package).map(|d| d.version);
+
+    let installed = package_prefix(global_dir, package).join("node_modules").join(package);
+    let bin_names = |pkg_dir: &Path| -> Vec<String> {
+        package_bins(package, pkg_dir).unwrap_or_default().into_iter().map(|(name, _)| name).collect()
+    };
+    let old_bins = bin_names(&installed);
+
+    let version = install_isolated(global_dir, bin_dir, registry, package, lockfile, &HashMap::new()).await?;
+    let new_bins = bin_names(&installed);
+    let dropped: Vec<String> = old_bins.into_iter().filter(|name| !new_bins.contains(name)).collect();
+    remove_shims(&dropped, bin_dir)?;
+    Ok((previous, version))
+}
+
+/// Check every global package against the registry and update the outdated
+/// ones, or those the user picks with `interactive`. Failures are reported per
+/// package and don't stop the remaining updates.
+pub async fn update_all_globals(registry: &str, interactive: bool) -> Result<()> {
+    let mut candidates = Vec::new();
+    for package in list_global_packages()? {
+        let current = installed_global_version(&package)?.unwrap_or_else(|| "-".to_string());
+        candidates.push((package, current));
+    }
+    if candidates.is_empty() {
+        ui::print_info("No global packages installed");
+        return Ok(());
+    }
+
+    ui::print_step(ui::Icons::SEARCH, "Checking global packages for updates...");
+    let mut outdated = crate::update::outdated_among(candidates, registry).await?;
+    if outdated.is_empty() {
+        ui::print_success("All global packages are up to date!");
+        return Ok(());
+    }
+
+    ui::print_header(&format!("{} Updates available", ui::Icons::UPDATE));
+    let rows: Vec<Vec<String>> = outdated.iter()
+        .map(|(name, current, latest)| vec![name.clone(), style(current).dim().to_string(), style(latest).green().to_string()])
+        .collect();
+    ui::print_table(&["Package", "Current", "Latest"], &rows);
+
+    if interactive {
+        let items: Vec<String> = outdated.iter()
+            .map(|(name, current, latest)| format!("{} {} {} {}", name, current, crate::ui::icon("→"), latest))
+            .collect();
+        let Some(picked) = ui::prompt_multi_selection(&items, "Select packages to update")? else {
+            return Ok(());
+        };
+        outdated = outdated.into_iter().enumerate()
+            .filter(|(i, _)| picked.contains(i))
+            .map(|(_, row)| row)
+            .collect();
+    }
+    let packages: Vec<String> = outdated.into_iter().map(|(name, _, _)| name).collect();
+    if packages.is_empty() {
+        ui::print_info("Nothing selected");
+        return Ok(());
+    }
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for package in &packages {
+        match update_global(package).await {
+            Ok((previous, version)) => {
+                let previous = previous.unwrap_or_else(|| "-".to_string());
+                let status = if previous == version {
+                    style("up to date").dim().to_string()
+                } else {
+                    style("updated").green().to_string()
+                };
+                rows.push(vec![package.clone(), previous, version, status]);
+            }
+            Err(e) => {
+                ui::print_error(&format!("Failed to update {}: {}", package, e));
+                rows.push(vec![package.clone(), "-".to_string(), "-".to_string(), style("failed").red().to_string()]);
+                failed += 1;
+            }
         }
     }
+
+    println!();
+    ui::print_table(&["Package", "Previous", "Current", "Status"], &rows);
+    if failed > 0 {
+        anyhow::bail!("{} of {} global packages failed to update", failed, packages.len());
+    }
+    ui::print_success(&format!("Updated {} global packages", packages.len()));
+    Ok(())
+}
+
+/// The bin names a package declares, mapped to their script paths
+fn package_bins(pkg_name: &str, pkg_dir: &Path) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(pkg_dir.join("package.json"))?;
+    let json: serde_json::Value = serde_json::from_str(&manifest::clean_json_content(content))?;
+
+    let bins = match json.get("bin") {
+        Some(serde_json::Value::Object(map)) => map.iter()
+            .filter_map(|(name, path)| Some((name.clone(), path.as_str()?.to_string())))
+            .collect(),
+        // "bin": "./cli.js" -> name is the package name without its scope
+        Some(serde_json::Value::String(path)) => {
+            vec![(pkg_name.rsplit('/').next().unwrap_or(pkg_name).to_string(), path.clone())]
+        }
+        _ => Vec::new(),
+    };
+    Ok(bins)
+}
+
+/// Delete the global shims for every bin `pkg_dir` declares
+pub fn unlink_global_binaries(pkg_name: &str, pkg_dir: &Path, global_bin_dir: &Path) -> Result<()> {
+    let bin_names: Vec<String> = package_bins(pkg_name, pkg_dir).unwrap_or_default()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    remove_shims(&bin_names, global_bin_dir)
 }
 
-pub async fn update_global(package: &str) -> Result<()> {
-    println!("{} Updating global package {}...", style("🌍").bold().blue(), package);
-    // Reuse install logic as it fetches latest matching version
-    install_global(package).await
+/// Delete the shims named `bin_names` from `global_bin_dir`
+fn remove_shims(bin_names: &[String], global_bin_dir: &Path) -> Result<()> {
+    for bin_name in bin_names {
+        let shim = global_bin_dir.join(bin_name);
+        for path in [shim.clone(), shim.with_extension("cmd")] {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    Ok(())
 }
 
-fn link_global_binaries(pkg_name: &str, global_dir: &Path, global_bin_dir: &Path) -> Result<()> {
-    // Read the installed package.json from the global directory
-    let pkg_path = global_dir.join("node_modules").join(pkg_name).join("package.json");
+/// Create global shims for the bins of the package at `pkg_dir`. `shim_root`
+/// is where shims find the package, relative to ~/.crabby.
+pub fn link_global_binaries(pkg_name: &str, pkg_dir: &Path, shim_root: &str, global_bin_dir: &Path) -> Result<()> {
+    let pkg_path = pkg_dir.join("package.json");
     
     if !pkg_path.exists() {
-        println!("{} Warning: package.json not found at {}", style("⚠️").yellow(), style(pkg_path.display()).dim());
+        println!("{} Warning: package.json not found at {}", style(ui::icon("⚠️")).yellow(), style(pkg_path.display()).dim());
         return Ok(());
     }
     
-    let content = fs::read_to_string(&pkg_path)?;
-    let json: serde_json::Value = serde_json::from_str(&content)?;
-    
-    if let Some(bin) = json.get("bin") {
-        if let Some(bin_map) = bin.as_object() {
-            for (bin_name, script_path) in bin_map {
-                if let Some(path_str) = script_path.as_str() {
-                    create_global_shim(bin_name, pkg_name, path_str, global_bin_dir)?;
-                }
-            }
-        } else if let Some(path_str) = bin.as_str() {
-            // "bin": "./cli.js" -> name is package name
-            create_global_shim(pkg_name, pkg_name, path_str, global_bin_dir)?;
-        }
+    for (bin_name, script_path) in package_bins(pkg_name, pkg_dir)? {
+        create_global_shim(&bin_name, pkg_name, &script_path, shim_root, global_bin_dir)?;
     }
     
     Ok(())
 }
 
-fn create_global_shim(bin_name: &str, pkg_name: &str, script_path: &str, global_bin_dir: &Path) -> Result<()> {
+fn create_global_shim(bin_name: &str, pkg_name: &str, script_path: &str, shim_root: &str, global_bin_dir: &Path) -> Result<()> {
     // The target script path relative to the global node_modules
     // absolute path is global_modules / pkg / script
     let target_bin = global_bin_dir.join(bin_name);
@@ -137,24 +450,22 @@ fn create_global_shim(bin_name: &str, pkg_name: &str, script_path: &str, global_
     // Windows: Create .cmd and shell shim
     #[cfg(target_os = "windows")]
     {
-        // %~dp0 is the directory of the cmd file (global/bin)
-        // We need to go ../global/node_modules/pkg/script
-        // global/bin is sibling to global/global (where node_modules is)
-        // structure: ~/.crabby/global/node_modules
-        // structure: ~/.crabby/bin
-        // So from bin, go ../global/node_modules
+        // %~dp0 is the directory of the cmd file (~/.crabby/bin)
+        // The package lives in ~/.crabby/<shim_root>/pkg, e.g.
+        // ~/.crabby/global/pkg/node_modules/pkg
+        // So from bin, go ../<shim_root>
         
         // Shim content
         let cmd_content = format!(
-            "@ECHO OFF\r\nnode \"%~dp0\\..\\global\\node_modules\\{}\\{}\" %*",
-            pkg_name, script_path
+            "@ECHO OFF\r\nnode \"%~dp0\\..\\{}\\{}\\{}\" %*",
+            shim_root.replace('/', "\\"), pkg_name.replace('/', "\\"), script_path
         );
         fs::write(target_bin.with_extension("cmd"), cmd_content)?;
         
         // Also create bash shim for git bash
         let sh_content = format!(
-            "#!/bin/sh\nexec node \"$0/../../global/node_modules/{}/{}\" \"$@\"",
-            pkg_name, script_path
+            "#!/bin/sh\nexec node \"$0/../../{}/{}/{}\" \"$@\"",
+            shim_root, pkg_name, script_path
         );
          fs::write(&target_bin, sh_content)?;
     }
@@ -163,8 +474,8 @@ fn create_global_shim(bin_name: &str, pkg_name: &str, script_path: &str, global_
     {
         use std::os::unix::fs::PermissionsExt;
         let sh_content = format!(
-            "#!/bin/sh\nexec node \"$0/../../global/node_modules/{}/{}\" \"$@\"",
-            pkg_name, script_path
+            "#!/bin/sh\nexec node \"$0/../../{}/{}/{}\" \"$@\"",
+            shim_root, pkg_name, script_path
         );
         fs::write(&target_bin, sh_content)?;
         let mut perms = fs::metadata(&target_bin)?.permissions();
@@ -174,3 +485,76 @@ fn create_global_shim(bin_name: &str, pkg_name: &str, script_path: &str, global_
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_packages_with_versions_and_bins() {
+        let global_dir = tempfile::tempdir().unwrap();
+        let global_dir = global_dir.path();
+        fs::write(global_dir.join("package.json"), r#"{"dependencies": {"@acme/cli": "^2.0.0", "typescript": "^5.0.0"}}"#).unwrap();
+
+        // @acme/cli in its own prefix, with its own lock
+        let prefix = package_prefix(global_dir, "@acme/cli");
+        let installed = prefix.join("node_modules").join("@acme/cli");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(installed.join("package.json"), r#"{"name": "@acme/cli", "version": "2.1.0", "bin": "bin/cli.js"}"#).unwrap();
+        let mut lockfile = manifest::CrabbyLock::default();
+        lockfile.add_package("@acme/cli".into(), "2.1.0".into(), String::new(), HashMap::from([("chalk".to_string(), "^5.0.0".to_string())]));
+        lockfile.add_package("chalk".into(), "5.3.0".into(), String::new(), HashMap::new());
+        lockfile.save_to(&prefix.join("crabby.lock")).unwrap();
+
+        // typescript from before global packages were isolated: the shared lock only
+        let mut flat = manifest::CrabbyLock::default();
+        flat.add_package("typescript".into(), "5.4.5".into(), String::new(), HashMap::new());
+        flat.save_to(&global_dir.join("crabby.lock")).unwrap();
+        let installed = global_dir.join("node_modules").join("typescript");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(installed.join("package.json"), r#"{"bin": {"tsserver": "bin/tsserver", "tsc": "bin/tsc"}}"#).unwrap();
+
+        let packages = global_packages(global_dir).unwrap();
+        let listed: Vec<_> = packages.iter().map(|p| (p.name.as_str(), p.version.as_deref(), p.bins.clone())).collect();
+        assert_eq!(listed, [
+            ("@acme/cli", Some("2.1.0"), vec!["cli".to_string()]),
+            ("typescript", Some("5.4.5"), vec!["tsc".to_string(), "tsserver".to_string()]),
+        ]);
+        assert!(packages[0].lockfile.dependencies.contains_key("chalk"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_update_keeps_shims() {
+        use sha1::Digest;
+
+        let home = tempfile::tempdir().unwrap();
+        let global_dir = home.path().join("global");
+        let bin_dir = home.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let installed = package_prefix(&global_dir, "shim-demo").join("node_modules/shim-demo");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(installed.join("package.json"), r#"{"name": "shim-demo", "version": "1.0.0", "bin": {"shim-demo": "cli.js", "shim-old": "old.js"}}"#).unwrap();
+        link_global_binaries("shim-demo", &installed, &shim_root("shim-demo"), &bin_dir).unwrap();
+
+        // The registry doesn't know the package: the install fails and the shims stay
+        let missing = crate::test_utils::serve(|_| None);
+        assert!(update_isolated(&global_dir, &bin_dir, &missing, "shim-demo").await.is_err());
+        assert!(bin_dir.join("shim-demo").is_file());
+        assert!(bin_dir.join("shim-old").is_file());
+
+        // 2.0.0 drops shim-old: only that shim goes
+        let tarball = crate::test_utils::tarball(&[("package.json", r#"{"name": "shim-demo", "version": "2.0.0", "bin": {"shim-demo": "cli.js"}}"#)]);
+        let shasum = format!("{:x}", sha1::Sha1::digest(&tarball));
+        let registry = crate::test_utils::serve_with_base(move |registry, path| {
+            if path.ends_with(".tgz") {
+                return Some(tarball.clone());
+            }
+            Some(format!(r#"{{"name": "shim-demo", "dist-tags": {{"latest": "2.0.0"}}, "versions": {{"2.0.0": {{"version": "2.0.0", "dist": {{"tarball": "{}/shim-demo.tgz", "shasum": "{}"}}}}}}}}"#,
+                registry, shasum).into_bytes())
+        });
+        let (_, version) = update_isolated(&global_dir, &bin_dir, &registry, "shim-demo").await.unwrap();
+        assert_eq!(version, "2.0.0");
+        assert!(bin_dir.join("shim-demo").is_file());
+        assert!(!bin_dir.join("shim-old").exists());
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::Result;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::{manifest, ui};
+
+/// The kind of starter project `crabby init` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectType {
+    #[default]
+    #[serde(alias = "typescript")]
+    Ts,
+    #[serde(alias = "javascript")]
+    Js,
+}
+
+impl ProjectType {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "ts" | "typescript" => Some(ProjectType::Ts),
+            "js" | "javascript" => Some(ProjectType::Js),
+            _ => None,
+        }
+    }
+}
+
+/// What goes into the starter entry point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Starter {
+    /// An Express server, with express and its types as dependencies
+    #[default]
+    Express,
+    /// A file that logs hello, and no framework dependencies
+    Bare,
+}
+
+impl Starter {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "express" => Some(Starter::Express),
+            "bare" | "minimal" => Some(Starter::Bare),
+            _ => None,
+        }
+    }
+}
+
+pub struct InitOptions {
+    /// Chosen on the command line; skips the prompt
+    pub project_type: Option<ProjectType>,
+    /// Used when nothing was chosen, and offered as the prompt's default
+    pub default_type: ProjectType,
+    /// Ask for the project name, type and starter. Off for `--yes` and when stdin isn't a terminal.
+    pub interactive: bool,
+    /// Chosen on the command line (`--express` or `--bare`); skips the prompt.
+    /// Express when nothing was chosen.
+    pub starter: Option<Starter>,
+    /// Overwrite existing starter files instead of keeping them
+    pub force: bool,
+}
+
+const TS_INDEX: &str = r#"import express from 'express';
+
+const app = express();
+const port = 3000;
+
+app.get('/', (req, res) => {
+  res.send('Hello from Crabby Server! 🦀');
+});
+
+app.listen(port, () => {
+  console.log(`🚀 Server ready at http://localhost:${port}`);
+  console.log("💡 Try autocomplete: type 'app.' below!");
+});
+"#;
+
+const TSCONFIG: &str = r#"{
+  "compilerOptions": {
+    "target": "ES2020",
+    "module": "commonjs",
+    "moduleResolution": "node",
+    "esModuleInterop": true,
+    "strict": true,
+    "skipLibCheck": true,
+    "lib": ["ES2020", "DOM"],
+    "typeRoots": ["./node_modules/@types"]
+  },
+  "include": ["src/**/*.ts"]
+}"#;
+
+const TS_BARE_INDEX: &str = r#"const greeting: string = 'Hello from Crabby! 🦀';
+
+console.log(greeting);
+"#;
+
+const JS_BARE_INDEX: &str = r#"console.log('Hello from Crabby! 🦀');
+"#;
+
+const JS_INDEX: &str = r#"const express = require('express');
+
+const app = express();
+const port = 3000;
+
+app.get('/', (req, res) => {
+  res.send('Hello from Crabby Server! 🦀');
+});
+
+app.listen(port, () => {
+  console.log(`🚀 Server ready at http://localhost:${port}`);
+});
+"#;
+
+fn ask(question: &str) -> Result<String> {
+    print!("{} {}", style(ui::icon("❓")).bold().yellow(), question);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Write `content` to `root`/`relative` unless the file exists and `force` is off.
+/// Returns whether the file was written.
+fn write_starter(root: &Path, relative: &str, content: &str, force: bool) -> Result<bool> {
+    let path = root.join(relative);
+    if path.exists() && !force {
+        println!("{} Kept existing {}", style(ui::icon("↷")).dim(), relative);
+        return Ok(false);
+    }
+    fs::write(&path, content)?;
+    println!("{} Created {}", style(ui::icon("✅")).green(), relative);
+    Ok(true)
+}
+
+/// Set up a starter project in `root`. In an existing project only the
+/// missing pieces are added, unless `options.force` is set.
+pub fn run(root: &Path, options: InitOptions) -> Result<()> {
+    print!("{} ", style(ui::icon("🦀")).bold().cyan());
+    println!("{}", style("Initializing Crabby Kitchen...").bold());
+
+    let pkg_path = root.join("package.json");
+    let existing = pkg_path.exists();
+    if existing && !options.force {
+        ui::print_info("package.json already exists; only adding what is missing (use --force to overwrite)");
+    }
+
+    let current_name = manifest::PackageJson::load_from(&pkg_path)?.name;
+    let default_name = if current_name.is_empty() {
+        root.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("my-crabby-project")
+            .to_string()
+    } else {
+        current_name
+    };
+
+    let project_name = if options.interactive {
+        let answer = ask(&format!("Project name [default: {}]: ", style(&default_name).dim()))?;
+        if answer.is_empty() { default_name } else { answer }
+    } else {
+        default_name
+    };
+
+    manifest::ensure_package_files(root, Some(&project_name))?;
+    println!("{}", style("Created/Updated package.json").green());
+
+    // Create default config file
+    let config_path = root.join("crabby.config.json");
+    if !config_path.exists() {
+        let default_config = serde_json::json!({
+            "registry": "https://registry.npmjs.org",
+            "log_level": "info"
+        });
+        fs::write(&config_path, serde_json::to_string_pretty(&default_config)?)?;
+        println!("{}", style("Created crabby.config.json").green());
+    }
+
+    let project_type = match options.project_type {
+        Some(chosen) => chosen,
+        None if options.interactive => {
+            let default = match options.default_type {
+                ProjectType::Ts => "ts",
+                ProjectType::Js => "js",
+            };
+            let answer = ask(&format!("TypeScript or JavaScript? (ts/js) [default: {}]: ", default))?;
+            ProjectType::parse(&answer).unwrap_or(options.default_type)
+        }
+        None => options.default_type,
+    };
+    let starter = match options.starter {
+        Some(chosen) => chosen,
+        None if options.interactive => {
+            let answer = ask("Start from an Express server or a bare file? (express/bare) [default: express]: ")?;
+            Starter::parse(&answer).unwrap_or_default()
+        }
+        None => Starter::default(),
+    };
+
+    fs::create_dir_all(root.join("src"))?;
+
+    match project_type {
+        ProjectType::Ts => {
+            let index = match starter {
+                Starter::Express => TS_INDEX,
+                Starter::Bare => TS_BARE_INDEX,
+            };
+            // The starter server needs express; an existing entry point may not
+            let wrote_server = write_starter(root, "src/index.ts", index, options.force)?
+                && starter == Starter::Express;
+            // tsconfig.json is never overwritten, not even with --force
+            write_starter(root, "tsconfig.json", TSCONFIG, false)?;
+
+            // Add dependencies for better IDE experience, keeping versions the project already pins
+            let mut pkg = manifest::PackageJson::load_from(&pkg_path)?;
+            let mut wanted = vec![("typescript", "^5.0.0"), ("tsx", "^4.0.0"), ("@types/node", "^20.0.0")];
+            if wrote_server {
+                pkg.dependencies.insert("express".to_string(), "^4.18.2".to_string());
+                wanted.push(("@types/express", "^4.17.0"));
+            }
+            for (name, range) in wanted {
+                if options.force || !pkg.has_dependency(name) {
+                    pkg.add_dev_dependency(name.to_string(), range.to_string());
+                }
+            }
+            pkg.save_to(&pkg_path)?;
+            println!("{} Added TypeScript types to package.json", style(ui::icon("✅")).green());
+
+            println!("{} Run {} to enable IDE autocomplete", style(ui::icon("💡")).dim(), style("crabby install").cyan());
+            println!("{} Run with: crabby run src/index.ts", style(ui::icon("💡")).dim());
+        }
+        ProjectType::Js => {
+            let index = match starter {
+                Starter::Express => JS_INDEX,
+                Starter::Bare => JS_BARE_INDEX,
+            };
+            write_starter(root, "src/index.js", index, options.force)?;
+            println!("{} Run with: crabby run src/index.js", style(ui::icon("💡")).dim());
+        }
+    }
+
+    println!("\n{} Project initialized successfully!", style(ui::icon("🎉")).bold().green());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_interactive_init_defaults_to_typescript() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("my-app");
+        fs::create_dir(&root).unwrap();
+
+        run(&root, InitOptions { project_type: None, default_type: ProjectType::default(), interactive: false, starter: None, force: false }).unwrap();
+
+        assert!(root.join("src/index.ts").exists());
+        assert!(fs::read_to_string(root.join("src/index.ts")).unwrap().contains("express"));
+        assert!(root.join("tsconfig.json").exists());
+        assert!(!root.join("src/index.js").exists());
+        let pkg = manifest::PackageJson::load_from(&root.join("package.json")).unwrap();
+        assert_eq!(pkg.name, "my-app");
+        assert!(pkg.dev_dependencies.contains_key("typescript"));
+
+        let config: crate::config::CrabbyConfig = serde_json::from_str(r#"{"defaultProjectType": "javascript"}"#).unwrap();
+        assert_eq!(config.default_project_type, Some(ProjectType::Js));
+        assert_eq!(Starter::parse(" Minimal"), Some(Starter::Bare));
+        assert_eq!(Starter::parse("react"), None);
+    }
+
+    #[test]
+    fn test_init_keeps_existing_project_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/index.ts"), "console.log('mine');\n").unwrap();
+        fs::write(root.join("package.json"), r#"{"name": "existing", "version": "2.0.0", "devDependencies": {"typescript": "^4.9.0"}}"#).unwrap();
+
+        let options = |force| InitOptions { project_type: Some(ProjectType::Ts), default_type: ProjectType::Ts, interactive: false, starter: Some(Starter::Express), force };
+        run(root, options(false)).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("src/index.ts")).unwrap(), "console.log('mine');\n");
+        assert!(root.join("tsconfig.json").exists());
+        let pkg = manifest::PackageJson::load_from(&root.join("package.json")).unwrap();
+        assert_eq!(pkg.name, "existing");
+        assert_eq!(pkg.version, "2.0.0");
+        assert_eq!(pkg.dev_dependencies["typescript"], "^4.9.0");
+        assert!(pkg.dev_dependencies.contains_key("tsx"));
+        assert!(!pkg.dependencies.contains_key("express"));
+
+        run(root, options(true)).unwrap();
+        assert_eq!(fs::read_to_string(root.join("src/index.ts")).unwrap(), TS_INDEX);
+        let pkg = manifest::PackageJson::load_from(&root.join("package.json")).unwrap();
+        assert!(pkg.dependencies.contains_key("express"));
+    }
+
+    #[test]
+    fn test_bare_init_has_no_framework() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+
+        run(root, InitOptions { project_type: None, default_type: ProjectType::Ts, interactive: false, starter: Some(Starter::Bare), force: false }).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("src/index.ts")).unwrap(), TS_BARE_INDEX);
+        assert!(root.join("tsconfig.json").exists());
+        let pkg = manifest::PackageJson::load_from(&root.join("package.json")).unwrap();
+        assert!(!pkg.dependencies.contains_key("express"));
+        assert!(!pkg.dev_dependencies.contains_key("@types/express"));
+        assert!(pkg.dev_dependencies.contains_key("typescript"));
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{global, manifest, ui, workspace};
+
+/// Where `crabby link` stashes a registry-installed copy while a link replaces it
+const UNLINKED_DIR: &str = ".crabby-unlinked";
+
+/// Registered local packages live in ~/.crabby/links/<name>
+pub fn get_links_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir()
+        .context("Could not determine home directory")?;
+
+    let links_dir = home.join(".crabby").join("links");
+    if !links_dir.exists() {
+        fs::create_dir_all(&links_dir)?;
+    }
+
+    Ok(links_dir)
+}
+
+pub fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+/// `crabby link` inside a package: register it and expose its bins globally
+pub fn register(package_dir: &Path, links_dir: &Path) -> Result<String> {
+    let pkg = load_package(package_dir)?;
+    let registration = links_dir.join(&pkg.name);
+
+    if is_link(&registration) {
+        workspace::remove_link(&registration)?;
+    } else if registration.exists() {
+        anyhow::bail!("{} exists and is not a link, remove it first", registration.display());
+    }
+    if let Some(parent) = registration.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    workspace::link_dir(&package_dir.canonicalize()?, &registration)?;
+    Ok(pkg.name)
+}
+
+/// `crabby unlink` inside a package: drop its registration
+pub fn unregister(package_dir: &Path, links_dir: &Path) -> Result<String> {
+    let pkg = load_package(package_dir)?;
+    let registration = links_dir.join(&pkg.name);
+    if !is_link(&registration) {
+        anyhow::bail!("{} is not linked", pkg.name);
+    }
+    workspace::remove_link(&registration)?;
+    Ok(pkg.name)
+}
+
+/// `crabby link <name>` inside a project: point node_modules/<name> at the registered package
+pub fn link_into_project(root: &Path, name: &str, links_dir: &Path) -> Result<PathBuf> {
+    crate::safety::validate_package_name(name)?;
+    let registration = links_dir.join(name);
+    let source = fs::canonicalize(&registration)
+        .with_context(|| format!("{} is not registered. Run `crabby link` in its directory first.", name))?;
+
+    let node_modules = root.join("node_modules");
+    let target = node_modules.join(name);
+    if is_link(&target) {
+        workspace::remove_link(&target)?;
+    } else if target.exists() {
+        // Keep the registry copy so `unlink` can put it back
+        let backup = node_modules.join(UNLINKED_DIR).join(name);
+        if backup.exists() {
+            fs::remove_dir_all(&backup)?;
+        }
+        fs::create_dir_all(backup.parent().context("Invalid package name")?)?;
+        fs::rename(&target, &backup)?;
+    }
+
+    fs::create_dir_all(target.parent().context("Invalid package name")?)?;
+    workspace::link_dir(&source, &target)?;
+    Ok(source)
+}
+
+/// `crabby unlink <name>` inside a project. Returns `true` when a previously
+/// installed copy was restored.
+pub fn unlink_from_project(root: &Path, name: &str) -> Result<bool> {
+    crate::safety::validate_package_name(name)?;
+    let node_modules = root.join("node_modules");
+    let target = node_modules.join(name);
+    if !is_link(&target) {
+        anyhow::bail!("node_modules/{} is not a link", name);
+    }
+    workspace::remove_link(&target)?;
+
+    let backup = node_modules.join(UNLINKED_DIR).join(name);
+    if !backup.exists() {
+        return Ok(false);
+    }
+    fs::rename(&backup, &target)?;
+    // Leave no empty stash (or empty @scope) behind
+    for dir in [backup.parent(), Some(&node_modules.join(UNLINKED_DIR))].into_iter().flatten() {
+        let _ = fs::remove_dir(dir);
+    }
+    Ok(true)
+}
+
+fn load_package(package_dir: &Path) -> Result<manifest::PackageJson> {
+    let content = fs::read_to_string(package_dir.join("package.json"))
+        .context("No package.json in the current directory")?;
+    let pkg: manifest::PackageJson = serde_json::from_str(&manifest::clean_json_content(content))
+        .context("Failed to parse package.json")?;
+    if pkg.name.is_empty() {
+        anyhow::bail!("package.json has no \"name\" to link under");
+    }
+    crate::safety::validate_package_name(&pkg.name)?;
+    Ok(pkg)
+}
+
+/// `crabby link [name]`
+pub fn run_link(name: Option<&str>) -> Result<()> {
+    let links_dir = get_links_dir()?;
+    let cwd = std::env::current_dir()?;
+
+    match name {
+        None => {
+            let name = register(&cwd, &links_dir)?;
+            global::link_global_binaries(&name, &cwd, "links", &global::get_global_bin_dir()?)?;
+            ui::print_success(&format!("Registered {} -> {}", style(&name).cyan(), cwd.display()));
+            ui::print_info(&format!("Run `crabby link {}` in a project to use it", name));
+        }
+        Some(name) => {
+            let source = link_into_project(&cwd, name, &links_dir)?;
+            ui::print_success(&format!("Linked node_modules/{} -> {}", style(name).cyan(), source.display()));
+        }
+    }
+    Ok(())
+}
+
+/// `crabby unlink [name]`
+pub fn run_unlink(name: Option<&str>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+
+    match name {
+        None => {
+            let name = unregister(&cwd, &get_links_dir()?)?;
+            global::unlink_global_binaries(&name, &cwd, &global::get_global_bin_dir()?)?;
+            ui::print_success(&format!("Unregistered {}", style(&name).cyan()));
+        }
+        Some(name) => {
+            if unlink_from_project(&cwd, name)? {
+                ui::print_success(&format!("Unlinked {} and restored the installed copy", style(name).cyan()));
+            } else {
+                ui::print_success(&format!("Unlinked {}", style(name).cyan()));
+                ui::print_info(&format!("Run `crabby install` to install {} from the registry", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_and_unlink_restores_installed_copy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let links_dir = tmp.path().join("links");
+        let library = tmp.path().join("my-lib");
+        let app = tmp.path().join("app");
+        fs::create_dir_all(&library).unwrap();
+        fs::write(library.join("package.json"), r#"{"name": "@me/lib", "version": "0.1.0"}"#).unwrap();
+        fs::write(library.join("index.js"), "local").unwrap();
+
+        let installed = app.join("node_modules/@me/lib");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(installed.join("index.js"), "from registry").unwrap();
+
+        assert_eq!(register(&library, &links_dir).unwrap(), "@me/lib");
+        link_into_project(&app, "@me/lib", &links_dir).unwrap();
+        assert!(is_link(&installed));
+        assert_eq!(fs::read_to_string(installed.join("index.js")).unwrap(), "local");
+
+        assert!(unlink_from_project(&app, "@me/lib").unwrap());
+        assert!(!is_link(&installed));
+        assert_eq!(fs::read_to_string(installed.join("index.js")).unwrap(), "from registry");
+        assert!(!app.join("node_modules").join(UNLINKED_DIR).exists());
+
+        unregister(&library, &links_dir).unwrap();
+        assert!(link_into_project(&app, "@me/lib", &links_dir).is_err());
+    }
+
+    #[test]
+    fn test_link_refuses_names_outside_node_modules() {
+        let tmp = tempfile::tempdir().unwrap();
+        let links_dir = tmp.path().join("links");
+        let app = tmp.path().join("app");
+        fs::create_dir_all(app.join("node_modules")).unwrap();
+        fs::create_dir_all(&links_dir).unwrap();
+        // Both resolve through "../foo": links/../foo and app/node_modules/../foo
+        let library = tmp.path().join("foo");
+        let sibling = app.join("foo");
+        for dir in [&library, &sibling] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("package.json"), r#"{"name": "../foo", "version": "1.0.0"}"#).unwrap();
+        }
+
+        assert!(register(&library, &links_dir).is_err());
+        assert!(unregister(&library, &links_dir).is_err());
+        assert!(link_into_project(&app, "../foo", &links_dir).is_err());
+        assert!(unlink_from_project(&app, "../foo").is_err());
+        assert!(sibling.join("package.json").is_file());
+        assert!(!app.join("node_modules").join(UNLINKED_DIR).exists());
+    }
+}
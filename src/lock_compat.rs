@@ -0,0 +1,451 @@
+use anyhow::{Context, Result};
+use console::style;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use crate::manifest::{self, CrabbyLock, LockDependency};
+
+#[derive(Debug, Deserialize)]
+struct NpmLockfile {
+    #[serde(default, rename = "lockfileVersion")]
+    lockfile_version: u32,
+    #[serde(default)]
+    packages: HashMap<String, NpmLockPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmLockPackage {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    resolved: Option<String>,
+    #[serde(default)]
+    integrity: Option<String>,
+    #[serde(default)]
+    link: bool,
+    #[serde(default)]
+    dependencies: HashMap<String, String>,
+    #[serde(default, rename = "optionalDependencies")]
+    optional_dependencies: HashMap<String, String>,
+}
+
+/// Load crabby.lock, seeding it from package-lock.json or yarn.lock the first time
+/// crabby runs in a project that was managed by another tool. With
+/// `force_regenerate` the existing lockfile is ignored and resolution starts fresh.
+pub fn load_or_import(no_import: bool, force_regenerate: bool) -> Result<CrabbyLock> {
+    if force_regenerate {
+        if Path::new("crabby.lock").exists() {
+            crate::ui::print_warning("Ignoring existing crabby.lock, all versions will be resolved again");
+        }
+        return Ok(CrabbyLock::default());
+    }
+    if no_import || Path::new("crabby.lock").exists() {
+        return CrabbyLock::load();
+    }
+
+    let pkg = manifest::PackageJson::load().unwrap_or_default();
+    let imported = if Path::new("package-lock.json").exists() {
+        let content = fs::read_to_string("package-lock.json")?;
+        Some((import_package_lock(&content)?, "package-lock.json"))
+    } else if Path::new("yarn.lock").exists() {
+        let content = fs::read_to_string("yarn.lock")?;
+        Some((import_yarn_lock(&content, &pkg.get_all_dependencies())?, "yarn.lock"))
+    } else {
+        None
+    };
+
+    match imported {
+        Some((lock, source)) => {
+            if crate::reporter::human() {
+                println!("{} Imported {} entries from {}",
+                    crate::ui::icon(crate::ui::Icons::LOCK),
+                    style(lock.dependencies.len()).bold(),
+                    style(source).cyan()
+                );
+            }
+            Ok(lock)
+        }
+        None => Ok(CrabbyLock::default()),
+    }
+}
+
+/// Convert an npm lockfile (lockfileVersion 2 or 3) into a crabby lock.
+/// Only the hoisted top-level `node_modules/<name>` entries are kept since
+/// crabby installs a flat tree.
+pub fn import_package_lock(content: &str) -> Result<CrabbyLock> {
+    let npm_lock: NpmLockfile = serde_json::from_str(&manifest::clean_json_content(content.to_string()))
+        .context("Failed to parse package-lock.json")?;
+
+    if npm_lock.lockfile_version < 2 {
+        anyhow::bail!(
+            "package-lock.json uses lockfileVersion {}, only versions 2 and 3 can be imported",
+            npm_lock.lockfile_version
+        );
+    }
+
+    let mut lock = CrabbyLock::default();
+    for (key, entry) in npm_lock.packages {
+        let name = match key.strip_prefix("node_modules/") {
+            Some(name) if !name.contains("/node_modules/") => name.to_string(),
+            _ => continue, // root project or a nested (non-hoisted) copy
+        };
+        if entry.link {
+            continue; // workspace symlinks are recreated by crabby itself
+        }
+        let (version, tarball) = match (entry.version, entry.resolved) {
+            (Some(v), Some(r)) => (v, r),
+            _ => continue,
+        };
+
+        let mut dependencies = entry.dependencies;
+        dependencies.extend(entry.optional_dependencies);
+        lock.dependencies.insert(name, LockDependency {
+            version,
+            tarball,
+            integrity: entry.integrity,
+            dependencies,
+            registry: None,
+            committish: None,
+        });
+    }
+
+    Ok(lock)
+}
+
+/// Convert crabby.lock into an npm lockfileVersion 3 document. Entries whose
+/// tarball isn't an http(s) URL (git or file dependencies) have no faithful npm
+/// representation; their names are returned so the caller can warn about them.
+pub fn export_package_lock(pkg: &manifest::PackageJson, lock: &CrabbyLock) -> (serde_json::Value, Vec<String>) {
+    let prod = reachable(lock, pkg.dependencies.keys().chain(pkg.peer_dependencies.keys()));
+    let optional = reachable(lock, pkg.optional_dependencies.keys());
+    let dev = reachable(lock, pkg.dev_dependencies.keys());
+
+    let mut root = serde_json::Map::new();
+    root.insert("name".into(), pkg.name.clone().into());
+    root.insert("version".into(), pkg.version.clone().into());
+    for (key, deps) in [
+        ("dependencies", &pkg.dependencies),
+        ("devDependencies", &pkg.dev_dependencies),
+        ("optionalDependencies", &pkg.optional_dependencies),
+        ("peerDependencies", &pkg.peer_dependencies),
+    ] {
+        if !deps.is_empty() {
+            root.insert(key.into(), serde_json::to_value(deps).unwrap_or_default());
+        }
+    }
+
+    let mut packages = serde_json::Map::new();
+    packages.insert(String::new(), root.into());
+
+    let mut skipped = Vec::new();
+    for (name, dep) in &lock.dependencies {
+        if !dep.tarball.starts_with("http://") && !dep.tarball.starts_with("https://") {
+            skipped.push(name.clone());
+            continue;
+        }
+
+        let mut entry = serde_json::Map::new();
+        entry.insert("version".into(), dep.version.clone().into());
+        entry.insert("resolved".into(), dep.tarball.clone().into());
+        if let Some(integrity) = &dep.integrity {
+            entry.insert("integrity".into(), integrity.clone().into());
+        }
+        if !prod.contains(name.as_str()) {
+            match (dev.contains(name.as_str()), optional.contains(name.as_str())) {
+                (true, true) => { entry.insert("devOptional".into(), true.into()); }
+                (true, false) => { entry.insert("dev".into(), true.into()); }
+                (false, true) => { entry.insert("optional".into(), true.into()); }
+                (false, false) => {}
+            }
+        }
+        if !dep.dependencies.is_empty() {
+            entry.insert("dependencies".into(), serde_json::to_value(&dep.dependencies).unwrap_or_default());
+        }
+        packages.insert(format!("node_modules/{}", name), entry.into());
+    }
+    skipped.sort();
+
+    let document = serde_json::json!({
+        "name": pkg.name,
+        "version": pkg.version,
+        "lockfileVersion": 3,
+        "requires": true,
+        "packages": packages,
+    });
+    (document, skipped)
+}
+
+/// Names of every locked package reachable from `roots`
+fn reachable<'a>(lock: &'a CrabbyLock, roots: impl Iterator<Item = &'a String>) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    let mut queue: Vec<&String> = roots.collect();
+    while let Some(name) = queue.pop() {
+        if let Some((key, dep)) = lock.dependencies.get_key_value(name) {
+            if seen.insert(key.as_str()) {
+                queue.extend(dep.dependencies.keys());
+            }
+        }
+    }
+    seen
+}
+
+#[derive(Debug, Default)]
+struct YarnEntry {
+    specifiers: Vec<String>,
+    version: String,
+    resolved: String,
+    integrity: Option<String>,
+    dependencies: HashMap<String, String>,
+}
+
+/// Convert a classic (v1) yarn.lock into a crabby lock. When yarn kept several
+/// versions of a package, the one satisfying the project's own range wins, then
+/// the highest version.
+pub fn import_yarn_lock(content: &str, direct: &HashMap<String, String>) -> Result<CrabbyLock> {
+    if content.contains("__metadata:") {
+        anyhow::bail!("yarn.lock was written by Yarn 2+ (berry), only classic yarn.lock files can be imported");
+    }
+
+    let mut entries: Vec<YarnEntry> = Vec::new();
+    let mut current: Option<YarnEntry> = None;
+    let mut in_dependencies = false;
+
+    for line in content.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+
+        if indent == 0 {
+            if let Some(entry) = current.take() {
+                entries.push(entry);
+            }
+            let specifiers = trimmed.trim_end_matches(':')
+                .split(',')
+                .map(|s| s.trim().trim_matches('"').to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            current = Some(YarnEntry { specifiers, ..Default::default() });
+            in_dependencies = false;
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if indent <= 2 {
+            in_dependencies = trimmed == "dependencies:" || trimmed == "optionalDependencies:";
+            if let Some((key, value)) = split_yarn_field(trimmed) {
+                match key {
+                    "version" => entry.version = value,
+                    "resolved" => entry.resolved = value,
+                    "integrity" => entry.integrity = Some(value),
+                    _ => {}
+                }
+            }
+        } else if in_dependencies {
+            if let Some((name, range)) = split_yarn_field(trimmed) {
+                entry.dependencies.insert(name.to_string(), range);
+            }
+        }
+    }
+    if let Some(entry) = current.take() {
+        entries.push(entry);
+    }
+
+    let mut by_name: HashMap<String, Vec<YarnEntry>> = HashMap::new();
+    for entry in entries {
+        if entry.version.is_empty() || entry.resolved.is_empty() {
+            continue;
+        }
+        let name = match entry.specifiers.first().map(|s| yarn_specifier_name(s)) {
+            Some(n) => n.to_string(),
+            None => continue,
+        };
+        by_name.entry(name).or_default().push(entry);
+    }
+
+    let mut lock = CrabbyLock::default();
+    for (name, mut candidates) in by_name {
+        let wanted = direct.get(&name).map(|range| format!("{}@{}", name, range));
+        candidates.sort_by(|a, b| {
+            let va = semver::Version::parse(&a.version).ok();
+            let vb = semver::Version::parse(&b.version).ok();
+            vb.cmp(&va)
+        });
+        let chosen_idx = wanted
+            .and_then(|w| candidates.iter().position(|c| c.specifiers.contains(&w)))
+            .unwrap_or(0);
+        let chosen = candidates.swap_remove(chosen_idx);
+
+        // yarn appends "#<sha1>" to the tarball URL
+        let tarball = chosen.resolved.split('#').next().unwrap_or_default().to_string();
+        lock.dependencies.insert(name, LockDependency {
+            version: chosen.version,
+            tarball,
+            integrity: chosen.integrity,
+            dependencies: chosen.dependencies,
+            registry: None,
+            committish: None,
+        });
+    }
+
+    Ok(lock)
+}
+
+/// Split a `key "value"` / `key value` line from yarn.lock
+fn split_yarn_field(line: &str) -> Option<(&str, String)> {
+    let (key, value) = if let Some(rest) = line.strip_prefix('"') {
+        let end = rest.find('"')?;
+        (&rest[..end], rest[end + 1..].trim())
+    } else {
+        let (k, v) = line.split_once(' ')?;
+        (k, v.trim())
+    };
+    Some((key, value.trim_matches('"').to_string()))
+}
+
+/// `@scope/pkg@^1.0.0` -> `@scope/pkg`
+fn yarn_specifier_name(specifier: &str) -> &str {
+    // A scope's `@` comes first and doesn't separate the range
+    let scope = if specifier.starts_with('@') { 1 } else { 0 };
+    match specifier[scope..].find('@') {
+        Some(idx) => &specifier[..scope + idx],
+        None => specifier,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_package_lock_v3() {
+        let content = r#"{
+          "name": "app",
+          "lockfileVersion": 3,
+          "packages": {
+            "": { "name": "app", "dependencies": { "express": "^4.18.0" } },
+            "node_modules/express": {
+              "version": "4.18.2",
+              "resolved": "https://registry.npmjs.org/express/-/express-4.18.2.tgz",
+              "integrity": "sha512-abc",
+              "dependencies": { "accepts": "~1.3.8" }
+            },
+            "node_modules/@types/node": {
+              "version": "20.1.0",
+              "resolved": "https://registry.npmjs.org/@types/node/-/node-20.1.0.tgz"
+            },
+            "node_modules/express/node_modules/debug": {
+              "version": "2.6.9",
+              "resolved": "https://registry.npmjs.org/debug/-/debug-2.6.9.tgz"
+            },
+            "node_modules/my-workspace": { "resolved": "packages/ws", "link": true }
+          }
+        }"#;
+
+        let lock = import_package_lock(content).unwrap();
+        assert_eq!(lock.dependencies.len(), 2);
+        let express = &lock.dependencies["express"];
+        assert_eq!(express.version, "4.18.2");
+        assert_eq!(express.integrity.as_deref(), Some("sha512-abc"));
+        assert_eq!(express.dependencies["accepts"], "~1.3.8");
+        assert_eq!(lock.dependencies["@types/node"].version, "20.1.0");
+    }
+
+    #[test]
+    fn test_import_package_lock_v1_is_rejected() {
+        assert!(import_package_lock(r#"{"lockfileVersion": 1, "dependencies": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_export_package_lock_round_trip() {
+        let mut lock = CrabbyLock::default();
+        lock.add_package("express".into(), "4.18.2".into(),
+            "https://registry.npmjs.org/express/-/express-4.18.2.tgz".into(),
+            HashMap::from([("accepts".to_string(), "~1.3.8".to_string())]));
+        lock.add_package("accepts".into(), "1.3.8".into(),
+            "https://registry.npmjs.org/accepts/-/accepts-1.3.8.tgz".into(), HashMap::new());
+        lock.add_package("typescript".into(), "5.3.3-beta".into(),
+            "https://registry.npmjs.org/typescript/-/typescript-5.3.3-beta.tgz".into(), HashMap::new());
+        lock.add_package("local-lib".into(), "0.1.0".into(), "file:../local-lib".into(), HashMap::new());
+        lock.dependencies.get_mut("express").unwrap().integrity = Some("sha512-abc".into());
+
+        let pkg = manifest::PackageJson {
+            name: "app".into(),
+            version: "1.0.0".into(),
+            dependencies: HashMap::from([("express".to_string(), "^4.18.0".to_string())]),
+            dev_dependencies: HashMap::from([("typescript".to_string(), "^5.3.0".to_string())]),
+            ..Default::default()
+        };
+
+        let (document, skipped) = export_package_lock(&pkg, &lock);
+        assert_eq!(skipped, vec!["local-lib".to_string()]);
+        assert_eq!(document["lockfileVersion"], 3);
+        assert_eq!(document["packages"][""]["devDependencies"]["typescript"], "^5.3.0");
+        assert_eq!(document["packages"]["node_modules/typescript"]["dev"], true);
+        assert!(document["packages"]["node_modules/accepts"].get("dev").is_none());
+
+        let reimported = import_package_lock(&document.to_string()).unwrap();
+        assert_eq!(reimported.dependencies.len(), 3);
+        for (name, dep) in &reimported.dependencies {
+            let original = &lock.dependencies[name];
+            assert_eq!(dep.version, original.version);
+            assert_eq!(dep.tarball, original.tarball);
+            assert_eq!(dep.integrity, original.integrity);
+            assert_eq!(dep.dependencies, original.dependencies);
+        }
+    }
+
+    #[test]
+    fn test_import_yarn_lock() {
+        let content = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/code-frame@^7.0.0", "@babel/code-frame@^7.10.4":
+  version "7.12.13"
+  resolved "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz#dcfc826beef65e75c50e21d3837d7d95798dd658"
+  integrity sha512-HV1Cm0Q3ZrpCR93tkWOYiuYIgLxZXZFVG2VgK+MBWjUqZTundupbfx2aXarXuw5Ko5aMcjtJgbSs4vUGBS5v6g==
+  dependencies:
+    "@babel/highlight" "^7.12.13"
+
+debug@2.6.9, debug@^2.2.0:
+  version "2.6.9"
+  resolved "https://registry.yarnpkg.com/debug/-/debug-2.6.9.tgz#5d128515df134ff327e90a4c93f4e077a536341f"
+  dependencies:
+    ms "2.0.0"
+
+debug@^4.1.0:
+  version "4.3.4"
+  resolved "https://registry.yarnpkg.com/debug/-/debug-4.3.4.tgz#1319f6579357f2338d3337d2cdd4914bb5dcc865"
+"#;
+        let mut direct = HashMap::new();
+        direct.insert("debug".to_string(), "^2.2.0".to_string());
+
+        let lock = import_yarn_lock(content, &direct).unwrap();
+        let babel = &lock.dependencies["@babel/code-frame"];
+        assert_eq!(babel.version, "7.12.13");
+        assert_eq!(babel.tarball, "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz");
+        assert_eq!(babel.dependencies["@babel/highlight"], "^7.12.13");
+
+        // The project asks for ^2.2.0, so that copy wins over the newer 4.x
+        let debug = &lock.dependencies["debug"];
+        assert_eq!(debug.version, "2.6.9");
+        assert_eq!(debug.dependencies["ms"], "2.0.0");
+    }
+
+    #[test]
+    fn test_yarn_specifier_names() {
+        assert_eq!(yarn_specifier_name("@scope/pkg@^1.0.0"), "@scope/pkg");
+        assert_eq!(yarn_specifier_name("pkg@npm:other@^2"), "pkg");
+        assert_eq!(yarn_specifier_name("ünicode@1.0.0"), "ünicode");
+        assert_eq!(yarn_specifier_name("@"), "@");
+        assert_eq!(yarn_specifier_name(""), "");
+    }
+}
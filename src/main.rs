@@ -16,12 +16,37 @@ mod self_upgrade;
 mod ui;
 mod templates;
 mod explorer;
+mod doctor;
+mod check;
+mod lock_compat;
+mod store;
+mod link;
+mod pack;
+mod publish;
+mod version;
+mod reporter;
+mod completion;
+mod port;
+mod throttle;
+mod init;
+mod setup_path;
+mod fs_utils;
+mod backup;
+mod plan;
+mod native;
+mod resume;
+mod size;
+mod error;
+mod source;
+mod rebuild;
+mod env_info;
+#[cfg(test)]
+mod test_utils;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use console::style;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::Path;
-use std::collections::HashSet;
 use std::fs;
 
 const MAX_CONCURRENT_DOWNLOADS: usize = 10;
@@ -30,9 +55,34 @@ const MAX_CONCURRENT_DOWNLOADS: usize = 10;
 #[command(name = "crabby")]
 #[command(version)]
 #[command(about = "A modern Node.js packet manager in Rust", long_about = None)]
+#[command(after_help = error::EXIT_CODES)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Output format: human-readable, or newline-delimited JSON events on stdout
+    #[arg(long, global = true, value_enum, default_value = "default")]
+    reporter: reporter::ReporterKind,
+
+    /// Plain output: no colors, ASCII instead of emoji (also with NO_COLOR set)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// ASCII instead of emoji and box drawing (also with the `ascii` setting)
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Seconds a registry request may take (default 60, or the `timeout` setting)
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    registry_timeout: Option<u64>,
+
+    /// Seconds to wait for a connection to the registry (default 10)
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    connect_timeout: Option<u64>,
+
+    /// Idle connections kept open per registry host; 0 disables keep-alive
+    #[arg(long, global = true, value_name = "N")]
+    pool_max_idle: Option<usize>,
 }
 #[derive(Subcommand)]
 enum Commands {
@@ -50,18 +100,75 @@ enum Commands {
         #[arg(long, short = 'j', alias = "js")]
         js: Option<String>,
 
+        /// Node.js version to run on (`20`, `lts`, ...), over .nvmrc and engines.node
+        #[arg(long, conflicts_with_all = ["workspaces", "filter"])]
+        node: Option<String>,
+
         /// Watch for changes and restart (listen)
         #[arg(long, alias = "listen")]
         listen: bool,
+
+        /// Install tsx without asking when a TypeScript file needs it
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        /// Before each restart, kill whatever still listens on the script's --port (or $PORT)
+        #[arg(long, requires = "listen")]
+        free_port: bool,
+
+        /// Run the script in every workspace that defines it
+        #[arg(long, requires = "script")]
+        workspaces: bool,
+
+        /// Only run in workspaces whose name matches (name or glob, repeatable); implies --workspaces
+        #[arg(long, requires = "script")]
+        filter: Vec<String>,
+
+        /// Run workspace scripts concurrently, stopping all of them on the first failure
+        #[arg(long)]
+        parallel: bool,
+
+        /// Maximum number of workspace scripts running at once with --parallel
+        #[arg(long, requires = "parallel")]
+        concurrency: Option<usize>,
+
+        /// Do nothing, successfully, when package.json has no such script
+        #[arg(long, requires = "script")]
+        if_present: bool,
     },
     /// Initialize a new Crabby project
-    Init,
+    Init {
+        /// Create a TypeScript project without asking
+        #[arg(long, conflicts_with = "js")]
+        ts: bool,
+        /// Create a JavaScript project without asking
+        #[arg(long)]
+        js: bool,
+        /// Accept the defaults instead of prompting
+        #[arg(short, long)]
+        yes: bool,
+        /// Start from a file that logs hello, without Express
+        #[arg(long, alias = "minimal", conflicts_with = "express")]
+        bare: bool,
+        /// Start from an Express server without asking (the default without a prompt)
+        #[arg(long)]
+        express: bool,
+        /// Overwrite existing starter files
+        #[arg(long)]
+        force: bool,
+    },
     /// Create a new project from a template
     Create {
         /// The name of the template
         template: Option<String>,
         /// The name of the project directory
         name: Option<String>,
+        /// Initialize a git repository with a .gitignore and an initial commit
+        #[arg(long, conflicts_with = "no_git")]
+        git: bool,
+        /// Don't initialize a git repository, and don't ask
+        #[arg(long)]
+        no_git: bool,
     },
     /// Install a package from NPM registry
     #[command(visible_aliases = ["i", "add"])]
@@ -75,21 +182,84 @@ enum Commands {
         global: bool,
         
         /// Save as dev dependency
-        #[arg(long, short = 'D')]
+        #[arg(long, short = 'D', conflicts_with_all = ["save_optional", "save_peer"])]
         save_dev: bool,
+
+        /// Save as optional dependency
+        #[arg(long, short = 'O', conflicts_with = "save_peer")]
+        save_optional: bool,
+
+        /// Save as peer dependency
+        #[arg(long)]
+        save_peer: bool,
+
+        /// Don't seed crabby.lock from an existing package-lock.json or yarn.lock
+        #[arg(long)]
+        no_import: bool,
+
+        /// Discard an unreadable or conflicted crabby.lock and resolve everything again
+        #[arg(long, visible_alias = "fix-lockfile")]
+        force_regenerate: bool,
+
+        /// Don't check peer dependencies at all, not even for warnings.
+        /// The installed packages may then not work together.
+        #[arg(long)]
+        legacy_peer_deps: bool,
+
+        /// Install one package at a time, in name order, to reproduce a run when debugging
+        #[arg(long)]
+        sequential: bool,
+
+        /// Don't print the summary at the end
+        #[arg(long, short = 'q')]
+        quiet: bool,
+
+        /// Add the packages to this workspace (name, unscoped name or directory) instead of the root
+        #[arg(long, short = 'w', requires = "packages", conflicts_with = "global")]
+        workspace: Option<String>,
+
+        /// Don't run prepare/build in workspaces that other workspaces depend on
+        #[arg(long)]
+        no_build: bool,
+
+        /// Check the installed packages for known vulnerabilities afterwards
+        #[arg(long)]
+        audit: bool,
+
+        /// Resolve and print what would be added, updated or reused, without downloading or writing anything
+        #[arg(long, conflicts_with_all = ["global", "workspace"])]
+        dry_run: bool,
+
+        /// Download every tarball again, even if cached, and re-extract every
+        /// package; locked versions are kept
+        #[arg(long, conflicts_with = "dry_run")]
+        force: bool,
+
+        /// Print how long each package spent fetching metadata, downloading,
+        /// checking and extracting, and the slowest packages at the end
+        #[arg(long, conflicts_with = "quiet")]
+        verbose: bool,
     },
     /// Start the application (alias for `run start`)
     Start,
     /// Test the application (alias for `run test`)
-    Test,
+    Test {
+        /// Succeed when package.json has no test script instead of failing
+        #[arg(long)]
+        pass_with_no_tests: bool,
+    },
     /// Remove a package
     #[command(alias = "rm")]
     Remove {
-        /// The name of the package to remove
-        package: String,
+        /// The names of the packages to remove
+        #[arg(required = true)]
+        packages: Vec<String>,
         /// Skip confirmation prompt
         #[arg(long)]
         force: bool,
+        /// Uninstall global packages and their commands
+        #[arg(long, short = 'g')]
+        global: bool,
     },
     /// List all installed packages
     #[command(alias = "ls")]
@@ -97,6 +267,23 @@ enum Commands {
         /// Show dependency tree
         #[arg(long)]
         tree: bool,
+
+        /// Only packages declared in package.json, without their transitive dependencies
+        #[arg(long, alias = "top-level")]
+        direct: bool,
+
+        /// Levels of dependencies to show under each package; implies --tree
+        /// for project packages, and lists nothing below global ones when 0
+        #[arg(long)]
+        depth: Option<usize>,
+
+        /// List globally installed packages
+        #[arg(long, short = 'g', conflicts_with_all = ["tree", "direct"])]
+        global: bool,
+
+        /// Print the global packages, their versions and bins as JSON
+        #[arg(long, requires = "global")]
+        json: bool,
     },
     /// Update packages to latest versions
     Update {
@@ -106,12 +293,48 @@ enum Commands {
         /// Update global package
         #[arg(long, short = 'g')]
         global: bool,
+
+        /// Update every global package (same as `-g` without a package)
+        #[arg(long, requires = "global", conflicts_with = "package")]
+        all: bool,
+
+        /// With -g, pick which outdated global packages to update
+        #[arg(long, short = 'i', requires = "global", conflicts_with = "package")]
+        interactive: bool,
+
+        /// Show current -> new versions and the resulting install plan without changing anything
+        #[arg(long, conflicts_with = "global")]
+        dry_run: bool,
+
+        /// With --dry-run, also show whether each bump is patch, minor or major
+        #[arg(long, requires = "dry_run")]
+        diff: bool,
+
+        /// With --dry-run, plan the latest releases instead of the newest versions the declared ranges allow
+        #[arg(long, requires = "dry_run")]
+        latest: bool,
     },
     /// Show outdated packages
-    Outdated,
+    Outdated {
+        /// Only packages declared in package.json (the default; undoes an earlier --all)
+        #[arg(long, alias = "top-level", overrides_with = "all")]
+        direct: bool,
+        /// Also transitive packages from crabby.lock
+        #[arg(long, overrides_with = "direct")]
+        all: bool,
+        /// Pick which packages to update, to the newest version in range or the latest
+        #[arg(long, short = 'i', conflicts_with = "all")]
+        interactive: bool,
+        /// Only devDependencies
+        #[arg(long, conflicts_with_all = ["prod", "interactive"])]
+        dev: bool,
+        /// Only dependencies and optionalDependencies
+        #[arg(long, alias = "production", conflicts_with = "interactive")]
+        prod: bool,
+    },
     /// Show package information
     Info {
-        /// Package name
+        /// Package name, optionally with @version, @range or @tag
         package: String,
     },
     /// Explain why a package is installed
@@ -124,6 +347,24 @@ enum Commands {
         /// Show what would be removed without actually removing
         #[arg(long)]
         dry_run: bool,
+        /// Also remove devDependencies and everything only they need
+        #[arg(long)]
+        production: bool,
+    },
+    /// Run the install scripts of installed packages again and re-link their bins, e.g. after switching Node.js versions
+    Rebuild {
+        /// Only these packages (default: everything in node_modules)
+        names: Vec<String>,
+    },
+    /// Show how much disk space node_modules, its biggest packages and the cache take
+    #[command(alias = "du")]
+    Size {
+        /// How many of the biggest packages to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Print the sizes in bytes as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// Clean node_modules and cache
     Clean {
@@ -137,6 +378,17 @@ enum Commands {
         #[arg(long)]
         dry_run: bool,
     },
+    /// Restore package.json and crabby.lock from a backup
+    Restore {
+        /// The backup to restore (see --list); asks when omitted
+        id: Option<String>,
+        /// List backups instead of restoring one
+        #[arg(long, short = 'l')]
+        list: bool,
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
     /// Search for packages in npm registry
     Search {
         /// Search query
@@ -158,17 +410,293 @@ enum Commands {
         #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
         args: Vec<String>,
     },
+    /// Diagnose common environment problems
+    Doctor,
+    /// Print crabby's version and build, OS, Node.js, registry and directories, to paste into bug reports
+    Env {
+        /// Print it as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Verify that node_modules matches crabby.lock (offline unless --fix)
+    #[command(alias = "verify")]
+    Check {
+        /// Print the discrepancies as JSON
+        #[arg(long, conflicts_with = "fix")]
+        json: bool,
+        /// Reinstall missing or wrong packages and remove extraneous ones
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Register this package for local development, or link a registered package into this project
+    Link {
+        /// Registered package to link into node_modules
+        name: Option<String>,
+    },
+    /// Undo `crabby link`
+    Unlink {
+        /// Linked package to remove from node_modules
+        name: Option<String>,
+    },
+    /// Build a publishable tarball from the current package
+    Pack {
+        /// List the files without writing the tarball
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Publish the current package to the registry
+    Publish {
+        /// Dist-tag to publish under
+        #[arg(long, default_value = "latest")]
+        tag: String,
+
+        /// Visibility of a scoped package
+        #[arg(long, value_enum)]
+        access: Option<publish::Access>,
+
+        /// Do everything except upload
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bump the package version, then commit and tag it in git
+    Version {
+        /// patch, minor, major, prerelease or an explicit version like 2.1.0
+        target: String,
+
+        /// Prerelease identifier, e.g. `--preid beta` gives 1.2.4-beta.0
+        #[arg(long)]
+        preid: Option<String>,
+
+        /// Don't create a git commit and tag
+        #[arg(long)]
+        no_git_tag: bool,
+
+        /// Run even if the git working tree has uncommitted changes
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the path to node_modules/.bin
+    Bin {
+        /// Print the global bin directory (~/.crabby/bin) instead
+        #[arg(long, short = 'g')]
+        global: bool,
+    },
+    /// Print the path to node_modules
+    Root {
+        /// Print the global install directory, which holds one tree per global package
+        #[arg(long, short = 'g')]
+        global: bool,
+
+        /// Print the directory containing package.json instead of node_modules
+        #[arg(long)]
+        project: bool,
+    },
+    /// Create a .gitignore for a Node project, or add the entries it lacks
+    Gitignore,
+    /// Manage the Node.js versions scripts run on
+    Node {
+        #[command(subcommand)]
+        action: NodeCommands,
+    },
+    /// Show or change settings in crabby.config.json
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+    /// Add the global bin directory to PATH in your shell's startup file
+    SetupPath {
+        /// Shell to configure (detected from $SHELL by default)
+        #[arg(long, value_enum)]
+        shell: Option<setup_path::Shell>,
+    },
+    /// Print a shell completion script, e.g. `eval "$(crabby completion bash)"`
+    Completion {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+    /// List completion candidates (used by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        kind: completion::CompleteKind,
+    },
+    /// Work with crabby.lock
+    Lock {
+        #[command(subcommand)]
+        action: LockCommands,
+    },
+    /// Inspect the workspaces of a monorepo
+    #[command(alias = "ws")]
+    Workspaces {
+        #[command(subcommand)]
+        action: WorkspaceCommands,
+    },
     /// Upgrade crabby to the latest version
     Upgrade {
-        /// Upgrade crabby itself
+        /// Upgrade crabby itself (what `crabby upgrade` does anyway)
         #[arg(long, alias = "self")]
         self_upgrade: bool,
+
+        /// Only report whether a newer version exists; exits with 1 if one does
+        #[arg(long)]
+        check: bool,
+
+        /// Upgrade without asking
+        #[arg(long, short = 'y', conflicts_with = "check")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LockCommands {
+    /// Convert crabby.lock into another package manager's lockfile
+    Export {
+        /// Lockfile format to write
+        #[arg(long, value_enum, default_value = "npm")]
+        format: LockFormat,
+
+        /// Where to write the converted lockfile
+        #[arg(long, short = 'o', default_value = "package-lock.json")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCommands {
+    /// Download a Node.js version: `20`, `20.11.0`, `lts`, `lts/iron` or `latest`
+    Install {
+        version: String,
+    },
+    /// Show installed versions and the current LTS releases
+    #[command(alias = "ls")]
+    List,
+    /// Make a version the default for projects that don't ask for one
+    Use {
+        version: String,
+    },
+    /// Print the path of the node this project runs on
+    Which,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the value crabby uses for a key
+    Get {
+        key: String,
+
+        /// Read ~/.crabby/config.json instead
+        #[arg(long, short = 'g')]
+        global: bool,
+    },
+    /// Set a key, checking the value first
+    Set {
+        key: String,
+        value: String,
+
+        /// Write ~/.crabby/config.json, which applies to every project
+        #[arg(long, short = 'g')]
+        global: bool,
+    },
+    /// Show every key and its value
+    #[command(alias = "ls")]
+    List {
+        /// Only show ~/.crabby/config.json
+        #[arg(long, short = 'g')]
+        global: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum WorkspaceCommands {
+    /// Show each workspace, its path and version, and whether it is linked
+    #[command(alias = "ls")]
+    List {
+        /// Print the workspaces and problems as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum LockFormat {
+    /// package-lock.json (lockfileVersion 3)
+    Npm,
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let cli = Cli::parse();
+    reporter::init(cli.reporter);
+    // A broken config file is reported by the command that needs it
+    ui::init_color(cli.no_color, cli.ascii || config::load_config().is_ok_and(|c| c.ascii));
+    registry::set_network_options(registry::NetworkOptions {
+        timeout: cli.registry_timeout,
+        connect_timeout: cli.connect_timeout,
+        pool_max_idle: cli.pool_max_idle,
+    });
+
+    // Commands whose output is read by other programs, and upgrade itself, skip the update check
+    let update_check = match cli.command {
+        Commands::Upgrade { .. } | Commands::Completion { .. } | Commands::Complete { .. }
+        | Commands::Bin { .. } | Commands::Root { .. } => None,
+        _ if reporter::is_json() => None,
+        _ => self_upgrade::start_update_check(),
+    };
+
+    let result = run(cli).await;
+    fs_utils::report_leftovers();
+    if let Some(check) = update_check {
+        check.finish().await;
+    }
+    if let Err(e) = &result {
+        let kind = error::classify(e);
+        if reporter::is_json() {
+            reporter::emit(reporter::Event::Error {
+                message: format!("{:#}", e),
+                kind,
+                exit_code: kind.exit_code(),
+                hint: kind.hint(),
+            });
+        } else {
+            error::report(e);
+        }
+        std::process::exit(kind.exit_code());
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    // Completion runs on every <Tab> and bin/root feed `$(...)`, so answer
+    // them before touching config
+    match &cli.command {
+        Commands::Bin { global } => {
+            let dir = if *global {
+                global::get_global_bin_dir()?
+            } else {
+                locate_project()?.join("node_modules").join(".bin")
+            };
+            println!("{}", dir.display());
+            return Ok(());
+        }
+        Commands::Root { global, project } => {
+            // Each global package has its own node_modules under the global dir
+            let dir = match (*global, *project) {
+                (true, _) => global::get_global_dir()?,
+                (false, true) => locate_project()?,
+                (false, false) => locate_project()?.join("node_modules"),
+            };
+            println!("{}", dir.display());
+            return Ok(());
+        }
+        Commands::Completion { shell } => {
+            print!("{}", completion::script(*shell, &mut Cli::command()));
+            return Ok(());
+        }
+        Commands::Complete { kind } => {
+            completion::run_complete(*kind);
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let config = config::CrabbyConfig::load()?;
     
     match &cli.command {
@@ -183,134 +711,161 @@ async fn main() -> Result<()> {
             };
             runner::run_script(&command_str, None)?;
         }
-        Commands::Upgrade { self_upgrade } => {
-            if *self_upgrade {
-                self_upgrade::check_and_upgrade().await?;
-            }
+        Commands::Gitignore => {
+            templates::run_gitignore(&std::env::current_dir()?)?;
         }
-        Commands::Init => {
-            print!("{} ", style("🦀").bold().cyan());
-            println!("{}", style("Initializing Crabby Kitchen...").bold());
-            
-            use std::io::{self, Write};
-            
-            // Ask for project name
-            let current_dir = std::env::current_dir()?;
-            let dir_name = current_dir.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("my-crabby-project");
-
-            print!("\n{} Project name [default: {}]: ", style("❓").bold().yellow(), style(dir_name).dim());
-            io::stdout().flush()?;
-            let mut name_input = String::new();
-            io::stdin().read_line(&mut name_input)?;
-            let trimmed_name = name_input.trim();
-            
-            let final_project_name = if trimmed_name.is_empty() {
-                dir_name.to_string()
-            } else {
-                trimmed_name.to_string()
-            };
-
-            manifest::ensure_package_files(Some(&final_project_name))?;
-            println!("{}", style("Created/Updated package.json").green());
-            
-            // Create default config file
-            let config_path = std::path::Path::new("crabby.config.json");
-            if !config_path.exists() {
-                let default_config = serde_json::json!({
-                    "registry": "https://registry.npmjs.org",
-                    "log_level": "info"
-                });
-                std::fs::write(config_path, serde_json::to_string_pretty(&default_config)?)?;
-                println!("{}", style("Created crabby.config.json").green());
+        Commands::Node { action } => match action {
+            NodeCommands::Install { version } => node_runtime::run_install(version).await?,
+            NodeCommands::List => node_runtime::run_list().await?,
+            NodeCommands::Use { version } => node_runtime::run_use(version).await?,
+            NodeCommands::Which => node_runtime::run_which()?,
+        },
+        Commands::Config { action } => match action {
+            ConfigCommands::Get { key, global } => {
+                let config = if *global {
+                    config::CrabbyConfig::load_from(&config::user_config_path()?)?
+                } else {
+                    config::load_config()?
+                };
+                match config::get_value(&config, key)? {
+                    Some(value) => println!("{}", config::display_value(&value)),
+                    None => std::process::exit(1),
+                }
             }
-            
-            // Ask for project type
-            print!("{} TypeScript or JavaScript? (ts/js) [default: ts]: ", style("❓").bold().yellow());
-            io::stdout().flush()?;
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            let project_type = input.trim().to_lowercase();
-            let is_typescript = project_type.is_empty() || project_type == "ts" || project_type == "typescript";
-            
-            // Create src directory
-            if !std::path::Path::new("src").exists() {
-                std::fs::create_dir("src")?;
+            ConfigCommands::Set { key, value, global } => {
+                let path = config::config_path(*global)?;
+                config::set_value(&path, key, value)?;
+                ui::print_success(&format!("Set {} in {}", key, path.display()));
             }
+            ConfigCommands::List { global } => {
+                let config = if *global {
+                    config::CrabbyConfig::load_from(&config::user_config_path()?)?
+                } else {
+                    config::load_config()?
+                };
+                config::print_list(&config)?;
+            }
+        },
+        Commands::SetupPath { shell } => {
+            setup_path::run(*shell)?;
+        }
+        Commands::Env { json } => env_info::run(*json)?,
+        Commands::Doctor => {
+            if !doctor::run()? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Check { json, fix } => {
+            let pkg = manifest::PackageJson::load()?;
+            let lockfile = manifest::CrabbyLock::load()?;
+            let registry = (!config.replace_registry_host).then_some(config.registry.as_str());
+            let mut report = check::compare(Path::new("."), &pkg, &lockfile, registry)?;
 
-            if is_typescript {
-                let ts_content = r#"import express from 'express';
+            if *fix && !report.is_consistent() {
+                ui::print_step(ui::Icons::INSTALL, "Reconciling node_modules with crabby.lock...");
+                let lockfile = check::fix(Path::new("."), &report, &pkg, lockfile).await?;
+                lockfile.save()?;
+                report = check::compare(Path::new("."), &pkg, &lockfile, registry)?;
+            }
 
-const app = express();
-const port = 3000;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                ui::print_step(ui::Icons::SEARCH, "Checking node_modules against crabby.lock...");
+                check::print_report(&report);
+            }
 
-app.get('/', (req, res) => {
-  res.send('Hello from Crabby Server! 🦀');
-});
+            if !report.is_consistent() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Workspaces { action: WorkspaceCommands::List { json } } => {
+            workspace::run_list(&std::env::current_dir()?, *json)?;
+        }
+        Commands::Link { name } => {
+            link::run_link(name.as_deref())?;
+        }
+        Commands::Unlink { name } => {
+            link::run_unlink(name.as_deref())?;
+        }
+        Commands::Pack { dry_run } => {
+            pack::run(*dry_run)?;
+        }
+        Commands::Publish { tag, access, dry_run } => {
+            publish::run(publish::PublishOptions {
+                tag: tag.clone(),
+                access: *access,
+                dry_run: *dry_run,
+            }).await?;
+        }
+        Commands::Version { target, preid, no_git_tag, force } => {
+            version::run(version::VersionOptions {
+                target: target.clone(),
+                preid: preid.clone(),
+                no_git_tag: *no_git_tag,
+                force: *force,
+            })?;
+        }
+        Commands::Bin { .. } | Commands::Root { .. }
+            | Commands::Completion { .. } | Commands::Complete { .. } => unreachable!(),
+        Commands::Lock { action: LockCommands::Export { format: LockFormat::Npm, output } } => {
+            if !Path::new("crabby.lock").exists() {
+                anyhow::bail!("No crabby.lock found. Run `crabby install` first.");
+            }
+            let pkg = manifest::PackageJson::load()?;
+            let lockfile = manifest::CrabbyLock::load()?;
+            let (document, skipped) = lock_compat::export_package_lock(&pkg, &lockfile);
 
-app.listen(port, () => {
-  console.log(`🚀 Server ready at http://localhost:${port}`);
-  console.log("💡 Try autocomplete: type 'app.' below!");
-});
-"#;
-                std::fs::write("src/index.ts", ts_content)?;
-                println!("{} Created src/index.ts", style("✅").green());
-                
-                // Create tsconfig.json
-                if !std::path::Path::new("tsconfig.json").exists() {
-                     let tsconfig = r#"{
-  "compilerOptions": {
-    "target": "ES2020",
-    "module": "commonjs",
-    "moduleResolution": "node",
-    "esModuleInterop": true,
-    "strict": true,
-    "skipLibCheck": true,
-    "lib": ["ES2020", "DOM"],
-    "typeRoots": ["./node_modules/@types"]
-  },
-  "include": ["src/**/*.ts"]
-}"#;
-                    std::fs::write("tsconfig.json", tsconfig)?;
-                    println!("{} Created tsconfig.json", style("✅").green());
-                }
+            for name in &skipped {
+                ui::print_warning(&format!(
+                    "Skipped {} ({}): git, file and workspace dependencies can't be represented in package-lock.json",
+                    name, lockfile.dependencies[name].tarball
+                ));
+            }
 
-                // Add dependencies for better IDE experience
-                let mut pkg = manifest::PackageJson::load()?;
-                pkg.dependencies.insert("express".to_string(), "^4.18.2".to_string());
-                pkg.add_dev_dependency("typescript".to_string(), "^5.0.0".to_string());
-                pkg.add_dev_dependency("tsx".to_string(), "^4.0.0".to_string());
-                pkg.add_dev_dependency("@types/node".to_string(), "^20.0.0".to_string());
-                pkg.add_dev_dependency("@types/express".to_string(), "^4.17.0".to_string());
-                pkg.save()?;
-                println!("{} Added TypeScript types to package.json", style("✅").green());
-
-                println!("{} Run {} to enable IDE autocomplete", style("💡").dim(), style("crabby install").cyan());
-                println!("{} Run with: crabby run src/index.ts", style("💡").dim());
+            fs::write(output, serde_json::to_string_pretty(&document)? + "\n")?;
+            ui::print_success(&format!(
+                "Exported {} entries to {}",
+                lockfile.dependencies.len() - skipped.len(),
+                output
+            ));
+        }
+        Commands::Upgrade { self_upgrade: _, check, yes } => {
+            let mode = if *check {
+                self_upgrade::Mode::Check
+            } else if *yes {
+                self_upgrade::Mode::Yes
             } else {
-                let index_js = r#"const express = require('express');
-
-const app = express();
-const port = 3000;
-
-app.get('/', (req, res) => {
-  res.send('Hello from Crabby Server! 🦀');
-});
-
-app.listen(port, () => {
-  console.log(`🚀 Server ready at http://localhost:${port}`);
-});
-"#;
-                std::fs::write("src/index.js", index_js)?;
-                println!("{} Created src/index.js", style("✅").green());
-                println!("{} Run with: crabby run src/index.js", style("💡").dim());
+                self_upgrade::Mode::Prompt
+            };
+            if self_upgrade::check_and_upgrade(mode).await? && *check {
+                std::process::exit(1);
             }
-            
-            println!("\n{} Project initialized successfully!", style("🎉").bold().green());
         }
-        Commands::Create { template, name } => {
+        Commands::Init { ts, js, yes, bare, express, force } => {
+            use std::io::IsTerminal;
+            let project_type = if *ts {
+                Some(init::ProjectType::Ts)
+            } else if *js {
+                Some(init::ProjectType::Js)
+            } else {
+                None
+            };
+            init::run(&std::env::current_dir()?, init::InitOptions {
+                project_type,
+                default_type: config::load_config()?.default_project_type.unwrap_or_default(),
+                interactive: !*yes && std::io::stdin().is_terminal(),
+                starter: if *bare {
+                    Some(init::Starter::Bare)
+                } else if *express {
+                    Some(init::Starter::Express)
+                } else {
+                    None
+                },
+                force: *force,
+            })?;
+        }
+        Commands::Create { template, name, git, no_git } => {
             let template_name = if let Some(t) = template {
                 t.clone()
             } else {
@@ -329,7 +884,7 @@ app.listen(port, () => {
                 n.clone()
             } else {
                 use std::io::{self, Write};
-                print!("\n{} Project name: ", style("❓").bold().yellow());
+                print!("\n{} Project name: ", style(ui::icon("❓")).bold().yellow());
                 io::stdout().flush()?;
                 let mut input = String::new();
                 io::stdin().read_line(&mut input)?;
@@ -337,63 +892,81 @@ app.listen(port, () => {
             };
 
             if project_name.is_empty() {
-                println!("{} Project name cannot be empty", style("❌").red());
+                println!("{} Project name cannot be empty", style(ui::icon("❌")).red());
                 return Ok(());
             }
 
             templates::create_project(&template_name, &project_name)?;
+
+            let use_git = if *git || *no_git {
+                *git
+            } else if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+                use std::io::{self, Write};
+                print!("{} Initialize a git repository? [Y/n]: ", style(ui::icon("❓")).bold().yellow());
+                io::stdout().flush()?;
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                matches!(input.trim().to_lowercase().as_str(), "" | "y" | "yes")
+            } else {
+                false
+            };
+            if use_git {
+                templates::init_git(std::path::Path::new(&project_name))?;
+            }
             
-            println!("\n{} Project created at {}", style("🎉").bold().green(), style(&project_name).cyan());
-            println!("{} Run these commands to start cooking:", style("💡").dim());
+            println!("\n{} Project created at {}", style(ui::icon("🎉")).bold().green(), style(&project_name).cyan());
+            println!("{} Run these commands to start cooking:", style(ui::icon("💡")).dim());
             println!("   cd {}", project_name);
             println!("   crabby install");
             println!("   crabby run dev");
         }
-        Commands::Cook { script, ts, js, listen } => {
-            let node_path = node_runtime::get_node_path()?;
+        Commands::Cook { script, ts, js, node, listen, yes, free_port, workspaces, filter, parallel, concurrency, if_present } => {
+            if *workspaces || !filter.is_empty() {
+                let concurrency = concurrency.unwrap_or_else(|| {
+                    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+                });
+                let options = workspace::RunOptions {
+                    script: script.as_deref().context("Name the script to run in each workspace")?,
+                    filters: filter,
+                    parallel: *parallel,
+                    concurrency,
+                };
+                workspace::run_script_in_workspaces(&std::env::current_dir()?, &options)?;
+                return Ok(());
+            }
+
+            let node_path = node_runtime::get_node_path(node.as_deref()).await?;
             let node_str = node_path.to_string_lossy();
             
             // Determine command to run and file to watch
-            let (cmd_template, file_to_watch, is_typescript) = if let Some(ts_file) = ts {
-                let cmd = match tsx_utils::get_tsx_command() {
-                    Ok(tsx_utils::TsxCommand::NodeMjs(p)) => format!("node \"{}\" {}", p.to_string_lossy(), ts_file),
-                    Ok(tsx_utils::TsxCommand::Executable(p)) => format!("\"{}\" {}", p.to_string_lossy(), ts_file),
-                    Err(_) => format!("{} --import tsx {}", node_str, ts_file),
+            let (cmd_template, file_to_watch) = if let Some(ts_file) = ts {
+                let Some(cmd) = tsx_utils::typescript_command(ts_file, &node_str, *yes).await? else {
+                    return Ok(());
                 };
-                (cmd, Some(ts_file.clone()), true)
+                (cmd, Some(ts_file.clone()))
             } else if let Some(js_file) = js {
-                (format!("{} {}", node_str, js_file), Some(js_file.clone()), false)
+                (format!("\"{}\" {}", node_str, js_file), Some(js_file.clone()))
             } else if let Some(script_name) = script {
                 let path = std::path::Path::new(&script_name);
-                if path.exists() && (script_name.ends_with(".js") || script_name.ends_with(".ts")) {
-                    if script_name.ends_with(".ts") {
-                        let script_name_norm = script_name.replace("\\", "/");
-                        let cmd = match tsx_utils::get_tsx_command() {
-                            Ok(tsx_utils::TsxCommand::NodeMjs(p)) => format!("node \"{}\" {}", p.to_string_lossy(), script_name_norm),
-                            Ok(tsx_utils::TsxCommand::Executable(p)) => format!("\"{}\" {}", p.to_string_lossy(), script_name_norm),
-                            Err(_) => format!("{} --import tsx {}", node_str, script_name_norm),
-                        };
-                        (cmd, Some(script_name_norm), true)
-                    } else {
-                        let script_name_norm = script_name.replace("\\", "/");
-                        let cmd = format!("{} {}", node_str, script_name_norm);
-                        (cmd, Some(script_name_norm), false)
-                    }
+                if path.exists() && runner::file_kind(script_name).is_some() {
+                    let script_name_norm = script_name.replace("\\", "/");
+                    let Some(cmd) = runner::file_command(&script_name_norm, &node_str, *yes).await? else {
+                        return Ok(());
+                    };
+                    (cmd, Some(script_name_norm))
                 } else {
                     // It's a package script
                     let pkg = manifest::PackageJson::load()?;
-                    if let Some(command_str) = pkg.scripts.get(script_name.as_str()) {
-                         (command_str.clone(), None, false)
-                    } else {
-                        println!("{} Script '{}' not found", style("❌").red(), script_name);
+                    let Some(command_str) = runner::script_command(&pkg.scripts, script_name, *if_present)? else {
                         return Ok(());
-                    }
+                    };
+                    (command_str, None)
                 }
             } else {
                 // Interactive Mode
                 let pkg = manifest::PackageJson::load()?;
                 if pkg.scripts.is_empty() {
-                    println!("{} No scripts found in package.json", style("❌").red());
+                    println!("{} No scripts found in package.json", style(ui::icon("❌")).red());
                     return Ok(());
                 }
 
@@ -410,27 +983,19 @@ app.listen(port, () => {
                 if let Some(index) = ui::prompt_fuzzy_selection(&items, "Pick a script to cook")? {
                     let selected_name = &script_names[index];
                     let command_str = pkg.scripts.get(selected_name).unwrap();
-                    (command_str.clone(), None, false)
+                    (command_str.clone(), None)
                 } else {
                     return Ok(());
                 }
             };
             
-            // Check if tsx is available if needed
-            if cmd_template.contains("tsx ") || cmd_template.contains(".ts") {
-                tsx_utils::ensure_tsx_available()?;
-            }
-            if is_typescript && !tsx_utils::ensure_tsx_available()? {
-                return Ok(());
-            }
-
             if !*listen {
-                runner::run_script(&cmd_template, None)?;
+                runner::run_script_with_node(&cmd_template, None, &node_str)?;
             } else {
                 // Watch mode
                 use chrono::Local;
                 
-                println!("\n{} {}", style("👀 Watch Mode Enabled").bold().blue(), style(&cmd_template).cyan());
+                println!("\n{} {}", style(ui::icon("👀 Watch Mode Enabled")).bold().blue(), style(&cmd_template).cyan());
                 
                 use notify::{Watcher, RecursiveMode};
                 use std::sync::mpsc::channel;
@@ -441,22 +1006,28 @@ app.listen(port, () => {
                 } else {
                     format!("Watching: {}", style("current directory").cyan())
                 };
-                println!("{} {}", style("📂").dim(), watch_info);
+                println!("{} {}", style(ui::icon("📂")).dim(), watch_info);
                 
                 // Initial run with timestamp
                 let timestamp = Local::now().format("%H:%M:%S");
                 println!("\n{} {} {}", 
-                    style("▶").green().bold(), 
+                    style(ui::icon("▶")).green().bold(), 
                     style(format!("[{}]", timestamp)).dim(),
                     style("Starting...").bold()
                 );
                 
-                let mut child = runner::spawn_script(&cmd_template, None, Some(&node_str)).ok();
-                let mut _pipes = if let Some(c) = &mut child {
-                     Some(runner::pipe_output(c))
+                let watched_port = if *free_port {
+                    let port = port::detect_port(&cmd_template);
+                    if port.is_none() {
+                        ui::print_warning("--free-port: no --port in the command and PORT is not set");
+                    }
+                    port
                 } else {
                     None
                 };
+
+                let mut child = runner::spawn_script(&cmd_template, None, Some(&node_str)).ok();
+                let mut _pipes = child.as_mut().map(runner::pipe_output);
                 
                 // Setup watcher
                 let (tx, rx) = channel();
@@ -485,12 +1056,7 @@ app.listen(port, () => {
                                 event.paths.iter().any(|p| p.to_string_lossy().contains(target))
                             } else {
                                 // Filter out common files to ignore
-                                event.paths.iter().any(|p| {
-                                    let path_str = p.to_string_lossy();
-                                    !path_str.contains("node_modules") && 
-                                    !path_str.contains(".git") &&
-                                    (path_str.ends_with(".js") || path_str.ends_with(".ts") || path_str.ends_with(".json"))
-                                })
+                                event.paths.iter().any(|p| runner::should_restart(p))
                             };
 
                             if should_restart {
@@ -500,25 +1066,38 @@ app.listen(port, () => {
                                     .unwrap_or("unknown");
                                     
                                 println!("\n{} {} {} {}", 
-                                    style("🔄").yellow(),
+                                    style(ui::icon("🔄")).yellow(),
                                     style(format!("[{}]", timestamp)).dim(),
                                     style("Changed:").yellow(),
                                     style(changed_file).cyan()
                                 );
                                 
-                                // Kill current process
-                                if let Some(mut c) = child {
-                                    let _ = c.kill();
-                                    let _ = c.wait(); // Prevent zombies
+                                // Kill the current process and wait until it is gone
+                                if let Some(c) = child.take() {
+                                    runner::stop_script(c)?;
+                                }
+
+                                // Grandchildren (e.g. a server started by a shell script) can
+                                // outlive it and keep the port bound
+                                if let Some(port) = watched_port.filter(|p| port::in_use(*p)) {
+                                    match port::kill_listeners(port) {
+                                        Ok(pids) if !pids.is_empty() => println!("{} Freed port {} (killed {})",
+                                            style(ui::icon("🔌")).dim(),
+                                            port,
+                                            pids.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+                                        ),
+                                        Ok(_) => {}
+                                        Err(e) => ui::print_warning(&format!("Could not free port {}: {}", port, e)),
+                                    }
                                 }
                                 
-                                // Wait a bit for file release & debounce
+                                // Debounce editors that write a file in several steps
                                 std::thread::sleep(std::time::Duration::from_millis(300));
                                 
                                 // Restart with timestamp
                                 let restart_time = Local::now().format("%H:%M:%S");
                                 println!("{} {} {}", 
-                                    style("▶").green().bold(),
+                                    style(ui::icon("▶")).green().bold(),
                                     style(format!("[{}]", restart_time)).dim(),
                                     style("Restarting...").bold()
                                 );
@@ -529,126 +1108,264 @@ app.listen(port, () => {
                                 }
                             }
                         },
-                        Ok(Err(e)) => println!("{} Watch error: {:?}", style("⚠️").yellow(), e),
+                        Ok(Err(e)) => println!("{} Watch error: {:?}", style(ui::icon("⚠️")).yellow(), e),
                         Err(_) => break,
                     }
                 }
             }
         }
         Commands::Start => {
-            run_package_script("start")?;
+            runner::run_package_script(Path::new("."), "start", false)?;
         }
-        Commands::Test => {
-            run_package_script("test")?;
+        Commands::Test { pass_with_no_tests } => {
+            if !runner::run_package_script(Path::new("."), "test", *pass_with_no_tests)? {
+                ui::print_info("No test script in package.json, nothing to run");
+            }
         }
-        Commands::Install { packages, global, save_dev } => {
+        Commands::Install { packages, global, save_dev, save_optional, save_peer, no_import, force_regenerate, legacy_peer_deps, sequential, quiet, workspace: target_workspace, no_build, audit, dry_run, force, verbose } => {
+            package_utils::set_verbose(*verbose);
+            package_utils::set_sequential(*sequential);
+            package_utils::set_force(*force);
+            let human = reporter::human();
             if *global {
                 if packages.is_empty() {
-                    println!("{} Please specify one or more packages to install globally", style("⚠️").yellow());
+                    println!("{} Please specify one or more packages to install globally", style(ui::icon("⚠️")).yellow());
                     return Ok(());
                 }
 
                 for pkg_name in packages {
                     match global::install_global(pkg_name).await {
                         Ok(_) => {}
-                        Err(e) => println!("{} Global install failed for {}: {}", style("❌").red(), pkg_name, e),
+                        Err(e) => ui::print_error(&format!("Global install failed for {}: {}", pkg_name, e)),
                     }
                 }
 
                 let bin_dir = global::get_global_bin_dir()?;
-                println!("\n{} Global installation complete!", style("✨").bold().green());
-                println!("   {} Ensure {} is in your PATH", style("💡").dim(), style(bin_dir.display()).cyan());
+                if human {
+                    println!("\n{} Global installation complete!", style(ui::icon("✨")).bold().green());
+                    setup_path::print_hint(&bin_dir);
+                }
+                return Ok(());
+            }
+
+            if let Some(problem) = manifest::PackageJson::load()?.package_manager_mismatch(env!("CARGO_PKG_VERSION")) {
+                if config::load_config()?.enforce_package_manager {
+                    anyhow::bail!("{} (enforcePackageManager is set)", problem);
+                }
+                ui::print_warning(&problem);
+            }
+
+            if *dry_run {
+                let root_path = std::env::current_dir()?;
+                if packages.is_empty() && !workspace::find_workspaces(&root_path)?.is_empty() {
+                    anyhow::bail!("--dry-run can't plan a whole workspace install yet; run it in a workspace or name the packages");
+                }
+                let requests: Vec<(String, Option<String>)> = if packages.is_empty() {
+                    plan::project_requests(&manifest::PackageJson::load()?)
+                } else {
+                    packages.iter().map(|name| (name.clone(), None)).collect()
+                };
+                let lockfile = lock_compat::load_or_import(*no_import, *force_regenerate)?;
+                let registry_url = config::load_config()?.registry;
+                let mut plan = plan::resolve(&root_path, &requests, &lockfile, &registry_url, &registry::get_client()?).await?;
+                for name in packages {
+                    if let Some(planned) = plan.packages.iter().find(|p| &p.name == name) {
+                        plan.saved.push((name.clone(), format!("^{}", planned.version)));
+                    }
+                }
+                plan.print();
                 return Ok(());
             }
 
+            let stats = package_utils::InstallStats::with_legacy_peer_deps(*legacy_peer_deps);
             if !packages.is_empty() {
-                let mut lockfile = manifest::CrabbyLock::load().unwrap_or_default();
+                let save_target = if *save_dev {
+                    manifest::SaveTarget::Dev
+                } else if *save_optional {
+                    manifest::SaveTarget::Optional
+                } else if *save_peer {
+                    manifest::SaveTarget::Peer
+                } else {
+                    manifest::SaveTarget::Prod
+                };
+                let mut lockfile = lock_compat::load_or_import(*no_import, *force_regenerate)?;
                 let config = config::load_config()?;
                 let _registry_url = config.registry.clone();
-                let mut pkg_json = manifest::PackageJson::load()?;
+
+                // With --workspace, packages land in the shared root node_modules and
+                // crabby.lock; only the workspace's own package.json changes
+                let root_path = std::env::current_dir()?;
+                let workspaces = match target_workspace {
+                    Some(_) => workspace::find_workspaces(&root_path)?,
+                    None => Vec::new(),
+                };
+                let manifest_path = match target_workspace {
+                    Some(query) => {
+                        let ws = workspace::find_workspace(&workspaces, query)?;
+                        ui::print_step(ui::Icons::TARGET, &format!("Adding to workspace {}", ws.name));
+                        ws.path.join("package.json")
+                    }
+                    None => std::path::PathBuf::from("package.json"),
+                };
+                let mut pkg_json = manifest::PackageJson::load_from(&manifest_path)?;
                 
                 for pkg_name in packages {
-                    println!("{} Installing {}...", ui::Icons::INSTALL, style(&pkg_name).cyan());
+                    if let Some(sibling) = workspaces.iter().find(|ws| &ws.name == pkg_name) {
+                        // Another workspace: link it rather than fetching it from the registry
+                        workspace::link_workspaces(&root_path, std::slice::from_ref(sibling))?;
+                        let range = "workspace:^".to_string();
+                        let mut local = std::collections::HashMap::from([(pkg_name.clone(), range.clone())]);
+                        workspace::resolve_protocol_deps(&mut local, &workspaces, &root_path, &mut lockfile)?;
+                        pkg_json.add_to(save_target, pkg_name.clone(), range);
+                        continue;
+                    }
+                    if human {
+                        println!("{} Installing {}...", ui::icon(ui::Icons::INSTALL), style(&pkg_name).cyan());
+                    }
                     
                     let pkg_name_clone = pkg_name.clone();
                 let registry_url_clone = config.registry.clone();
-                let lockfile_clone = manifest::CrabbyLock::load().unwrap_or_default();
+                let lockfile_clone = lockfile.clone();
                 
                 let client = registry::get_client()?;
                 // install_package now returns (version, tarball, updated_lockfile)
-                let (version_str, _, updated_lock) = package_utils::install_package(&pkg_name_clone, &registry_url_clone, &client, lockfile_clone).await?;
+                let (version_str, _, updated_lock) = package_utils::install_package(&root_path, &pkg_name_clone, &registry_url_clone, &client, lockfile_clone, &stats).await?;
 
                 lockfile = updated_lock;
                 
-                if *save_dev {
-                    pkg_json.add_dev_dependency(pkg_name.clone(), format!("^{}", version_str));
-                } else {
-                    pkg_json.add_dependency(pkg_name.clone(), format!("^{}", version_str));
-                }
+                pkg_json.add_to(save_target, pkg_name.clone(), format!("^{}", version_str));
                 
-                    println!("{} Installed {} v{}", ui::Icons::SUCCESS, style(&pkg_name).bold(), style(&version_str).dim());
+                    if human {
+                        println!("{} Installed {} v{}", ui::icon(ui::Icons::SUCCESS), style(&pkg_name).bold(), style(&version_str).dim());
+                    }
                 }
                 
                 lockfile.save()?;
-                pkg_json.save()?;
+                pkg_json.save_to(&manifest_path)?;
             } else {
                 // Check if this is a workspace root
                 let root_path = std::env::current_dir()?;
                 let workspaces = workspace::find_workspaces(&root_path)?;
                 
                 if !workspaces.is_empty() {
-                    println!("{} Found {} workspaces", style("🏢").bold().blue(), workspaces.len());
+                    if human {
+                        println!("{} Found {} workspaces", style(ui::icon("🏢")).bold().blue(), workspaces.len());
+                    }
                     workspace::link_workspaces(&root_path, &workspaces)?;
+                    let names: Vec<&str> = workspaces.iter().map(|ws| ws.name.as_str()).collect();
+                    package_utils::unlink_removed_workspace_binaries(&root_path.join("node_modules"), &names)?;
                     
-                    // Install dependencies for each workspace
-                    println!("{} Installing workspace dependencies...", style("📦").bold().blue());
+                    // Versions every workspace agrees on are installed once at the root
+                    if human {
+                        println!("{} Installing shared dependencies...", style(ui::icon("📦")).bold().blue());
+                    }
                     let config = config::load_config()?;
-                    
-                    for ws in workspaces {
-                        println!("   Processing {}", style(&ws.name).cyan());
-                        let registry_url = config.registry.clone();
-                        let ws_path = ws.path.clone();
-                        
-                        let original_cwd = std::env::current_dir()?;
-                        std::env::set_current_dir(&ws_path)?;
-                        
-                        let pkg = manifest::PackageJson::load()?;
-                        let lockfile = manifest::CrabbyLock::load().unwrap_or_default();
-                        let all_deps = pkg.get_all_dependencies();
-                        
-                        if !all_deps.is_empty() {
-                            let client = registry::get_client()?;
-                            let updated_lock = package_utils::install_all_packages(&all_deps, &registry_url, &client, lockfile).await?;
-                            updated_lock.save()?;
-                        }
-                        
-                        std::env::set_current_dir(original_cwd)?;
+                    let client = registry::get_client()?;
+                    let root_pkg = manifest::PackageJson::load()?;
+                    let mut root_lock = lock_compat::load_or_import(*no_import, *force_regenerate)?;
+
+                    let requirements = workspace::collect_requirements(&root_pkg, &workspaces);
+                    let unlocked = workspace::unlocked_requirements(&requirements, &root_lock);
+                    let available = package_utils::fetch_versions(&unlocked, &config.registry, &client).await?;
+                    let plan = workspace::plan_hoisting(&requirements, &root_lock, &available);
+
+                    let mut root_links = root_pkg.get_all_dependencies();
+                    root_links.retain(|_, range| range.starts_with(manifest::LockDependency::WORKSPACE_PREFIX));
+                    workspace::resolve_protocol_deps(&mut root_links, &workspaces, &root_path, &mut root_lock)?;
+                    let root_deps: std::collections::HashMap<String, String> = plan.root.into_iter().collect();
+                    let root_lock = package_utils::install_all_packages(&root_path, &root_deps, &config.registry, &client, root_lock, &stats).await?;
+                    root_lock.save()?;
+
+                    // Conflicting versions go in the workspaces that need them
+                    let results = workspace::install_workspaces(&root_path, &workspaces, plan.local, &config.registry, &client, &stats).await?;
+                    workspace::report_installs(&results)?;
+                    if !*no_build {
+                        workspace::build_linked_workspaces(&workspaces)?;
+                    }
+                    if human {
+                        println!("{} Workspace installation complete", style(ui::icon("✅")).bold().green());
                     }
-                     println!("{} Workspace installation complete", style("✅").bold().green());
                 } else {
                      // Standard install all from package.json
-                     println!("{} Installing dependencies...", style("📦").bold().blue());
+                     if human {
+                         println!("{} Installing dependencies...", style(ui::icon("📦")).bold().blue());
+                     }
                      let pkg_json = manifest::PackageJson::load()?;
-                     let all_deps = pkg_json.get_all_dependencies();
+                     let mut all_deps = pkg_json.get_all_dependencies();
                      let config = config::load_config()?;
                      let registry_url = config.registry.clone();
                      
-                     let lockfile = manifest::CrabbyLock::load().unwrap_or_default();
+                     let mut lockfile = lock_compat::load_or_import(*no_import, *force_regenerate)?;
+                     // In a monorepo member, workspace: ranges name its siblings
+                     let uses_protocol = all_deps.values().any(|range| range.starts_with(manifest::LockDependency::WORKSPACE_PREFIX));
+                     let monorepo = if uses_protocol { workspace::find_monorepo_root(&root_path)? } else { None };
+                     let siblings = match &monorepo {
+                         Some((monorepo_root, workspaces)) => {
+                             let wanted: Vec<workspace::Workspace> = workspaces.iter()
+                                 .filter(|ws| all_deps.get(&ws.name).is_some_and(|range| range.starts_with(manifest::LockDependency::WORKSPACE_PREFIX)))
+                                 .cloned()
+                                 .collect();
+                             workspace::link_workspaces(monorepo_root, &wanted)?;
+                             workspaces.as_slice()
+                         }
+                         None => &[],
+                     };
+                     workspace::resolve_protocol_deps(&mut all_deps, siblings, &root_path, &mut lockfile)?;
                      
                      let client = registry::get_client()?;
-                     let updated_lockfile = package_utils::install_all_packages(&all_deps, &registry_url, &client, lockfile).await?;
+                     let updated_lockfile = package_utils::install_all_packages(&root_path, &all_deps, &registry_url, &client, lockfile, &stats).await?;
 
                      updated_lockfile.save()?;
-                     println!("{} Done!", style("✅").bold().green());
+                     if human {
+                         println!("{} Done!", style(ui::icon("✅")).bold().green());
+                     }
+                }
+            }
+
+            package_utils::link_project_binaries(&std::env::current_dir()?, &manifest::PackageJson::load()?)?;
+
+            if !*quiet || !human {
+                stats.print_summary();
+            }
+            if human && (*audit || config::load_config()?.audit_on_install) {
+                audit::print_install_summary(&manifest::CrabbyLock::load()?).await;
+            }
+        }
+        Commands::Remove { packages, force: _, global: true } => {
+            ui::print_step(ui::Icons::REMOVE, &format!("Removing global {}...", packages.join(", ")));
+            for package in packages {
+                match global::remove_global(package).await {
+                    Ok(()) => ui::print_success(&format!("Removed {}", package)),
+                    Err(e) => ui::print_error(&format!("Failed to remove {}: {}", package, e)),
                 }
             }
         }
-        Commands::Remove { package, force } => {
-            ui::print_step(ui::Icons::REMOVE, &format!("Removing {}...", package));
+        Commands::Remove { packages, force, global: false } => {
+            ui::print_step(ui::Icons::REMOVE, &format!("Removing {}...", packages.join(", ")));
             
+            for package in packages {
+                safety::validate_package_name(package)?;
+            }
             let mut pkg_json = manifest::PackageJson::load()?;
-            if !pkg_json.dependencies.contains_key(package) && !pkg_json.dev_dependencies.contains_key(package) {
-                ui::print_error(&format!("Package '{}' not found in dependencies", package));
+            let (found, not_found): (Vec<&String>, Vec<&String>) = packages.iter()
+                .partition(|p| pkg_json.has_dependency(p));
+
+            let installed: Vec<String> = pkg_json.get_all_dependencies().into_keys()
+                .chain(pkg_json.peer_dependencies.keys().cloned())
+                .collect();
+            for package in &not_found {
+                let suggestions = search::closest_names(package, installed.iter().map(String::as_str), 3);
+                if suggestions.is_empty() {
+                    ui::print_warning(&format!("Package '{}' not found in dependencies, skipping", package));
+                } else {
+                    ui::print_warning(&format!(
+                        "Package '{}' not found in dependencies, skipping (did you mean {}?)",
+                        package, suggestions.join(", ")
+                    ));
+                }
+            }
+            if found.is_empty() {
+                ui::print_error("None of the given packages are in package.json");
                 return Ok(());
             }
             
@@ -667,36 +1384,85 @@ app.listen(port, () => {
                 }
             }
             
-            // Create backup of package.json
-            let pkg_json_path = std::path::Path::new("package.json");
-            if pkg_json_path.exists() {
-                let backup_path = safety::create_backup(pkg_json_path)?;
-                ui::print_info(&format!("Created backup: {}", backup_path.display()));
+            if let Some(backup) = backup::create(&format!("remove {}", packages.join(" ")), config.backup_limit())? {
+                ui::print_info(&format!("Backed up {} (crabby restore {})", backup.files.join(" and "), backup.id));
             }
             
-            pkg_json.remove_dependency(package);
-            pkg_json.save()?;
-            
             let mut lockfile = manifest::CrabbyLock::load()?;
-            lockfile.dependencies.remove(package);
+            let names: Vec<&str> = found.iter().map(|p| p.as_str()).collect();
+            // Whatever was only there because of the removed packages goes too
+            let orphans = explorer::remove_packages(Path::new("."), &names, &mut pkg_json, &mut lockfile)?;
+            pkg_json.save()?;
             lockfile.save()?;
-            
-            let package_path = std::path::Path::new("node_modules").join(package);
-            if package_path.exists() {
-                std::fs::remove_dir_all(&package_path)?;
+            for name in &orphans {
+                println!("{} Pruning {}", style(ui::icon(ui::Icons::REMOVE)).red(), name);
+            }
+
+            for package in &found {
+                ui::print_success(&format!("Removed {}", package));
+            }
+        }
+        Commands::List { global: true, depth, json, .. } => {
+            let global_dir = global::get_global_dir()?;
+            let bin_dir = global::get_global_bin_dir()?;
+            let packages = global::global_packages(&global_dir)?;
+            if *json {
+                let listing = serde_json::json!({ "root": global_dir, "binDir": bin_dir, "packages": packages });
+                println!("{}", serde_json::to_string_pretty(&listing)?);
+            } else {
+                ui::print_header(&format!("{} Global Packages", ui::icon(ui::Icons::PACKAGE)));
+                println!("  {:<5} {}", style("root").dim(), global_dir.display());
+                println!("  {:<5} {}\n", style("bin").dim(), bin_dir.display());
+                let bins = |package: &global::GlobalPackage| if package.bins.is_empty() { "-".to_string() } else { package.bins.join(", ") };
+                if packages.is_empty() {
+                    ui::print_info("No global packages installed");
+                } else if depth.unwrap_or(0) == 0 {
+                    let rows: Vec<Vec<String>> = packages.iter()
+                        .map(|p| vec![p.name.clone(), p.version.clone().unwrap_or_else(|| "-".to_string()), bins(p)])
+                        .collect();
+                    ui::print_table(&["Package", "Version", "Bins"], &rows);
+                } else {
+                    for (i, package) in packages.iter().enumerate() {
+                        let is_last = i == packages.len() - 1;
+                        println!("{} {} {} {}",
+                            style(if is_last { "└─" } else { "├─" }).dim(),
+                            style(&package.name).cyan(),
+                            style(package.version.as_deref().unwrap_or("-")).dim(),
+                            style(format!("[{}]", bins(package))).magenta()
+                        );
+                        print_tree_recursive(&package.name, &package.lockfile, if is_last { "   " } else { "│  " }, 1, depth.unwrap_or(0))?;
+                    }
+                }
             }
-            
-            ui::print_success(&format!("Removed {}", package));
         }
-        Commands::List { tree } => {
+        Commands::List { tree, direct, depth, global: false, .. } => {
             let pkg = manifest::PackageJson::load()?;
-            ui::print_header(&format!("{} Installed Packages", ui::Icons::PACKAGE));
+            ui::print_header(&format!("{} Installed Packages", ui::icon(ui::Icons::PACKAGE)));
             
-            if *tree {
-                let lockfile = manifest::CrabbyLock::load().ok();
-                print_dependency_tree(&pkg, lockfile.as_ref())?;
+            if *tree || depth.is_some() {
+                // --direct keeps the declared packages but doesn't expand them
+                let lockfile = if *direct || *depth == Some(0) { None } else { manifest::CrabbyLock::load().ok() };
+                let max_depth = depth.unwrap_or(TREE_DEPTH);
+                print_dependency_tree(&pkg, lockfile.as_ref(), max_depth)?;
+
+                let workspaces = workspace::find_workspaces(&std::env::current_dir()?)?;
+                if !workspaces.is_empty() {
+                    print_hoisted_tree(&workspaces, lockfile.as_ref(), max_depth)?;
+                }
             } else {
-                if pkg.dependencies.is_empty() && pkg.dev_dependencies.is_empty() {
+                let node_modules = Path::new("node_modules");
+                let linked: Vec<String> = explorer::installed_packages(node_modules)?
+                    .into_iter()
+                    .filter(|(_, path)| link::is_link(path))
+                    .map(|(name, _)| name)
+                    .collect();
+                let link_note = |name: &str| if linked.iter().any(|l| l == name) {
+                    format!(" {}", style("(linked)").magenta())
+                } else {
+                    String::new()
+                };
+
+                if pkg.dependencies.is_empty() && pkg.dev_dependencies.is_empty() && linked.is_empty() {
                     ui::print_info("No packages installed");
                 } else {
                     // Prepare table data
@@ -708,7 +1474,7 @@ app.listen(port, () => {
                         rows.push(vec![
                             name.clone(),
                             version.clone(),
-                            "production".to_string()
+                            format!("production{}", link_note(name))
                         ]);
                         total_count += 1;
                     }
@@ -718,38 +1484,103 @@ app.listen(port, () => {
                         rows.push(vec![
                             name.clone(),
                             version.clone(),
-                            style("dev").yellow().to_string()
+                            format!("{}{}", style("dev").yellow(), link_note(name))
+                        ]);
+                        total_count += 1;
+                    }
+
+                    // Linked packages the project doesn't declare
+                    for name in linked.iter().filter(|n| !*direct && !pkg.has_dependency(n)) {
+                        rows.push(vec![
+                            name.clone(),
+                            "-".to_string(),
+                            style("linked").magenta().to_string()
                         ]);
                         total_count += 1;
                     }
                     
                     ui::print_table(&["Package", "Version", "Type"], &rows);
-                    println!("\n{} {} packages total", ui::Icons::INFO, total_count);
+                    println!("\n{} {} packages total", ui::icon(ui::Icons::INFO), total_count);
                 }
             }
         }
-        Commands::Update { package, global } => {
+        Commands::Update { package, global, all, interactive, dry_run, diff, latest } => {
+            if *dry_run {
+                let names = match package {
+                    Some(pkg) => vec![pkg.clone()],
+                    None => {
+                        let mut names: Vec<String> = manifest::PackageJson::load()?.get_all_dependencies().into_keys().collect();
+                        names.sort();
+                        names
+                    }
+                };
+                let planned = update::plan_updates(&names, &config.registry, *latest).await?;
+                let changed: Vec<_> = planned.iter().filter(|p| update::bump_kind(&p.current, &p.new) != "none").collect();
+
+                if changed.is_empty() {
+                    ui::print_success("All packages are up to date!");
+                    ui::print_info("DRY RUN - package.json, crabby.lock and node_modules were not changed");
+                } else {
+                    ui::print_header(&format!("{} Updates that would be applied", ui::icon(ui::Icons::UPDATE)));
+                    let rows: Vec<Vec<String>> = changed.iter().map(|p| {
+                        let mut row = vec![
+                            p.name.clone(),
+                            style(&p.current).dim().to_string(),
+                            style(&p.new).green().to_string(),
+                        ];
+                        if *diff {
+                            let kind = update::bump_kind(&p.current, &p.new);
+                            row.push(match kind {
+                                "major" => style(kind).red().bold().to_string(),
+                                "minor" => style(kind).yellow().to_string(),
+                                _ => style(kind).green().to_string(),
+                            });
+                        }
+                        row
+                    }).collect();
+                    let mut headers = vec!["Package", "Current", "New"];
+                    if *diff {
+                        headers.push("Bump");
+                    }
+                    ui::print_table(&headers, &rows);
+
+                    // The install the updates lead to, the same plan `install --dry-run` prints
+                    let mut requests = plan::project_requests(&manifest::PackageJson::load()?);
+                    for (name, range) in &mut requests {
+                        if let Some(update) = changed.iter().find(|p| &p.name == name) {
+                            *range = Some(update.new.clone());
+                        }
+                    }
+                    let root_path = std::env::current_dir()?;
+                    let lockfile = manifest::CrabbyLock::load()?;
+                    let mut plan = plan::resolve(&root_path, &requests, &lockfile, &config.registry, &registry::get_client()?).await?;
+                    plan.saved = changed.iter().map(|p| (p.name.clone(), format!("^{}", p.new))).collect();
+                    plan.print();
+                }
+                return Ok(());
+            }
+
             if *global {
-                 if let Some(pkg) = package {
-                    match global::update_global(pkg).await {
+                 match package {
+                    Some(pkg) if !*all => match global::update_global(pkg).await {
                         Ok(_) => ui::print_success("Global update complete!"),
                         Err(e) => ui::print_error(&format!("Global update failed: {}", e)),
-                    }
-                 } else {
-                     ui::print_warning("Please specify a global package to update");
+                    },
+                    _ => global::update_all_globals(&config.registry, *interactive).await?,
                  }
                  return Ok(());
             }
 
             if let Some(pkg_name) = package {
+                backup::create(&format!("update {}", pkg_name), config.backup_limit())?;
                 ui::print_step(ui::Icons::UPDATE, &format!("Updating {}...", pkg_name));
-                let (version, _tarball) = update::update_package(&pkg_name, &config.registry).await?;
+                let (version, _tarball) = update::update_package(pkg_name, &config.registry).await?;
                 
-                 let lockfile = manifest::CrabbyLock::load().unwrap_or_default();
+                 let lockfile = manifest::CrabbyLock::load()?;
                  let registry_url = config.registry.clone();
                  
                  let client = registry::get_client()?;
-                 let (_, _, updated_lock) = package_utils::install_package(&pkg_name, &registry_url, &client, lockfile).await?;
+                 let (_, _, updated_lock) = package_utils::install_package(&std::env::current_dir()?, pkg_name, &registry_url, &client, lockfile, &package_utils::InstallStats::new()).await?;
                  updated_lock.save()?;
                  
                 let mut pkg_json = manifest::PackageJson::load()?;
@@ -759,48 +1590,68 @@ app.listen(port, () => {
                 ui::print_success(&format!("Updated {} to {}", pkg_name, version));
             } else {
                 ui::print_step(ui::Icons::SEARCH, "Checking for updates...");
-                let outdated = update::check_outdated(&config.registry).await?;
+                let outdated = update::check_outdated(&config.registry, false, update::OutdatedFilter::All).await?;
                 
                 if outdated.is_empty() {
                     ui::print_success("All packages are up to date!");
                 } else {
-                    ui::print_header(&format!("{} Updates available", ui::Icons::UPDATE));
+                    ui::print_header(&format!("{} Updates available", ui::icon(ui::Icons::UPDATE)));
                     let mut rows = Vec::new();
-                    for (name, current, latest) in &outdated {
+                    for package in &outdated {
                         rows.push(vec![
-                            name.clone(),
-                            style(current).dim().to_string(),
-                            style(latest).green().to_string()
+                            package.name.clone(),
+                            style(&package.current).dim().to_string(),
+                            style(&package.latest).green().to_string()
                         ]);
                     }
                     ui::print_table(&["Package", "Current", "Latest"], &rows);
                 }
             }
         }
-        Commands::Outdated => {
+        Commands::Outdated { interactive: true, .. } => {
+            update::update_interactively(&config.registry).await?;
+        }
+        Commands::Outdated { all, interactive: false, dev, prod, .. } => {
             ui::print_step(ui::Icons::SEARCH, "Checking for outdated packages...");
-            let outdated = update::check_outdated(&config.registry).await?;
+            let filter = match (*dev, *prod) {
+                (true, _) => update::OutdatedFilter::Dev,
+                (_, true) => update::OutdatedFilter::Prod,
+                _ => update::OutdatedFilter::All,
+            };
+            let outdated = update::check_outdated(&config.registry, *all, filter).await?;
             
             if outdated.is_empty() {
                 ui::print_success("All packages are up to date!");
             } else {
-                ui::print_header(&format!("{} Outdated packages", ui::Icons::WARNING));
+                ui::print_header(&format!("{} Outdated packages", ui::icon(ui::Icons::WARNING)));
                 let mut rows = Vec::new();
-                for (name, current, latest) in outdated {
+                for package in outdated {
+                    let kind = match package.kind {
+                        update::DependencyType::Dev => style(package.kind.label()).yellow(),
+                        update::DependencyType::Transitive => style(package.kind.label()).dim(),
+                        _ => style(package.kind.label()),
+                    };
                     rows.push(vec![
-                        name,
-                        style(current).dim().to_string(),
-                        style(latest).green().to_string()
+                        package.name,
+                        style(package.current).dim().to_string(),
+                        style(package.latest).green().to_string(),
+                        kind.to_string(),
                     ]);
                 }
-                ui::print_table(&["Package", "Current", "Latest"], &rows);
+                ui::print_table(&["Package", "Current", "Latest", "Type"], &rows);
             }
         }
         Commands::Info { package } => {
-            update::get_package_info(&package, &config.registry).await?;
+            update::get_package_info(package, &config.registry).await?;
+        }
+        Commands::Restore { id, list, yes } => {
+            backup::run_restore(id.as_deref(), *list, *yes, config.backup_limit())?;
         }
         Commands::Search { query, limit } => {
-            search::search_packages(&query, *limit).await?;
+            search::search_packages(query, *limit).await?;
+        }
+        Commands::Size { top, json } => {
+            size::run(*top, *json)?;
         }
         Commands::Clean { cache, force, dry_run } => {
             if *dry_run {
@@ -808,10 +1659,10 @@ app.listen(port, () => {
             }
             
             ui::print_warning("This will remove:");
-            println!("  • node_modules/");
-            println!("  • crabby.lock");
+            println!("  {} node_modules/", ui::icon("•"));
+            println!("  {} crabby.lock", ui::icon("•"));
             if *cache {
-                println!("  • Global cache");
+                println!("  {} Global cache", ui::icon("•"));
             }
             
             if !*force && !*dry_run {
@@ -833,11 +1684,16 @@ app.listen(port, () => {
                 return Ok(());
             }
             
+            if let Some(backup) = backup::create("clean", config.backup_limit())? {
+                ui::print_info(&format!("Backed up {} (crabby restore {})", backup.files.join(" and "), backup.id));
+            }
             ui::print_step(ui::Icons::CLEAN, "Cleaning...");
             
             let node_modules = std::path::Path::new("node_modules");
+            // Gone first, so a node_modules that can't be removed completely isn't taken as installed
+            resume::clear(std::path::Path::new("."))?;
             if node_modules.exists() {
-                std::fs::remove_dir_all(node_modules)?;
+                fs_utils::remove_dir_all(node_modules)?;
                 ui::print_success("Removed node_modules/");
             }
             
@@ -850,7 +1706,7 @@ app.listen(port, () => {
             if *cache {
                 let cache_dir = config::get_cache_dir()?;
                 if cache_dir.exists() {
-                    std::fs::remove_dir_all(&cache_dir)?;
+                    fs_utils::remove_dir_all(&cache_dir)?;
                     ui::print_success("Cleared global cache");
                 }
             }
@@ -865,17 +1721,17 @@ app.listen(port, () => {
             
             let mut found = false;
             if pkg.dependencies.contains_key(package) {
-                println!("{} Direct dependency in {}", style(ui::Icons::CHECKMARK).green(), style("package.json").dim());
+                println!("{} Direct dependency in {}", style(ui::icon(ui::Icons::CHECKMARK)).green(), style("package.json").dim());
                 found = true;
             }
             if pkg.dev_dependencies.contains_key(package) {
-                println!("{} Direct devDependency in {}", style(ui::Icons::CHECKMARK).green(), style("package.json").dim());
+                println!("{} Direct devDependency in {}", style(ui::icon(ui::Icons::CHECKMARK)).green(), style("package.json").dim());
                 found = true;
             }
             
             let paths = explorer::find_dependency_paths(&lockfile, &pkg, package);
             for path in paths {
-                println!("{} {}", style(ui::Icons::CHECKMARK).green(), path.join(style(" → ").dim().to_string().as_str()));
+                println!("{} {}", style(ui::icon(ui::Icons::CHECKMARK)).green(), path.join(style(format!(" {} ", ui::icon("→"))).dim().to_string().as_str()));
                 found = true;
             }
 
@@ -883,20 +1739,30 @@ app.listen(port, () => {
                 ui::print_error(&format!("Package {} not found in dependency graph", package));
             }
         }
-        Commands::Prune { dry_run } => {
+        Commands::Rebuild { names } => {
+            let root = locate_project()?;
+            // Scripts run on the Node.js the project selects; get it first if it's missing
+            node_runtime::get_node_path(None).await?;
+            ui::print_step(ui::Icons::BUILD, "Rebuilding installed packages...");
+            let results = rebuild::rebuild(&root, names)?;
+            rebuild::print_report(&results);
+            let failed = results.iter().filter(|r| r.error.is_some()).count();
+            if failed > 0 {
+                return Err(error::failure(error::ErrorKind::Script, format!("{} of {} packages failed to rebuild", failed, results.len())));
+            }
+        }
+        Commands::Prune { dry_run, production } => {
             let pkg = manifest::PackageJson::load()?;
             let lockfile = manifest::CrabbyLock::load()?;
             
             ui::print_step(ui::Icons::CLEAN, "Pruning unneeded dependencies...");
             
-            // Collect all reachable dependencies
-            let mut reachable = HashSet::new();
-            let all_deps = pkg.get_all_dependencies();
-            
-            for (name, _) in all_deps {
-                collect_reachable(&name, &lockfile, &mut reachable);
-            }
-            
+            let reachable = if *production {
+                explorer::production_packages(&pkg, &lockfile)
+            } else {
+                explorer::reachable_packages(&pkg, &lockfile)
+            };
+
             if *dry_run {
                 ui::print_info("DRY RUN - No files will be removed\n");
             }
@@ -907,35 +1773,11 @@ app.listen(port, () => {
                 return Ok(());
             }
 
-            let mut pruned_count = 0;
-            
-            // Helper to visit directories recursively (for scopes)
-            fn visit_dirs(dir: &Path, reachable: &HashSet<String>, base: &Path, dry_run: bool, count: &mut usize) -> Result<()> {
-                for entry in fs::read_dir(dir)? {
-                    let entry = entry?;
-                    let path = entry.path();
-                    if !path.is_dir() { continue; }
-                    
-                    let relative = path.strip_prefix(base)?;
-                    let pkg_name = relative.to_string_lossy().replace("\\", "/");
-                    
-                    if pkg_name.starts_with(".") { continue; } // Skip .bin, .cache etc
-                    
-                    if pkg_name.starts_with("@") {
-                        // It's a scope, look inside
-                        visit_dirs(&path, reachable, base, dry_run, count)?;
-                    } else if !reachable.contains(&pkg_name) {
-                        println!("{} Pruning {}", style(ui::Icons::REMOVE).red(), pkg_name);
-                        if !dry_run {
-                            fs::remove_dir_all(&path)?;
-                        }
-                        *count += 1;
-                    }
-                }
-                Ok(())
+            let pruned = explorer::prune_orphans(node_modules, &reachable, *dry_run)?;
+            for pkg_name in &pruned {
+                println!("{} Pruning {}", style(ui::icon(ui::Icons::REMOVE)).red(), pkg_name);
             }
-
-            visit_dirs(node_modules, &reachable, node_modules, *dry_run, &mut pruned_count)?;
+            let pruned_count = pruned.len();
 
             if pruned_count == 0 {
                 ui::print_success("No unneeded packages found");
@@ -954,28 +1796,17 @@ fn calculate_checksum(_file_path: &Path) -> Result<String> {
     Ok("".to_string())
 }
 */
-fn run_package_script(script_name: &str) -> Result<()> {
-    let pkg = manifest::PackageJson::load()?;
-    if let Some(command_str) = pkg.scripts.get(script_name) {
-        runner::run_script(command_str, None)?;
-    } else {
-        println!(
-            "{} Script '{}' not found in package.json. Available scripts: {:?}", 
-            style("❌").red(), 
-            style(script_name).bold(),
-            pkg.scripts.keys()
-        );
-        if script_name == "test" {
-            println!("{}", style("Error: no test specified").red());
-            std::process::exit(1);
-        }
-    }
-    Ok(())
+/// Directory of the nearest package.json, searching up from the current directory
+fn locate_project() -> Result<std::path::PathBuf> {
+    let cwd = std::env::current_dir()?;
+    manifest::find_project_root(&cwd)
+        .with_context(|| format!("No package.json found in {} or any parent directory", cwd.display()))
 }
 
 
 
-fn print_dependency_tree(pkg: &manifest::PackageJson, _lockfile: Option<&manifest::CrabbyLock>) -> Result<()> {
+
+fn print_dependency_tree(pkg: &manifest::PackageJson, _lockfile: Option<&manifest::CrabbyLock>, max_depth: usize) -> Result<()> {
     // Collect all dependencies
     let mut all_deps = Vec::new();
     for (name, version) in &pkg.dependencies {
@@ -1007,15 +1838,44 @@ fn print_dependency_tree(pkg: &manifest::PackageJson, _lockfile: Option<&manifes
         );
         
         if let Some(lock) = _lockfile {
-            print_tree_recursive(name, lock, if is_last { "   " } else { "│  " }, 1)?;
+            print_tree_recursive(name, lock, if is_last { "   " } else { "│  " }, 1, max_depth)?;
         }
     }
     
     Ok(())
 }
 
-fn print_tree_recursive(name: &str, lock: &manifest::CrabbyLock, prefix: &str, depth: usize) -> Result<()> {
-    if depth > 5 { return Ok(()); } // Limit depth to keep it readable
+/// Packages in a monorepo's root node_modules and the workspaces using them
+fn print_hoisted_tree(workspaces: &[workspace::Workspace], lockfile: Option<&manifest::CrabbyLock>, max_depth: usize) -> Result<()> {
+    let requirers = workspace::hoisted_requirers(workspaces);
+    if requirers.is_empty() {
+        return Ok(());
+    }
+    println!("\n{}", style("Hoisted workspace dependencies").bold());
+
+    let total = requirers.len();
+    for (i, (name, users)) in requirers.iter().enumerate() {
+        let is_last = i == total - 1;
+        let version = lockfile.and_then(|lock| lock.dependencies.get(name)).map(|dep| dep.version.as_str()).unwrap_or("-");
+        println!("{} {} {} {}",
+            style(if is_last { "└─" } else { "├─" }).dim(),
+            style(name).cyan(),
+            style(version).dim(),
+            style(format!("(required by {})", users.join(", "))).magenta()
+        );
+
+        if let Some(lock) = lockfile {
+            print_tree_recursive(name, lock, if is_last { "   " } else { "│  " }, 1, max_depth)?;
+        }
+    }
+    Ok(())
+}
+
+/// How deep `list --tree` goes without `--depth`, to keep it readable
+const TREE_DEPTH: usize = 5;
+
+fn print_tree_recursive(name: &str, lock: &manifest::CrabbyLock, prefix: &str, depth: usize, max_depth: usize) -> Result<()> {
+    if depth > max_depth { return Ok(()); }
 
     if let Some(dep_info) = lock.dependencies.get(name) {
         let sub_deps: Vec<_> = dep_info.dependencies.iter().collect();
@@ -1033,19 +1893,8 @@ fn print_tree_recursive(name: &str, lock: &manifest::CrabbyLock, prefix: &str, d
             );
             
             let next_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
-            print_tree_recursive(sub_name, lock, &next_prefix, depth + 1)?;
+            print_tree_recursive(sub_name, lock, &next_prefix, depth + 1, max_depth)?;
         }
     }
     Ok(())
 }
-
-fn collect_reachable(name: &str, lock: &manifest::CrabbyLock, reachable: &mut HashSet<String>) {
-    if reachable.contains(name) { return; }
-    reachable.insert(name.to_string());
-    
-    if let Some(dep_info) = lock.dependencies.get(name) {
-        for sub_dep in dep_info.dependencies.keys() {
-            collect_reachable(sub_dep, lock, reachable);
-        }
-    }
-}
@@ -1,54 +1,305 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use crate::error::{failure, ErrorKind};
 
 pub fn clean_json_content(content: String) -> String {
-    let mut cleaned = content;
-    if cleaned.starts_with('\u{FEFF}') {
-        cleaned = cleaned.trim_start_matches('\u{FEFF}').to_string();
+    relax_json(&content).json
+}
+
+/// JSON text after the lenient pre-pass, and what the pass had to ignore
+pub struct Relaxed {
+    /// Strict JSON with the same line and column for everything that's left:
+    /// ignored comments and trailing commas are blanked out with spaces
+    pub json: String,
+    /// `trailing comma at line 3, column 18`, in file order
+    pub fixes: Vec<String>,
+}
+
+/// Accept what people write in package.json by hand that strict JSON doesn't:
+/// a byte order mark, `//` and `/* */` comments, and trailing commas
+pub fn relax_json(content: &str) -> Relaxed {
+    let (bytes, fixes) = blank_lenient(content, b' ');
+    Relaxed {
+        // Only ASCII bytes were replaced, and only by spaces
+        json: String::from_utf8(bytes).unwrap_or_else(|_| content.to_string()),
+        fixes,
+    }
+}
+
+/// `content` without its byte order mark, with comments and trailing commas
+/// overwritten by `fill` (newlines excepted), and where those were
+fn blank_lenient(content: &str, fill: u8) -> (Vec<u8>, Vec<String>) {
+    let mut bytes = content.trim_start_matches('\u{FEFF}').as_bytes().to_vec();
+    let mut fixes = Vec::new();
+    let position = |bytes: &[u8], at: usize| {
+        let before = &bytes[..at];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let column = at - before.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1) + 1;
+        format!("line {}, column {}", line, column)
+    };
+    // Newlines stay so that positions after a comment don't move
+    let blank = |bytes: &mut [u8], from: usize, to: usize| {
+        for b in &mut bytes[from..to] {
+            if *b != b'\n' {
+                *b = fill;
+            }
+        }
+    };
+
+    // Comments first, so that a comment between a comma and `}` doesn't hide the comma
+    let mut i = 0;
+    let mut in_string = false;
+    while i < bytes.len() {
+        match (bytes[i], in_string) {
+            (b'\\', true) => i += 1,
+            (b'"', _) => in_string = !in_string,
+            (b'/', false) if bytes.get(i + 1) == Some(&b'/') => {
+                let end = bytes[i..].iter().position(|b| *b == b'\n').map_or(bytes.len(), |n| i + n);
+                fixes.push((i, format!("comment at {}", position(&bytes, i))));
+                blank(&mut bytes, i, end);
+                i = end;
+            }
+            (b'/', false) if bytes.get(i + 1) == Some(&b'*') => {
+                let end = bytes[i + 2..].windows(2).position(|w| w == b"*/").map_or(bytes.len(), |n| i + 2 + n + 2);
+                fixes.push((i, format!("comment at {}", position(&bytes, i))));
+                blank(&mut bytes, i, end);
+                i = end;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], in_string) {
+            (b'\\', true) => i += 1,
+            (b'"', _) => in_string = !in_string,
+            (b',', false) => {
+                let next = bytes[i + 1..].iter().find(|b| !b.is_ascii_whitespace());
+                if matches!(next, Some(b'}' | b']')) {
+                    fixes.push((i, format!("trailing comma at {}", position(&bytes, i))));
+                    bytes[i] = fill;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    fixes.sort_by_key(|(at, _)| *at);
+    (bytes, fixes.into_iter().map(|(_, fix)| fix).collect())
+}
+
+/// `content` without what `relax_json` ignores, for writing back to disk:
+/// lines that held only a comment are dropped and trailing spaces trimmed
+pub fn strict_json(content: &str) -> String {
+    // A raw NUL can't occur in valid JSON, so it marks what to drop
+    let (mut bytes, _) = blank_lenient(content, 0);
+    bytes.retain(|b| *b != 0);
+    let stripped = String::from_utf8(bytes).unwrap_or_else(|_| content.to_string());
+    let original = content.trim_start_matches('\u{FEFF}').lines();
+    let mut out: Vec<&str> = stripped.lines()
+        .zip(original)
+        .filter(|(line, before)| !line.trim().is_empty() || before.trim().is_empty())
+        .map(|(line, _)| line.trim_end())
+        .collect();
+    while out.last().is_some_and(|line| line.is_empty()) {
+        out.pop();
     }
-    cleaned.trim().to_string()
+    out.join("\n") + "\n"
+}
+
+/// Files whose lenient JSON has been warned about already
+static WARNED: std::sync::Mutex<Vec<PathBuf>> = std::sync::Mutex::new(Vec::new());
+
+/// Parse the JSON `content` of the file at `path`. Comments and trailing
+/// commas are accepted with a warning (once per file); syntax errors point at
+/// the line and column with a snippet of the file.
+pub fn parse_json<T: serde::de::DeserializeOwned>(content: &str, path: &Path) -> Result<T> {
+    let relaxed = relax_json(content);
+    let value = parse_relaxed(&relaxed, path)?;
+    if !relaxed.fixes.is_empty() {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut warned = WARNED.lock().unwrap();
+        if !warned.contains(&key) {
+            warned.push(key);
+            crate::ui::print_warning(&format!(
+                "{} isn't strict JSON; ignored {}. `crabby doctor` can rewrite it.",
+                path.display(),
+                relaxed.fixes.join(", ")
+            ));
+        }
+    }
+    Ok(value)
+}
+
+/// `parse_json` without the warning, for callers that report leniency themselves
+pub fn parse_relaxed<T: serde::de::DeserializeOwned>(relaxed: &Relaxed, path: &Path) -> Result<T> {
+    serde_json::from_str(&relaxed.json)
+        .map_err(|e| located_error(&relaxed.json, &e))
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// `err` from parsing `content`, with the offending line and a caret under the column
+fn located_error(content: &str, err: &serde_json::Error) -> anyhow::Error {
+    let message = err.to_string();
+    // serde_json appends the position to the message; it's shown below instead
+    let message = message.split(" at line ").next().unwrap_or(&message);
+    if err.line() == 0 {
+        return anyhow::anyhow!("{}", message);
+    }
+    let (line, column) = (err.line(), err.column());
+    let mut snippet = String::new();
+    let gutter = (line + 1).to_string().len();
+    for (number, text) in content.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        if number + 1 < line || number > line + 1 {
+            continue;
+        }
+        snippet.push_str(&format!("\n {:>width$} | {}", number, text, width = gutter));
+        if number == line {
+            // Columns count bytes; the caret goes under the character
+            let chars = text.get(..column.saturating_sub(1)).map_or(column.saturating_sub(1), |p| p.chars().count());
+            snippet.push_str(&format!("\n {:>width$} | {}^", "", " ".repeat(chars), width = gutter));
+        }
+    }
+    anyhow::anyhow!("line {}, column {}: {}{}", line, column, message, snippet)
+}
+
+/// The line of the first git merge conflict marker in `content`, if any
+pub fn conflict_marker_line(content: &str) -> Option<usize> {
+    content.lines()
+        .position(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+        .map(|index| index + 1)
+}
+
+/// The nearest directory at or above `start` that has a package.json
+pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .find(|dir| dir.join("package.json").is_file())
+        .map(Path::to_path_buf)
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct PackageJson {
     pub name: String,
     pub version: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
     #[serde(default)]
     pub scripts: HashMap<String, String>,
     #[serde(default)]
     pub dependencies: HashMap<String, String>,
     #[serde(default, rename = "devDependencies")]
     pub dev_dependencies: HashMap<String, String>,
-    #[serde(default)]
-    pub workspaces: Option<Vec<String>>,
+    #[serde(default, rename = "optionalDependencies", skip_serializing_if = "HashMap::is_empty")]
+    pub optional_dependencies: HashMap<String, String>,
+    #[serde(default, rename = "peerDependencies", skip_serializing_if = "HashMap::is_empty")]
+    pub peer_dependencies: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<WorkspacesField>,
+    /// Allow-list of files to include when packing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    /// `"module"` or `"commonjs"`: how Node.js treats the package's `.js` (and tsx its `.ts`) files
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub module_type: Option<String>,
+    /// Commands the package provides, linked into node_modules/.bin of its dependents
+    #[serde(default, skip_serializing_if = "crate::package_utils::PackageBin::is_none")]
+    pub bin: crate::package_utils::PackageBin,
+    /// Runtime versions the package works with, e.g. `{"node": ">=18"}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub engines: HashMap<String, String>,
+    /// Versions to force for dependencies anywhere in the tree, kept as npm writes them
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub overrides: serde_json::Map<String, serde_json::Value>,
+    /// `"<name>@<version>"`: the package manager the project expects, e.g. `crabby@2.9.7` or `npm@10.2.0`
+    #[serde(default, rename = "packageManager", skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<String>,
+    /// Fields crabby doesn't model (description, main, license, ...) kept as-is on save
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `"workspaces"`: a list of globs, or yarn's `{"packages": [...], "nohoist": [...]}`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspacesField {
+    Patterns(Vec<String>),
+    Config {
+        #[serde(default)]
+        packages: Vec<String>,
+        /// `nohoist` and anything else yarn reads, kept as-is on save
+        #[serde(flatten)]
+        extra: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+impl WorkspacesField {
+    /// The globs, including `!negated` ones, in declaration order
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            Self::Patterns(patterns) => patterns,
+            Self::Config { packages, .. } => packages,
+        }
+    }
+}
+
+/// Which dependency map of package.json a package gets saved into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveTarget {
+    Prod,
+    Dev,
+    Optional,
+    Peer,
 }
 
 impl PackageJson {
+    /// Why the `packageManager` field says this project shouldn't be installed
+    /// with crabby `running` (e.g. `2.9.7`), or `None` if it may be
+    pub fn package_manager_mismatch(&self, running: &str) -> Option<String> {
+        let field = self.package_manager.as_deref()?.trim();
+        // Corepack allows a hash after the version: `pnpm@8.15.0+sha256.abc...`
+        let (name, version) = field.split_once('@').unwrap_or((field, ""));
+        let version = version.split('+').next().unwrap_or_default();
+        if name != "crabby" {
+            return Some(format!("This project uses {} (packageManager in package.json), not crabby", field));
+        }
+        if !version.is_empty() && version != running {
+            return Some(format!("This project expects crabby {}, but this is crabby {}", version, running));
+        }
+        None
+    }
+
     pub fn load() -> Result<Self> {
-        if !Path::new("package.json").exists() {
+        Self::load_from(Path::new("package.json"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
            return Ok(Self::default());
         }
-        let content = fs::read_to_string("package.json")?;
-        let cleaned = clean_json_content(content);
-        
-        // Debug
-        // println!("DEBUG: Loaded package.json: '{}'", cleaned);
-        
-        let pkg: PackageJson = match serde_json::from_str(&cleaned) {
-            Ok(p) => p,
-            Err(e) => {
-                 return Err(anyhow::anyhow!("Failed to parse package.json: {} (Content: '{}')", e, cleaned));
-            }
-        };
-        Ok(pkg)
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if let Some(line) = conflict_marker_line(&content) {
+            return Err(failure(ErrorKind::Config, format!(
+                "{} has unresolved merge conflict markers (line {}). Resolve the conflict, then run crabby install again.",
+                path.display(), line
+            )));
+        }
+        parse_json(&content, path)
     }
 
     pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new("package.json"))
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        fs::write("package.json", content)?;
+        fs::write(path, content)?;
         Ok(())
     }
 
@@ -59,63 +310,265 @@ impl PackageJson {
     pub fn add_dev_dependency(&mut self, name: String, version: String) {
         self.dev_dependencies.insert(name, version);
     }
+
+    pub fn add_to(&mut self, target: SaveTarget, name: String, version: String) {
+        match target {
+            SaveTarget::Prod => self.add_dependency(name, version),
+            SaveTarget::Dev => self.add_dev_dependency(name, version),
+            SaveTarget::Optional => { self.optional_dependencies.insert(name, version); }
+            SaveTarget::Peer => { self.peer_dependencies.insert(name, version); }
+        }
+    }
     
+    /// Remove `name` from every dependency map, returning the range it was saved with
     pub fn remove_dependency(&mut self, name: &str) -> Option<String> {
-        self.dependencies.remove(name)
+        [
+            self.dependencies.remove(name),
+            self.dev_dependencies.remove(name),
+            self.optional_dependencies.remove(name),
+            self.peer_dependencies.remove(name),
+        ].into_iter().flatten().next()
+    }
+
+    /// Save `range` for `name` in every dependency map that declares it
+    pub fn set_range(&mut self, name: &str, range: &str) {
+        for deps in [&mut self.dependencies, &mut self.dev_dependencies, &mut self.optional_dependencies, &mut self.peer_dependencies] {
+            if let Some(existing) = deps.get_mut(name) {
+                *existing = range.to_string();
+            }
+        }
+    }
+
+    pub fn has_dependency(&self, name: &str) -> bool {
+        self.dependencies.contains_key(name)
+            || self.dev_dependencies.contains_key(name)
+            || self.optional_dependencies.contains_key(name)
+            || self.peer_dependencies.contains_key(name)
     }
     
     pub fn get_all_dependencies(&self) -> HashMap<String, String> {
         let mut all_deps = self.dependencies.clone();
+        all_deps.extend(self.optional_dependencies.clone());
         all_deps.extend(self.dev_dependencies.clone());
         all_deps
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+/// Format version written to crabby.lock. Files without a `lockfileVersion`
+/// field predate versioning and are treated as version 0.
+pub const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CrabbyLock {
+    #[serde(default, rename = "lockfileVersion")]
+    pub lockfile_version: u32,
+    #[serde(serialize_with = "serialize_sorted")]
     pub dependencies: HashMap<String, LockDependency>,
 }
 
+impl Default for CrabbyLock {
+    fn default() -> Self {
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            dependencies: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LockDependency {
     pub version: String,
     pub tarball: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub dependencies: HashMap<String, String>,
+    /// Registry the version was resolved from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// For a git dependency, the branch, tag or commit package.json asked
+    /// for; `tarball` has the commit that resolved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committish: Option<String>,
+}
+
+/// `scheme://host[:port]` of `url`
+fn url_origin(url: &str) -> Option<&str> {
+    let start = url.find("://")? + 3;
+    Some(&url[..url[start..].find('/').map_or(url.len(), |end| start + end)])
+}
+
+impl LockDependency {
+    /// Prefix of `tarball` for dependencies satisfied by a sibling workspace
+    pub const WORKSPACE_PREFIX: &'static str = "workspace:";
+
+    pub fn is_workspace_link(&self) -> bool {
+        self.tarball.starts_with(Self::WORKSPACE_PREFIX)
+    }
+
+    /// Whether the package came from a directory or git repository, see `crate::source`
+    pub fn is_source(&self) -> bool {
+        crate::source::Source::parse(&self.tarball, Path::new("")).is_some()
+    }
+
+    /// Whether the entry was installed from `source` (relative paths from
+    /// `root`): the same directory, or the same repository at the same
+    /// requested branch, tag or commit
+    pub fn installed_from(&self, source: &crate::source::Source, root: &Path) -> bool {
+        use crate::source::Source;
+        match (Source::parse(&self.tarball, root), source) {
+            (Some(Source::Git { url, .. }), Source::Git { url: wanted, committish }) => url == *wanted && self.committish == *committish,
+            (Some(locked), _) => locked == *source,
+            (None, _) => false,
+        }
+    }
+
+    /// The registry the entry came from: as recorded, or for entries from
+    /// before that was recorded, the origin of the tarball URL
+    pub fn source_registry(&self) -> Option<&str> {
+        if self.is_workspace_link() || self.is_source() {
+            return None;
+        }
+        self.registry.as_deref().or_else(|| url_origin(&self.tarball))
+    }
+
+    /// The tarball URL of package `name` with the registry it came from swapped
+    /// for `registry`. Without a recorded registry, the old one ends where the
+    /// `<name>/-/` path registries serve tarballs under starts, so a path
+    /// prefix such as `/npm` is replaced along with the host.
+    pub fn rehost_tarball(&self, name: &str, registry: &str) -> String {
+        let path = self.registry.as_deref()
+            .and_then(|from| self.tarball.strip_prefix(from.trim_end_matches('/')))
+            .or_else(|| self.tarball.find(&format!("/{}/-/", name)).map(|i| &self.tarball[i..]))
+            .or_else(|| Some(&self.tarball[url_origin(&self.tarball)?.len()..]));
+        match path {
+            Some(path) if !self.is_workspace_link() && !self.is_source() => format!("{}{}", registry.trim_end_matches('/'), path),
+            _ => self.tarball.clone(),
+        }
+    }
+}
+
+/// Write map keys in sorted order so lockfile diffs stay stable
+fn serialize_sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    let sorted: std::collections::BTreeMap<&String, &V> = map.iter().collect();
+    sorted.serialize(serializer)
 }
 
 impl CrabbyLock {
     pub fn load() -> Result<Self> {
-        if !Path::new("crabby.lock").exists() {
+        Self::load_from(Path::new("crabby.lock"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
             return Ok(Self::default());
         }
-        let content = fs::read_to_string("crabby.lock")?;
-        let cleaned = clean_json_content(content);
-        let lock: CrabbyLock = match serde_json::from_str(&cleaned) {
-            Ok(p) => p,
-            Err(e) => {
-                // Return default on error but maybe log?
-                eprintln!("Warning: Failed to parse crabby.lock, returning default: {}", e);
-                CrabbyLock::default()
-            }
-        };
+        let content = fs::read_to_string(path)?;
+        // Merging both sides by hand rarely gives a consistent graph; resolving again does
+        if let Some(line) = conflict_marker_line(&content) {
+            return Err(failure(ErrorKind::Config, format!(
+                "{} has unresolved merge conflict markers (line {}). Resolve any conflict in \
+                 package.json, then run `crabby install --fix-lockfile` to rebuild the lockfile from it.",
+                path.display(), line
+            )));
+        }
+        Self::parse(&content).map_err(|e| anyhow::anyhow!(
+            "{:#}\nRefusing to discard the pinned versions. Fix crabby.lock by hand, or run \
+             `crabby install --fix-lockfile` to rebuild it from package.json.",
+            e
+        ))
+    }
+
+    /// Parse lockfile contents, migrating older formats to the current version
+    pub fn parse(content: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(&clean_json_content(content.to_string()))
+            .context("Failed to parse crabby.lock")?;
+
+        let version = value.get("lockfileVersion").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+        if version > LOCKFILE_VERSION {
+            anyhow::bail!(
+                "crabby.lock has lockfileVersion {} but this crabby only understands up to {}. Upgrade crabby with `crabby upgrade --self`.",
+                version, LOCKFILE_VERSION
+            );
+        }
+
+        let mut lock: CrabbyLock = serde_json::from_value(migrate(value, version))
+            .context("Failed to parse crabby.lock")?;
+        lock.lockfile_version = LOCKFILE_VERSION;
         Ok(lock)
     }
 
     pub fn save(&self) -> Result<()> {
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write("crabby.lock", content)?;
+        self.save_to(Path::new("crabby.lock"))
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
         Ok(())
     }
 
+    /// The file contents `save_to` writes
+    pub fn to_json(&self) -> Result<String> {
+        let content = serde_json::to_string_pretty(&Self {
+            lockfile_version: LOCKFILE_VERSION,
+            dependencies: self.dependencies.clone(),
+        })?;
+        Ok(content + "\n")
+    }
+
     pub fn add_package(&mut self, name: String, version: String, tarball: String, dependencies: HashMap<String, String>) {
-        self.dependencies.insert(name, LockDependency { version, tarball, dependencies });
+        self.dependencies.insert(name, LockDependency { version, tarball, integrity: None, dependencies, registry: None, committish: None });
+    }
+
+    /// Registries that locked entries came from other than `current`, with how
+    /// many entries each. Entries without a recorded registry are compared by host.
+    pub fn foreign_registries(&self, current: &str) -> Vec<(String, usize)> {
+        let current = current.trim_end_matches('/');
+        let mut found: BTreeMap<String, usize> = BTreeMap::new();
+        for dep in self.dependencies.values() {
+            let Some(source) = dep.source_registry().map(|s| s.trim_end_matches('/')) else {
+                continue;
+            };
+            let same = match dep.registry {
+                Some(_) => source == current,
+                None => Some(source) == url_origin(current),
+            };
+            if !same {
+                *found.entry(source.to_string()).or_default() += 1;
+            }
+        }
+        found.into_iter().collect()
+    }
+}
+
+/// Upgrade raw lockfile JSON one format version at a time until it matches `LOCKFILE_VERSION`
+fn migrate(mut value: serde_json::Value, from: u32) -> serde_json::Value {
+    for version in from..LOCKFILE_VERSION {
+        match version {
+            // v0 -> v1: same entries, plus the version marker. Entries written
+            // before `dependencies` was recorded get an empty map.
+            0 => {
+                if let Some(deps) = value.get_mut("dependencies").and_then(|d| d.as_object_mut()) {
+                    for entry in deps.values_mut().filter_map(|e| e.as_object_mut()) {
+                        entry.entry("dependencies").or_insert_with(|| serde_json::json!({}));
+                    }
+                }
+            }
+            _ => unreachable!("no migration defined from lockfile version {}", version),
+        }
+        value["lockfileVersion"] = (version + 1).into();
     }
+    value
 }
 
-pub fn ensure_package_files(project_name: Option<&str>) -> Result<()> {
-    let mut pkg = if Path::new("package.json").exists() {
-        PackageJson::load()?
+pub fn ensure_package_files(root: &Path, project_name: Option<&str>) -> Result<()> {
+    let pkg_path = root.join("package.json");
+    let mut pkg = if pkg_path.exists() {
+        PackageJson::load_from(&pkg_path)?
     } else {
         PackageJson {
             version: "1.0.0".to_string(),
@@ -127,13 +580,249 @@ pub fn ensure_package_files(project_name: Option<&str>) -> Result<()> {
         pkg.name = name.to_string();
     } else if pkg.name.is_empty() {
         // Fallback to directory name if name is missing
-        let current_dir = std::env::current_dir()?;
-        let dir_name = current_dir.file_name()
+        let dir_name = root.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("my-crabby-project");
         pkg.name = dir_name.to_string();
     }
 
-    pkg.save().context("Failed to save package.json during initialization")?;
+    pkg.save_to(&pkg_path).context("Failed to save package.json during initialization")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_project_root_searches_upward() {
+        let tmp = tempfile::tempdir().unwrap();
+        let project = tmp.path().join("app");
+        let nested = project.join("src/components");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(project.join("package.json"), "{}").unwrap();
+
+        assert_eq!(find_project_root(&nested), Some(project.clone()));
+        assert_eq!(find_project_root(&project), Some(project));
+        assert_eq!(find_project_root(tmp.path()), None);
+    }
+
+    #[test]
+    fn test_package_manager_mismatch() {
+        let with = |field: &str| PackageJson { package_manager: Some(field.to_string()), ..Default::default() };
+
+        assert_eq!(PackageJson::default().package_manager_mismatch("2.9.7"), None);
+        assert_eq!(with("crabby@2.9.7").package_manager_mismatch("2.9.7"), None);
+        assert_eq!(with("crabby").package_manager_mismatch("2.9.7"), None);
+        assert_eq!(
+            with("crabby@3.0.0").package_manager_mismatch("2.9.7").unwrap(),
+            "This project expects crabby 3.0.0, but this is crabby 2.9.7"
+        );
+        let npm = with("npm@10.2.0+sha256.deadbeef").package_manager_mismatch("2.9.7").unwrap();
+        assert!(npm.starts_with("This project uses npm@10.2.0+sha256.deadbeef"), "{}", npm);
+
+        let parsed: PackageJson = serde_json::from_str(r#"{"name": "a", "version": "1.0.0", "packageManager": "yarn@4.1.0"}"#).unwrap();
+        assert_eq!(parsed.package_manager.as_deref(), Some("yarn@4.1.0"));
+    }
+
+    #[test]
+    fn test_lenient_package_json() {
+        let content = "\u{FEFF}{\n  // the app\n  \"name\": \"app\", /* inline */\n  \"version\": \"1.0.0\",\n  \"scripts\": {\"url\": \"http://x/*y*/,}\", \"q\": \"\\\"//\",},\n}\n";
+        let relaxed = relax_json(content);
+        assert_eq!(relaxed.fixes, [
+            "comment at line 2, column 3",
+            "comment at line 3, column 18",
+            "trailing comma at line 5, column 52",
+            "trailing comma at line 5, column 54",
+        ]);
+        let pkg: PackageJson = parse_json(content, Path::new("package.json")).unwrap();
+        assert_eq!(pkg.name, "app");
+        assert_eq!(pkg.scripts["url"], "http://x/*y*/,}");
+        assert_eq!(pkg.scripts["q"], "\"//");
+
+        let strict = strict_json(content);
+        assert!(relax_json(&strict).fixes.is_empty());
+        assert!(strict.starts_with("{\n  \"name\": \"app\",\n  \"version\""), "{}", strict);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&strict).unwrap()["scripts"]["q"], "\"//");
+    }
+
+    #[test]
+    fn test_parse_error_points_at_line_and_column() {
+        let content = "{\n  \"name\": \"app\"\n  \"version\": \"1.0.0\"\n}";
+        let err = parse_json::<PackageJson>(content, Path::new("package.json")).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("Failed to parse package.json: line 3, column 3: expected `,` or `}`"), "{}", message);
+        assert!(message.ends_with("\n 2 |   \"name\": \"app\"\n 3 |   \"version\": \"1.0.0\"\n   |   ^\n 4 | }"), "{}", message);
+        // The file isn't pasted into the error
+        assert_eq!(message.matches("\"name\"").count(), 1);
+    }
+
+    #[test]
+    fn test_every_modelled_field_round_trips() {
+        let original = serde_json::json!({
+            "name": "@acme/app",
+            "version": "2.1.0",
+            "private": true,
+            "type": "module",
+            "main": "dist/index.js",
+            "bin": {"acme": "bin/acme.js"},
+            "files": ["dist", "bin"],
+            "engines": {"node": ">=18", "npm": ">=9"},
+            "scripts": {"build": "tsc"},
+            "dependencies": {"react": "^18.2.0"},
+            "devDependencies": {"typescript": "^5.4.0"},
+            "optionalDependencies": {"fsevents": "^2.3.0"},
+            "peerDependencies": {"react-dom": "^18.0.0"},
+            "overrides": {"semver": "7.5.4", "foo": {"bar": "1.0.0"}},
+            "workspaces": ["packages/*"],
+            "packageManager": "crabby@2.9.7",
+            "license": "MIT"
+        });
+        let pkg: PackageJson = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(pkg.engines["node"], ">=18");
+        assert_eq!(pkg.bin.files(), ["bin/acme.js"]);
+        assert_eq!(pkg.overrides["foo"]["bar"], "1.0.0");
+        assert_eq!(pkg.module_type.as_deref(), Some("module"));
+        // Nothing is lost or added on the way back
+        assert_eq!(serde_json::to_value(round_trip(&pkg)).unwrap(), original);
+
+        let single: PackageJson = serde_json::from_str(r#"{"name": "tool", "version": "1.0.0", "bin": "./cli.js"}"#).unwrap();
+        assert_eq!(single.bin, crate::package_utils::PackageBin::String("./cli.js".into()));
+        let bare = serde_json::to_value(PackageJson { name: "a".into(), version: "1.0.0".into(), ..Default::default() }).unwrap();
+        for absent in ["bin", "engines", "overrides", "private", "type", "packageManager"] {
+            assert!(bare.get(absent).is_none(), "{} should not be written when unset", absent);
+        }
+    }
+
+    fn round_trip(pkg: &PackageJson) -> PackageJson {
+        let content = serde_json::to_string_pretty(pkg).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn test_save_targets() {
+        let cases = [
+            (SaveTarget::Prod, "dependencies"),
+            (SaveTarget::Dev, "devDependencies"),
+            (SaveTarget::Optional, "optionalDependencies"),
+            (SaveTarget::Peer, "peerDependencies"),
+        ];
+
+        for (target, key) in cases {
+            let mut pkg = PackageJson { name: "app".into(), version: "1.0.0".into(), ..Default::default() };
+            pkg.add_to(target, "left-pad".into(), "^1.3.0".into());
+
+            let json: serde_json::Value = serde_json::to_value(&pkg).unwrap();
+            assert_eq!(json[key]["left-pad"], "^1.3.0", "{:?} should write {}", target, key);
+
+            let loaded = round_trip(&pkg);
+            let map = match target {
+                SaveTarget::Prod => &loaded.dependencies,
+                SaveTarget::Dev => &loaded.dev_dependencies,
+                SaveTarget::Optional => &loaded.optional_dependencies,
+                SaveTarget::Peer => &loaded.peer_dependencies,
+            };
+            assert_eq!(map.get("left-pad").map(String::as_str), Some("^1.3.0"));
+        }
+    }
+
+    #[test]
+    fn test_lockfile_v0_is_migrated() {
+        let v0 = r#"{
+          "dependencies": {
+            "express": {
+              "version": "4.18.2",
+              "tarball": "https://registry.npmjs.org/express/-/express-4.18.2.tgz",
+              "dependencies": { "accepts": "~1.3.8" }
+            },
+            "accepts": {
+              "version": "1.3.8",
+              "tarball": "https://registry.npmjs.org/accepts/-/accepts-1.3.8.tgz"
+            }
+          }
+        }"#;
+
+        let lock = CrabbyLock::parse(v0).unwrap();
+        assert_eq!(lock.lockfile_version, LOCKFILE_VERSION);
+        assert_eq!(lock.dependencies["express"].version, "4.18.2");
+        assert_eq!(lock.dependencies["express"].dependencies["accepts"], "~1.3.8");
+        assert!(lock.dependencies["accepts"].dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_lockfile_parse_errors_are_not_swallowed() {
+        assert!(CrabbyLock::parse(r#"{"dependencies": {"a": {"version": "1.0.0"#).is_err());
+        assert!(CrabbyLock::parse(r#"{"lockfileVersion": 99, "dependencies": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_merge_conflict_markers_are_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let conflicted = "{\n  \"dependencies\": {\n<<<<<<< HEAD\n    \"a\": \"^1.0.0\"\n=======\n    \"a\": \"^2.0.0\"\n>>>>>>> feature\n  }\n}\n";
+        let lock_path = tmp.path().join("crabby.lock");
+        fs::write(&lock_path, conflicted).unwrap();
+        let err = format!("{:#}", CrabbyLock::load_from(&lock_path).unwrap_err());
+        assert!(err.contains("line 3") && err.contains("--fix-lockfile"), "{}", err);
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), conflicted);
+
+        let pkg_path = tmp.path().join("package.json");
+        fs::write(&pkg_path, conflicted).unwrap();
+        let err = PackageJson::load_from(&pkg_path).unwrap_err();
+        assert_eq!(crate::error::classify(&err), ErrorKind::Config);
+        assert_eq!(conflict_marker_line("{\"a\": \"=======\"}"), None);
+    }
+
+    #[test]
+    fn test_lockfile_keys_are_sorted() {
+        let mut lock = CrabbyLock::default();
+        for name in ["zod", "axios", "minimist", "chalk"] {
+            lock.add_package(name.into(), "1.0.0".into(), String::new(),
+                HashMap::from([("z-dep".to_string(), "1".to_string()), ("a-dep".to_string(), "1".to_string())]));
+        }
+
+        let content = serde_json::to_string(&lock).unwrap();
+        let positions: Vec<usize> = ["\"axios\"", "\"chalk\"", "\"minimist\"", "\"zod\""]
+            .iter()
+            .map(|k| content.find(k).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert!(content.find("a-dep").unwrap() < content.find("z-dep").unwrap());
+    }
+
+    #[test]
+    fn test_empty_optional_maps_are_omitted_and_unknown_fields_kept() {
+        let pkg: PackageJson = serde_json::from_str(
+            r#"{"name": "app", "version": "1.0.0", "description": "demo", "main": "index.js"}"#
+        ).unwrap();
+
+        let json: serde_json::Value = serde_json::to_value(&pkg).unwrap();
+        assert!(json.get("optionalDependencies").is_none());
+        assert!(json.get("peerDependencies").is_none());
+        assert!(json.get("workspaces").is_none());
+        assert_eq!(json["description"], "demo");
+        assert_eq!(json["main"], "index.js");
+    }
+
+    #[test]
+    fn test_lock_registries_are_compared_and_rehosted() {
+        let mut lock = CrabbyLock::default();
+        lock.add_package("chalk".into(), "5.3.0".into(), "https://registry.npmjs.org/chalk/-/chalk-5.3.0.tgz".into(), HashMap::new());
+        lock.add_package("zod".into(), "3.22.4".into(), "https://npm.corp.example/api/npm/zod/-/zod-3.22.4.tgz".into(), HashMap::new());
+        lock.dependencies.get_mut("zod").unwrap().registry = Some("https://npm.corp.example/api/npm/".into());
+        lock.add_package("@acme/ui".into(), "1.0.0".into(), "workspace:packages/ui".into(), HashMap::new());
+
+        assert_eq!(lock.foreign_registries("https://registry.npmjs.org/"), [("https://npm.corp.example/api/npm".to_string(), 1)]);
+        assert_eq!(lock.foreign_registries("https://npm.corp.example/api/npm"), [("https://registry.npmjs.org".to_string(), 1)]);
+
+        let mirror = "http://localhost:4873/";
+        let rehost = |name: &str, registry: &str| lock.dependencies[name].rehost_tarball(name, registry);
+        assert_eq!(rehost("chalk", mirror), "http://localhost:4873/chalk/-/chalk-5.3.0.tgz");
+        assert_eq!(rehost("zod", mirror), "http://localhost:4873/zod/-/zod-3.22.4.tgz");
+        assert_eq!(rehost("@acme/ui", mirror), "workspace:packages/ui");
+        assert_eq!(rehost("chalk", "https://host/npm/"), "https://host/npm/chalk/-/chalk-5.3.0.tgz");
+
+        // Without a recorded registry, the old registry's own path goes too
+        let scoped = LockDependency { tarball: "https://old.example/npm/@scope/pkg/-/pkg-1.0.0.tgz".into(), ..lock.dependencies["chalk"].clone() };
+        assert_eq!(scoped.rehost_tarball("@scope/pkg", "https://host/registry"), "https://host/registry/@scope/pkg/-/pkg-1.0.0.tgz");
+    }
+}
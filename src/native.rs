@@ -0,0 +1,295 @@
+use console::style;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::reporter::{self, Event};
+
+/// Where a failed build's output is kept, inside the package
+pub const LOG_FILE: &str = "crabby-build.log";
+
+/// A package with a native addon, built once the whole tree is installed so
+/// that its build dependencies (node-addon-api, prebuild-install, ...) are there
+#[derive(Debug)]
+pub struct NativePackage {
+    pub name: String,
+    pub version: String,
+    pub dir: PathBuf,
+    install: Option<String>,
+    postinstall: Option<String>,
+    binding_gyp: bool,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    scripts: HashMap<String, String>,
+}
+
+/// What one way of getting the addon does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepKind {
+    /// Downloads a prebuilt binary (prebuild-install, node-pre-gyp)
+    Prebuilt,
+    /// Compiles with node-gyp
+    Compile,
+    /// Anything else, e.g. node-gyp-build, which uses a bundled binary or compiles
+    Other,
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    command: String,
+    kind: StepKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Prebuilt,
+    Built,
+    Failed { missing: Vec<&'static str>, log: Option<PathBuf> },
+}
+
+#[derive(Debug)]
+pub struct NativeResult {
+    pub name: String,
+    pub version: String,
+    pub outcome: Outcome,
+}
+
+/// The package installed in `dir`, if it has a native addon: a binding.gyp, or
+/// an install script that runs node-gyp or fetches a prebuilt binary
+pub fn detect(name: &str, dir: &Path) -> Option<NativePackage> {
+    let content = fs::read_to_string(dir.join("package.json")).ok()?;
+    let manifest: Manifest = serde_json::from_str(&crate::manifest::clean_json_content(content)).ok()?;
+    let binding_gyp = dir.join("binding.gyp").is_file();
+    let install = manifest.scripts.get("install").cloned();
+    let native_script = install.as_deref().is_some_and(|script| {
+        ["node-gyp", "prebuild-install", "node-pre-gyp"].iter().any(|tool| script.contains(tool))
+    });
+    if !binding_gyp && !native_script {
+        return None;
+    }
+    Some(NativePackage {
+        name: name.to_string(),
+        version: manifest.version,
+        dir: dir.to_path_buf(),
+        install,
+        postinstall: manifest.scripts.get("postinstall").cloned(),
+        binding_gyp,
+    })
+}
+
+/// The commands to try in order until one works. `a || b` in the install
+/// script means "b if a fails", and node-pre-gyp's `--fallback-to-build` is
+/// split off so the prebuilt download is tried before anything compiles.
+fn steps(install: Option<&str>, binding_gyp: bool) -> Vec<Step> {
+    let script = match install {
+        Some(script) => script.to_string(),
+        // npm's default for packages that ship a binding.gyp
+        None if binding_gyp => "node-gyp rebuild".to_string(),
+        None => return Vec::new(),
+    };
+    let mut steps = Vec::new();
+    for command in script.split("||").map(str::trim).filter(|c| !c.is_empty()) {
+        let program = command.split_whitespace().next().unwrap_or_default();
+        match program {
+            "node-pre-gyp" | "prebuild-install" => {
+                let words: Vec<&str> = command.split_whitespace().collect();
+                let fallback = words.iter().any(|w| w.starts_with("--fallback-to-build"));
+                let download: Vec<&str> = words.into_iter().filter(|w| !w.starts_with("--fallback-to-build")).collect();
+                steps.push(Step { command: download.join(" "), kind: StepKind::Prebuilt });
+                if fallback {
+                    steps.push(Step { command: "node-gyp rebuild".to_string(), kind: StepKind::Compile });
+                }
+            }
+            "node-gyp" => steps.push(Step { command: command.to_string(), kind: StepKind::Compile }),
+            _ => steps.push(Step { command: command.to_string(), kind: StepKind::Other }),
+        }
+    }
+    steps
+}
+
+/// What compiling with node-gyp needs that `has` can't find
+fn missing_tools(has: impl Fn(&str) -> bool, windows: bool) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if !["python3", "python", "py"].iter().any(|p| has(p)) {
+        missing.push("Python 3");
+    }
+    if windows {
+        if !has("cl") && !has("vswhere") {
+            missing.push("Visual Studio Build Tools (\"Desktop development with C++\")");
+        }
+    } else {
+        if !has("make") {
+            missing.push("make");
+        }
+        if !["c++", "g++", "clang++"].iter().any(|c| has(c)) {
+            missing.push("a C++ compiler (g++ or clang++)");
+        }
+    }
+    if !has("node-gyp") {
+        missing.push("node-gyp");
+    }
+    missing
+}
+
+/// `missing_tools` for this machine, also looking in `bin_dir` (node_modules/.bin)
+fn missing_here(bin_dir: &Path) -> Vec<&'static str> {
+    missing_tools(|tool| {
+        if tool == "vswhere" {
+            // node-gyp finds Visual Studio through vswhere even when cl isn't on PATH
+            return std::env::var_os("ProgramFiles(x86)").is_some_and(|dir| {
+                Path::new(&dir).join("Microsoft Visual Studio").join("Installer").join("vswhere.exe").is_file()
+            });
+        }
+        which::which(tool).is_ok() || which::which_in(tool, Some(bin_dir), ".").is_ok()
+    }, cfg!(target_os = "windows"))
+}
+
+/// Get the addon of `package` working: a prebuilt binary if there is one,
+/// otherwise a node-gyp build when the tools for it are there. Output is only
+/// kept, in `crabby-build.log`, when nothing worked.
+pub fn build(package: &NativePackage, bin_dir: &Path) -> NativeResult {
+    let started = Instant::now();
+    let mut log = String::new();
+    let mut missing = Vec::new();
+    let mut outcome = None;
+
+    for step in steps(package.install.as_deref(), package.binding_gyp) {
+        if step.kind == StepKind::Compile {
+            missing = missing_here(bin_dir);
+            if !missing.is_empty() {
+                log.push_str(&format!("$ {}\nskipped, missing: {}\n\n", step.command, missing.join(", ")));
+                continue;
+            }
+        }
+        let (success, output) = run(&step.command, package, bin_dir);
+        log.push_str(&format!("$ {}\n{}\n", step.command, output));
+        if success {
+            outcome = Some(if step.kind == StepKind::Prebuilt { Outcome::Prebuilt } else { Outcome::Built });
+            break;
+        }
+    }
+
+    // postinstall may need the addon, so it only runs once there is one
+    if let (Some(_), Some(script)) = (&outcome, &package.postinstall) {
+        let (success, output) = run(script, package, bin_dir);
+        if !success {
+            log.push_str(&format!("$ {}\n{}\n", script, output));
+            outcome = None;
+        }
+    }
+
+    let outcome = outcome.unwrap_or_else(|| {
+        if missing.is_empty() {
+            missing = missing_here(bin_dir);
+        }
+        let path = package.dir.join(LOG_FILE);
+        Outcome::Failed { missing, log: fs::write(&path, &log).ok().map(|_| path) }
+    });
+    reporter::emit(Event::Script {
+        name: &package.name,
+        script: "install",
+        success: !matches!(outcome, Outcome::Failed { .. }),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    });
+    NativeResult { name: package.name.clone(), version: package.version.clone(), outcome }
+}
+
+fn run(command: &str, package: &NativePackage, bin_dir: &Path) -> (bool, String) {
+    crate::runner::run_captured(command, &package.dir, Some(bin_dir))
+        .unwrap_or_else(|e| (false, e.to_string()))
+}
+
+/// The end-of-install section on native addons
+pub fn print_report(results: &[NativeResult], root: &Path) {
+    if results.is_empty() || !reporter::human() {
+        return;
+    }
+    crate::ui::print_header(&format!("{} Native addons", crate::ui::icon(crate::ui::Icons::BUILD)));
+    let width = results.iter().map(|r| r.name.len() + r.version.len() + 1).max().unwrap_or(0);
+    let mut missing_any: Vec<&str> = Vec::new();
+    for result in results {
+        let label = format!("{:<width$}", format!("{}@{}", result.name, result.version), width = width);
+        match &result.outcome {
+            Outcome::Prebuilt => println!("  {} {}  {}", style(crate::ui::icon("✓")).green(), label, style("prebuilt binary").dim()),
+            Outcome::Built => println!("  {} {}  {}", style(crate::ui::icon("✓")).green(), label, style("built from source").dim()),
+            Outcome::Failed { missing, log } => {
+                let reason = if missing.is_empty() { "build failed".to_string() } else { format!("not built, missing {}", missing.join(", ")) };
+                println!("  {} {}  {}", style(crate::ui::icon("✗")).red(), label, style(reason).red());
+                if let Some(log) = log {
+                    println!("    {} {}", style("log:").dim(), log.strip_prefix(root).unwrap_or(log).display());
+                }
+                for tool in missing {
+                    if !missing_any.contains(tool) {
+                        missing_any.push(tool);
+                    }
+                }
+            }
+        }
+    }
+    if !missing_any.is_empty() {
+        crate::ui::print_info("Packages that failed throw when required. Install what's missing, then run `crabby rebuild`:");
+        for tool in missing_any {
+            println!("    {}: {}", tool, style(install_hint(tool)).cyan());
+        }
+    }
+}
+
+/// How to get `tool`, one of the names `missing_tools` reports
+fn install_hint(tool: &str) -> &'static str {
+    let (windows, macos) = (cfg!(target_os = "windows"), cfg!(target_os = "macos"));
+    match tool {
+        "node-gyp" => "npm install -g node-gyp",
+        "Python 3" if windows => "winget install Python.Python.3.12",
+        "Python 3" if macos => "brew install python",
+        "Python 3" => "apt install python3 (or your distribution's package)",
+        _ if windows => "winget install Microsoft.VisualStudio.2022.BuildTools",
+        _ if macos => "xcode-select --install",
+        _ => "apt install build-essential (or your distribution's package)",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prebuilt_binaries_come_before_compiling() {
+        let kinds = |script: Option<&str>, gyp| steps(script, gyp).into_iter().map(|s| (s.command, s.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds(None, true), [("node-gyp rebuild".to_string(), StepKind::Compile)]);
+        assert_eq!(kinds(None, false), []);
+        assert_eq!(kinds(Some("prebuild-install || node-gyp rebuild --release"), true), [
+            ("prebuild-install".to_string(), StepKind::Prebuilt),
+            ("node-gyp rebuild --release".to_string(), StepKind::Compile),
+        ]);
+        assert_eq!(kinds(Some("node-pre-gyp install --fallback-to-build"), false), [
+            ("node-pre-gyp install".to_string(), StepKind::Prebuilt),
+            ("node-gyp rebuild".to_string(), StepKind::Compile),
+        ]);
+        assert_eq!(kinds(Some("node-gyp-build"), true), [("node-gyp-build".to_string(), StepKind::Other)]);
+    }
+
+    #[test]
+    fn test_detect_and_missing_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("package.json"), r#"{"name": "plain", "version": "1.0.0", "scripts": {"install": "node setup.js"}}"#).unwrap();
+        assert!(detect("plain", dir.path()).is_none());
+        fs::write(dir.path().join("binding.gyp"), "{}").unwrap();
+        let native = detect("addon", dir.path()).unwrap();
+        assert_eq!((native.version.as_str(), native.binding_gyp), ("1.0.0", true));
+
+        let unix = |tools: &[&str]| {
+            let tools = tools.to_vec();
+            missing_tools(move |tool| tools.contains(&tool), false)
+        };
+        assert!(unix(&["python3", "make", "clang++", "node-gyp"]).is_empty());
+        assert_eq!(unix(&["python", "node-gyp"]), ["make", "a C++ compiler (g++ or clang++)"]);
+        assert_eq!(missing_tools(|tool| tool == "vswhere", true), ["Python 3", "node-gyp"]);
+    }
+}
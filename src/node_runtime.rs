@@ -1,17 +1,147 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const DIST_URL: &str = "https://nodejs.org/dist";
+
+/// Written next to a portable install with the codename of its LTS line, so
+/// that `lts` and codenames match it without fetching index.json
+const LTS_FILE: &str = ".lts";
+
+/// Where a project or the user asked for a Node.js version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `"node"` in the project's crabby.config.json
+    ProjectConfig,
+    Nvmrc,
+    /// `engines.node` in package.json
+    Engines,
+    /// Set with `crabby node use`
+    UserDefault,
+    /// `--node` on the command line
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::ProjectConfig => "crabby.config.json",
+            Source::Nvmrc => ".nvmrc",
+            Source::Engines => "engines.node",
+            Source::UserDefault => "crabby node use",
+            Source::CommandLine => "--node",
+        })
+    }
+}
+
+/// A Node.js version request such as `20`, `v20.11.0`, `>=18`, `lts`, `lts/iron` or `latest`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub spec: String,
+    pub source: Source,
+}
+
+/// One entry of https://nodejs.org/dist/index.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub version: String,
+    /// `false`, or the codename of the LTS line
+    #[serde(default)]
+    lts: serde_json::Value,
+}
+
+impl Release {
+    pub fn lts_name(&self) -> Option<&str> {
+        self.lts.as_str()
+    }
+}
+
+/// Get the path to Node.js executable: `requested` (from `--node`), else the
+/// version the project (or the user's default) asks for, else system Node.js,
+/// else a portable one
+pub async fn get_node_path(requested: Option<&str>) -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    let selection = match requested {
+        Some(spec) => Some(Selection { spec: normalize_spec(spec), source: Source::CommandLine }),
+        None => requested_version(&cwd)?,
+    };
+    if let Some(selection) = selection {
+        let (path, version) = node_for(&selection).await?;
+        if crate::reporter::human() {
+            let shown = which::which(&path).unwrap_or_else(|_| path.clone());
+            println!(
+                "{} Using Node.js v{} ({}) for {} from {}",
+                console::style(crate::ui::icon("🟢")).dim(), version, shown.display(), selection.spec, selection.source
+            );
+        }
+        return Ok(path);
+    }
 
-/// Get the path to Node.js executable
-/// Returns system Node.js if available, otherwise downloads portable version
-pub fn get_node_path() -> Result<PathBuf> {
     // First, try to find system Node.js
     if let Ok(path) = find_system_node() {
         return Ok(path);
     }
-    
+
     // If not found, use or download portable Node.js
-    get_portable_node()
+    if let Some(version) = installed_versions()?.pop() {
+        return Ok(node_exe(&version_dir(&version)?));
+    }
+    println!("{} Downloading Node.js runtime (one-time setup)...", crate::ui::icon("📥"));
+    let version = install("lts").await?;
+    Ok(node_exe(&version_dir(&version)?))
+}
+
+/// The portable node `dir` asks for when system Node.js doesn't satisfy it,
+/// without downloading anything. Scripts started from `dir` run on it.
+pub fn selected_installed_node(dir: &Path) -> Option<PathBuf> {
+    let selection = requested_version(dir).ok()??;
+    match resolve_available(&selection).ok()?? {
+        (_, Available::System) => None,
+        (path, Available::Portable(_)) => Some(path),
+    }
+}
+
+/// Where an already available node comes from
+enum Available {
+    System,
+    Portable(semver::Version),
+}
+
+/// Node.js for `selection` without downloading: system Node.js if it
+/// satisfies it, else the newest matching portable install
+fn resolve_available(selection: &Selection) -> Result<Option<(PathBuf, Available)>> {
+    if let Ok(path) = find_system_node() {
+        let lts = if names_lts(&selection.spec) { node_lts(&path) } else { None };
+        if node_version(&path).is_some_and(|v| matches_spec(&selection.spec, &v, lts.as_deref())) {
+            return Ok(Some((path, Available::System)));
+        }
+    }
+    match best_installed(&get_runtime_dir()?, &selection.spec)? {
+        Some(version) => Ok(Some((node_exe(&version_dir(&version)?), Available::Portable(version)))),
+        None => Ok(None),
+    }
+}
+
+/// Node.js for `selection` and its version: what is available, or else the
+/// newest matching release, downloaded now
+async fn node_for(selection: &Selection) -> Result<(PathBuf, semver::Version)> {
+    match resolve_available(selection)? {
+        Some((path, Available::Portable(version))) => Ok((path, version)),
+        Some((path, Available::System)) => {
+            let version = node_version(&path).context("Could not read the system Node.js version")?;
+            Ok((path, version))
+        }
+        None => {
+            println!("{} Downloading Node.js {} (from {})...", crate::ui::icon("📥"), selection.spec, selection.source);
+            let version = install(&selection.spec).await?;
+            Ok((node_exe(&version_dir(&version)?), version))
+        }
+    }
 }
 
 /// Try to find Node.js in system PATH
@@ -21,12 +151,12 @@ fn find_system_node() -> Result<PathBuf> {
     } else {
         "node"
     };
-    
+
     // Try running node --version to check if it exists
     let output = Command::new(node_cmd)
         .arg("--version")
         .output();
-    
+
     if output.is_ok() {
         Ok(PathBuf::from(node_cmd))
     } else {
@@ -34,134 +164,589 @@ fn find_system_node() -> Result<PathBuf> {
     }
 }
 
-/// Get portable Node.js path, download if needed
-fn get_portable_node() -> Result<PathBuf> {
-    let runtime_dir = get_runtime_dir()?;
-    let node_exe = if cfg!(target_os = "windows") {
-        runtime_dir.join("node.exe")
-    } else {
-        runtime_dir.join("bin").join("node")
+/// The version `node --version` reports, without the leading `v`
+pub fn node_version(node: &Path) -> Option<semver::Version> {
+    let output = Command::new(node).arg("--version").output().ok()?;
+    parse_version(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// The LTS codename `node` reports for itself, if it is an LTS release
+fn node_lts(node: &Path) -> Option<String> {
+    let output = Command::new(node).args(["-p", "process.release.lts || ''"]).output().ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+fn parse_version(version: &str) -> Option<semver::Version> {
+    semver::Version::parse(version.trim().trim_start_matches('v')).ok()
+}
+
+/// The version `dir` asks for: crabby.config.json, .nvmrc and engines.node of
+/// its project, then the user's default
+pub fn requested_version(dir: &Path) -> Result<Option<Selection>> {
+    let root = crate::manifest::find_project_root(dir).unwrap_or_else(|| dir.to_path_buf());
+
+    let config = crate::config::CrabbyConfig::load_from(&root.join("crabby.config.json"))?;
+    if let Some(spec) = config.node {
+        return Ok(Some(Selection { spec, source: Source::ProjectConfig }));
+    }
+
+    if let Ok(content) = std::fs::read_to_string(root.join(".nvmrc")) {
+        let spec = normalize_spec(content.lines().next().unwrap_or(""));
+        if !spec.is_empty() {
+            return Ok(Some(Selection { spec, source: Source::Nvmrc }));
+        }
+    }
+
+    let pkg = crate::manifest::PackageJson::load_from(&root.join("package.json")).unwrap_or_default();
+    if let Some(range) = pkg.engines.get("node") {
+        return Ok(Some(Selection { spec: normalize_spec(range), source: Source::Engines }));
+    }
+
+    Ok(user_default()?.map(|spec| Selection { spec, source: Source::UserDefault }))
+}
+
+/// Bring nvm spellings to ours: `lts/*` → `lts`, `lts/iron` → `iron`, `node` → `latest`, `v20` → `20`
+pub fn normalize_spec(spec: &str) -> String {
+    let spec = spec.trim();
+    let spec = match spec {
+        "lts/*" => "lts",
+        "node" | "current" | "stable" => "latest",
+        other => other.strip_prefix("lts/").unwrap_or(other),
     };
-    
-    // Check if already downloaded
-    if node_exe.exists() {
-        return Ok(node_exe);
+    match spec.strip_prefix('v') {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest.to_string(),
+        _ => spec.to_lowercase(),
     }
-    
-    // Download Node.js
-    println!("📥 Downloading Node.js runtime (one-time setup)...");
-    download_node(&runtime_dir)?;
-    
-    Ok(node_exe)
 }
 
-/// Get the runtime directory path
+/// Whether `spec` asks for an LTS line (`lts` or a codename) rather than a version
+fn names_lts(spec: &str) -> bool {
+    spec != "latest" && spec.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// Whether `version` (of the LTS line `lts`, if known) satisfies `spec`
+fn matches_spec(spec: &str, version: &semver::Version, lts: Option<&str>) -> bool {
+    match spec {
+        "latest" => true,
+        "lts" => lts.is_some(),
+        _ if spec.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            lts.is_some_and(|name| name.eq_ignore_ascii_case(spec))
+        }
+        _ => {
+            if let Ok(exact) = semver::Version::parse(spec) {
+                return *version == exact;
+            }
+            crate::package_utils::satisfies(spec, &version.to_string())
+        }
+    }
+}
+
+/// ~/.crabby/runtime, with one directory per installed version
 fn get_runtime_dir() -> Result<PathBuf> {
     let home = std::env::var("HOME")
         .or_else(|_| std::env::var("USERPROFILE"))
         .context("Could not determine home directory")?;
-    
+
     let runtime_dir = PathBuf::from(home).join(".crabby").join("runtime");
-    
+
     if !runtime_dir.exists() {
         std::fs::create_dir_all(&runtime_dir)?;
     }
-    
+    migrate_flat_install(&runtime_dir)?;
+
     Ok(runtime_dir)
 }
 
-/// Download portable Node.js
-fn download_node(runtime_dir: &PathBuf) -> Result<()> {
-    use std::io::Write;
-    
-    // Determine Node.js download URL based on platform
-    let (url, archive_name) = get_node_download_url()?;
-    
+/// Before versions got a directory each, the one portable node was unpacked
+/// straight into the runtime dir. Move it into its `v<version>` directory.
+fn migrate_flat_install(runtime_dir: &Path) -> Result<()> {
+    let node = node_exe(runtime_dir);
+    if !node.exists() {
+        return Ok(());
+    }
+    // A node that can't report its version can't run scripts either
+    let Some(version) = node_version(&node) else {
+        return Ok(());
+    };
+
+    let dest = runtime_dir.join(format!("v{}", version));
+    let staging = runtime_dir.join(format!("v{}.partial", version));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+    let flat: Vec<_> = std::fs::read_dir(runtime_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_version = name.strip_prefix('v').and_then(parse_version).is_some();
+            !is_version && !name.ends_with(".part") && !name.ends_with(".partial")
+        })
+        .collect();
+    for entry in flat {
+        std::fs::rename(entry.path(), staging.join(entry.file_name()))
+            .with_context(|| format!("Failed to move {} into {}", entry.path().display(), dest.display()))?;
+    }
+    if dest.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    } else {
+        std::fs::rename(&staging, &dest)?;
+    }
+    Ok(())
+}
+
+/// ~/.crabby/runtime/v<version>
+pub fn version_dir(version: &semver::Version) -> Result<PathBuf> {
+    Ok(get_runtime_dir()?.join(format!("v{}", version)))
+}
+
+pub fn node_exe(dir: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        dir.join("node.exe")
+    } else {
+        dir.join("bin").join("node")
+    }
+}
+
+/// Installed portable versions, oldest first
+pub fn installed_versions() -> Result<Vec<semver::Version>> {
+    Ok(installed_in(&get_runtime_dir()?)?.into_iter().map(|(version, _)| version).collect())
+}
+
+/// The versions installed in `runtime_dir` with the LTS line each belongs to, oldest first
+fn installed_in(runtime_dir: &Path) -> Result<Vec<(semver::Version, Option<String>)>> {
+    let mut versions: Vec<_> = std::fs::read_dir(runtime_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let version = parse_version(entry.file_name().to_str()?.strip_prefix('v')?)?;
+            let path = entry.path();
+            if !node_exe(&path).exists() {
+                return None;
+            }
+            let lts = std::fs::read_to_string(path.join(LTS_FILE)).ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty());
+            Some((version, lts))
+        })
+        .collect();
+    versions.sort();
+    Ok(versions)
+}
+
+/// The newest version installed in `runtime_dir` matching `spec`. `lts` and
+/// codenames match the versions installed as part of an LTS line.
+fn best_installed(runtime_dir: &Path, spec: &str) -> Result<Option<semver::Version>> {
+    Ok(installed_in(runtime_dir)?.into_iter().rev()
+        .find(|(version, lts)| matches_spec(spec, version, lts.as_deref()))
+        .map(|(version, _)| version))
+}
+
+/// Every release, newest first
+pub async fn fetch_releases() -> Result<Vec<Release>> {
+    let client = crate::registry::get_client()?;
+    let url = format!("{}/index.json", DIST_URL);
+    client.get(&url).send().await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .json().await
+        .with_context(|| format!("Failed to read {}", url))
+}
+
+/// The newest release matching `spec`
+fn resolve_release<'a>(spec: &str, releases: &'a [Release]) -> Option<&'a Release> {
+    releases.iter()
+        .filter(|r| parse_version(&r.version).is_some_and(|v| matches_spec(spec, &v, r.lts_name())))
+        .max_by_key(|r| parse_version(&r.version))
+}
+
+/// Download the newest release matching `spec` unless it is already installed.
+/// Returns the installed version.
+pub async fn install(spec: &str) -> Result<semver::Version> {
+    let spec = normalize_spec(spec);
+    let releases = fetch_releases().await?;
+    let release = resolve_release(&spec, &releases)
+        .with_context(|| format!("No Node.js release matches {}", spec))?;
+    let version = parse_version(&release.version).context("Invalid release version")?;
+
+    let dir = version_dir(&version)?;
+    if !node_exe(&dir).exists() {
+        download_node(&version, &dir).await?;
+    }
+    if let Some(name) = release.lts_name() {
+        std::fs::write(dir.join(LTS_FILE), name)?;
+    }
+    Ok(version)
+}
+
+/// The version `crabby node use` made the default
+pub fn user_default() -> Result<Option<String>> {
+    let Ok(content) = std::fs::read_to_string(crate::config::user_config_path()?) else {
+        return Ok(None);
+    };
+    let json: serde_json::Value = serde_json::from_str(&content).context("Invalid ~/.crabby/config.json")?;
+    Ok(json.get("node").and_then(|n| n.as_str()).map(str::to_string))
+}
+
+/// Make `version` the default for projects that don't ask for one
+pub fn set_user_default(version: &semver::Version) -> Result<()> {
+    let path = crate::config::user_config_path()?;
+    let mut json: serde_json::Value = std::fs::read_to_string(&path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_else(|| serde_json::json!({}));
+    json["node"] = version.to_string().into();
+    std::fs::write(&path, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
+}
+
+/// `crabby node install <version>`
+pub async fn run_install(spec: &str) -> Result<()> {
+    let version = install(spec).await?;
+    crate::ui::print_success(&format!("Node.js v{} is installed in {}", version, version_dir(&version)?.display()));
+    Ok(())
+}
+
+/// `crabby node use <version>`: the newest installed match, or a fresh
+/// download, becomes the default
+pub async fn run_use(spec: &str) -> Result<()> {
+    let spec = normalize_spec(spec);
+    let version = match best_installed(&get_runtime_dir()?, &spec)? {
+        Some(version) => version,
+        None => install(&spec).await?,
+    };
+    set_user_default(&version)?;
+    crate::ui::print_success(&format!("Node.js v{} is now the default", version));
+
+    let cwd = std::env::current_dir()?;
+    if let Some(selection) = requested_version(&cwd)?.filter(|s| s.source != Source::UserDefault) {
+        crate::ui::print_info(&format!("This project asks for {} in {}, which takes precedence", selection.spec, selection.source));
+    }
+    Ok(())
+}
+
+/// `crabby node which`: the node scripts of this project run on. Never downloads.
+pub fn run_which() -> Result<()> {
+    println!("{}", which_node(&std::env::current_dir()?)?.display());
+    Ok(())
+}
+
+/// The node scripts started from `dir` run on, if it's there already
+pub fn which_node(dir: &Path) -> Result<PathBuf> {
+    let path = match requested_version(dir)? {
+        Some(selection) => match resolve_available(&selection)? {
+            Some((path, Available::System)) => which::which(&path).unwrap_or(path),
+            Some((path, Available::Portable(_))) => path,
+            None => anyhow::bail!(
+                "Node.js {} (from {}) is not installed. Run: crabby node install {}",
+                selection.spec, selection.source, selection.spec
+            ),
+        },
+        None => match which::which("node") {
+            Ok(node) => node,
+            Err(_) => match installed_versions()?.pop() {
+                Some(version) => node_exe(&version_dir(&version)?),
+                None => anyhow::bail!("No Node.js found. Run: crabby node install lts"),
+            },
+        },
+    };
+    Ok(path)
+}
+
+/// `crabby node list`: installed versions, then the newest release of each LTS line
+pub async fn run_list() -> Result<()> {
+    use console::style;
+
+    let installed = installed_versions()?;
+    let default = user_default()?.and_then(|v| parse_version(&v));
+
+    crate::ui::print_header("Installed Node.js versions");
+    if installed.is_empty() {
+        println!("  {}", style("none").dim());
+    }
+    for version in installed.iter().rev() {
+        let marker = if Some(version) == default.as_ref() { style(" (default)").green().to_string() } else { String::new() };
+        println!("  v{}{}", version, marker);
+    }
+
+    let cwd = std::env::current_dir()?;
+    if let Some(selection) = requested_version(&cwd)? {
+        println!("\n  This project: {} {}", style(&selection.spec).cyan(), style(format!("(from {})", selection.source)).dim());
+    }
+
+    match fetch_releases().await {
+        Ok(releases) => {
+            crate::ui::print_header("LTS releases");
+            let mut seen = std::collections::HashSet::new();
+            let mut rows = Vec::new();
+            for release in &releases {
+                let Some(name) = release.lts_name() else { continue };
+                if !seen.insert(name.to_string()) {
+                    continue;
+                }
+                let is_installed = parse_version(&release.version).is_some_and(|v| installed.contains(&v));
+                rows.push(vec![
+                    release.version.clone(),
+                    name.to_string(),
+                    if is_installed { style("installed").green().to_string() } else { String::new() },
+                ]);
+            }
+            crate::ui::print_table(&["Version", "LTS", ""], &rows);
+        }
+        Err(e) => crate::ui::print_warning(&format!("Could not list remote releases: {}", e)),
+    }
+    Ok(())
+}
+
+/// How long the runtime archive may take to download, instead of the client's
+/// request timeout which is sized for registry metadata
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Download portable Node.js `version`, check it against the release's
+/// SHASUMS256.txt and unpack it so that `dest` holds the contents of its top-level folder
+async fn download_node(version: &semver::Version, dest: &Path) -> Result<()> {
+    let (url, archive_name) = get_node_download_url(version)?;
+    let client = crate::registry::get_client()?;
+
+    let shasums_url = format!("{}/v{}/SHASUMS256.txt", DIST_URL, version);
+    let shasums = client.get(&shasums_url).send().await
+        .and_then(|r| r.error_for_status())
+        .context("Failed to download SHASUMS256.txt")?
+        .text().await?;
+    let expected = published_checksum(&shasums, &archive_name)
+        .with_context(|| format!("{} is not listed in {}", archive_name, shasums_url))?;
+
+    // Neither the download nor the unpacked files count as installed until
+    // they are complete: the archive is a .part file next to the runtime dir
+    // and is unpacked into a staging dir that is swapped in at the end
+    let part = dest.with_file_name(format!("{}.part", archive_name));
+    let result = download_to(&client, &url, &part, &expected).await;
+    let result = match result {
+        Ok(()) => install_archive(part.clone(), dest.to_path_buf()).await,
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_file(&part);
+    result?;
+
+    println!("{} Node.js v{} installed!", crate::ui::icon("✅"), version);
+    Ok(())
+}
+
+/// Stream `url` into `path` with a progress bar, checking its SHA-256 against `expected`
+async fn download_to(client: &reqwest::Client, url: &str, path: &Path, expected: &str) -> Result<()> {
     println!("Downloading from: {}", url);
-    
-    // Download the archive
-    let response = reqwest::blocking::get(&url)
-        .context("Failed to download Node.js")?
-        .bytes()?;
-    
-    // Save to temp file
-    let temp_file = runtime_dir.join(&archive_name);
-    let mut file = std::fs::File::create(&temp_file)?;
-    file.write_all(&response)?;
-    
-    // Extract archive
-    println!("📦 Extracting Node.js...");
-    extract_node_archive(&temp_file, runtime_dir)?;
-    
-    // Clean up temp file
-    std::fs::remove_file(&temp_file)?;
-    
-    println!("✅ Node.js runtime installed!");
-    
+    let mut response = client.get(url).timeout(DOWNLOAD_TIMEOUT).send().await
+        .and_then(|r| r.error_for_status())
+        .context("Failed to download Node.js")?;
+
+    let progress = crate::ui::create_download_progress_bar(response.content_length().unwrap_or(0), "Downloading Node.js");
+    let mut file = tokio::fs::File::create(path).await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = response.chunk().await.context("Node.js download was interrupted")? {
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        progress.inc(chunk.len() as u64);
+    }
+    file.flush().await?;
+    progress.finish_and_clear();
+
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != expected {
+        return Err(crate::error::failure(
+            crate::error::ErrorKind::Integrity,
+            format!("Checksum mismatch for {}: expected {}, got {}", url, expected, actual),
+        ));
+    }
     Ok(())
 }
 
-/// Get Node.js download URL for current platform
-fn get_node_download_url() -> Result<(String, String)> {
-    let version = "v20.11.0"; // LTS version
-    
-    let (os, arch, ext) = if cfg!(target_os = "windows") {
-        if cfg!(target_arch = "x86_64") {
-            ("win", "x64", "zip")
-        } else {
-            ("win", "x86", "zip")
+/// Unpack `archive` into a staging dir and swap it in as `runtime_dir`
+async fn install_archive(archive: PathBuf, runtime_dir: PathBuf) -> Result<()> {
+    println!("{} Extracting Node.js...", crate::ui::icon("📦"));
+    tokio::task::spawn_blocking(move || {
+        let name = runtime_dir.file_name().context("Invalid runtime directory")?.to_string_lossy().to_string();
+        let staging = runtime_dir.with_file_name(format!("{}.partial", name));
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        let result = std::fs::File::open(&archive)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| extract_node_archive(file, &staging));
+        if let Err(e) = result {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        if runtime_dir.exists() {
+            std::fs::remove_dir_all(&runtime_dir)?;
         }
+        std::fs::rename(&staging, &runtime_dir)?;
+        Ok(())
+    }).await?
+}
+
+/// The Node.js release naming of this platform: (os, arch, archive extension)
+fn node_platform() -> Result<(&'static str, &'static str, &'static str)> {
+    let (os, ext) = if cfg!(target_os = "windows") {
+        ("win", "zip")
     } else if cfg!(target_os = "macos") {
-        ("darwin", "x64", "tar.gz")
+        ("darwin", "tar.gz")
+    } else if cfg!(target_os = "linux") {
+        ("linux", "tar.gz")
     } else {
-        ("linux", "x64", "tar.xz")
+        anyhow::bail!("No portable Node.js build for {}", std::env::consts::OS);
     };
-    
-    let filename = format!("node-{}-{}-{}", version, os, arch);
-    let url = format!(
-        "https://nodejs.org/dist/{}/{}.{}",
-        version, filename, ext
-    );
-    
-    Ok((url, format!("{}.{}", filename, ext)))
-}
-
-/// Extract Node.js archive
-fn extract_node_archive(archive_path: &PathBuf, dest_dir: &PathBuf) -> Result<()> {
+
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        "x86" if os == "win" => "x86",
+        other => anyhow::bail!("No portable Node.js build for {} on {}", std::env::consts::OS, other),
+    };
+
+    Ok((os, arch, ext))
+}
+
+/// Get Node.js download URL and archive file name for current platform
+fn get_node_download_url(version: &semver::Version) -> Result<(String, String)> {
+    let (os, arch, ext) = node_platform()?;
+
+    let archive_name = format!("node-v{}-{}-{}.{}", version, os, arch, ext);
+    let url = format!("{}/v{}/{}", DIST_URL, version, archive_name);
+
+    Ok((url, archive_name))
+}
+
+/// The checksum SHASUMS256.txt lists for `file` (lines are `<sha256>  <file>`)
+fn published_checksum(shasums: &str, file: &str) -> Option<String> {
+    shasums.lines().find_map(|line| {
+        let (sum, name) = line.split_once(char::is_whitespace)?;
+        (name.trim() == file).then(|| sum.to_lowercase())
+    })
+}
+
+/// Extract a Node.js archive into `dest_dir`, dropping the top-level
+/// `node-vX-os-arch/` folder so that `bin/node` (or `node.exe`) lands in `dest_dir`
+fn extract_node_archive(archive: std::fs::File, dest_dir: &Path) -> Result<()> {
     if cfg!(target_os = "windows") {
-        // Extract ZIP on Windows
-        let file = std::fs::File::open(archive_path)?;
-        let mut archive = zip::ZipArchive::new(file)?;
-        
+        let mut archive = zip::ZipArchive::new(archive)?;
+
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
-            let outpath = match file.enclosed_name() {
-                Some(path) => dest_dir.join(path),
+            let relative: PathBuf = match file.enclosed_name() {
+                Some(path) => path.components().skip(1).collect(),
                 None => continue,
             };
-            
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+            let outpath = dest_dir.join(relative);
+
             if file.name().ends_with('/') {
                 std::fs::create_dir_all(&outpath)?;
             } else {
                 if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)?;
-                    }
+                    std::fs::create_dir_all(p)?;
                 }
                 let mut outfile = std::fs::File::create(&outpath)?;
                 std::io::copy(&mut file, &mut outfile)?;
             }
         }
     } else {
-        // Extract tar.gz/tar.xz on Unix
-        use flate2::read::GzDecoder;
-        use tar::Archive;
-        
-        let tar_gz = std::fs::File::open(archive_path)?;
-        let tar = GzDecoder::new(tar_gz);
-        let mut archive = Archive::new(tar);
-        archive.unpack(dest_dir)?;
-    }
-    
+        crate::package_utils::extract_tarball_from(std::io::BufReader::new(archive), dest_dir)?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_published_checksum() {
+        let shasums = "\
+0e19d10f8fbd31b6a1b4a8f1e1d8e0ad4d1d8e3f1b9b1d7c1f2c8b2a7e8d9c0a  node-v20.11.0-darwin-arm64.tar.gz
+9556262f6cd4c020af027782afba31ca6d1a37e45ac0b56cecd2d5a4daf720e0  node-v20.11.0-linux-arm64.tar.gz
+";
+        assert_eq!(
+            published_checksum(shasums, "node-v20.11.0-linux-arm64.tar.gz").as_deref(),
+            Some("9556262f6cd4c020af027782afba31ca6d1a37e45ac0b56cecd2d5a4daf720e0")
+        );
+        assert_eq!(published_checksum(shasums, "node-v20.11.0-linux-arm64.tar.xz"), None);
+
+        let (url, name) = get_node_download_url(&semver::Version::new(20, 11, 0)).unwrap();
+        assert!(url.ends_with(&name));
+        assert!(!name.ends_with(".tar.xz"), "tar.xz archives can't be decoded");
+    }
+
+    #[test]
+    fn test_version_specs() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+        assert_eq!(normalize_spec("v20.11.0\n"), "20.11.0");
+        assert_eq!(normalize_spec("lts/*"), "lts");
+        assert_eq!(normalize_spec("lts/Iron"), "iron");
+        assert_eq!(normalize_spec("node"), "latest");
+
+        assert!(matches_spec("20", &v("20.11.1"), None));
+        assert!(!matches_spec("20", &v("21.0.0"), None));
+        assert!(matches_spec("20.11.0", &v("20.11.0"), None));
+        assert!(!matches_spec("20.11.0", &v("20.11.1"), None));
+        assert!(matches_spec(">=18 <21", &v("20.0.0"), None));
+        assert!(matches_spec("^16 || ^22", &v("22.1.0"), None));
+        assert!(matches_spec("iron", &v("20.11.1"), Some("Iron")));
+        assert!(!matches_spec("lts", &v("21.0.0"), None));
+
+        let releases: Vec<Release> = serde_json::from_str(r#"[
+            {"version": "v21.6.0", "lts": false},
+            {"version": "v20.11.1", "lts": "Iron"},
+            {"version": "v20.11.0", "lts": "Iron"},
+            {"version": "v18.19.0", "lts": "Hydrogen"}
+        ]"#).unwrap();
+        assert_eq!(resolve_release("lts", &releases).unwrap().version, "v20.11.1");
+        assert_eq!(resolve_release("latest", &releases).unwrap().version, "v21.6.0");
+        assert_eq!(resolve_release("hydrogen", &releases).unwrap().version, "v18.19.0");
+        assert_eq!(resolve_release("20.11.0", &releases).unwrap().version, "v20.11.0");
+        assert!(resolve_release("19", &releases).is_none());
+    }
+
+    #[test]
+    fn test_lts_matches_installed_versions() {
+        let runtime = tempfile::tempdir().unwrap();
+        for (version, lts) in [("18.19.0", Some("Hydrogen")), ("20.11.1", Some("Iron")), ("21.6.0", None)] {
+            let dir = runtime.path().join(format!("v{}", version));
+            std::fs::create_dir_all(node_exe(&dir).parent().unwrap()).unwrap();
+            std::fs::write(node_exe(&dir), "").unwrap();
+            if let Some(name) = lts {
+                std::fs::write(dir.join(LTS_FILE), name).unwrap();
+            }
+        }
+
+        let best = |spec: &str| best_installed(runtime.path(), spec).unwrap().map(|v| v.to_string());
+        assert_eq!(best("lts").as_deref(), Some("20.11.1"));
+        assert_eq!(best("hydrogen").as_deref(), Some("18.19.0"));
+        assert_eq!(best("latest").as_deref(), Some("21.6.0"));
+        assert_eq!(best("gallium"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_flat_install_is_migrated() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let runtime = tempfile::tempdir().unwrap();
+        let node = node_exe(runtime.path());
+        std::fs::create_dir_all(node.parent().unwrap()).unwrap();
+        std::fs::write(&node, "#!/bin/sh\necho v18.19.0\n").unwrap();
+        std::fs::set_permissions(&node, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(runtime.path().join("LICENSE"), "MIT").unwrap();
+        std::fs::create_dir_all(runtime.path().join("v20.11.1")).unwrap();
+
+        migrate_flat_install(runtime.path()).unwrap();
+
+        let migrated = runtime.path().join("v18.19.0");
+        assert!(node_exe(&migrated).exists());
+        assert!(migrated.join("LICENSE").exists());
+        assert!(!node.exists());
+        assert!(runtime.path().join("v20.11.1").exists(), "other versions stay put");
+    }
+}
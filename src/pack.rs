@@ -0,0 +1,370 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use console::style;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use std::fs;
+use std::path::Path;
+use crate::{manifest, ui};
+
+/// Paths npm never puts in a package, whatever .npmignore says
+const DEFAULT_EXCLUDES: &[&str] = &[
+    ".git",
+    ".svn",
+    ".hg",
+    "CVS",
+    "node_modules",
+    ".DS_Store",
+    "npm-debug.log",
+    ".npmrc",
+    "*.orig",
+    ".*.swp",
+    ".npmignore",
+    ".gitignore",
+    "/crabby.lock",
+    "/crabby.config.json",
+    "/package-lock.json",
+    "/yarn.lock",
+    "/pnpm-lock.yaml",
+    // Tarballs, e.g. leftovers from an earlier `crabby pack`
+    "*.tgz",
+];
+
+/// npm stamps every entry with this mtime (1985-10-26T08:15:00Z) so tarballs are reproducible
+const NPM_MTIME: u64 = 499162500;
+
+#[derive(Debug)]
+pub struct PackedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub struct PackResult {
+    pub filename: String,
+    pub files: Vec<PackedFile>,
+    pub unpacked_size: u64,
+    pub tarball: Vec<u8>,
+    /// sha1 hex digest, as reported in `dist.shasum`
+    pub shasum: String,
+    /// Subresource Integrity string, as reported in `dist.integrity`
+    pub integrity: String,
+}
+
+/// A minimal .gitignore-style matcher: the last matching rule wins and `!` re-includes
+struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+struct IgnoreRule {
+    pattern: glob::Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// Rules containing a slash match against the whole relative path, others against the file name
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let rules = lines.into_iter()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let dir_only = line.ends_with('/');
+                let line = line.trim_end_matches('/');
+                let anchored = line.contains('/');
+                let pattern = glob::Pattern::new(line.trim_start_matches('/')).ok()?;
+                Some(IgnoreRule { pattern, negated, dir_only, anchored })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Whether `relative` (a file) or any directory above it is ignored
+    fn excludes(&self, relative: &str) -> bool {
+        let mut dirs = relative.match_indices('/').map(|(i, _)| &relative[..i]);
+        dirs.any(|dir| self.is_ignored(dir, true)) || self.is_ignored(relative, false)
+    }
+
+    fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let target = if rule.anchored { relative } else { name };
+            if rule.pattern.matches(target) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Files npm ships whatever `files` or .npmignore say: package.json, the
+/// README/LICENSE at the root, and the `main` and `bin` entry points
+fn always_included(pkg: &manifest::PackageJson) -> impl Fn(&str) -> bool {
+    let mut entry_points: Vec<String> = Vec::new();
+    if let Some(main) = pkg.extra.get("main").and_then(|v| v.as_str()) {
+        entry_points.push(main.to_string());
+    }
+    entry_points.extend(pkg.bin.files().into_iter().map(String::from));
+    let entry_points: Vec<String> = entry_points.iter()
+        .map(|p| p.trim_start_matches("./").to_string())
+        .collect();
+
+    move |path: &str| {
+        if path == "package.json" || entry_points.iter().any(|p| p == path) {
+            return true;
+        }
+        if path.contains('/') {
+            return false;
+        }
+        let stem = path.split('.').next().unwrap_or(path).to_ascii_lowercase();
+        matches!(stem.as_str(), "readme" | "license" | "licence" | "copying")
+    }
+}
+
+/// Work out which files under `root` belong in the package, as sorted `/`-separated relative paths
+pub fn collect_files(root: &Path, pkg: &manifest::PackageJson) -> Result<Vec<String>> {
+    let defaults = IgnoreRules::parse(DEFAULT_EXCLUDES.iter().copied());
+    let forced = always_included(pkg);
+
+    // `files` is an allow-list; without it .npmignore (or .gitignore) excludes
+    let allow: Option<Vec<glob::Pattern>> = pkg.files.as_ref().map(|files| {
+        files.iter()
+            .filter_map(|f| glob::Pattern::new(f.trim_start_matches("./").trim_end_matches('/')).ok())
+            .collect()
+    });
+    let ignore_file = [".npmignore", ".gitignore"].iter()
+        .map(|f| root.join(f))
+        .find(|p| p.exists());
+    let ignore = match (&allow, ignore_file) {
+        (None, Some(path)) => IgnoreRules::parse(fs::read_to_string(path)?.lines().collect::<Vec<_>>()),
+        _ => IgnoreRules::parse([]),
+    };
+
+    let mut files = Vec::new();
+    walk(root, root, &defaults, &mut files)?;
+
+    files.retain(|path| {
+        if forced(path) {
+            return true;
+        }
+        match &allow {
+            Some(allow) => allow.iter().any(|pattern| {
+                // A pattern naming a directory pulls in everything below it
+                std::iter::successors(Some(path.as_str()), |p| p.rsplit_once('/').map(|(parent, _)| parent))
+                    .any(|candidate| pattern.matches(candidate))
+            }),
+            None => !ignore.excludes(path),
+        }
+    });
+
+    files.sort();
+    Ok(files)
+}
+
+/// Every file under `dir` except the defaults npm always leaves out
+fn walk(root: &Path, dir: &Path, defaults: &IgnoreRules, files: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+        let is_dir = entry.file_type()?.is_dir();
+
+        if defaults.is_ignored(&relative, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(root, &path, defaults, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// `@scope/name` + `1.0.0` -> `scope-name-1.0.0.tgz`, matching npm
+pub fn tarball_name(pkg: &manifest::PackageJson) -> String {
+    format!("{}-{}.tgz", pkg.name.trim_start_matches('@').replace('/', "-"), pkg.version)
+}
+
+/// Build the gzipped tarball with every file under the `package/` prefix
+pub fn pack(root: &Path, pkg: &manifest::PackageJson) -> Result<PackResult> {
+    if pkg.name.is_empty() || pkg.version.is_empty() {
+        anyhow::bail!("package.json must have a name and version to be packed");
+    }
+
+    let paths = collect_files(root, pkg)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+    let mut files = Vec::new();
+
+    for relative in paths {
+        let full_path = root.join(&relative);
+        let data = fs::read(&full_path).with_context(|| format!("Failed to read {}", relative))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(file_mode(&full_path));
+        header.set_mtime(NPM_MTIME);
+        builder.append_data(&mut header, format!("package/{}", relative), data.as_slice())?;
+
+        files.push(PackedFile { path: relative, size: data.len() as u64 });
+    }
+
+    let tarball = builder.into_inner()?.finish()?;
+    let shasum = format!("{:x}", Sha1::digest(&tarball));
+    let integrity = format!("sha512-{}", base64::engine::general_purpose::STANDARD.encode(Sha512::digest(&tarball)));
+
+    Ok(PackResult {
+        filename: tarball_name(pkg),
+        unpacked_size: files.iter().map(|f| f.size).sum(),
+        files,
+        tarball,
+        shasum,
+        integrity,
+    })
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    match fs::metadata(path) {
+        Ok(m) if m.permissions().mode() & 0o111 != 0 => 0o755,
+        _ => 0o644,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> u32 {
+    0o644
+}
+
+/// `crabby pack`: print what would ship and write the tarball unless `dry_run`
+pub fn run(dry_run: bool) -> Result<()> {
+    let pkg = manifest::PackageJson::load()?;
+    let result = pack(Path::new("."), &pkg)?;
+
+    ui::print_header(&format!("{} {}@{}", ui::Icons::PACKAGE, pkg.name, pkg.version));
+    println!("{}", style("Tarball Contents").bold());
+    for file in &result.files {
+        println!("  {:>9}  {}", style(ui::format_size(file.size)).dim(), file.path);
+    }
+
+    println!("\n{}", style("Tarball Details").bold());
+    let details = [
+        ("name", pkg.name.clone()),
+        ("version", pkg.version.clone()),
+        ("filename", result.filename.clone()),
+        ("package size", ui::format_size(result.tarball.len() as u64)),
+        ("unpacked size", ui::format_size(result.unpacked_size)),
+        ("shasum", result.shasum.clone()),
+        ("integrity", result.integrity.clone()),
+        ("total files", result.files.len().to_string()),
+    ];
+    for (label, value) in details {
+        println!("  {:<14} {}", style(label).dim(), value);
+    }
+    println!();
+
+    if dry_run {
+        ui::print_info("DRY RUN - no tarball written");
+    } else {
+        fs::write(&result.filename, &result.tarball)?;
+        ui::print_success(&format!("Created {}", result.filename));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_collect_files_respects_npmignore_and_defaults() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write(root, "package.json", r#"{"name": "demo", "version": "1.0.0"}"#);
+        write(root, "index.js", "module.exports = 1;");
+        write(root, "src/util.js", "");
+        write(root, "test/util.test.js", "");
+        write(root, "debug.log", "");
+        write(root, "node_modules/dep/index.js", "");
+        write(root, ".git/HEAD", "");
+        write(root, "crabby.lock", "{}");
+        write(root, ".npmignore", "test/\n*.log\n");
+
+        let pkg = manifest::PackageJson { name: "demo".into(), version: "1.0.0".into(), ..Default::default() };
+        let files = collect_files(root, &pkg).unwrap();
+        assert_eq!(files, vec!["index.js", "package.json", "src/util.js"]);
+    }
+
+    #[test]
+    fn test_files_allow_list_keeps_npm_mandatory_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let manifest = r#"{
+            "name": "demo",
+            "version": "1.0.0",
+            "main": "./lib/main.js",
+            "bin": {"demo": "./bin/demo.js"},
+            "files": ["dist"]
+        }"#;
+        write(root, "package.json", manifest);
+        write(root, "README.md", "# demo");
+        write(root, "LICENSE", "MIT");
+        write(root, "CHANGELOG.md", "");
+        write(root, "dist/index.js", "");
+        write(root, "dist/demo-0.9.0.tgz", "");
+        write(root, "lib/main.js", "");
+        write(root, "lib/other.js", "");
+        write(root, "bin/demo.js", "");
+        write(root, "src/index.ts", "");
+        write(root, "docs/README.md", "");
+        write(root, "node_modules/dep/index.js", "");
+        write(root, "demo-1.0.0.tgz", "");
+        // Excluding the README does not stop it from shipping
+        write(root, ".npmignore", "README.md\n");
+
+        let pkg: manifest::PackageJson = serde_json::from_str(manifest).unwrap();
+        let files = collect_files(root, &pkg).unwrap();
+        assert_eq!(files, vec!["LICENSE", "README.md", "bin/demo.js", "dist/index.js", "lib/main.js", "package.json"]);
+    }
+
+    #[test]
+    fn test_pack_uses_package_prefix() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        write(root, "package.json", r#"{"name": "@scope/demo", "version": "2.0.0"}"#);
+        write(root, "lib/index.js", "export {}");
+
+        let pkg = manifest::PackageJson { name: "@scope/demo".into(), version: "2.0.0".into(), ..Default::default() };
+        let result = pack(root, &pkg).unwrap();
+        assert_eq!(result.filename, "scope-demo-2.0.0.tgz");
+        assert!(result.integrity.starts_with("sha512-"));
+
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(result.tarball.as_slice()).read_to_end(&mut decoded).unwrap();
+        let mut archive = tar::Archive::new(decoded.as_slice());
+        let mut entries: Vec<String> = archive.entries().unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, vec!["package/lib/index.js", "package/package.json"]);
+    }
+}
@@ -1,19 +1,25 @@
 use anyhow::{Context, Result};
 use console::style;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use flate2::read::GzDecoder;
 use tar::Archive;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::{Mutex, Semaphore};
+use std::time::Instant;
+use sha1::Digest;
+use tokio::sync::Mutex;
+use indicatif::ProgressBar;
 
+use crate::reporter::{self, Event};
 use crate::runner;
+use crate::source::Source;
+use crate::error::{failure, ErrorKind};
 
 #[derive(Debug, Deserialize)]
 pub struct PackageMetadata {
-    pub name: String,
     pub versions: HashMap<String, PackageVersion>,
     #[serde(rename = "dist-tags")]
     pub dist_tags: DistTags,
@@ -26,14 +32,18 @@ pub struct DistTags {
 
 #[derive(Debug, Deserialize)]
 pub struct PackageVersion {
-    pub version: String,
     pub dist: PackageDist,
+    #[serde(default)]
+    pub dependencies: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PackageDist {
     pub tarball: String,
     pub shasum: String,
+    /// Size of the unpacked files, when the registry reports it
+    #[serde(default, rename = "unpackedSize")]
+    pub unpacked_size: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -44,86 +54,664 @@ pub struct InstalledPackageJson {
     pub scripts: HashMap<String, String>,
     #[serde(default)]
     pub bin: PackageBin,
+    #[serde(default, rename = "peerDependencies")]
+    pub peer_dependencies: HashMap<String, String>,
+    #[serde(default, rename = "peerDependenciesMeta")]
+    pub peer_dependencies_meta: HashMap<String, PeerMeta>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PeerMeta {
+    #[serde(default)]
+    pub optional: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `"bin"`: one command named after the package, or a map of command names to files
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 #[serde(untagged)]
 pub enum PackageBin {
     String(String),
     Map(HashMap<String, String>),
+    #[default]
     None,
 }
 
-impl Default for PackageBin {
-    fn default() -> Self {
-        PackageBin::None
+impl PackageBin {
+    pub fn is_none(&self) -> bool {
+        matches!(self, PackageBin::None)
+    }
+
+    /// The files the commands run
+    pub fn files(&self) -> Vec<&str> {
+        match self {
+            PackageBin::String(file) => vec![file.as_str()],
+            PackageBin::Map(map) => map.values().map(String::as_str).collect(),
+            PackageBin::None => Vec::new(),
+        }
     }
 }
 
-pub async fn fetch_package_version(name: &str, registry_url: &str, version_req: Option<&str>, client: &reqwest::Client) -> anyhow::Result<(String, String, String)> {
+pub async fn fetch_metadata(name: &str, registry_url: &str, client: &reqwest::Client) -> Result<PackageMetadata> {
     let url = format!("{}/{}", registry_url.trim_end_matches('/'), name);
-    let response = client.get(&url)
-        .send()
+    let (response, _permit) = crate::registry::get(client, &url)
+        .await
+        .context("Failed to fetch package metadata")?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(package_not_found(name, registry_url, client).await);
+    }
+    let response = response.error_for_status()?;
+
+    response.json::<PackageMetadata>()
         .await
-        .context("Failed to fetch package metadata")?
-        .error_for_status()?;
+        .context("Failed to parse package metadata")
+}
+
+/// Every published version of each package in `names`, sorted ascending
+pub async fn fetch_versions(names: &[String], registry_url: &str, client: &reqwest::Client) -> Result<HashMap<String, Vec<semver::Version>>> {
+    use futures::stream::{self, StreamExt, TryStreamExt};
 
-    let metadata = response.json::<PackageMetadata>()
+    stream::iter(names)
+        .map(|name| async move {
+            let metadata = fetch_metadata(name, registry_url, client).await?;
+            let mut versions: Vec<semver::Version> = metadata.versions.keys()
+                .filter_map(|v| semver::Version::parse(v).ok())
+                .collect();
+            versions.sort();
+            Ok::<_, anyhow::Error>((name.clone(), versions))
+        })
+        .buffer_unordered(crate::MAX_CONCURRENT_DOWNLOADS)
+        .try_collect()
         .await
-        .context("Failed to parse package metadata")?;
+}
+
+/// Metadata fetched in this run, by URL, so that installs running side by side
+/// (one per workspace) ask the registry about each package once
+static METADATA: std::sync::OnceLock<std::sync::Mutex<HashMap<String, SharedMetadata>>> = std::sync::OnceLock::new();
+
+/// Filled by whichever install asks first; the others wait for it
+type SharedMetadata = Arc<tokio::sync::OnceCell<Arc<PackageMetadata>>>;
+
+async fn shared_metadata(name: &str, registry_url: &str, client: &reqwest::Client) -> Result<Arc<PackageMetadata>> {
+    let url = format!("{}/{}", registry_url.trim_end_matches('/'), name);
+    let cell = METADATA.get_or_init(Default::default).lock().unwrap().entry(url).or_default().clone();
+    let metadata = cell.get_or_try_init(|| async {
+        fetch_metadata(name, registry_url, client).await.map(Arc::new)
+    }).await?;
+    Ok(metadata.clone())
+}
+
+pub async fn fetch_package_version(name: &str, registry_url: &str, version_req: Option<&str>, client: &reqwest::Client) -> anyhow::Result<(String, String, String)> {
+    let metadata = shared_metadata(name, registry_url, client).await?;
+    let version = resolve_version(&metadata, name, version_req)?;
+    let version_info = metadata.versions.get(&version)
+        .context("Version not found in map")?;
+    Ok((version, version_info.dist.tarball.clone(), version_info.dist.shasum.clone()))
+}
 
+/// The newest version in `metadata` matching `version_req` (latest when `None`)
+pub fn resolve_version(metadata: &PackageMetadata, name: &str, version_req: Option<&str>) -> Result<String> {
     let req_str = version_req.unwrap_or("latest");
     
     // Resolve version
-    if let Ok(req) = semver::VersionReq::parse(req_str) {
+    if req_str == "latest" {
+        metadata.versions.get(&metadata.dist_tags.latest)
+            .context("Latest version not found")?;
+        Ok(metadata.dist_tags.latest.clone())
+    } else if let Some(range) = npm_range(req_str) {
         let mut versions: Vec<semver::Version> = metadata.versions.keys()
             .filter_map(|v| semver::Version::parse(v).ok())
             .collect();
         versions.sort();
         
-        let best_version = versions.into_iter()
-            .rev()
-            .find(|v| req.matches(v))
-            .context(format!("No matching version found for {}@{}", name, req_str))?;
-
-        let best_version_str = best_version.to_string();
-        let version_info = metadata.versions.get(&best_version_str)
-            .context("Version not found in map")?;
-        Ok((best_version_str, version_info.dist.tarball.clone(), version_info.dist.shasum.clone()))
+        match versions.iter().rev().find(|v| range.iter().any(|set| set.matches(v))) {
+            Some(v) => Ok(v.to_string()),
+            None => {
+                let suggestions: Vec<String> = closest_versions(&versions, req_str, 3)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect();
+                let message = if suggestions.is_empty() {
+                    format!("Version '{}' of package '{}' not found. It has no published versions.", req_str, name)
+                } else {
+                    format!("Version '{}' of package '{}' not found. Closest available versions: {}", req_str, name, suggestions.join(", "))
+                };
+                Err(failure(ErrorKind::Registry, message))
+            }
+        }
     } else {
         crate::ui::print_warning(&format!("No matching version for {} {}, using latest", name, req_str));
         // Fallback to latest to try our best
-        let latest_version = metadata.dist_tags.latest.clone();
-        let version_info = metadata.versions.get(&latest_version)
+        metadata.versions.get(&metadata.dist_tags.latest)
             .context("Latest version not found")?;
-        Ok((latest_version, version_info.dist.tarball.clone(), version_info.dist.shasum.clone()))
+        Ok(metadata.dist_tags.latest.clone())
+    }
+}
+
+/// Whether the locked `version` can be kept for `version_req`
+pub fn lock_satisfies(version: &str, version_req: Option<&str>) -> bool {
+    match version_req {
+        Some(req) => req == "latest" || req == version || satisfies(req, version),
+        None => true,
+    }
+}
+
+/// Where the tarball of `name`@`version` is cached
+pub fn cached_tarball(name: &str, version: &str) -> Result<PathBuf> {
+    Ok(crate::config::get_cache_dir()?.join(tarball_file_name(name, version)))
+}
+
+/// File name of the tarball of `name`@`version` in the cache
+fn tarball_file_name(name: &str, version: &str) -> String {
+    format!("{}-{}.tgz", name.replace("/", "-"), version)
+}
+
+/// Whether `version` falls inside the npm range `req`; false when either doesn't parse
+pub fn satisfies(req: &str, version: &str) -> bool {
+    range_matches(req, version).unwrap_or(false)
+}
+
+/// Whether `version` falls inside the npm range `req`, `None` when either doesn't parse
+fn range_matches(req: &str, version: &str) -> Option<bool> {
+    let range = npm_range(req)?;
+    let version = semver::Version::parse(version.trim_start_matches('v')).ok()?;
+    Some(range.iter().any(|set| set.matches(&version)))
+}
+
+/// An npm range as the `||` alternatives it allows. Each alternative is a set
+/// of space-separated comparators (`>=1.2.0 <2`), a hyphen range (`1.2 - 2.3`)
+/// or empty for any version. `None` when a part doesn't parse.
+pub fn npm_range(req: &str) -> Option<Vec<semver::VersionReq>> {
+    req.split("||").map(|set| {
+        let comparators: Vec<String> = match set.trim().split_once(" - ") {
+            Some((low, high)) => vec![format!(">={}", low.trim()), format!("<={}", high.trim())],
+            None => {
+                let mut comparators = Vec::new();
+                let mut pending = String::new();
+                for token in set.split_whitespace() {
+                    pending.push_str(token);
+                    // `>= 1.2` puts a space between the operator and its version
+                    if !token.chars().all(|c| "<>=~^".contains(c)) {
+                        comparators.push(std::mem::take(&mut pending));
+                    }
+                }
+                if !pending.is_empty() {
+                    return None;
+                }
+                comparators
+            }
+        };
+        let comparators: Vec<String> = comparators.iter()
+            .map(|c| {
+                // A bare version is exact in npm but a caret range in the semver crate
+                match c.trim_start_matches('v') {
+                    bare if bare.starts_with(|ch: char| ch.is_ascii_digit()) => format!("={}", bare),
+                    _ => c.clone(),
+                }
+            })
+            .collect();
+        if comparators.is_empty() {
+            return Some(semver::VersionReq::STAR);
+        }
+        semver::VersionReq::parse(&comparators.join(", ")).ok()
+    }).collect()
+}
+
+/// Friendly error for a package name the registry doesn't know about, with
+/// the closest names the registry's search comes up with
+pub async fn package_not_found(name: &str, registry_url: &str, client: &reqwest::Client) -> anyhow::Error {
+    let message = format!("Package '{}' not found on {}", name, registry_url.trim_end_matches('/'));
+    let suggestions = crate::search::similar_packages(name, registry_url, client).await;
+    let message = if suggestions.is_empty() {
+        message
+    } else {
+        format!("{}\n  Did you mean: {}?", message, suggestions.join(", "))
+    };
+    failure(ErrorKind::Registry, message)
+}
+
+/// Pick the `limit` versions closest to what was requested.
+/// When the request names a concrete version, rank by distance to it; otherwise
+/// fall back to the most recent releases.
+fn closest_versions(versions: &[semver::Version], requested: &str, limit: usize) -> Vec<semver::Version> {
+    let wanted = semver::Version::parse(requested.trim_start_matches(['^', '~', '=', 'v']));
+
+    let mut candidates: Vec<semver::Version> = versions.to_vec();
+    match wanted {
+        Ok(wanted) => {
+            candidates.sort_by_key(|v| (
+                v.major.abs_diff(wanted.major),
+                v.minor.abs_diff(wanted.minor),
+                v.patch.abs_diff(wanted.patch),
+            ));
+        }
+        Err(_) => candidates.reverse(),
+    }
+    candidates.truncate(limit);
+    candidates
+}
+
+/// Counters for the end-of-install summary. One instance is shared by every
+/// package installed in a single command.
+pub struct InstallStats {
+    /// Skip the peer dependency check (`--legacy-peer-deps`)
+    legacy_peer_deps: bool,
+    started: Instant,
+    added: AtomicUsize,
+    reused: AtomicUsize,
+    downloaded_bytes: AtomicU64,
+    /// Cached tarballs downloaded again by `--force`
+    refreshed: std::sync::Mutex<HashSet<PathBuf>>,
+    /// Every package downloaded or taken from the cache in this run
+    timings: std::sync::Mutex<Vec<PackageTiming>>,
+}
+
+/// How long one package took in each phase of the install, in milliseconds
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackageTiming {
+    pub name: String,
+    pub version: String,
+    pub metadata_ms: u64,
+    pub download_ms: u64,
+    pub checksum_ms: u64,
+    pub extract_ms: u64,
+    /// The tarball came from the cache
+    pub cached: bool,
+}
+
+impl PackageTiming {
+    pub fn total_ms(&self) -> u64 {
+        self.metadata_ms + self.download_ms + self.checksum_ms + self.extract_ms
     }
 }
 
+/// Time spent in each phase across all packages, in milliseconds. Packages
+/// install concurrently, so these add up to more than the wall-clock time.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct PhaseTotals {
+    pub metadata_ms: u64,
+    pub download_ms: u64,
+    pub checksum_ms: u64,
+    pub extract_ms: u64,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+}
+
+/// Timings in the `--reporter json` summary
+#[derive(Debug, Serialize)]
+pub struct TimingSummary {
+    pub phases: PhaseTotals,
+    /// The packages that took longest, slowest first
+    pub slowest: Vec<PackageTiming>,
+}
+
+/// Rows in the slowest-packages table of `install --verbose`
+const SLOWEST: usize = 10;
+
+/// `metadata 120ms · download 80ms (cache miss) · checksum 1ms · extract 12ms`
+fn format_timing(timing: &PackageTiming) -> String {
+    let ms = crate::ui::format_duration;
+    style(format!("metadata {} · download {} ({}) · checksum {} · extract {}",
+        ms(timing.metadata_ms),
+        ms(timing.download_ms),
+        if timing.cached { "cache hit" } else { "cache miss" },
+        ms(timing.checksum_ms),
+        ms(timing.extract_ms),
+    )).dim().to_string()
+}
+
+impl InstallStats {
+    pub fn new() -> Arc<Self> {
+        Self::with_legacy_peer_deps(false)
+    }
+
+    /// For a command that doesn't check `peerDependencies` (`--legacy-peer-deps`)
+    pub fn with_legacy_peer_deps(legacy_peer_deps: bool) -> Arc<Self> {
+        Arc::new(Self {
+            legacy_peer_deps,
+            started: Instant::now(),
+            added: AtomicUsize::new(0),
+            reused: AtomicUsize::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+            refreshed: std::sync::Mutex::new(HashSet::new()),
+            timings: std::sync::Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Keep `timing`, and print it as it happens with `--verbose`
+    fn record_timing(&self, timing: PackageTiming, progress: &ProgressBar) {
+        if VERBOSE.load(Ordering::Relaxed) && reporter::human() {
+            progress.suspend(|| println!("  {} {}", style(format!("{}@{}", timing.name, timing.version)).dim(), format_timing(&timing)));
+        }
+        self.timings.lock().unwrap().push(timing);
+    }
+
+    pub fn phase_totals(&self) -> PhaseTotals {
+        let timings = self.timings.lock().unwrap();
+        let mut totals = PhaseTotals::default();
+        for timing in timings.iter() {
+            totals.metadata_ms += timing.metadata_ms;
+            totals.download_ms += timing.download_ms;
+            totals.checksum_ms += timing.checksum_ms;
+            totals.extract_ms += timing.extract_ms;
+            if timing.cached { totals.cache_hits += 1 } else { totals.cache_misses += 1 }
+        }
+        totals
+    }
+
+    /// The `limit` packages that took longest, slowest first
+    pub fn slowest(&self, limit: usize) -> Vec<PackageTiming> {
+        let mut timings = self.timings.lock().unwrap().clone();
+        timings.sort_by(|a, b| b.total_ms().cmp(&a.total_ms()).then_with(|| a.name.cmp(&b.name)));
+        timings.truncate(limit);
+        timings
+    }
+
+    fn print_timings(&self) {
+        let slowest = self.slowest(SLOWEST);
+        if slowest.is_empty() {
+            return;
+        }
+        let ms = crate::ui::format_duration;
+        crate::ui::print_section("Slowest packages");
+        let rows: Vec<Vec<String>> = slowest.iter()
+            .map(|t| vec![
+                format!("{}@{}", t.name, t.version),
+                ms(t.total_ms()),
+                ms(t.metadata_ms),
+                ms(t.download_ms),
+                ms(t.checksum_ms),
+                ms(t.extract_ms),
+                if t.cached { "hit" } else { "miss" }.to_string(),
+            ])
+            .collect();
+        crate::ui::print_table(&["Package", "Total", "Metadata", "Download", "Checksum", "Extract", "Cache"], &rows);
+        let totals = self.phase_totals();
+        println!("  {} metadata {} · download {} · checksum {} · extract {} · cache {} hit{}, {} miss{}",
+            style("Totals:").bold(),
+            ms(totals.metadata_ms), ms(totals.download_ms), ms(totals.checksum_ms), ms(totals.extract_ms),
+            totals.cache_hits, if totals.cache_hits == 1 { "" } else { "s" },
+            totals.cache_misses, if totals.cache_misses == 1 { "" } else { "es" },
+        );
+    }
+
+    fn record(&self, downloaded: Option<u64>) {
+        match downloaded {
+            Some(bytes) => {
+                self.added.fetch_add(1, Ordering::Relaxed);
+                self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+            None => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// `🎉 Installed 3 packages in 1.2s (2 added, 1 reused from cache, 4.5 KB downloaded)`
+    fn summary(&self, elapsed_ms: u64) -> String {
+        let added = self.added.load(Ordering::Relaxed);
+        let reused = self.reused.load(Ordering::Relaxed);
+        format!("{} Installed {} packages in {} {}",
+            crate::ui::icon(crate::ui::Icons::PARTY),
+            style(added + reused).bold(),
+            style(crate::ui::format_duration(elapsed_ms)).bold(),
+            style(format!(
+                "({} added, {} reused from cache, {} downloaded)",
+                added,
+                reused,
+                crate::ui::format_size(self.downloaded_bytes.load(Ordering::Relaxed))
+            )).dim()
+        )
+    }
+
+    pub fn print_summary(&self) {
+        let added = self.added.load(Ordering::Relaxed);
+        let reused = self.reused.load(Ordering::Relaxed);
+        let elapsed_ms = self.started.elapsed().as_millis() as u64;
+        if reporter::is_json() {
+            reporter::emit(Event::Done {
+                packages: added + reused,
+                added,
+                reused,
+                bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+                elapsed_ms,
+                timings: Some(TimingSummary { phases: self.phase_totals(), slowest: self.slowest(SLOWEST) }),
+            });
+            return;
+        }
+        if VERBOSE.load(Ordering::Relaxed) {
+            self.print_timings();
+        }
+        println!("{}", self.summary(elapsed_ms));
+        let refreshed = self.refreshed.lock().unwrap().len();
+        if refreshed > 0 {
+            crate::ui::print_info(&format!("Refreshed {} cache entr{}", refreshed, if refreshed == 1 { "y" } else { "ies" }));
+        }
+    }
+}
+
+/// Set by `crabby install --sequential`
+static SEQUENTIAL: AtomicBool = AtomicBool::new(false);
+/// Set by `crabby install --force`
+static FORCE: AtomicBool = AtomicBool::new(false);
+/// Set by `crabby install --verbose`
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Install one dependency at a time, in name order, so that a run can be
+/// reproduced when debugging
+pub fn set_sequential(sequential: bool) {
+    SEQUENTIAL.store(sequential, Ordering::Relaxed);
+}
+
+/// Download every tarball again, even if cached, and extract every package
+/// again, even if an earlier install finished it
+pub fn set_force(force: bool) {
+    FORCE.store(force, Ordering::Relaxed);
+}
+
+/// Print per-package timings during the install and the slowest packages after it
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Whether `--sequential` was given
+pub fn is_sequential() -> bool {
+    SEQUENTIAL.load(Ordering::Relaxed)
+}
+
+/// Run `install` for each of `deps` (name to version range): one at a time in
+/// name order when `sequential`, otherwise all at once. Stops at the first error.
+async fn for_each_dependency<F, Fut>(deps: &HashMap<String, String>, sequential: bool, install: F) -> Result<()>
+where
+    F: Fn(String, String) -> Fut,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    let mut deps: Vec<(String, String)> = deps.iter().map(|(n, r)| (n.clone(), r.clone())).collect();
+    deps.sort();
+
+    if sequential {
+        for (name, range) in deps {
+            install(name, range).await?;
+        }
+        return Ok(());
+    }
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for (name, range) in deps {
+        tasks.spawn(install(name, range));
+    }
+    while let Some(res) = tasks.join_next().await {
+        res??; // Check for JoinError and Result calls
+    }
+    Ok(())
+}
+
+/// A `peerDependencies` entry of an installed package
+#[derive(Debug, Clone)]
+struct PeerRequirement {
+    package: String,
+    peer: String,
+    range: String,
+    optional: bool,
+}
+
+/// Check `peers` against the installed `versions` (name to version) and
+/// return a warning for each missing or conflicting peer. The packages are
+/// already in node_modules by now, so none of this fails the install.
+fn check_peers(peers: &[PeerRequirement], versions: &HashMap<String, String>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for req in peers {
+        let needs = format!("{} needs peer {}@{}", req.package, req.peer, req.range);
+        match versions.get(&req.peer) {
+            None if req.optional => {}
+            None => warnings.push(format!("{}, which is not installed", needs)),
+            Some(version) => match range_matches(&req.range, version) {
+                Some(true) => {}
+                Some(false) => warnings.push(format!("{}, but {} is installed", needs, version)),
+                None => warnings.push(format!("{}, a range crabby can't check against {}", needs, version)),
+            },
+        }
+    }
+    warnings
+}
+
 // Shared state for recursion
 struct InstallState {
+    // Project whose node_modules receives the packages
+    root: PathBuf,
     visited: Mutex<HashSet<String>>,
     package_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
     lockfile: Mutex<crate::manifest::CrabbyLock>,
     client: reqwest::Client,
     registry_url: String,
-    semaphore: Semaphore,
+    // One aggregate bar for the whole install; per-package spinners would overlap
+    progress: ProgressBar,
+    stats: Arc<InstallStats>,
+    // Hard-link from the global store instead of extracting per project
+    use_store: bool,
+    // Download locked tarballs from `registry_url` (replaceRegistryHost)
+    replace_registry_host: bool,
+    // Ignore the tarball cache and earlier progress (--force)
+    force: bool,
+    // Where downloaded tarballs are kept, see `crate::config::get_cache_dir`
+    cache_dir: PathBuf,
+    // peerDependencies of the packages installed so far, checked at the end
+    peers: std::sync::Mutex<Vec<PeerRequirement>>,
+    // Don't check peers at all (--legacy-peer-deps)
+    legacy_peer_deps: bool,
+    // One dependency at a time, in name order (--sequential)
+    sequential: bool,
+    // Packages with native addons, built once everything is installed, with
+    // the checksums of their tarballs
+    native: std::sync::Mutex<Vec<(crate::native::NativePackage, String)>>,
+    // What this and earlier runs have fully installed, see `crate::resume`
+    resume: crate::resume::InstallProgress,
 }
 
-pub async fn install_package(name: &str, registry_url: &str, client: &reqwest::Client, lockfile: crate::manifest::CrabbyLock) -> Result<(String, String, crate::manifest::CrabbyLock)> {
-    let state = Arc::new(InstallState {
-        visited: Mutex::new(HashSet::new()),
-        package_locks: Mutex::new(HashMap::new()),
-        lockfile: Mutex::new(lockfile),
-        client: client.clone(),
-        registry_url: registry_url.to_string(),
-        semaphore: Semaphore::new(crate::MAX_CONCURRENT_DOWNLOADS),
-    });
+impl InstallState {
+    fn new(root: &Path, lockfile: crate::manifest::CrabbyLock, client: &reqwest::Client, registry_url: &str, stats: &Arc<InstallStats>) -> Result<Self> {
+        let config = crate::config::load_config()?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            visited: Mutex::new(HashSet::new()),
+            package_locks: Mutex::new(HashMap::new()),
+            lockfile: Mutex::new(lockfile),
+            client: client.clone(),
+            registry_url: registry_url.to_string(),
+            progress: crate::ui::create_progress_bar(0, "Resolving packages"),
+            stats: stats.clone(),
+            use_store: config.use_store,
+            replace_registry_host: config.replace_registry_host,
+            force: FORCE.load(Ordering::Relaxed),
+            cache_dir: crate::config::get_cache_dir()?,
+            peers: std::sync::Mutex::new(Vec::new()),
+            legacy_peer_deps: stats.legacy_peer_deps,
+            sequential: SEQUENTIAL.load(Ordering::Relaxed),
+            native: std::sync::Mutex::new(Vec::new()),
+            resume: crate::resume::InstallProgress::load(root),
+        })
+    }
+
+    /// Warn about missing and conflicting peers, see `check_peers`
+    async fn check_peers(&self) {
+        if self.legacy_peer_deps {
+            return;
+        }
+        let versions: HashMap<String, String> = self.lockfile.lock().await.dependencies.iter()
+            .map(|(name, dep)| (name.clone(), dep.version.clone()))
+            .collect();
+        let peers = self.peers.lock().unwrap().clone();
+        for warning in check_peers(&peers, &versions) {
+            crate::ui::print_warning(&warning);
+        }
+    }
+
+    /// Build the native addons found during the install and report how it went
+    fn build_native(&self) {
+        let mut packages = std::mem::take(&mut *self.native.lock().unwrap());
+        if packages.is_empty() {
+            return;
+        }
+        packages.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        let bin_dir = self.root.join("node_modules").join(".bin");
+        let progress = crate::ui::create_progress_bar(packages.len() as u64, "Building native addons");
+        let results: Vec<_> = packages.iter().map(|(package, checksum)| {
+            progress.set_message(format!("Building {}", package.name));
+            let result = crate::native::build(package, &bin_dir);
+            // Only a working addon counts as installed for the next run
+            if !matches!(result.outcome, crate::native::Outcome::Failed { .. }) {
+                self.resume.record(&package.name, &package.version, checksum);
+            }
+            progress.inc(1);
+            result
+        }).collect();
+        progress.finish_and_clear();
+        crate::native::print_report(&results, &self.root);
+    }
+
+    /// Note that `name`@`version` is fully installed, unless it has a native
+    /// addon, which is recorded once it's built
+    fn finished(&self, name: &str, version: &str, checksum: &str) {
+        match crate::native::detect(name, &self.root.join("node_modules").join(name)) {
+            Some(native) => self.native.lock().unwrap().push((native, checksum.to_string())),
+            None => self.resume.record(name, version, checksum),
+        }
+    }
+
+    /// Wrap up after the packages are installed: check peers, build native
+    /// addons and keep the progress for the next run, whether `result` is an error or not
+    async fn complete(&self, result: Result<()>) -> Result<crate::manifest::CrabbyLock> {
+        self.progress.finish_and_clear();
+        if let Err(e) = result {
+            // The next install picks up from here
+            let _ = self.resume.save();
+            return Err(e);
+        }
+        self.check_peers().await;
+        self.build_native();
+        let lockfile = self.lockfile.lock().await.clone();
+        self.resume.finish(&lockfile)?;
+        Ok(lockfile)
+    }
+}
+
+/// Install `name` and its dependencies into `root`/node_modules
+pub async fn install_package(root: &Path, name: &str, registry_url: &str, client: &reqwest::Client, lockfile: crate::manifest::CrabbyLock, stats: &Arc<InstallStats>) -> Result<(String, String, crate::manifest::CrabbyLock)> {
+    install_package_matching(root, name, None, registry_url, client, lockfile, stats).await
+}
 
-    install_package_recursive(name.to_string(), None, state.clone()).await?;
+/// `install_package` with a version or range for `name`; a locked version
+/// outside of it is replaced
+#[allow(clippy::too_many_arguments)]
+pub async fn install_package_matching(root: &Path, name: &str, version_req: Option<&str>, registry_url: &str, client: &reqwest::Client, lockfile: crate::manifest::CrabbyLock, stats: &Arc<InstallStats>) -> Result<(String, String, crate::manifest::CrabbyLock)> {
+    let state = Arc::new(InstallState::new(root, lockfile, client, registry_url, stats)?);
 
-    let lockfile = state.lockfile.lock().await.clone();
-    Ok(("".to_string(), "".to_string(), lockfile))
+    let result = install_package_recursive(name.to_string(), version_req.map(str::to_string), state.clone()).await;
+    let lockfile = state.complete(result).await?;
+    let (version, tarball) = lockfile.dependencies.get(name)
+        .map(|d| (d.version.clone(), d.tarball.clone()))
+        .unwrap_or_default();
+    Ok((version, tarball, lockfile))
 }
 
 // Recursive async function using BoxFuture for recursion
@@ -131,6 +719,8 @@ fn install_package_recursive(name: String, version_req: Option<String>, state: A
     -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send>> 
 {
     Box::pin(async move {
+        // Names from package.json, lockfiles and dependencies all end up as paths
+        crate::safety::validate_package_name(&name)?;
         let visit_key = format!("{}@{}", name, version_req.as_deref().unwrap_or("latest"));
         
         {
@@ -140,35 +730,19 @@ fn install_package_recursive(name: String, version_req: Option<String>, state: A
             }
             visited.insert(visit_key);
         }
+        state.progress.inc_length(1);
 
         // Check lockfile first
-        // Check lockfile first
-        let lock_data = {
+        let locked = {
             let lockfile = state.lockfile.lock().await;
-            if let Some(dep) = lockfile.dependencies.get(&name) {
-                let use_lock_version = match &version_req {
-                    Some(req) => req == "latest" || req == &dep.version,
-                    None => true,
-                };
-                
-                if use_lock_version {
-                    Some((dep.version.clone(), dep.tarball.clone()))
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
+            lockfile.dependencies.get(&name)
+                .filter(|dep| lock_satisfies(&dep.version, version_req.as_deref()))
+                .map(|dep| {
+                    let tarball = if state.replace_registry_host { dep.rehost_tarball(&name, &state.registry_url) } else { dep.tarball.clone() };
+                    (dep.version.clone(), tarball, dep.integrity.clone(), dep.dependencies.clone())
+                })
         };
 
-        if let Some((ver, tar)) = lock_data {
-            println!("{} Using locked version {}", crate::ui::Icons::LOCK, style(&ver).dim());
-            download_and_extract(&name, &ver, &tar, &state.client, None).await?;
-            return Ok(());
-        }
-
-        println!("{} Resolving {} {}", crate::ui::Icons::SEARCH, style(&name).cyan(), style(version_req.as_deref().unwrap_or("latest")).dim());
-
         // Acquire per-package lock to prevent concurrent extraction of the same package name
         let pkg_lock = {
             let mut locks = state.package_locks.lock().await;
@@ -177,19 +751,64 @@ fn install_package_recursive(name: String, version_req: Option<String>, state: A
         
         let _lock_guard = pkg_lock.lock().await;
 
-        let (version, tarball, checksum) = fetch_package_version(&name, &state.registry_url, version_req.as_deref(), &state.client).await?;
+        if let Some(source) = version_req.as_deref().and_then(|spec| Source::parse(spec, &state.root)) {
+            return install_from_source(&name, version_req.as_deref().unwrap_or_default(), source, &state).await;
+        }
+
+        // A locked package goes through the same steps as a resolved one, so
+        // that its own dependencies, binaries and scripts are installed too
+        let is_locked = locked.is_some();
+        let metadata_started = Instant::now();
+        let (version, tarball, checksum, integrity, locked_deps) = match locked {
+            Some((version, tarball, integrity, deps)) => {
+                if reporter::human() {
+                    state.progress.suspend(|| println!("{} Using locked {}", crate::ui::icon(crate::ui::Icons::LOCK), style(format!("{}@{}", name, version)).dim()));
+                }
+                reporter::emit(Event::Resolve { name: &name, version: &version, locked: true, elapsed_ms: 0 });
+                // Without an integrity in the lock, a fresh download is checked against the registry's SHA-1
+                let checksum = if state.force && integrity.is_none() {
+                    shared_metadata(&name, &state.registry_url, &state.client).await.ok()
+                        .and_then(|metadata| Some(metadata.versions.get(&version)?.dist.shasum.clone()))
+                } else {
+                    None
+                };
+                (version, tarball, checksum, integrity, deps)
+            }
+            None => {
+                state.progress.set_message(format!("Resolving {} {}", name, version_req.as_deref().unwrap_or("latest")));
+                let started = Instant::now();
+                let (version, tarball, checksum) = fetch_package_version(&name, &state.registry_url, version_req.as_deref(), &state.client).await?;
+                reporter::emit(Event::Resolve { name: &name, version: &version, locked: false, elapsed_ms: started.elapsed().as_millis() as u64 });
+                (version, tarball, Some(checksum), None, HashMap::new())
+            }
+        };
         
-        // Acquire permit for download slots
-        let _permit = state.semaphore.acquire().await?;
-        download_and_extract(&name, &version, &tarball, &state.client, Some(&checksum)).await?;
-        drop(_permit);
+        // Download slots are shared through crate::throttle. Packages an earlier,
+        // interrupted install finished (scripts included) are left as they are.
+        let resumed = !state.force && state.resume.is_done(&name, &version, checksum.as_deref());
+        let (tarball_checksum, tarball_integrity) = if resumed {
+            state.stats.record(None);
+            (String::new(), None)
+        } else {
+            let mut timing = PackageTiming {
+                name: name.clone(),
+                version: version.clone(),
+                metadata_ms: metadata_started.elapsed().as_millis() as u64,
+                ..Default::default()
+            };
+            let expected = integrity.as_deref().or(checksum.as_deref());
+            let (downloaded, data) = download_and_extract(&state, &name, &version, &tarball, expected, &mut timing).await?;
+            state.stats.record(downloaded);
+            state.stats.record_timing(timing, &state.progress);
+            (format!("{:x}", sha1::Sha1::digest(&data)), Some(crate::safety::integrity_of(&data)))
+        };
 
-        let node_modules = Path::new("node_modules");
+        let node_modules = state.root.join("node_modules");
         // Normalize name for filesystem (handle scoped packages @types/node)
         #[cfg(target_os = "windows")]
         let safe_name = name.replace("/", "\\");
         #[cfg(not(target_os = "windows"))]
-        let safe_name = name.replace("/", "/");
+        let safe_name = name.to_string();
         
         let install_dir = node_modules.join(&safe_name);
         let mut pkg_deps = HashMap::new();
@@ -205,12 +824,14 @@ fn install_package_recursive(name: String, version_req: Option<String>, state: A
                     InstalledPackageJson { 
                         dependencies: HashMap::new(), 
                         scripts: HashMap::new(),
-                        bin: PackageBin::None 
+                        bin: PackageBin::None,
+                        peer_dependencies: HashMap::new(),
+                        peer_dependencies_meta: HashMap::new(),
                     }
                 }
             };
 
-            link_binaries(&name, &pkg_json.bin)?;
+            link_binaries(&node_modules, &name, &pkg_json.bin)?;
 
             // Run scripts (sequentially for now within this task, but we should be careful about concurrency here)
             // Ideally scripts run after all installs, but npm runs them post-extract often.
@@ -220,82 +841,291 @@ fn install_package_recursive(name: String, version_req: Option<String>, state: A
             // To be truly safe, we should probably collect scripts and run them at the end. 
             // But for "speed boost", parallel download is key.
             
-            if let Some(script) = pkg_json.scripts.get("preinstall") {
-                // println!("{} Running preinstall for {}", style("⚙️").yellow(), name);
-                 runner::run_script(script, Some(&install_dir))?;
+            if let Some(script) = pkg_json.scripts.get("preinstall").filter(|_| !resumed) {
+                // println!("{} Running preinstall for {}", style(crate::ui::icon("⚙️")).yellow(), name);
+                 run_lifecycle_script(&state, &name, "preinstall", script, &install_dir)?;
             }
 
-            pkg_deps = pkg_json.dependencies.clone();
+            // The lockfile's graph when it has one, else what the package asks for
+            pkg_deps = if locked_deps.is_empty() { pkg_json.dependencies.clone() } else { locked_deps };
+            state.peers.lock().unwrap().extend(pkg_json.peer_dependencies.iter().map(|(peer, range)| PeerRequirement {
+                package: name.clone(),
+                peer: peer.clone(),
+                range: range.clone(),
+                optional: pkg_json.peer_dependencies_meta.get(peer).is_some_and(|meta| meta.optional),
+            }));
             
             // Spawn parallel tasks for dependencies
-            let mut tasks = tokio::task::JoinSet::new();
-            
-            for (dep_name, dep_ver) in pkg_deps.clone() {
-                let state_clone = state.clone();
-                tasks.spawn(install_package_recursive(dep_name, Some(dep_ver), state_clone));
-            }
+            for_each_dependency(&pkg_deps, state.sequential, |dep_name, dep_ver| {
+                install_package_recursive(dep_name, Some(dep_ver), state.clone())
+            }).await?;
 
-            while let Some(res) = tasks.join_next().await {
-                res??; // Check for JoinError and Result calls
-            }
-
-            if let Some(script) = pkg_json.scripts.get("install") {
-                 runner::run_script(script, Some(&install_dir))?;
-            }
+            // Native addons are built at the end, see `InstallState::build_native`
+            if !resumed && crate::native::detect(&name, &install_dir).is_none() {
+                if let Some(script) = pkg_json.scripts.get("install") {
+                     run_lifecycle_script(&state, &name, "install", script, &install_dir)?;
+                }
 
-            if let Some(script) = pkg_json.scripts.get("postinstall") {
-                 runner::run_script(script, Some(&install_dir))?;
+                if let Some(script) = pkg_json.scripts.get("postinstall") {
+                     run_lifecycle_script(&state, &name, "postinstall", script, &install_dir)?;
+                }
             }
         }
 
         {
             let mut lockfile = state.lockfile.lock().await;
-            lockfile.add_package(name.clone(), version.clone(), tarball.clone(), pkg_deps);
+            match lockfile.dependencies.get_mut(&name).filter(|_| is_locked) {
+                // Keep the locked entry (and its integrity), filling in a missing graph
+                Some(dep) => if dep.dependencies.is_empty() { dep.dependencies = pkg_deps },
+                None => {
+                    lockfile.add_package(name.clone(), version.clone(), tarball.clone(), pkg_deps);
+                    if let Some(dep) = lockfile.dependencies.get_mut(&name) {
+                        dep.registry = Some(state.registry_url.trim_end_matches('/').to_string());
+                        dep.integrity = tarball_integrity;
+                    }
+                }
+            }
         }
-        
+        if !resumed {
+            state.finished(&name, &version, &tarball_checksum);
+        }
+
+        state.progress.inc(1);
         Ok(())
     })
 }
 
-fn link_binaries(pkg_name: &str, bin: &PackageBin) -> Result<()> {
-    let node_modules = Path::new("node_modules");
+/// Install `name` from a directory or git repository, like a published
+/// package: its dependencies, binaries and lifecycle scripts, and `prepare`
+/// to build it from source. `spec` is what the dependent asked for.
+async fn install_from_source(name: &str, spec: &str, source: Source, state: &Arc<InstallState>) -> Result<()> {
+    // A git dependency stays at the commit crabby.lock has for the same
+    // repository and branch, tag or commit
+    let requested = source.committish().map(str::to_string);
+    let source = {
+        let lockfile = state.lockfile.lock().await;
+        lockfile.dependencies.get(name)
+            .filter(|dep| matches!(source, Source::Git { .. }) && dep.installed_from(&source, &state.root))
+            .and_then(|dep| Source::parse(&dep.tarball, &state.root))
+            .unwrap_or(source)
+    };
+    state.progress.set_message(format!("Fetching {}", source));
+    let started = Instant::now();
+    let install_dir = state.root.join("node_modules").join(name);
+    let commit = {
+        let (fetch, install_dir) = (source.clone(), install_dir.clone());
+        tokio::task::spawn_blocking(move || fetch.install_into(&install_dir)).await?
+            .with_context(|| format!("Failed to install {} from {}", name, source))?
+    };
+    let version = crate::explorer::installed_version(&state.root.join("node_modules"), name).unwrap_or_default();
+    reporter::emit(Event::Resolve { name, version: &version, locked: false, elapsed_ms: started.elapsed().as_millis() as u64 });
+    state.stats.record(None);
+
+    let content = fs::read_to_string(install_dir.join("package.json"))?;
+    let pkg_json: InstalledPackageJson = serde_json::from_str(&crate::manifest::clean_json_content(content))
+        .with_context(|| format!("Failed to parse package.json of {}", name))?;
+    link_binaries(&state.root.join("node_modules"), name, &pkg_json.bin)?;
+    if let Some(script) = pkg_json.scripts.get("preinstall") {
+        run_lifecycle_script(state, name, "preinstall", script, &install_dir)?;
+    }
+
+    // Its own file: dependencies are relative to where it came from; the
+    // lock has them relative to the project, like the ones in package.json
+    let base = match &source {
+        Source::File(path) => path.clone(),
+        Source::Git { .. } => install_dir.clone(),
+    };
+    let deps: HashMap<String, String> = pkg_json.dependencies.iter()
+        .map(|(dep, spec)| {
+            let spec = match Source::parse(spec, &base) {
+                Some(Source::File(path)) => format!("file:{}", crate::source::relative_path(&path, &state.root).display()),
+                _ => spec.clone(),
+            };
+            (dep.clone(), spec)
+        })
+        .collect();
+    state.peers.lock().unwrap().extend(pkg_json.peer_dependencies.iter().map(|(peer, range)| PeerRequirement {
+        package: name.to_string(),
+        peer: peer.clone(),
+        range: range.clone(),
+        optional: pkg_json.peer_dependencies_meta.get(peer).is_some_and(|meta| meta.optional),
+    }));
+    for_each_dependency(&deps, state.sequential, |dep_name, dep_spec| {
+        install_package_recursive(dep_name, Some(dep_spec), state.clone())
+    }).await?;
+
+    // Dependencies are in place, so `prepare` can build it
+    for script_name in ["prepare", "install", "postinstall"] {
+        if let Some(script) = pkg_json.scripts.get(script_name) {
+            run_lifecycle_script(state, name, script_name, script, &install_dir)?;
+        }
+    }
+
+    let tarball = match &source {
+        Source::File(_) => spec.to_string(),
+        Source::Git { .. } => source.lock_spec(commit.as_deref()),
+    };
+    let mut lockfile = state.lockfile.lock().await;
+    lockfile.add_package(name.to_string(), version, tarball, deps);
+    if let Some(dep) = lockfile.dependencies.get_mut(name) {
+        dep.committish = requested;
+    }
+    state.progress.inc(1);
+    Ok(())
+}
+
+fn run_lifecycle_script(state: &InstallState, name: &str, script_name: &str, command: &str, dir: &Path) -> Result<()> {
+    let started = Instant::now();
+    // Tools the package depends on are linked into the project's .bin
+    let bin_dir = state.root.join("node_modules").join(".bin");
+    let result = state.progress.suspend(|| runner::run_script_with_bin(command, dir, &bin_dir));
+    reporter::emit(Event::Script {
+        name,
+        script: script_name,
+        success: result.is_ok(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    });
+    result
+}
+
+pub fn link_binaries(node_modules: &Path, pkg_name: &str, bin: &PackageBin) -> Result<()> {
+    crate::safety::validate_package_name(pkg_name)?;
+    write_shims(node_modules, pkg_name, pkg_name, bin)
+}
+
+/// Where the project's own bins are, seen from its node_modules
+const PROJECT_DIR: &str = "..";
+
+/// The .bin shims written for the project's own bins and its workspaces' bins,
+/// by package directory, so the ones they stop declaring can be removed
+const LINKED_BINS_FILE: &str = ".crabby-linked-bins.json";
+
+fn load_linked_bins(node_modules: &Path) -> HashMap<String, Vec<String>> {
+    fs::read_to_string(node_modules.join(LINKED_BINS_FILE)).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_linked_bins(node_modules: &Path, linked: &HashMap<String, Vec<String>>) -> Result<()> {
+    if linked.is_empty() {
+        let _ = fs::remove_file(node_modules.join(LINKED_BINS_FILE));
+        return Ok(());
+    }
+    fs::create_dir_all(node_modules)?;
+    fs::write(node_modules.join(LINKED_BINS_FILE), serde_json::to_string_pretty(linked)?)?;
+    Ok(())
+}
+
+/// Remove the shims of `names` that no other linked package still declares
+fn remove_shims(node_modules: &Path, names: &[String], linked: &HashMap<String, Vec<String>>) -> Result<()> {
     let bin_dir = node_modules.join(".bin");
-    if !bin_dir.exists() {
-        fs::create_dir_all(&bin_dir)?;
+    for name in names {
+        if linked.values().any(|bins| bins.contains(name)) {
+            continue;
+        }
+        for file in [bin_dir.join(name), bin_dir.join(format!("{}.cmd", name))] {
+            if file.is_file() {
+                fs::remove_file(file)?;
+            }
+        }
     }
+    Ok(())
+}
 
-    let links = match bin {
-        PackageBin::String(path) => {
-            let mut map = HashMap::new();
-            map.insert(pkg_name.to_string(), path.clone());
-            map
-        },
+/// Link the bins of the project or workspace at `package_dir` (seen from
+/// `node_modules`) into node_modules/.bin and remove the shims of bins it
+/// declared at the last link but no longer does
+fn relink_local_binaries(node_modules: &Path, pkg_name: &str, package_dir: &str, bin: &PackageBin) -> Result<()> {
+    let mut linked = load_linked_bins(node_modules);
+    let mut declared: Vec<String> = bin_links(pkg_name, bin).into_keys().collect();
+    declared.sort();
+    let before = linked.remove(package_dir).unwrap_or_default();
+    if !declared.is_empty() {
+        linked.insert(package_dir.to_string(), declared.clone());
+    }
+    let dropped: Vec<String> = before.into_iter().filter(|name| !declared.contains(name)).collect();
+    remove_shims(node_modules, &dropped, &linked)?;
+    if !declared.is_empty() {
+        write_shims(node_modules, pkg_name, package_dir, bin)?;
+    }
+    save_linked_bins(node_modules, &linked)
+}
+
+/// Link the bins the project in `root` declares itself into its
+/// node_modules/.bin, so its scripts can call them by name. Shims of bins it
+/// no longer declares are removed.
+pub fn link_project_binaries(root: &Path, pkg: &crate::manifest::PackageJson) -> Result<()> {
+    relink_local_binaries(&root.join("node_modules"), &pkg.name, PROJECT_DIR, &pkg.bin)
+}
+
+/// Link the bins of the workspace `name` into the monorepo's node_modules/.bin,
+/// removing the shims of bins it no longer declares
+pub fn link_workspace_binaries(node_modules: &Path, name: &str, bin: &PackageBin) -> Result<()> {
+    crate::safety::validate_package_name(name)?;
+    relink_local_binaries(node_modules, name, name, bin)
+}
+
+/// Remove the shims of workspaces that are no longer among `workspaces`
+pub fn unlink_removed_workspace_binaries(node_modules: &Path, workspaces: &[&str]) -> Result<()> {
+    let mut linked = load_linked_bins(node_modules);
+    let removed: Vec<String> = linked.keys()
+        .filter(|dir| dir.as_str() != PROJECT_DIR && !workspaces.contains(&dir.as_str()))
+        .cloned()
+        .collect();
+    if removed.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = removed.iter().flat_map(|dir| linked.remove(dir).unwrap_or_default()).collect();
+    remove_shims(node_modules, &names, &linked)?;
+    save_linked_bins(node_modules, &linked)
+}
+
+/// Command name to file for `bin`; a plain string is a command named after the package
+fn bin_links(pkg_name: &str, bin: &PackageBin) -> HashMap<String, String> {
+    match bin {
+        PackageBin::String(path) => HashMap::from([(pkg_name.to_string(), path.clone())]),
         PackageBin::Map(map) => map.clone(),
-        PackageBin::None => return Ok(()),
-    };
+        PackageBin::None => HashMap::new(),
+    }
+}
 
-    for (bin_name, file_path) in links {
+/// A shim running `file` of the package at `package_dir`, relative to node_modules
+fn shim_content(package_dir: &str, file: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("@ECHO OFF\r\nnode \"%~dp0\\..\\{}\\{}\" %*", package_dir, file)
+    } else {
+        format!("#!/bin/sh\nexec node \"$0/../../{}/{}\" \"$@\"", package_dir, file)
+    }
+}
+
+/// Write a .bin shim for each of `bin`, run from the package at `package_dir`
+fn write_shims(node_modules: &Path, pkg_name: &str, package_dir: &str, bin: &PackageBin) -> Result<()> {
+    let bin_dir = node_modules.join(".bin");
+    if !bin_dir.exists() {
+        fs::create_dir_all(&bin_dir)?;
+    }
+
+    for (bin_name, file_path) in bin_links(pkg_name, bin) {
+        // A bin name is a file in .bin, and the shim must not point outside the package
+        let escapes = |p: &str| p.split(['/', '\\']).any(|part| part == "..");
+        if bin_name.is_empty() || bin_name == "." || bin_name.contains(['/', '\\']) || escapes(&bin_name) || escapes(&file_path) {
+            crate::ui::print_warning(&format!("Skipping bin '{}' of {}: invalid name or path", bin_name.escape_debug(), pkg_name));
+            continue;
+        }
         let target = bin_dir.join(&bin_name);
+        let shim_content = shim_content(package_dir, &file_path);
         
         #[cfg(target_os = "windows")]
         {
-            let shim_content = format!(
-                "@ECHO OFF\r\nnode \"%~dp0\\..\\{}\\{}\" %*", 
-                pkg_name, file_path
-            );
             fs::write(target.with_extension("cmd"), shim_content)?;
         }
 
         #[cfg(not(target_os = "windows"))]
         {
              use std::os::unix::fs::PermissionsExt;
-             let shim_content = format!(
-                "#!/bin/sh\nexec node \"$0/../../{}/{}\" \"$@\"",
-                pkg_name, file_path
-             );
              fs::write(&target, shim_content)?;
              // Permissions need to be set on unix
-             if let Ok(mut perms) = fs::metadata(&target).and_then(|m| Ok(m.permissions())) {
+             if let Ok(mut perms) = fs::metadata(&target).map(|m| m.permissions()) {
                  perms.set_mode(0o755);
                  let _ = fs::set_permissions(&target, perms);
              }
@@ -304,117 +1134,745 @@ fn link_binaries(pkg_name: &str, bin: &PackageBin) -> Result<()> {
     Ok(())
 }
 
-pub async fn download_and_extract(name: &str, version: &str, tarball_url: &str, client: &reqwest::Client, expected_checksum: Option<&str>) -> Result<()> {
-    use crate::config::get_cache_dir;
+/// Download `name`@`version` into the cache, unless it's there already, and
+/// extract it into node_modules. `expected` is the registry's SHA-1 or the
+/// lock's integrity; a tarball that doesn't match it is removed from the cache
+/// and fails the install. How long each step took goes into `timing`.
+/// Returns the bytes fetched from the network (`None` on a cache hit) and the tarball.
+async fn download_and_extract(state: &InstallState, name: &str, version: &str, tarball_url: &str, expected: Option<&str>, timing: &mut PackageTiming) -> Result<(Option<u64>, Vec<u8>)> {
+    crate::safety::validate_package_name(name)?;
+    let progress = &state.progress;
     
-    let cache_key = format!("{}-{}.tgz", name.replace("/", "-"), version);
-    let cache_dir = get_cache_dir()?;
-    let cached_file = cache_dir.join(&cache_key);
+    let cached_file = state.cache_dir.join(tarball_file_name(name, version));
+    // Concurrent installs (one per workspace) share the cache: one downloads, the others wait for it
+    let download_lock = {
+        static DOWNLOADS: std::sync::OnceLock<std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> = std::sync::OnceLock::new();
+        DOWNLOADS.get_or_init(Default::default).lock().unwrap().entry(cached_file.clone()).or_default().clone()
+    };
+    let download_guard = download_lock.lock().await;
     
-    let tar_gz_data = if cached_file.exists() {
-        // println!("{} Using cached tarball for {}", style("📦").dim(), name);
+    let started = Instant::now();
+    // --force downloads each cached tarball once more
+    let refresh = state.force && cached_file.exists() && state.stats.refreshed.lock().unwrap().insert(cached_file.clone());
+    let from_cache = cached_file.exists() && !refresh;
+    let tar_gz_data = if from_cache {
+        // println!("{} Using cached tarball for {}", style(crate::ui::icon("📦")).dim(), name);
         fs::read(&cached_file)?
     } else {
-        println!("{} Downloading {}", crate::ui::Icons::DOWNLOAD, style(name).cyan());
-        let response = client.get(tarball_url)
-            .send()
+        if reporter::human() {
+            progress.suspend(|| println!("{} Downloading {}", crate::ui::icon(crate::ui::Icons::DOWNLOAD), style(format!("{}@{}", name, version)).cyan()));
+        }
+        // Downloads land in a .part file that a later run can resume
+        let part = cached_file.with_extension("tgz.part");
+        if refresh && part.exists() {
+            fs::remove_file(&part)?;
+        }
+        let resumed = crate::throttle::download_resumable(&state.client, tarball_url, &part)
             .await
-            .context("Failed to download tarball")?
-            .error_for_status()?;
-        
-        let bytes = response.bytes().await?.to_vec();
-        fs::write(&cached_file, &bytes)?;
-        bytes
+            .context("Failed to download tarball")?;
+        // Bytes from an earlier run may not belong to the same tarball; start over once
+        let corrupt = |part: &Path| expected.is_some_and(|expected| {
+            !expected.is_empty() && fs::read(part).is_ok_and(|data| matches!(crate::safety::matches_integrity(&data, expected), Ok(false)))
+        });
+        if resumed && corrupt(&part) {
+            fs::remove_file(&part)?;
+            crate::throttle::download_resumable(&state.client, tarball_url, &part)
+                .await
+                .context("Failed to download tarball")?;
+        }
+        fs::rename(&part, &cached_file)?;
+        fs::read(&cached_file)?
     };
+    drop(download_guard);
+    timing.download_ms = started.elapsed().as_millis() as u64;
+    timing.cached = from_cache;
 
-    if let Some(expected) = expected_checksum {
-        if !expected.is_empty() {
-             match crate::safety::verify_checksum(&cached_file, Some(expected)) {
-                Ok(true) => {
-                    // Verified
-                },
-                Ok(false) => {
-                    println!("{} {} Checksum mismatch for package '{}'", 
-                        style("⚠️").yellow(), 
-                        style("WARNING:").bold().yellow(),
-                        name
-                    );
-                },
-                Err(e) => {
-                    println!("{} Could not verify checksum: {}", style("⚠️").yellow(), e);
-                }
+    let checksum_started = Instant::now();
+    if let Some(expected) = expected.filter(|expected| !expected.is_empty()) {
+        match crate::safety::matches_integrity(&tar_gz_data, expected) {
+            Ok(true) => {}
+            Ok(false) => {
+                // Whether the download or the cache went bad, the next install fetches it again
+                let _ = fs::remove_file(&cached_file);
+                return Err(failure(ErrorKind::Integrity, format!(
+                    "Checksum mismatch for {}@{}: the tarball from {} isn't the one expected (removed it from the cache)",
+                    name, version, if from_cache { "the cache" } else { tarball_url }
+                )));
+            }
+            Err(e) => {
+                progress.suspend(|| crate::ui::print_warning(&format!("Could not verify checksum of {}: {}", name, e)));
             }
         }
     }
 
-    let tar_gz = GzDecoder::new(&tar_gz_data[..]);
-    let mut archive = Archive::new(tar_gz);
+    timing.checksum_ms = checksum_started.elapsed().as_millis() as u64;
+
+    let downloaded = if from_cache { None } else { Some(tar_gz_data.len() as u64) };
+    reporter::emit(Event::Download {
+        name,
+        version,
+        bytes: downloaded.unwrap_or(0),
+        cached: from_cache,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    });
+    let started = Instant::now();
 
-    let node_modules = Path::new("node_modules");
+    let node_modules = state.root.join("node_modules");
     if !node_modules.exists() {
-        fs::create_dir_all(node_modules)?;
+        fs::create_dir_all(&node_modules)?;
     }
     
     // Normalize name for filesystem (handle scoped packages @types/node)
     #[cfg(target_os = "windows")]
     let safe_name = name.replace("/", "\\");
     #[cfg(not(target_os = "windows"))]
-    let safe_name = name.replace("/", "/");
+    let safe_name = name.to_string();
 
-    let target_dir = node_modules.join(&safe_name);
+    let target_dir = crate::fs_utils::extended_path(&node_modules.join(&safe_name));
     if target_dir.exists() {
-        fs::remove_dir_all(&target_dir)?;
+        crate::fs_utils::remove_dir_all(&target_dir)?;
     }
-    fs::create_dir_all(&target_dir)?;
+
+    if state.use_store {
+        let entry = crate::store::ensure_extracted(&crate::store::store_dir()?, &tar_gz_data)?;
+        crate::store::link_tree(&entry, &target_dir)?;
+    } else {
+        extract_tarball(&tar_gz_data, &target_dir)?;
+    }
+    timing.extract_ms = started.elapsed().as_millis() as u64;
+    reporter::emit(Event::Extract { name, version, elapsed_ms: timing.extract_ms });
+    Ok((downloaded, tar_gz_data))
+}
+
+/// Unpack a package tarball into `target_dir`, dropping the folder every entry
+/// is under (`package/` in npm tarballs)
+pub fn extract_tarball(tar_gz_data: &[u8], target_dir: &Path) -> Result<()> {
+    extract_tarball_from(std::io::Cursor::new(tar_gz_data), target_dir)
+}
+
+/// `extract_tarball` reading the .tar.gz from `reader`, which is read twice:
+/// once to find the root folder and once to unpack
+pub fn extract_tarball_from<R: std::io::Read + std::io::Seek>(mut reader: R, target_dir: &Path) -> Result<()> {
+    let root = tarball_root(&mut reader)?;
+    reader.seek(std::io::SeekFrom::Start(0))?;
+    let mut archive = Archive::new(GzDecoder::new(reader));
+    fs::create_dir_all(target_dir)?;
 
     for entry in archive.entries()? {
         let mut entry = entry?;
-        let path = entry.path()?.to_path_buf();
-        
-        let mut components = path.components();
-        let _root = components.next();
-        let relative_path = components.as_path();
+        let Some(path) = file_entry_path(&entry)? else {
+            continue;
+        };
+        let relative = root.as_deref().and_then(|root| path.strip_prefix(root).ok()).unwrap_or(&path);
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
 
-        if relative_path.as_os_str().is_empty() {
-             continue; 
+        // Nothing is written through a symlink an earlier entry made
+        if writes_through_symlink(target_dir, relative) {
+            continue;
         }
 
-        let extract_path = target_dir.join(relative_path);
+        // One component at a time: `\\?\` paths don't get `/` turned into `\`
+        let extract_path = relative.components().fold(target_dir.to_path_buf(), |path, part| path.join(part));
         if let Some(parent) = extract_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        entry.unpack(&extract_path)?;
+
+        let kind = entry.header().entry_type();
+        if kind.is_hard_link() {
+            // A hard link names another entry of the same archive
+            let Some(source) = entry.link_name()?.and_then(|link| normalize_entry_path(&link)) else {
+                continue;
+            };
+            let source = root.as_deref().and_then(|root| source.strip_prefix(root).ok()).unwrap_or(&source);
+            let source = target_dir.join(source);
+            if !source.is_file() {
+                continue;
+            }
+            remove_existing(&extract_path)?;
+            if fs::hard_link(&source, &extract_path).is_err() {
+                fs::copy(&source, &extract_path)?;
+            }
+        } else if kind.is_symlink() {
+            let Some(link) = entry.link_name()? else {
+                continue;
+            };
+            if !symlink_stays_inside(relative, &link) {
+                continue;
+            }
+            remove_existing(&extract_path)?;
+            // Windows only allows symlinks with developer mode or as admin
+            if let Err(e) = entry.unpack(&extract_path) {
+                if !cfg!(windows) {
+                    return Err(e.into());
+                }
+            }
+        } else {
+            let mode = entry.header().mode().ok();
+            entry.unpack(&extract_path)?;
+            #[cfg(unix)]
+            normalize_mode(&extract_path, mode, kind.is_dir())?;
+            #[cfg(not(unix))]
+            let _ = mode;
+        }
     }
     Ok(())
 }
 
-pub async fn install_all_packages(deps: &HashMap<String, String>, registry_url: &str, client: &reqwest::Client, lockfile: crate::manifest::CrabbyLock) -> Result<crate::manifest::CrabbyLock> {
-    let state = Arc::new(InstallState {
-        visited: Mutex::new(HashSet::new()),
-        package_locks: Mutex::new(HashMap::new()),
-        lockfile: Mutex::new(lockfile),
-        client: client.clone(),
-        registry_url: registry_url.to_string(),
-        semaphore: Semaphore::new(crate::MAX_CONCURRENT_DOWNLOADS),
-    });
+/// Whether a parent folder of `relative` under `target_dir` is a symlink
+fn writes_through_symlink(target_dir: &Path, relative: &Path) -> bool {
+    relative.ancestors().skip(1)
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .any(|parent| fs::symlink_metadata(target_dir.join(parent)).is_ok_and(|m| m.file_type().is_symlink()))
+}
 
-    let mut tasks = tokio::task::JoinSet::new();
-    
+/// Whether a symlink at `relative` pointing to `link` stays inside the package
+fn symlink_stays_inside(relative: &Path, link: &Path) -> bool {
+    use std::path::Component;
+
+    let mut depth = relative.components().count().saturating_sub(1);
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Make room for an entry that replaces whatever an earlier entry put at `path`
+fn remove_existing(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+/// Keep the archive's permissions, executable bits included, but like npm make
+/// everything readable, folders traversable and nothing writable by others
+#[cfg(unix)]
+fn normalize_mode(path: &Path, mode: Option<u32>, is_dir: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let floor = if is_dir { 0o755 } else { 0o644 };
+    let mode = (mode.unwrap_or(floor) & 0o755) | floor;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// The folder every file in the tarball is under, if there is one. npm always
+/// packs into `package/`, but other tools pick their own name or none at all.
+fn tarball_root(reader: impl std::io::Read) -> Result<Option<PathBuf>> {
+    let mut archive = Archive::new(GzDecoder::new(reader));
+    let mut root: Option<PathBuf> = None;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let Some(path) = file_entry_path(&entry)? else {
+            continue;
+        };
+        let mut components = path.components();
+        let Some(first) = components.next() else {
+            continue;
+        };
+        // A file next to the folders means there's no root to drop
+        if components.next().is_none() && !entry.header().entry_type().is_dir() {
+            return Ok(None);
+        }
+        match &root {
+            None => root = Some(PathBuf::from(first.as_os_str())),
+            Some(root) if root.as_os_str() != first.as_os_str() => return Ok(None),
+            Some(_) => {}
+        }
+    }
+    Ok(root)
+}
+
+/// The path of a tarball entry that holds a file or folder, without `./`.
+/// `None` for tar metadata like `pax_global_header`, and for paths that
+/// would escape the target folder.
+fn file_entry_path<R: std::io::Read>(entry: &tar::Entry<R>) -> Result<Option<PathBuf>> {
+    if matches!(entry.header().entry_type(), tar::EntryType::XGlobalHeader | tar::EntryType::XHeader) {
+        return Ok(None);
+    }
+    let path = entry.path()?;
+    if path.as_os_str() == "pax_global_header" {
+        return Ok(None);
+    }
+    Ok(normalize_entry_path(&path))
+}
+
+/// `path` without `./`, or `None` if it's absolute or climbs out with `..`
+fn normalize_entry_path(path: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Install every entry of `deps` into `root`/node_modules
+pub async fn install_all_packages(root: &Path, deps: &HashMap<String, String>, registry_url: &str, client: &reqwest::Client, lockfile: crate::manifest::CrabbyLock, stats: &Arc<InstallStats>) -> Result<crate::manifest::CrabbyLock> {
+    install_all(Arc::new(InstallState::new(root, lockfile, client, registry_url, stats)?), deps).await
+}
+
+/// Install `deps` (name to version range) with `state` and wrap up
+async fn install_all(state: Arc<InstallState>, deps: &HashMap<String, String>) -> Result<crate::manifest::CrabbyLock> {
     if deps.is_empty() {
         return Ok(state.lockfile.lock().await.clone());
     }
 
-    for (name, version_req) in deps {
-        let state_clone = state.clone();
-        let name = name.clone();
-        let version_req = version_req.clone();
-        tasks.spawn(install_package_recursive(name, Some(version_req), state_clone));
+    let result = for_each_dependency(deps, state.sequential, |name, version_req| {
+        install_package_recursive(name, Some(version_req), state.clone())
+    }).await;
+    state.complete(result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sequential_installs_in_sorted_order() {
+        let deps: HashMap<String, String> = ["zod", "express", "lodash", "axios"].iter()
+            .map(|name| (name.to_string(), "*".to_string()))
+            .collect();
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        for_each_dependency(&deps, true, |name, _| {
+            let order = order.clone();
+            async move {
+                // Later names would finish first if they didn't wait their turn
+                let delay = 40 - 10 * ["axios", "express", "lodash", "zod"].iter().position(|n| *n == name).unwrap() as u64;
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                order.lock().unwrap().push(name);
+                Ok(())
+            }
+        }).await.unwrap();
+
+        assert_eq!(*order.lock().unwrap(), ["axios", "express", "lodash", "zod"]);
     }
 
-    while let Some(res) = tasks.join_next().await {
-        res??;
+    #[tokio::test]
+    async fn test_locked_install_brings_transitive_dependencies() {
+        let package = |name: &str, deps: &str| {
+            let manifest = format!(r#"{{"name": "{}", "version": "1.0.0", "dependencies": {}}}"#, name, deps);
+            crate::test_utils::tarball(&[("package.json", &manifest)])
+        };
+        let tarballs = HashMap::from([
+            ("lock-tree-app", package("lock-tree-app", r#"{"lock-tree-mid": "^1.0.0"}"#)),
+            ("lock-tree-mid", package("lock-tree-mid", r#"{"lock-tree-leaf": "^1.0.0"}"#)),
+            ("lock-tree-leaf", package("lock-tree-leaf", "{}")),
+        ]);
+
+        // Only tarballs: any metadata request means the lockfile wasn't used
+        let registry = crate::test_utils::serve(move |path| {
+            path.strip_prefix("/-/").and_then(|file| tarballs.get(file.trim_end_matches(".tgz"))).cloned()
+        });
+
+        // A fresh clone: crabby.lock but no node_modules. The lock has the
+        // app's graph but not mid's, which comes from its package.json.
+        let mut lockfile = crate::manifest::CrabbyLock::default();
+        let tarball = |name: &str| format!("{}/-/{}.tgz", registry, name);
+        lockfile.add_package("lock-tree-app".into(), "1.0.0".into(), tarball("lock-tree-app"), HashMap::from([("lock-tree-mid".to_string(), "^1.0.0".to_string())]));
+        lockfile.add_package("lock-tree-mid".into(), "1.0.0".into(), tarball("lock-tree-mid"), HashMap::new());
+        lockfile.add_package("lock-tree-leaf".into(), "1.0.0".into(), tarball("lock-tree-leaf"), HashMap::new());
+
+        // The leaf's integrity was recorded when it was first installed
+        lockfile.dependencies.get_mut("lock-tree-leaf").unwrap().integrity = Some(crate::safety::integrity_of(&package("lock-tree-leaf", "{}")));
+
+        let root = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let deps = HashMap::from([("lock-tree-app".to_string(), "^1.0.0".to_string())]);
+        let state = state_with_cache(root.path(), cache.path(), lockfile.clone(), &registry);
+        let installed = install_all(state, &deps).await.unwrap();
+
+        let node_modules = root.path().join("node_modules");
+        for name in ["lock-tree-app", "lock-tree-mid", "lock-tree-leaf"] {
+            assert_eq!(crate::explorer::installed_version(&node_modules, name).as_deref(), Some("1.0.0"), "{}", name);
+        }
+        assert!(installed.dependencies["lock-tree-mid"].dependencies.contains_key("lock-tree-leaf"));
+
+        // A cached tarball that no longer matches the lock's integrity is refused and dropped
+        let other = tempfile::tempdir().unwrap();
+        let leaf = cache.path().join("lock-tree-leaf-1.0.0.tgz");
+        fs::write(&leaf, package("lock-tree-leaf", r#"{"evil": "*"}"#)).unwrap();
+        let state = state_with_cache(other.path(), cache.path(), lockfile, &registry);
+        let err = install_all(state, &deps).await.unwrap_err();
+        assert_eq!(crate::error::classify(&err), ErrorKind::Integrity, "{:#}", err);
+        assert!(!leaf.exists());
+    }
+
+    /// An install into `root` that caches tarballs in `cache`
+    fn state_with_cache(root: &Path, cache: &Path, lockfile: crate::manifest::CrabbyLock, registry: &str) -> Arc<InstallState> {
+        let mut state = InstallState::new(root, lockfile, &reqwest::Client::new(), registry, &InstallStats::new()).unwrap();
+        state.cache_dir = cache.to_path_buf();
+        Arc::new(state)
+    }
+
+    #[tokio::test]
+    async fn test_force_downloads_and_extracts_again() {
+        let manifest = r#"{"name": "force-demo", "version": "1.0.0"}"#;
+        let tarball = crate::test_utils::tarball(&[("package.json", manifest)]);
+        let shasum = format!("{:x}", sha1::Sha1::digest(&tarball));
+        let expected = shasum.clone();
+
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let counter = downloads.clone();
+        let registry = crate::test_utils::serve_with_base(move |registry, path| {
+            if path.ends_with(".tgz") {
+                counter.fetch_add(1, Ordering::SeqCst);
+                return Some(tarball.clone());
+            }
+            Some(format!(r#"{{"name": "force-demo", "dist-tags": {{"latest": "1.0.0"}}, "versions": {{"1.0.0": {{"version": "1.0.0", "dist": {{"tarball": "{}/force-demo.tgz", "shasum": "{}"}}}}}}}}"#,
+                registry, expected).into_bytes())
+        });
+
+        let root = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let cached = cache.path().join("force-demo-1.0.0.tgz");
+        let client = reqwest::Client::new();
+        let deps = HashMap::from([("force-demo".to_string(), "^1.0.0".to_string())]);
+        let lockfile = install_all(state_with_cache(root.path(), cache.path(), Default::default(), &registry), &deps).await.unwrap();
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+        // A crash left a truncated tarball in the cache and a half-written package
+        fs::write(&cached, b"truncated").unwrap();
+        let installed = root.path().join("node_modules/force-demo/package.json");
+        fs::write(&installed, "{").unwrap();
+
+        let forced_install = |lockfile: crate::manifest::CrabbyLock| {
+            let stats = InstallStats::new();
+            let mut state = InstallState::new(root.path(), lockfile, &client, &registry, &stats).unwrap();
+            state.force = true;
+            state.cache_dir = cache.path().to_path_buf();
+            let state = Arc::new(state);
+            async move {
+                let result = install_package_recursive("force-demo".to_string(), Some("^1.0.0".to_string()), state.clone()).await;
+                (state.complete(result).await, stats)
+            }
+        };
+        let (forced, stats) = forced_install(lockfile.clone()).await;
+        let forced = forced.unwrap();
+
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+        assert_eq!(stats.refreshed.lock().unwrap().len(), 1);
+        assert_eq!(format!("{:x}", sha1::Sha1::digest(fs::read(&cached).unwrap())), shasum);
+        assert_eq!(fs::read_to_string(&installed).unwrap(), manifest);
+        assert_eq!(forced.to_json().unwrap(), lockfile.to_json().unwrap());
+
+        // A download that doesn't match the lock's integrity fails and leaves nothing in the cache
+        let mut tampered = lockfile.clone();
+        tampered.dependencies.get_mut("force-demo").unwrap().integrity = Some(crate::safety::integrity_of(b"something else"));
+        let (result, _) = forced_install(tampered).await;
+        let err = result.unwrap_err();
+        assert_eq!(crate::error::classify(&err), ErrorKind::Integrity, "{:#}", err);
+        assert_eq!(downloads.load(Ordering::SeqCst), 3);
+        assert!(!cached.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_tarball_keeps_modes_and_links() {
+        use flate2::write::GzEncoder;
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut add = |path: &str, kind: tar::EntryType, mode: u32, link: Option<&str>, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_mode(mode);
+            header.set_size(data.len() as u64);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            builder.append_data(&mut header, path, data).unwrap();
+        };
+        let script = b"#!/usr/bin/env node\nconsole.log('hi')\n";
+        add("package/bin/cli.js", tar::EntryType::Regular, 0o755, None, script);
+        add("package/lib/private.js", tar::EntryType::Regular, 0o600, None, b"module.exports = 1;");
+        add("package/cli", tar::EntryType::Symlink, 0o777, Some("bin/cli.js"), b"");
+        add("package/lib/same.js", tar::EntryType::Link, 0o644, Some("package/lib/private.js"), b"");
+        add("package/escape", tar::EntryType::Symlink, 0o777, Some("../../etc/passwd"), b"");
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        extract_tarball(&tarball, dir.path()).unwrap();
+        let mode = |path: &str| fs::metadata(dir.path().join(path)).unwrap().permissions().mode() & 0o777;
+
+        assert_eq!(mode("bin/cli.js"), 0o755);
+        assert_eq!(mode("lib/private.js"), 0o644);
+        assert_eq!(fs::read_link(dir.path().join("cli")).unwrap(), Path::new("bin/cli.js"));
+        assert_eq!(fs::read(dir.path().join("cli")).unwrap(), script);
+        assert_eq!(fs::read_to_string(dir.path().join("lib/same.js")).unwrap(), "module.exports = 1;");
+        assert!(fs::symlink_metadata(dir.path().join("escape")).is_err());
+    }
+
+    #[test]
+    fn test_project_bins_are_linked_and_stale_ones_removed() {
+        let root = tempfile::tempdir().unwrap();
+        let node_modules = root.path().join("node_modules");
+        let shim = |name: &str| node_modules.join(".bin").join(if cfg!(windows) { format!("{}.cmd", name) } else { name.to_string() });
+        link_binaries(&node_modules, "dep", &PackageBin::String("cli.js".into())).unwrap();
+
+        let mut pkg = crate::manifest::PackageJson { name: "my-tool".into(), ..Default::default() };
+        pkg.bin = PackageBin::Map(HashMap::from([("my-tool".into(), "bin/cli.js".into()), ("helper".into(), "bin/helper.js".into())]));
+        link_project_binaries(root.path(), &pkg).unwrap();
+        let content = fs::read_to_string(shim("my-tool")).unwrap();
+        assert_eq!(content, shim_content("..", "bin/cli.js"));
+        assert!(shim("helper").is_file());
+
+        pkg.bin = PackageBin::String("bin/cli.js".into());
+        link_project_binaries(root.path(), &pkg).unwrap();
+        assert!(shim("my-tool").is_file());
+        assert!(!shim("helper").exists());
+        // Dependency shims aren't the project's to remove
+        assert!(shim("dep").is_file());
+    }
+
+    #[test]
+    fn test_workspace_bins_are_relinked_and_unlinked() {
+        let root = tempfile::tempdir().unwrap();
+        let node_modules = root.path().join("node_modules");
+        let shim = |name: &str| node_modules.join(".bin").join(if cfg!(windows) { format!("{}.cmd", name) } else { name.to_string() });
+        link_binaries(&node_modules, "dep", &PackageBin::String("cli.js".into())).unwrap();
+
+        let both = PackageBin::Map(HashMap::from([("ws-cli".into(), "cli.js".into()), ("ws-gen".into(), "gen.js".into())]));
+        link_workspace_binaries(&node_modules, "@acme/ws", &both).unwrap();
+        link_workspace_binaries(&node_modules, "other-ws", &PackageBin::String("main.js".into())).unwrap();
+        assert_eq!(fs::read_to_string(shim("ws-gen")).unwrap(), shim_content("@acme/ws", "gen.js"));
+
+        // A bin the workspace drops goes
+        link_workspace_binaries(&node_modules, "@acme/ws", &PackageBin::Map(HashMap::from([("ws-cli".into(), "cli.js".into())]))).unwrap();
+        assert!(shim("ws-cli").is_file());
+        assert!(!shim("ws-gen").exists());
+
+        // So do the bins of a workspace that left the monorepo
+        unlink_removed_workspace_binaries(&node_modules, &["@acme/ws"]).unwrap();
+        assert!(!shim("other-ws").exists());
+        assert!(shim("ws-cli").is_file());
+        assert!(shim("dep").is_file());
+    }
+
+    #[test]
+    fn test_timings_add_up_per_phase() {
+        let stats = InstallStats::new();
+        let timing = |name: &str, download_ms, extract_ms, cached| PackageTiming {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            metadata_ms: 10,
+            download_ms,
+            checksum_ms: 1,
+            extract_ms,
+            cached,
+        };
+        for t in [timing("fast", 0, 5, true), timing("slow", 900, 40, false), timing("medium", 200, 30, false)] {
+            stats.record_timing(t, &ProgressBar::hidden());
+        }
+
+        let slowest: Vec<(String, u64)> = stats.slowest(2).into_iter().map(|t| (t.name.clone(), t.total_ms())).collect();
+        assert_eq!(slowest, [("slow".to_string(), 951), ("medium".to_string(), 241)]);
+        assert_eq!(stats.phase_totals(), PhaseTotals {
+            metadata_ms: 30,
+            download_ms: 1100,
+            checksum_ms: 3,
+            extract_ms: 75,
+            cache_hits: 1,
+            cache_misses: 2,
+        });
+    }
+
+    #[test]
+    fn test_summary_counts_added_and_reused() {
+        let stats = InstallStats::new();
+        stats.record(Some(3 * 1024));
+        stats.record(Some(1536));
+        stats.record(None);
+
+        let summary = console::strip_ansi_codes(&stats.summary(1200)).to_string();
+        assert!(summary.ends_with("Installed 3 packages in 1.2s (2 added, 1 reused from cache, 4.5 KB downloaded)"), "{}", summary);
+    }
+
+    #[test]
+    fn test_missing_version_suggests_closest() {
+        let versions: Vec<semver::Version> = ["1.0.0", "1.2.0", "1.2.5", "2.0.0", "3.1.0"].iter().map(|v| semver::Version::parse(v).unwrap()).collect();
+        let closest = |requested: &str| closest_versions(&versions, requested, 3).iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(closest("1.2.3"), ["1.2.5", "1.2.0", "1.0.0"]);
+        assert_eq!(closest("^9.0.0"), ["3.1.0", "2.0.0", "1.0.0"]);
+        assert_eq!(closest(">=4 <5"), ["3.1.0", "2.0.0", "1.2.5"]);
+        assert!(closest_versions(&[], "1.0.0", 3).is_empty());
+
+        let metadata: PackageMetadata = serde_json::from_str(r#"{"versions": {"1.2.0": {"dist": {"tarball": "t", "shasum": ""}}}, "dist-tags": {"latest": "1.2.0"}}"#).unwrap();
+        let error = resolve_version(&metadata, "pkg", Some("^2.0.0")).unwrap_err().to_string();
+        assert!(error.ends_with("Closest available versions: 1.2.0"), "{}", error);
+
+        let unpublished: PackageMetadata = serde_json::from_str(r#"{"versions": {}, "dist-tags": {"latest": ""}}"#).unwrap();
+        let error = resolve_version(&unpublished, "pkg", Some("^2.0.0")).unwrap_err().to_string();
+        assert!(error.ends_with("It has no published versions."), "{}", error);
+    }
+
+    #[test]
+    fn test_npm_ranges() {
+        for (range, version, expected) in [
+            ("^16.8.0 || ^17.0.0 || ^18.0.0", "18.2.0", Some(true)),
+            ("^16.8.0 || ^17.0.0 || ^18.0.0", "15.7.0", Some(false)),
+            (">=1 <2", "1.9.9", Some(true)),
+            (">=1 <2", "2.0.0", Some(false)),
+            (">= 1.2.0", "1.2.0", Some(true)),
+            ("1.2 - 2.3", "2.3.9", Some(true)),
+            ("1.2 - 2.3", "2.4.0", Some(false)),
+            ("1.2.3", "1.2.4", Some(false)),
+            ("1.x || 3", "3.1.0", Some(true)),
+            ("*", "0.0.1", Some(true)),
+            ("", "4.0.0", Some(true)),
+            ("next", "1.0.0", None),
+            (">=", "1.0.0", None),
+        ] {
+            assert_eq!(range_matches(range, version), expected, "{} against {}", range, version);
+        }
+    }
+
+    #[test]
+    fn test_peer_conflicts_only_warn() {
+        let peer = |peer: &str, range: &str, optional| PeerRequirement {
+            package: "react-dom".to_string(),
+            peer: peer.to_string(),
+            range: range.to_string(),
+            optional,
+        };
+        let versions = HashMap::from([("react".to_string(), "17.0.2".to_string())]);
+
+        let satisfied = [peer("react", "^16.8.0 || ^17.0.0", false), peer("scheduler", "*", true)];
+        assert!(check_peers(&satisfied, &versions).is_empty());
+
+        let missing = [peer("react-native", "*", false)];
+        assert_eq!(check_peers(&missing, &versions).len(), 1);
+
+        let conflicting = [peer("react", "^18.0.0", false)];
+        assert_eq!(check_peers(&conflicting, &versions), ["react-dom needs peer react@^18.0.0, but 17.0.2 is installed"]);
+
+        let unreadable = [peer("react", "17 or newer", false)];
+        assert_eq!(check_peers(&unreadable, &versions), ["react-dom needs peer react@17 or newer, a range crabby can't check against 17.0.2"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_file_and_git_dependencies_are_prepared_with_their_dependencies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let write_package = |dir: &str, manifest: &str| {
+            let dir = tmp.path().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("package.json"), manifest).unwrap();
+            dir
+        };
+        // Built from source by `prepare`; its devDependencies stay out
+        let lib = r#"{"name": "src-lib", "version": "0.3.0",
+            "dependencies": {"src-helper": "file:../helper"},
+            "devDependencies": {"never-installed": "1.0.0"},
+            "scripts": {"prepare": "sh -c 'mkdir -p dist && echo built > dist/index.js'"}}"#;
+        write_package("lib", lib);
+        write_package("helper", r#"{"name": "src-helper", "version": "1.2.0"}"#);
+        let app = write_package("app", r#"{"name": "app", "version": "1.0.0"}"#);
+
+        let mut deps = HashMap::from([("src-lib".to_string(), "file:../lib".to_string())]);
+        let repo = write_package("repo", &lib.replace("src-lib", "git-lib").replace(r#""src-helper": "file:../helper""#, ""));
+        let git = |args: &[&str]| std::process::Command::new("git").args(args).current_dir(&repo).output().is_ok_and(|o| o.status.success());
+        let has_git = git(&["init", "--quiet"]) && git(&["add", "."]) && git(&["-c", "user.name=t", "-c", "user.email=t@t", "commit", "--quiet", "-m", "init"]);
+        if has_git {
+            deps.insert("git-lib".to_string(), format!("git+file://{}", repo.display()));
+        }
+
+        // Relative file: specs are resolved from the project root, and from the
+        // directory of the package that declares them
+        let client = reqwest::Client::new();
+        let lockfile = install_all_packages(&app, &deps, "http://127.0.0.1:9", &client, Default::default(), &InstallStats::new()).await.unwrap();
+
+        let node_modules = app.join("node_modules");
+        assert_eq!(fs::read_to_string(node_modules.join("src-lib/dist/index.js")).unwrap().trim(), "built");
+        assert_eq!(crate::explorer::installed_version(&node_modules, "src-helper").as_deref(), Some("1.2.0"));
+        assert!(!node_modules.join("never-installed").exists());
+        assert_eq!(lockfile.dependencies["src-lib"].tarball, "file:../lib");
+        assert_eq!(lockfile.dependencies["src-lib"].version, "0.3.0");
+        // The helper is locked relative to the project, not where this test ran
+        assert_eq!(lockfile.dependencies["src-lib"].dependencies["src-helper"], "file:../helper");
+        assert_eq!(lockfile.dependencies["src-helper"].tarball, "file:../helper");
+
+        if has_git {
+            assert!(node_modules.join("git-lib/dist/index.js").exists());
+            assert!(!node_modules.join("git-lib/.git").exists());
+            let locked = &lockfile.dependencies["git-lib"].tarball;
+            assert!(locked.starts_with("git+file://") && locked.contains('#'), "{}", locked);
+
+            // Asking for another branch moves off the locked commit
+            fs::write(repo.join("package.json"), lib.replace("src-lib", "git-lib").replace("0.3.0", "0.4.0").replace(r#""src-helper": "file:../helper""#, "")).unwrap();
+            assert!(git(&["checkout", "--quiet", "-b", "next"]) && git(&["-c", "user.name=t", "-c", "user.email=t@t", "commit", "--quiet", "-am", "next"]));
+            deps.insert("git-lib".to_string(), format!("git+file://{}#next", repo.display()));
+            let lockfile = install_all_packages(&app, &deps, "http://127.0.0.1:9", &client, lockfile, &InstallStats::new()).await.unwrap();
+            assert_eq!(crate::explorer::installed_version(&node_modules, "git-lib").as_deref(), Some("0.4.0"));
+            assert_eq!(lockfile.dependencies["git-lib"].committish.as_deref(), Some("next"));
+        }
+    }
+
+    #[test]
+    fn test_extract_tarball_finds_the_root_folder() {
+        use flate2::write::GzEncoder;
+
+        let tarball = |entries: &[(&str, tar::EntryType)]| {
+            let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+            for (path, kind) in entries {
+                let data: &[u8] = match kind {
+                    tar::EntryType::XGlobalHeader => b"52 comment=0123456789abcdef0123456789abcdef01234567\n",
+                    tar::EntryType::Directory => b"",
+                    _ => b"module.exports = 1;",
+                };
+                let mut header = tar::Header::new_ustar();
+                header.set_entry_type(*kind);
+                header.set_size(data.len() as u64);
+                header.set_mode(if kind.is_dir() { 0o755 } else { 0o644 });
+                builder.append_data(&mut header, path, data).unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap()
+        };
+        let file = tar::EntryType::Regular;
+        fn files_under(dir: &Path, prefix: &str, found: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let entry = entry.unwrap();
+                let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+                if entry.file_type().unwrap().is_dir() {
+                    files_under(&entry.path(), &format!("{}/", name), found);
+                } else {
+                    found.push(name);
+                }
+            }
+        }
+        let extracted = |data: Vec<u8>| {
+            let dir = tempfile::tempdir().unwrap();
+            extract_tarball(&data, dir.path()).unwrap();
+            let mut files = Vec::new();
+            files_under(dir.path(), "", &mut files);
+            files.sort();
+            files
+        };
+
+        // `git archive` puts a pax global header in front of the files
+        let with_header = tarball(&[("pax_global_header", tar::EntryType::XGlobalHeader), ("package/", tar::EntryType::Directory), ("package/index.js", file), ("package/lib/util.js", file)]);
+        assert_eq!(extracted(with_header), ["index.js", "lib/util.js"]);
+
+        let other_root = tarball(&[("./left-pad-1.0.0/index.js", file), ("left-pad-1.0.0/package.json", file)]);
+        assert_eq!(extracted(other_root), ["index.js", "package.json"]);
+
+        // No common folder: nothing is dropped
+        let flat = tarball(&[("index.js", file), ("lib/util.js", file)]);
+        assert_eq!(extracted(flat), ["index.js", "lib/util.js"]);
     }
-    
-    let lockfile = state.lockfile.lock().await.clone();
-    Ok(lockfile)
 }
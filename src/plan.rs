@@ -0,0 +1,256 @@
+use anyhow::Result;
+use console::style;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::manifest::{CrabbyLock, LockDependency, PackageJson};
+use crate::source::Source;
+use crate::package_utils::{self, PackageMetadata};
+
+/// How a planned package relates to what's in node_modules now
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Add,
+    Update { from: String },
+    Reuse,
+}
+
+/// A package `crabby install` would put into node_modules
+#[derive(Debug)]
+pub struct PlannedPackage {
+    pub name: String,
+    pub version: String,
+    pub change: Change,
+    /// Whether the tarball is already in the download cache
+    pub cached: bool,
+    /// Unpacked size from the registry (`dist.unpackedSize`), when it reports
+    /// one. Tarballs are compressed, so the download itself is smaller.
+    pub size: Option<u64>,
+}
+
+/// What an install would do, resolved without downloading or writing anything
+#[derive(Debug, Default)]
+pub struct InstallPlan {
+    /// By name
+    pub packages: Vec<PlannedPackage>,
+    /// Ranges that would be saved to package.json
+    pub saved: Vec<(String, String)>,
+}
+
+struct Resolved {
+    version: String,
+    dependencies: HashMap<String, String>,
+    size: Option<u64>,
+    /// From a directory or git repository, so there's nothing to download
+    from_source: bool,
+}
+
+/// Every dependency `pkg` declares, with its range, except links to workspaces
+pub fn project_requests(pkg: &PackageJson) -> Vec<(String, Option<String>)> {
+    let mut deps = pkg.get_all_dependencies();
+    deps.retain(|_, range| !range.starts_with(LockDependency::WORKSPACE_PREFIX));
+    deps.into_iter().map(|(name, range)| (name, Some(range))).collect()
+}
+
+/// What a `file:` or git dependency resolves to without fetching it: the
+/// directory's package.json, else what crabby.lock recorded for the same source
+fn resolve_source(name: &str, source: &Source, root: &Path, lockfile: &CrabbyLock) -> Resolved {
+    let locked = lockfile.dependencies.get(name).filter(|dep| dep.installed_from(source, root));
+    let manifest = match source {
+        Source::File(path) => std::fs::read_to_string(path.join("package.json")).ok()
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&crate::manifest::clean_json_content(content)).ok()),
+        Source::Git { .. } => None,
+    };
+    let from_manifest = manifest.and_then(|json| Some((
+        json.get("version")?.as_str()?.to_string(),
+        serde_json::from_value(json.get("dependencies").cloned().unwrap_or_default()).unwrap_or_default(),
+    )));
+    let (version, dependencies) = match (from_manifest, locked) {
+        (Some(found), _) => found,
+        (None, Some(dep)) => (dep.version.clone(), dep.dependencies.clone()),
+        (None, None) => (source.to_string(), HashMap::new()),
+    };
+    Resolved { version, dependencies, size: None, from_source: true }
+}
+
+/// Resolve `requests` (name and range, `None` for latest) and their dependencies
+/// the way an install into `root` would: locked versions are kept when they fit,
+/// everything else is picked from registry metadata. Packages that are locked
+/// and already cached need no request at all.
+pub async fn resolve(root: &Path, requests: &[(String, Option<String>)], lockfile: &CrabbyLock, registry_url: &str, client: &reqwest::Client) -> Result<InstallPlan> {
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    let mut metadata: HashMap<String, PackageMetadata> = HashMap::new();
+    let mut resolved: BTreeMap<String, Resolved> = BTreeMap::new();
+    let mut visited = HashSet::new();
+    let mut queue: Vec<(String, Option<String>)> = requests.to_vec();
+
+    // One level of the tree at a time, fetching that level's metadata concurrently
+    while !queue.is_empty() {
+        let level: Vec<_> = queue.drain(..)
+            .filter(|(name, req)| visited.insert(format!("{}@{}", name, req.as_deref().unwrap_or("latest"))))
+            .collect();
+        for (name, _) in &level {
+            crate::safety::validate_package_name(name)?;
+        }
+
+        let locked = |name: &str, req: Option<&str>| lockfile.dependencies.get(name)
+            .filter(|dep| !dep.is_workspace_link() && package_utils::lock_satisfies(&dep.version, req));
+        let mut needed: Vec<String> = Vec::new();
+        let source = |req: &Option<String>| req.as_deref().and_then(|spec| Source::parse(spec, root));
+        for (name, req) in &level {
+            if source(req).is_some() {
+                continue;
+            }
+            let cached = match locked(name, req.as_deref()) {
+                Some(dep) => package_utils::cached_tarball(name, &dep.version)?.exists(),
+                None => false,
+            };
+            if !cached && !metadata.contains_key(name) && !needed.contains(name) {
+                needed.push(name.clone());
+            }
+        }
+        let fetched: Vec<(String, PackageMetadata)> = stream::iter(needed)
+            .map(|name| async move {
+                let found = package_utils::fetch_metadata(&name, registry_url, client).await?;
+                Ok::<_, anyhow::Error>((name, found))
+            })
+            .buffer_unordered(crate::MAX_CONCURRENT_DOWNLOADS)
+            .try_collect()
+            .await?;
+        metadata.extend(fetched);
+
+        for (name, req) in level {
+            let size_of = |version: &str| metadata.get(&name)
+                .and_then(|m| m.versions.get(version))
+                .and_then(|v| v.dist.unpacked_size);
+            let next = match (source(&req), locked(&name, req.as_deref())) {
+                (Some(source), _) => resolve_source(&name, &source, root, lockfile),
+                (None, Some(dep)) => Resolved {
+                    version: dep.version.clone(),
+                    dependencies: dep.dependencies.clone(),
+                    size: size_of(&dep.version),
+                    from_source: false,
+                },
+                (None, None) => {
+                    let found = &metadata[&name];
+                    let version = package_utils::resolve_version(found, &name, req.as_deref())?;
+                    Resolved {
+                        dependencies: found.versions[&version].dependencies.clone(),
+                        size: size_of(&version),
+                        version,
+                        from_source: false,
+                    }
+                }
+            };
+            // node_modules is flat: a later range that needs another version replaces it
+            if resolved.get(&name).is_some_and(|r| r.version == next.version) {
+                continue;
+            }
+            queue.extend(next.dependencies.iter().map(|(dep, range)| (dep.clone(), Some(range.clone()))));
+            resolved.insert(name, next);
+        }
+    }
+
+    let node_modules = root.join("node_modules");
+    let mut plan = InstallPlan::default();
+    for (name, r) in resolved {
+        let change = match crate::explorer::installed_version(&node_modules, &name) {
+            Some(installed) if installed == r.version => Change::Reuse,
+            Some(installed) => Change::Update { from: installed },
+            None => Change::Add,
+        };
+        plan.packages.push(PlannedPackage {
+            cached: r.from_source || package_utils::cached_tarball(&name, &r.version)?.exists(),
+            name,
+            version: r.version,
+            change,
+            size: r.size,
+        });
+    }
+    Ok(plan)
+}
+
+impl InstallPlan {
+    /// Unpacked bytes of the packages that aren't cached, and how many of them
+    /// have no size in the registry metadata
+    pub fn unpacked_size(&self) -> (u64, usize) {
+        let missing = self.packages.iter().filter(|p| !p.cached);
+        let bytes = missing.clone().filter_map(|p| p.size).sum();
+        let unknown = missing.filter(|p| p.size.is_none()).count();
+        (bytes, unknown)
+    }
+
+    pub fn print(&self) {
+        crate::ui::print_header(&format!("{} Install plan", crate::ui::icon(crate::ui::Icons::LIST)));
+        let count = |wanted: fn(&Change) -> bool| self.packages.iter().filter(|p| wanted(&p.change)).count();
+        for package in &self.packages {
+            match &package.change {
+                Change::Add => println!("  {} {} {}", style("+").green().bold(), package.name, style(&package.version).green()),
+                Change::Update { from } => println!("  {} {} {} {} {}", style("~").yellow().bold(), package.name, style(from).dim(), crate::ui::icon("→"), style(&package.version).yellow()),
+                Change::Reuse => {}
+            }
+        }
+        if !self.saved.is_empty() {
+            println!();
+            for (name, range) in &self.saved {
+                println!("  package.json: {} {}", style(name).cyan(), range);
+            }
+        }
+
+        let plural = |n: usize| format!("{} package{}", n, if n == 1 { "" } else { "s" });
+        let to_fetch = self.packages.iter().filter(|p| !p.cached).count();
+        let download = match self.unpacked_size() {
+            _ if to_fetch == 0 => "nothing to download".to_string(),
+            (0, _) => format!("{} to download", plural(to_fetch)),
+            (bytes, 0) => format!("{} to download, about {} unpacked", plural(to_fetch), crate::ui::format_size(bytes)),
+            (bytes, _) => format!("{} to download, at least {} unpacked", plural(to_fetch), crate::ui::format_size(bytes)),
+        };
+        println!("\n{}: {} added, {} updated, {} unchanged; {}",
+            style(plural(self.packages.len())).bold(),
+            count(|c| *c == Change::Add),
+            count(|c| matches!(c, Change::Update { .. })),
+            count(|c| *c == Change::Reuse),
+            download,
+        );
+        crate::ui::print_info("DRY RUN - package.json, crabby.lock and node_modules were not changed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_plans_without_writing() {
+        let registry = crate::test_utils::serve(|path| {
+            let version = |v: &str, deps: &str, size: &str| format!(
+                r#""{v}": {{"version": "{v}", "dependencies": {deps}, "dist": {{"tarball": "t", "shasum": "", {size}}}}}"#);
+            let body = if path == "/plan-app-dep" {
+                format!(r#"{{"name": "plan-app-dep", "dist-tags": {{"latest": "1.2.0"}}, "versions": {{{}, {}}}}}"#,
+                    version("1.0.0", "{}", r#""unpackedSize": 100"#), version("1.2.0", "{}", r#""unpackedSize": 300"#))
+            } else {
+                format!(r#"{{"name": "plan-app", "dist-tags": {{"latest": "2.0.0"}}, "versions": {{{}}}}}"#,
+                    version("2.0.0", r#"{"plan-app-dep": "^1.0.0"}"#, r#""x": 0"#))
+            };
+            Some(body.into_bytes())
+        });
+
+        let root = tempfile::tempdir().unwrap();
+        let installed = root.path().join("node_modules").join("plan-app-dep");
+        std::fs::create_dir_all(&installed).unwrap();
+        std::fs::write(installed.join("package.json"), r#"{"name": "plan-app-dep", "version": "1.0.0"}"#).unwrap();
+
+        let requests = [("plan-app".to_string(), None)];
+        let plan = resolve(root.path(), &requests, &CrabbyLock::default(), &registry, &reqwest::Client::new()).await.unwrap();
+        let summary: Vec<_> = plan.packages.iter().map(|p| (p.name.as_str(), p.version.as_str(), p.change.clone())).collect();
+        assert_eq!(summary, [
+            ("plan-app", "2.0.0", Change::Add),
+            ("plan-app-dep", "1.2.0", Change::Update { from: "1.0.0".to_string() }),
+        ]);
+        assert_eq!(plan.unpacked_size(), (300, 1));
+        // Nothing was installed
+        assert!(!root.path().join("node_modules").join("plan-app").exists());
+        assert!(!root.path().join("crabby.lock").exists());
+    }
+}
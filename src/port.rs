@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use std::net::TcpListener;
+use std::process::Command;
+
+/// The port a dev server will listen on: a `--port` argument in its command,
+/// otherwise the `PORT` environment variable
+pub fn detect_port(command: &str) -> Option<u16> {
+    let args = shlex::split(command).unwrap_or_default();
+    let from_args = args.iter().enumerate().find_map(|(i, arg)| {
+        match arg.strip_prefix("--port=") {
+            Some(value) => value.parse().ok(),
+            None if arg == "--port" => args.get(i + 1)?.parse().ok(),
+            None => None,
+        }
+    });
+    from_args.or_else(|| std::env::var("PORT").ok()?.parse().ok())
+}
+
+/// Whether something is already listening on `port` locally
+pub fn in_use(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_err()
+}
+
+/// Kill every process listening on `port`, returning their PIDs
+pub fn kill_listeners(port: u16) -> Result<Vec<u32>> {
+    let own = std::process::id();
+    let pids: Vec<u32> = listening_pids(port)?.into_iter().filter(|&pid| pid != own).collect();
+    for pid in &pids {
+        kill(*pid)?;
+    }
+    Ok(pids)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn listening_pids(port: u16) -> Result<Vec<u32>> {
+    let output = Command::new("lsof")
+        .args(["-t", &format!("-iTCP:{}", port), "-sTCP:LISTEN"])
+        .output()
+        .context("Failed to run lsof")?;
+    // lsof exits non-zero when nothing matches
+    let mut pids: Vec<u32> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+    Ok(pids)
+}
+
+#[cfg(target_os = "windows")]
+fn listening_pids(port: u16) -> Result<Vec<u32>> {
+    let output = Command::new("netstat")
+        .args(["-ano", "-p", "tcp"])
+        .output()
+        .context("Failed to run netstat")?;
+    let suffix = format!(":{}", port);
+    let mut pids: Vec<u32> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // Proto  Local Address  Foreign Address  State  PID
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() == 5 && cols[3] == "LISTENING" && cols[1].ends_with(&suffix) {
+                cols[4].parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+    Ok(pids)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn kill(pid: u32) -> Result<()> {
+    Command::new("kill").args(["-9", &pid.to_string()]).status().context("Failed to run kill")?;
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn kill(pid: u32) -> Result<()> {
+    Command::new("taskkill").args(["/F", "/T", "/PID", &pid.to_string()]).status().context("Failed to run taskkill")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_port_from_command() {
+        assert_eq!(detect_port("vite --port 5173"), Some(5173));
+        assert_eq!(detect_port("next dev --port=4000 --turbo"), Some(4000));
+        assert_eq!(detect_port("node server.js --port nope"), std::env::var("PORT").ok().and_then(|p| p.parse().ok()));
+    }
+}
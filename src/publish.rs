@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use console::style;
+use std::path::Path;
+use crate::{config, manifest, pack, registry, runner, ui};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Access {
+    Public,
+    Restricted,
+}
+
+impl Access {
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::Public => "public",
+            Access::Restricted => "restricted",
+        }
+    }
+}
+
+pub struct PublishOptions {
+    pub tag: String,
+    pub access: Option<Access>,
+    pub dry_run: bool,
+}
+
+/// `crabby publish`: pack the current package and upload it to the configured registry
+pub async fn run(options: PublishOptions) -> Result<()> {
+    let pkg = manifest::PackageJson::load()?;
+    validate(&pkg, options.access)?;
+
+    let config = config::load_config()?;
+    let registry_url = config.registry.trim_end_matches('/').to_string();
+    let token = config.auth_token();
+    if token.is_none() && !options.dry_run {
+        anyhow::bail!(
+            "No auth token for {}. Set \"authToken\" in crabby.config.json or the NPM_TOKEN environment variable.",
+            registry_url
+        );
+    }
+
+    let client = registry::get_client()?;
+    let spinner = ui::create_spinner(&format!("Checking {}@{} on {}...", pkg.name, pkg.version, registry_url));
+    let exists = version_exists(&client, &registry_url, &pkg.name, &pkg.version, token.as_deref()).await;
+    spinner.finish_and_clear();
+    if exists? {
+        anyhow::bail!("{}@{} is already published. Bump the version first (crabby version patch).", pkg.name, pkg.version);
+    }
+
+    // Same order as npm: prepublishOnly -> prepack -> pack -> postpack -> publish -> postpublish
+    run_lifecycle(&pkg, "prepublishOnly")?;
+    run_lifecycle(&pkg, "prepack")?;
+    // Lifecycle scripts may rewrite package.json (e.g. build metadata), so read it again
+    let pkg = manifest::PackageJson::load()?;
+    let packed = pack::pack(Path::new("."), &pkg)?;
+    run_lifecycle(&pkg, "postpack")?;
+
+    let payload = build_payload(&pkg, &packed, &registry_url, &options)?;
+
+    ui::print_step(ui::Icons::PACKAGE, &format!(
+        "{}@{} ({} files, {}) with tag {}",
+        pkg.name,
+        pkg.version,
+        packed.files.len(),
+        ui::format_size(packed.tarball.len() as u64),
+        style(&options.tag).cyan()
+    ));
+    println!("  {} {}", style("integrity").dim(), packed.integrity);
+
+    if options.dry_run {
+        ui::print_info("DRY RUN - nothing was uploaded");
+        return Ok(());
+    }
+
+    let spinner = ui::create_spinner(&format!("Publishing to {}...", registry_url));
+    let response = client.put(package_url(&registry_url, &pkg.name))
+        .bearer_auth(token.unwrap_or_default())
+        .json(&payload)
+        .send()
+        .await;
+    spinner.finish_and_clear();
+
+    let response = response.context("Failed to reach the registry")?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Registry rejected the publish ({}): {}", status, body.trim());
+    }
+
+    run_lifecycle(&pkg, "postpublish")?;
+    ui::print_success(&format!("Published {}@{}", pkg.name, pkg.version));
+    Ok(())
+}
+
+fn validate(pkg: &manifest::PackageJson, access: Option<Access>) -> Result<()> {
+    if pkg.private {
+        anyhow::bail!("This package has \"private\": true and cannot be published");
+    }
+    if pkg.name.is_empty() {
+        anyhow::bail!("package.json has no \"name\"");
+    }
+    if pkg.name != pkg.name.to_lowercase() || crate::safety::validate_package_name(&pkg.name).is_err() {
+        anyhow::bail!("\"{}\" is not a valid package name", pkg.name);
+    }
+    if pkg.version.is_empty() {
+        anyhow::bail!("package.json has no \"version\"");
+    }
+    semver::Version::parse(&pkg.version)
+        .with_context(|| format!("\"{}\" is not a valid semver version", pkg.version))?;
+    if access == Some(Access::Restricted) && !pkg.name.starts_with('@') {
+        anyhow::bail!("Unscoped packages are always public, --access restricted needs an @scope/ name");
+    }
+    Ok(())
+}
+
+fn run_lifecycle(pkg: &manifest::PackageJson, script: &str) -> Result<()> {
+    if let Some(command) = pkg.scripts.get(script) {
+        ui::print_step(ui::Icons::RUN, &format!("Running {}", script));
+        runner::run_script(command, None).with_context(|| format!("{} script failed", script))?;
+    }
+    Ok(())
+}
+
+/// Scoped names keep the `@` but escape the slash: `@scope%2fname`
+fn package_url(registry_url: &str, name: &str) -> String {
+    format!("{}/{}", registry_url, name.replace('/', "%2f"))
+}
+
+async fn version_exists(client: &reqwest::Client, registry_url: &str, name: &str, version: &str, token: Option<&str>) -> Result<bool> {
+    let mut request = client.get(package_url(registry_url, name));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.context("Failed to reach the registry")?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false); // first publish of this package
+    }
+    let document: serde_json::Value = response.error_for_status()?.json().await
+        .context("Failed to parse registry response")?;
+    Ok(document.get("versions").and_then(|v| v.get(version)).is_some())
+}
+
+/// The document npm's registry expects on `PUT /<name>`
+fn build_payload(pkg: &manifest::PackageJson, packed: &pack::PackResult, registry_url: &str, options: &PublishOptions) -> Result<serde_json::Value> {
+    // prepack may have rewritten package.json since it was first checked
+    validate(pkg, options.access)?;
+    // The registry serves tarballs under the unscoped name: @scope/tool -> tool-1.0.0.tgz
+    let basename = pkg.name.rsplit('/').next().unwrap_or(&pkg.name);
+    let tarball_url = format!("{}/{}/-/{}-{}.tgz", registry_url, pkg.name, basename, pkg.version);
+
+    let mut manifest = serde_json::to_value(pkg)?;
+    manifest["_id"] = format!("{}@{}", pkg.name, pkg.version).into();
+    manifest["dist"] = serde_json::json!({
+        "integrity": packed.integrity,
+        "shasum": packed.shasum,
+        "tarball": tarball_url,
+    });
+
+    let mut payload = serde_json::json!({
+        "_id": pkg.name,
+        "name": pkg.name,
+        "dist-tags": { options.tag.as_str(): pkg.version },
+        "versions": { pkg.version.as_str(): manifest },
+        // Keyed like npm does, by the full name: @scope/tool-1.0.0.tgz
+        "_attachments": {
+            format!("{}-{}.tgz", pkg.name, pkg.version): {
+                "content_type": "application/octet-stream",
+                "data": base64::engine::general_purpose::STANDARD.encode(&packed.tarball),
+                "length": packed.tarball.len(),
+            }
+        },
+    });
+    if let Some(access) = options.access {
+        payload["access"] = access.as_str().into();
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_payload() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("package.json"), r#"{"name": "@acme/tool", "version": "1.2.0"}"#).unwrap();
+        std::fs::write(tmp.path().join("index.js"), "module.exports = 42;").unwrap();
+
+        let pkg = manifest::PackageJson { name: "@acme/tool".into(), version: "1.2.0".into(), ..Default::default() };
+        let packed = pack::pack(tmp.path(), &pkg).unwrap();
+        let options = PublishOptions { tag: "beta".into(), access: Some(Access::Public), dry_run: true };
+
+        let payload = build_payload(&pkg, &packed, "https://registry.example.com", &options).unwrap();
+        assert_eq!(payload["dist-tags"]["beta"], "1.2.0");
+        assert_eq!(payload["access"], "public");
+
+        let version = &payload["versions"]["1.2.0"];
+        assert_eq!(version["_id"], "@acme/tool@1.2.0");
+        assert_eq!(version["dist"]["integrity"], packed.integrity.as_str());
+        assert_eq!(version["dist"]["tarball"], "https://registry.example.com/@acme/tool/-/tool-1.2.0.tgz");
+
+        let attachment = &payload["_attachments"]["@acme/tool-1.2.0.tgz"];
+        let data = base64::engine::general_purpose::STANDARD.decode(attachment["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data, packed.tarball);
+        assert_eq!(attachment["length"], packed.tarball.len());
+
+        let unversioned = manifest::PackageJson { version: String::new(), ..pkg };
+        assert!(build_payload(&unversioned, &packed, "https://registry.example.com", &options).is_err());
+    }
+
+    #[test]
+    fn test_validate_refuses_private_and_bad_versions() {
+        let private = manifest::PackageJson { name: "app".into(), version: "1.0.0".into(), private: true, ..Default::default() };
+        assert!(validate(&private, None).is_err());
+
+        let bad_version = manifest::PackageJson { name: "app".into(), version: "one".into(), ..Default::default() };
+        assert!(validate(&bad_version, None).is_err());
+
+        let unscoped = manifest::PackageJson { name: "app".into(), version: "1.0.0".into(), ..Default::default() };
+        assert!(validate(&unscoped, Some(Access::Restricted)).is_err());
+        assert!(validate(&unscoped, None).is_ok());
+    }
+}
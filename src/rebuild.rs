@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::package_utils::{self, InstalledPackageJson};
+use crate::reporter::{self, Event};
+use crate::{explorer, native, runner, ui};
+
+/// What `crabby rebuild` did for one package with scripts to run
+#[derive(Debug)]
+pub struct Rebuilt {
+    pub name: String,
+    pub version: String,
+    pub scripts: Vec<&'static str>,
+    /// Why it failed, `None` when every script succeeded
+    pub error: Option<String>,
+}
+
+/// Run the install and postinstall scripts of the packages in `root`'s
+/// node_modules again (only `names`, if given), dependencies first, and link
+/// their bins again. Native addons go through [`native::build`]. Scripts run
+/// on the Node.js version the project selects, like during an install.
+pub fn rebuild(root: &Path, names: &[String]) -> Result<Vec<Rebuilt>> {
+    let node_modules = root.join("node_modules");
+    let mut packages: BTreeMap<String, (PathBuf, InstalledPackageJson)> = BTreeMap::new();
+    for (name, dir) in explorer::installed_packages(&node_modules)? {
+        if !names.is_empty() && !names.contains(&name) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(dir.join("package.json")) else { continue };
+        let manifest = serde_json::from_str(&crate::manifest::clean_json_content(content))
+            .with_context(|| format!("Failed to parse package.json of {}", name))?;
+        packages.insert(name, (dir, manifest));
+    }
+    if let Some(missing) = names.iter().find(|name| !packages.contains_key(*name)) {
+        anyhow::bail!("{} is not installed in node_modules", missing);
+    }
+
+    let bin_dir = node_modules.join(".bin");
+    let graph: BTreeMap<&str, Vec<&str>> = packages.iter()
+        .map(|(name, (_, manifest))| (name.as_str(), manifest.dependencies.keys().map(String::as_str).collect()))
+        .collect();
+    let mut results = Vec::new();
+    for name in dependency_order(&graph) {
+        let (dir, manifest) = &packages[name];
+        package_utils::link_binaries(&node_modules, name, &manifest.bin)?;
+        let version = explorer::installed_version(&node_modules, name).unwrap_or_default();
+        let mut scripts: Vec<&'static str> = ["install", "postinstall"].into_iter()
+            .filter(|script| manifest.scripts.contains_key(*script))
+            .collect();
+
+        if let Some(package) = native::detect(name, dir) {
+            if scripts.first() != Some(&"install") {
+                scripts.insert(0, "install");
+            }
+            let error = match native::build(&package, &bin_dir).outcome {
+                native::Outcome::Failed { missing, log } => {
+                    let reason = if missing.is_empty() { "build failed".to_string() } else { format!("not built, missing {}", missing.join(", ")) };
+                    Some(match log {
+                        Some(log) => format!("{} (log: {})", reason, log.strip_prefix(root).unwrap_or(&log).display()),
+                        None => reason,
+                    })
+                }
+                _ => None,
+            };
+            results.push(Rebuilt { name: name.to_string(), version, scripts, error });
+            continue;
+        }
+        if scripts.is_empty() {
+            continue;
+        }
+
+        let mut error = None;
+        for script in &scripts {
+            let started = Instant::now();
+            let result = runner::run_script_with_bin(&manifest.scripts[*script], dir, &bin_dir);
+            reporter::emit(Event::Script { name, script, success: result.is_ok(), elapsed_ms: started.elapsed().as_millis() as u64 });
+            if let Err(e) = result {
+                error = Some(format!("{} failed: {}", script, e));
+                break;
+            }
+        }
+        results.push(Rebuilt { name: name.to_string(), version, scripts, error });
+    }
+    Ok(results)
+}
+
+/// The packages of `graph` with each one after the dependencies it has in
+/// `graph`, ties in name order. A cycle is broken where it's first entered.
+fn dependency_order<'a>(graph: &BTreeMap<&'a str, Vec<&'a str>>) -> Vec<&'a str> {
+    fn visit<'a>(name: &'a str, graph: &BTreeMap<&'a str, Vec<&'a str>>, seen: &mut HashSet<&'a str>, order: &mut Vec<&'a str>) {
+        if !seen.insert(name) {
+            return;
+        }
+        let mut deps = graph[name].clone();
+        deps.sort_unstable();
+        for dep in deps.into_iter().filter(|dep| graph.contains_key(dep)) {
+            visit(dep, graph, seen, order);
+        }
+        order.push(name);
+    }
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for name in graph.keys() {
+        visit(name, graph, &mut seen, &mut order);
+    }
+    order
+}
+
+/// Which packages had scripts to run, and how each went
+pub fn print_report(results: &[Rebuilt]) {
+    if !reporter::human() {
+        return;
+    }
+    if results.is_empty() {
+        ui::print_info("No installed packages have install scripts to run");
+        return;
+    }
+    ui::print_header(&format!("{} Rebuilt packages", ui::icon(ui::Icons::BUILD)));
+    let width = results.iter().map(|r| r.name.len() + r.version.len() + 1).max().unwrap_or(0);
+    for result in results {
+        let label = format!("{:<width$}", format!("{}@{}", result.name, result.version), width = width);
+        match &result.error {
+            None => println!("  {} {}  {}", style(ui::icon("✓")).green(), label, style(result.scripts.join(", ")).dim()),
+            Some(error) => println!("  {} {}  {}", style(ui::icon("✗")).red(), label, style(error).red()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies_come_first() {
+        let graph = BTreeMap::from([
+            ("app", vec!["zlib", "addon"]),
+            ("addon", vec!["bindings", "missing"]),
+            ("bindings", vec![]),
+            ("zlib", vec!["cycle"]),
+            ("cycle", vec!["zlib"]),
+        ]);
+        assert_eq!(dependency_order(&graph), ["bindings", "addon", "cycle", "zlib", "app"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rebuild_runs_scripts_and_links_bins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let node_modules = tmp.path().join("node_modules");
+        let package = |name: &str, json: &str| {
+            fs::create_dir_all(node_modules.join(name)).unwrap();
+            fs::write(node_modules.join(name).join("package.json"), json).unwrap();
+        };
+        package("app", r#"{"version": "1.0.0", "dependencies": {"base": "^1.0.0"}, "bin": {"app": "cli.js"},
+            "scripts": {"postinstall": "sh -c 'echo app >> ../../order.txt'"}}"#);
+        package("base", r#"{"version": "1.2.0", "scripts": {"install": "sh -c 'echo base >> ../../order.txt'"}}"#);
+        package("broken", r#"{"version": "0.1.0", "scripts": {"install": "false", "postinstall": "touch never"}}"#);
+        package("plain", r#"{"version": "2.0.0"}"#);
+
+        let results = rebuild(tmp.path(), &[]).unwrap();
+        let summary: Vec<(&str, bool)> = results.iter().map(|r| (r.name.as_str(), r.error.is_none())).collect();
+        assert_eq!(summary, [("base", true), ("app", true), ("broken", false)]);
+        assert_eq!(fs::read_to_string(tmp.path().join("order.txt")).unwrap(), "base\napp\n");
+        assert!(node_modules.join(".bin").join("app").is_file());
+        assert!(!node_modules.join("broken").join("never").exists());
+
+        let only = rebuild(tmp.path(), &["base".to_string()]).unwrap();
+        assert_eq!(only.len(), 1);
+        assert!(rebuild(tmp.path(), &["absent".to_string()]).is_err());
+    }
+}
@@ -1,140 +1,114 @@
-use serde::Deserialize;
 use anyhow::{Context, Result};
-use std::fs;
-use flate2::read::GzDecoder;
-use tar::Archive;
-use std::path::Path;
 use std::time::Duration;
-use console::style;
 
-const REGISTRY_URL: &str = "https://registry.npmjs.org";
 const MAX_RETRIES: u32 = 3;
 const TIMEOUT_SECS: u64 = 60;
+const CONNECT_TIMEOUT_SECS: u64 = 10;
 
-#[derive(Debug, Deserialize)]
-pub struct PackageDist {
-    pub tarball: String,
+/// Timeouts and connection pooling for registry requests. Unset fields fall
+/// back to the config file, then to the defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkOptions {
+    /// Seconds a whole request may take, response body included
+    pub timeout: Option<u64>,
+    /// Seconds to wait for a connection to be established
+    pub connect_timeout: Option<u64>,
+    /// Idle connections kept open per host
+    pub pool_max_idle: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct PackageVersion {
-    pub dist: PackageDist,
+impl NetworkOptions {
+    /// These options, with the ones they leave unset taken from `fallback`
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            timeout: self.timeout.or(fallback.timeout),
+            connect_timeout: self.connect_timeout.or(fallback.connect_timeout),
+            pool_max_idle: self.pool_max_idle.or(fallback.pool_max_idle),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct PackageMetadata {
-    #[serde(rename = "dist-tags")]
-    pub dist_tags: PackageDistTags,
-    pub versions: std::collections::HashMap<String, PackageVersion>,
-}
+/// Set from the command line, ahead of the config file
+static CLI_OPTIONS: std::sync::OnceLock<NetworkOptions> = std::sync::OnceLock::new();
 
-#[derive(Debug, Deserialize)]
-pub struct PackageDistTags {
-    pub latest: String,
+pub fn set_network_options(options: NetworkOptions) {
+    let _ = CLI_OPTIONS.set(options);
 }
 
+/// The shared client for registry requests, tuned by `--registry-timeout`
+/// and friends or the config file
 pub fn get_client() -> Result<reqwest::Client> {
-    reqwest::Client::builder()
-        .timeout(Duration::from_secs(TIMEOUT_SECS))
-        .build()
-        .context("Failed to create HTTP client")
+    let configured = crate::config::load_config().map(|c| c.network_options()).unwrap_or_default();
+    client_with(CLI_OPTIONS.get().copied().unwrap_or_default().or(configured))
 }
 
-pub async fn fetch_package_version(name: &str, client: &reqwest::Client) -> Result<(String, String)> {
-    let url = format!("{}/{}", REGISTRY_URL, name);
+pub fn client_with(options: NetworkOptions) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(options.timeout.unwrap_or(TIMEOUT_SECS)))
+        .connect_timeout(Duration::from_secs(options.connect_timeout.unwrap_or(CONNECT_TIMEOUT_SECS)));
+    if let Some(idle) = options.pool_max_idle {
+        builder = builder.pool_max_idle_per_host(idle);
+    }
+    builder.build().context("Failed to create HTTP client")
+}
 
+/// GET `url` through the shared throttle, retrying when the connection fails
+/// or drops. Keep the returned permit until the body has been read.
+pub async fn get<'a>(client: &reqwest::Client, url: &str) -> Result<(reqwest::Response, crate::throttle::Permit<'a>)> {
     let mut attempt = 0;
     loop {
         attempt += 1;
-        match client.get(&url).send().await {
-            Ok(resp) => {
-                let resp = resp.error_for_status()?;
-                let metadata: PackageMetadata = resp.json().await?;
-                let latest_version = metadata.dist_tags.latest.clone();
-                
-                let version_info = metadata.versions.get(&latest_version)
-                    .context("Latest version not found in metadata")?;
-                
-                return Ok((latest_version, version_info.dist.tarball.clone()));
-            }
-            Err(e) => {
-                if attempt >= MAX_RETRIES {
-                    return Err(anyhow::anyhow!("Failed to fetch metadata for package '{}' after {} attempts: {}", name, MAX_RETRIES, e));
-                }
-                println!("{} Retrying metadata fetch for {} (attempt {}/{}): {}", 
-                    style("⚠️").yellow(), 
-                    name, 
-                    attempt, 
-                    MAX_RETRIES, 
-                    e
-                );
+        match crate::throttle::global().send(|| client.get(url)).await {
+            Err(e) if attempt < MAX_RETRIES && crate::throttle::is_interruption(&e) => {
+                crate::ui::suspend_bars(|| crate::ui::print_warning(&format!("Retrying {} (attempt {}/{}): {}", url, attempt + 1, MAX_RETRIES, e)));
                 tokio::time::sleep(Duration::from_secs(2u64.pow(attempt - 1))).await;
             }
+            result => return result,
         }
     }
 }
 
-pub async fn download_and_extract(name: &str, _version: &str, tarball_url: &str, client: &reqwest::Client) -> Result<()> {
-    // Note: This function seems to be legacy or used for simple cases. 
-    // package_utils::download_and_extract is the main one used by install command.
-    // However, we update this one too for consistency.
-    
-    let mut attempt = 0;
-    let response = loop {
-        attempt += 1;
-        match client.get(tarball_url).send().await {
-            Ok(resp) => break resp.error_for_status()?,
-            Err(e) => {
-                 if attempt >= MAX_RETRIES {
-                    return Err(anyhow::anyhow!("Failed to download tarball for '{}' after {} attempts: {}", name, MAX_RETRIES, e));
-                }
-                println!("{} Retrying download for {} (attempt {}/{}): {}", 
-                    style("⚠️").yellow(), 
-                    name, 
-                    attempt, 
-                    MAX_RETRIES, 
-                    e
-                );
-                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt - 1))).await;
-            }
-        }
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let bytes = response.bytes().await?.to_vec();
-    let tar_gz = GzDecoder::new(&bytes[..]);
-    let mut archive = Archive::new(tar_gz);
+    #[tokio::test]
+    async fn test_client_with_custom_timeout() {
+        // Accepts the connection, then never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/left-pad", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _held: Vec<_> = listener.incoming().take(1).collect();
+            std::thread::sleep(Duration::from_secs(10));
+        });
 
-    let node_modules = Path::new("node_modules");
-    if !node_modules.exists() {
-        fs::create_dir_all(node_modules)?;
-    }
-    
-    let target_dir = node_modules.join(name);
-    if target_dir.exists() {
-        fs::remove_dir_all(&target_dir)?;
+        let client = client_with(NetworkOptions { timeout: Some(1), connect_timeout: Some(1), pool_max_idle: Some(0) }).unwrap();
+        let started = std::time::Instant::now();
+        let err = client.get(&url).send().await.unwrap_err();
+        assert!(err.is_timeout());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let cli = NetworkOptions { timeout: Some(5), ..Default::default() };
+        let config = NetworkOptions { timeout: Some(30), connect_timeout: Some(3), pool_max_idle: None };
+        assert_eq!(cli.or(config), NetworkOptions { timeout: Some(5), connect_timeout: Some(3), pool_max_idle: None });
     }
-    fs::create_dir_all(&target_dir)?;
 
-    // NPM tarballs usually contain a 'package' root directory. We want to strip that.
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?.to_path_buf();
-        
-        // Strip the first component (usually "package", but can be anything)
-        let mut components = path.components();
-        let _root = components.next();
-        let relative_path = components.as_path();
+    #[tokio::test]
+    async fn test_get_retries_a_dropped_connection() {
+        use std::io::{Read, Write};
 
-        if relative_path.as_os_str().is_empty() {
-             continue; 
-        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/left-pad", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            // The first connection closes without an answer
+            drop(listener.accept().unwrap());
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 1024]);
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").unwrap();
+        });
 
-        let extract_path = target_dir.join(relative_path);
-        if let Some(parent) = extract_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        entry.unpack(&extract_path)?;
+        let client = client_with(NetworkOptions::default()).unwrap();
+        let (response, _permit) = get(&client, &url).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
     }
-
-    Ok(())
 }
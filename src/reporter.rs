@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How progress is reported, selected with `--reporter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReporterKind {
+    /// Emoji lines and progress bars for people
+    Default,
+    /// Newline-delimited JSON events on stdout for editors and other tools
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn init(kind: ReporterKind) {
+    JSON.store(kind == ReporterKind::Json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Whether human-readable output should be printed
+pub fn human() -> bool {
+    !is_json()
+}
+
+/// One line of `--reporter json` output. Every event is an object whose
+/// `"event"` field names its kind; times are in milliseconds.
+///
+/// ```text
+/// {"event":"resolve","name":"express","version":"4.18.2","locked":false,"elapsed_ms":120}
+/// {"event":"download","name":"express","version":"4.18.2","bytes":54321,"cached":false,"elapsed_ms":80}
+/// {"event":"extract","name":"express","version":"4.18.2","elapsed_ms":12}
+/// {"event":"script","name":"esbuild","script":"postinstall","success":true,"elapsed_ms":950}
+/// {"event":"done","packages":57,"added":12,"reused":45,"bytes":1048576,"elapsed_ms":3200,"timings":{"phases":{...},"slowest":[...]}}
+/// {"event":"error","message":"Package 'nope' not found on https://registry.npmjs.org","kind":"registry","exit_code":4,"hint":"..."}
+/// ```
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<'a> {
+    /// A version was picked, from the registry or from crabby.lock (`locked`)
+    Resolve { name: &'a str, version: &'a str, locked: bool, elapsed_ms: u64 },
+    /// A tarball was fetched; `cached` tarballs came from disk and `bytes` is 0
+    Download { name: &'a str, version: &'a str, bytes: u64, cached: bool, elapsed_ms: u64 },
+    /// A tarball was unpacked into node_modules
+    Extract { name: &'a str, version: &'a str, elapsed_ms: u64 },
+    /// A lifecycle script of an installed package ran
+    Script { name: &'a str, script: &'a str, success: bool, elapsed_ms: u64 },
+    /// The install finished; `timings` sums up where the time went
+    Done {
+        packages: usize,
+        added: usize,
+        reused: usize,
+        bytes: u64,
+        elapsed_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timings: Option<crate::package_utils::TimingSummary>,
+    },
+    /// The command failed; `kind` and `exit_code` classify the failure
+    Error { message: String, kind: crate::error::ErrorKind, exit_code: i32, hint: Option<&'static str> },
+}
+
+/// Print `event` as a JSON line when the JSON reporter is active
+pub fn emit(event: Event) {
+    if is_json() {
+        let stdout = std::io::stdout();
+        let _ = write_event(&mut stdout.lock(), &event);
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &Event) -> std::io::Result<()> {
+    let line = serde_json::to_string(event)?;
+    writeln!(out, "{}", line)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_ndjson() {
+        let events = [
+            Event::Resolve { name: "express", version: "4.18.2", locked: false, elapsed_ms: 120 },
+            Event::Download { name: "express", version: "4.18.2", bytes: 54321, cached: false, elapsed_ms: 80 },
+            Event::Extract { name: "express", version: "4.18.2", elapsed_ms: 12 },
+            Event::Script { name: "esbuild", script: "postinstall", success: true, elapsed_ms: 950 },
+            Event::Done { packages: 1, added: 1, reused: 0, bytes: 54321, elapsed_ms: 300, timings: None },
+            Event::Error { message: "line one\nline two".to_string(), kind: crate::error::ErrorKind::Script, exit_code: 6, hint: None },
+        ];
+
+        let mut out = Vec::new();
+        for event in &events {
+            write_event(&mut out, event).unwrap();
+        }
+        let text = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), events.len());
+        let kinds: Vec<String> = lines.iter()
+            .map(|line| {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert!(value.is_object());
+                value["event"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(kinds, ["resolve", "download", "extract", "script", "done", "error"]);
+    }
+}
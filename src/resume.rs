@@ -0,0 +1,150 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Install progress, in node_modules so that it goes wherever node_modules goes
+pub const STATE_FILE: &str = ".crabby-state.json";
+
+/// How many finished packages may go unrecorded if the install is killed
+const SAVE_EVERY: usize = 20;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateFile {
+    /// SHA-1 of crabby.lock when the packages below were extracted
+    #[serde(rename = "lockHash")]
+    lock_hash: String,
+    /// `name@version` of every fully installed package, with the SHA-1 of its tarball
+    packages: BTreeMap<String, String>,
+}
+
+/// What an install has finished so far, so that an install that died halfway
+/// can pick up where it stopped. A changed crabby.lock starts it over.
+pub struct InstallProgress {
+    node_modules: PathBuf,
+    state: Mutex<(StateFile, usize)>,
+}
+
+fn lock_hash(root: &Path) -> String {
+    match fs::read(root.join("crabby.lock")) {
+        Ok(content) => format!("{:x}", Sha1::digest(&content)),
+        Err(_) => String::new(),
+    }
+}
+
+impl InstallProgress {
+    /// The progress recorded for the project at `root`, if crabby.lock is still the same
+    pub fn load(root: &Path) -> Self {
+        let node_modules = root.join("node_modules");
+        let hash = lock_hash(root);
+        let state = fs::read_to_string(node_modules.join(STATE_FILE)).ok()
+            .and_then(|content| serde_json::from_str::<StateFile>(&content).ok())
+            .filter(|state| state.lock_hash == hash)
+            .unwrap_or(StateFile { lock_hash: hash, packages: BTreeMap::new() });
+        Self { node_modules, state: Mutex::new((state, 0)) }
+    }
+
+    /// Whether `name`@`version` was fully installed by an earlier run and is
+    /// still there. `checksum` is the tarball SHA-1 the registry expects, if known.
+    pub fn is_done(&self, name: &str, version: &str, checksum: Option<&str>) -> bool {
+        let recorded = self.state.lock().unwrap().0.packages.get(&format!("{}@{}", name, version)).cloned();
+        let Some(recorded) = recorded else {
+            return false;
+        };
+        if checksum.is_some_and(|expected| !expected.is_empty() && expected != recorded) {
+            return false;
+        }
+        crate::explorer::installed_version(&self.node_modules, name).as_deref() == Some(version)
+    }
+
+    /// Note that `name`@`version` is installed, from a tarball with SHA-1 `checksum`
+    pub fn record(&self, name: &str, version: &str, checksum: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.0.packages.insert(format!("{}@{}", name, version), checksum.to_string());
+        state.1 += 1;
+        if state.1 >= SAVE_EVERY {
+            state.1 = 0;
+            let _ = write(&self.node_modules, &state.0);
+        }
+    }
+
+    /// Write what's recorded so far
+    pub fn save(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.1 = 0;
+        write(&self.node_modules, &state.0)
+    }
+
+    /// Write what's recorded after a successful install, for `lockfile`, the
+    /// crabby.lock the install is about to save and the next one starts from
+    pub fn finish(&self, lockfile: &crate::manifest::CrabbyLock) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.0.lock_hash = format!("{:x}", Sha1::digest(lockfile.to_json()?.as_bytes()));
+        state.1 = 0;
+        write(&self.node_modules, &state.0)
+    }
+}
+
+fn write(node_modules: &Path, state: &StateFile) -> Result<()> {
+    if !node_modules.is_dir() {
+        return Ok(());
+    }
+    fs::write(node_modules.join(STATE_FILE), serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+/// Forget the install progress of the project at `root`
+pub fn clear(root: &Path) -> Result<()> {
+    match fs::remove_file(root.join("node_modules").join(STATE_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_survives_until_the_lockfile_changes() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("crabby.lock"), r#"{"dependencies": {}}"#).unwrap();
+        let installed = root.join("node_modules").join("left-pad");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(installed.join("package.json"), r#"{"name": "left-pad", "version": "1.3.0"}"#).unwrap();
+
+        let progress = InstallProgress::load(root);
+        assert!(!progress.is_done("left-pad", "1.3.0", None));
+        progress.record("left-pad", "1.3.0", "abc123");
+        progress.record("right-pad", "1.0.0", "def456");
+        progress.save().unwrap();
+
+        // The next run after a failure: same lockfile
+        let progress = InstallProgress::load(root);
+        assert!(progress.is_done("left-pad", "1.3.0", None));
+        assert!(progress.is_done("left-pad", "1.3.0", Some("abc123")));
+        assert!(!progress.is_done("left-pad", "1.3.0", Some("fff000")));
+        assert!(!progress.is_done("left-pad", "1.2.0", None));
+        // Recorded, but no longer in node_modules
+        assert!(!progress.is_done("right-pad", "1.0.0", None));
+
+        // A successful install saves a new crabby.lock; progress carries over to it
+        let mut lockfile = crate::manifest::CrabbyLock::default();
+        lockfile.add_package("left-pad".into(), "1.3.0".into(), String::new(), Default::default());
+        progress.finish(&lockfile).unwrap();
+        lockfile.save_to(&root.join("crabby.lock")).unwrap();
+        assert!(InstallProgress::load(root).is_done("left-pad", "1.3.0", None));
+
+        // Any other change to crabby.lock starts over
+        fs::write(root.join("crabby.lock"), r#"{"dependencies": {}}"#).unwrap();
+        assert!(!InstallProgress::load(root).is_done("left-pad", "1.3.0", None));
+
+        clear(root).unwrap();
+        assert!(!root.join("node_modules").join(STATE_FILE).exists());
+        clear(root).unwrap();
+    }
+}
@@ -1,21 +1,122 @@
 use std::process::{Command, Stdio, Child};
-use std::path::Path;
 use std::time::Instant;
 use console::style;
-use anyhow::{Result, bail, Context};
+use anyhow::{Result, Context};
+use crate::error::{failure, ErrorKind};
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How a source file passed to `crabby run` is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// `.js`, `.mjs`, `.cjs`: straight on node
+    JavaScript,
+    /// `.ts`, `.mts`, `.cts`: through tsx
+    TypeScript,
+}
+
+/// The module system a source file is loaded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+    Esm,
+    CommonJs,
+}
+
+/// Like Node.js: `.mjs`/`.mts` are ESM, `.cjs`/`.cts` CommonJS, and anything
+/// else follows the `"type"` of the nearest package.json (CommonJS by default)
+pub fn module_format(file: &str, package_type: Option<&str>) -> ModuleFormat {
+    match std::path::Path::new(file).extension().and_then(|e| e.to_str()) {
+        Some("mjs" | "mts") => ModuleFormat::Esm,
+        Some("cjs" | "cts") => ModuleFormat::CommonJs,
+        _ if package_type == Some("module") => ModuleFormat::Esm,
+        _ => ModuleFormat::CommonJs,
+    }
+}
+
+pub fn file_kind(file: &str) -> Option<FileKind> {
+    match std::path::Path::new(file).extension()?.to_str()? {
+        "js" | "mjs" | "cjs" => Some(FileKind::JavaScript),
+        "ts" | "mts" | "cts" => Some(FileKind::TypeScript),
+        _ => None,
+    }
+}
+
+/// Command line that runs a source `file`, or `None` when it's TypeScript and
+/// tsx isn't installed (the user has been told how to get it)
+pub async fn file_command(file: &str, node: &str, assume_yes: bool) -> Result<Option<String>> {
+    match file_kind(file) {
+        Some(FileKind::TypeScript) => crate::tsx_utils::typescript_command(file, node, assume_yes).await,
+        _ => Ok(Some(format!("\"{}\" {}", node, file))),
+    }
+}
+
+/// Whether a change to `path` should restart `crabby run --listen` when no single file is watched
+pub fn should_restart(path: &std::path::Path) -> bool {
+    let path_str = path.to_string_lossy();
+    if path_str.contains("node_modules") || path_str.contains(".git") {
+        return false;
+    }
+    file_kind(&path_str).is_some() || path_str.ends_with(".json")
+}
+
+/// The command of the package.json script `name`. A missing script is an
+/// error, or `None` with `if_present` (`crabby run --if-present`).
+pub fn script_command(scripts: &std::collections::HashMap<String, String>, name: &str, if_present: bool) -> Result<Option<String>> {
+    if let Some(command) = scripts.get(name) {
+        return Ok(Some(command.clone()));
+    }
+    if if_present {
+        return Ok(None);
+    }
+    let mut available: Vec<&str> = scripts.keys().map(String::as_str).collect();
+    available.sort_unstable();
+    if available.is_empty() {
+        anyhow::bail!("Script '{}' not found: package.json has no scripts", name);
+    }
+    anyhow::bail!("Script '{}' not found in package.json. Available scripts: {}", name, available.join(", "))
+}
+
+/// Run the package.json script `name` of the project in `dir`. Returns
+/// whether there was one to run; without `if_present` a missing one fails.
+pub fn run_package_script(dir: &std::path::Path, name: &str, if_present: bool) -> Result<bool> {
+    let pkg = crate::manifest::PackageJson::load_from(&dir.join("package.json"))?;
+    match script_command(&pkg.scripts, name, if_present)? {
+        Some(command) => run_script(&command, Some(dir)).map(|_| true),
+        None => Ok(false),
+    }
+}
 
 pub fn run_script(command_str: &str, cwd: Option<&std::path::Path>) -> Result<()> {
-    run_script_impl(command_str, cwd, None)
+    run_script_impl(command_str, cwd, None, None)
 }
 
 pub fn run_script_with_node(command_str: &str, cwd: Option<&std::path::Path>, node_path: &str) -> Result<()> {
-    run_script_impl(command_str, cwd, Some(node_path))
+    run_script_impl(command_str, cwd, Some(node_path), None)
+}
+
+/// `run_script` with `bin_dir` on PATH after the `node_modules/.bin` of `cwd`
+pub fn run_script_with_bin(command_str: &str, cwd: &std::path::Path, bin_dir: &std::path::Path) -> Result<()> {
+    run_script_impl(command_str, Some(cwd), None, Some(bin_dir))
 }
 
 pub fn spawn_script(command_str: &str, cwd: Option<&std::path::Path>, node_path: Option<&str>) -> Result<Child> {
-    println!("{} {}", style("🍳 Cooking:").bold().yellow(), style(command_str).cyan());
+    if crate::reporter::human() {
+        println!("{} {}", style(crate::ui::icon("🍳 Cooking:")).bold().yellow(), style(command_str).cyan());
+    }
+    spawn_command(command_str, cwd, node_path, None)
+}
+
+/// Run `command_str` in `cwd` without showing its output; returns whether it
+/// succeeded and everything it printed. `bin_dir` goes on PATH after the
+/// `node_modules/.bin` of `cwd`.
+pub fn run_captured(command_str: &str, cwd: &std::path::Path, bin_dir: Option<&std::path::Path>) -> Result<(bool, String)> {
+    let output = spawn_command(command_str, Some(cwd), None, bin_dir)?.wait_with_output()?;
+    let mut log = String::from_utf8_lossy(&output.stdout).into_owned();
+    log.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok((output.status.success(), log))
+}
 
+fn spawn_command(command_str: &str, cwd: Option<&std::path::Path>, node_path: Option<&str>, bin_dir: Option<&std::path::Path>) -> Result<Child> {
     // Use shlex to split the command string (handles quotes)
     let parts = shlex::split(command_str).context("Failed to parse command string")?;
     let mut parts_iter = parts.iter();
@@ -34,16 +135,21 @@ pub fn spawn_script(command_str: &str, cwd: Option<&std::path::Path>, node_path:
     
     let mut paths = env::split_paths(&path_env).collect::<Vec<_>>();
     paths.insert(0, bin_path.clone());
+    if let Some(dir) = bin_dir {
+        paths.insert(1, dir.to_path_buf());
+    }
     
-    // If custom Node.js path provided, add its directory to PATH
-    if let Some(node) = node_path {
-        if let Some(parent) = std::path::Path::new(node).parent() {
-            paths.insert(0, parent.to_path_buf());
-        }
+    // Put the chosen Node.js first on PATH: the one passed in, else the
+    // installed version the project selects (see `crabby node`)
+    let node = node_path.map(std::path::PathBuf::from)
+        .or_else(|| crate::node_runtime::selected_installed_node(&working_dir));
+    if let Some(parent) = node.as_deref().and_then(|n| n.parent()).filter(|p| !p.as_os_str().is_empty()) {
+        paths.insert(0, parent.to_path_buf());
     }
     
     let new_path_env = env::join_paths(paths)?;
 
+    #[cfg_attr(not(target_os = "windows"), allow(unused_mut))]
     let mut command_name = cmd_name.to_string();
     
     #[cfg(target_os = "windows")]
@@ -61,7 +167,7 @@ pub fn spawn_script(command_str: &str, cwd: Option<&std::path::Path>, node_path:
         }
         
         // Also check node_modules/.bin specifically if not found
-        if !Path::new(&command_name).is_absolute() && !command_name.contains('/') && !command_name.contains('\\') {
+        if !std::path::Path::new(&command_name).is_absolute() && !command_name.contains('/') && !command_name.contains('\\') {
              for ext in &["cmd", "bat", "exe"] {
                 let bin_full_path = bin_path.join(format!("{}.{}", command_name, ext));
                 if bin_full_path.exists() {
@@ -85,7 +191,7 @@ pub fn spawn_script(command_str: &str, cwd: Option<&std::path::Path>, node_path:
            .stdout(Stdio::piped())
            .stderr(Stdio::piped());
 
-    Ok(command.spawn().map_err(|e| anyhow::anyhow!("Failed to execute '{}': {}", command_str, e))?)
+    command.spawn().map_err(|e| anyhow::anyhow!("Failed to execute '{}': {}", command_str, e))
 }
 
 pub fn pipe_output(child: &mut std::process::Child) -> (std::thread::JoinHandle<()>, std::thread::JoinHandle<()>) {
@@ -96,7 +202,12 @@ pub fn pipe_output(child: &mut std::process::Child) -> (std::thread::JoinHandle<
         use std::io::{Read, Write};
         let mut reader = std::io::BufReader::new(stdout);
         let mut buffer = [0; 1024];
-        let mut stdout_handle = std::io::stdout();
+        // Keep stdout clean for `--reporter json` events
+        let mut stdout_handle: Box<dyn Write> = if crate::reporter::human() {
+            Box::new(std::io::stdout())
+        } else {
+            Box::new(std::io::stderr())
+        };
         loop {
             match reader.read(&mut buffer) {
                 Ok(0) => break,
@@ -129,10 +240,90 @@ pub fn pipe_output(child: &mut std::process::Child) -> (std::thread::JoinHandle<
     (stdout_thread, stderr_thread)
 }
 
-fn run_script_impl(command_str: &str, cwd: Option<&std::path::Path>, node_path: Option<&str>) -> Result<()> {
+/// Returned by [`run_script_prefixed`] when the script was stopped through its cancel flag
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Like [`run_script`], but every output line starts with `prefix` so several
+/// scripts can share the terminal. Setting `cancel` kills the script.
+pub fn run_script_prefixed(command_str: &str, cwd: &std::path::Path, prefix: &str, cancel: &AtomicBool) -> Result<()> {
     let start = Instant::now();
+    println!("{} {} {}", prefix, style(crate::ui::icon("🍳 Cooking:")).bold().yellow(), style(command_str).cyan());
+
+    let mut child = spawn_command(command_str, Some(cwd), None, None)?;
+    let stdout = child.stdout.take().expect("Failed to open stdout");
+    let stderr = child.stderr.take().expect("Failed to open stderr");
+    let stdout_thread = prefix_lines(stdout, prefix.to_string(), false);
+    let stderr_thread = prefix_lines(stderr, prefix.to_string(), true);
 
-    let mut child = spawn_script(command_str, cwd, node_path)?;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = stdout_thread.join();
+            let _ = stderr_thread.join();
+            return Err(Cancelled.into());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    };
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+
+    if !status.success() {
+        println!("{} {} {}", prefix, style(crate::ui::icon("🔥 Burnt!")).bold().red(), style("Command failed").red());
+        return Err(failure(ErrorKind::Script, format!("Command failed with status: {}", status)));
+    }
+    println!(
+        "{} {} {} {}",
+        prefix,
+        style(crate::ui::icon("🍽️  Served!")).bold().green(),
+        style("Done in").dim(),
+        style(humantime::format_duration(start.elapsed())).bold().magenta()
+    );
+    Ok(())
+}
+
+fn prefix_lines(source: impl std::io::Read + Send + 'static, prefix: String, to_stderr: bool) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        use std::io::{BufRead, Write};
+        for line in std::io::BufReader::new(source).lines() {
+            let Ok(line) = line else { break };
+            // Lock per line so parallel scripts never interleave mid-line
+            if to_stderr || !crate::reporter::human() {
+                let _ = writeln!(std::io::stderr().lock(), "{} {}", prefix, line);
+            } else {
+                let _ = writeln!(std::io::stdout().lock(), "{} {}", prefix, line);
+            }
+        }
+    })
+}
+
+/// Kill a script started with [`spawn_script`] and block until the OS has
+/// reaped it, so a restart never overlaps the previous process
+pub fn stop_script(mut child: Child) -> Result<std::process::ExitStatus> {
+    // Fails only when the process already exited, which `wait` then collects
+    let _ = child.kill();
+    Ok(child.wait()?)
+}
+
+fn run_script_impl(command_str: &str, cwd: Option<&std::path::Path>, node_path: Option<&str>, bin_dir: Option<&std::path::Path>) -> Result<()> {
+    let start = Instant::now();
+
+    if crate::reporter::human() {
+        println!("{} {}", style(crate::ui::icon("🍳 Cooking:")).bold().yellow(), style(command_str).cyan());
+    }
+    let mut child = spawn_command(command_str, cwd, node_path, bin_dir)?;
     let (stdout_thread, stderr_thread) = pipe_output(&mut child);
 
     let status = child.wait()?;
@@ -141,21 +332,105 @@ fn run_script_impl(command_str: &str, cwd: Option<&std::path::Path>, node_path:
 
     let duration = start.elapsed();
     
-    if status.success() {
+    if !crate::reporter::human() {
+        if !status.success() {
+            return Err(failure(ErrorKind::Script, format!("Command failed with status: {}", status)));
+        }
+    } else if status.success() {
         println!(
             "{} {} {}", 
-            style("🍽️  Served!").bold().green(), 
+            style(crate::ui::icon("🍽️  Served!")).bold().green(), 
             style("Done in").dim(), 
             style(humantime::format_duration(duration)).bold().magenta()
         );
     } else {
          println!(
             "{} {}", 
-            style("🔥 Burnt!").bold().red(), 
+            style(crate::ui::icon("🔥 Burnt!")).bold().red(), 
             style("Command failed").red()
         );
-        bail!("Command failed with status: {}", status);
+        return Err(failure(ErrorKind::Script, format!("Command failed with status: {}", status)));
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_kinds() {
+        for file in ["index.js", "server.mjs", "legacy.cjs"] {
+            assert_eq!(file_kind(file), Some(FileKind::JavaScript));
+        }
+        for file in ["index.ts", "src/server.mts", "legacy.cts"] {
+            assert_eq!(file_kind(file), Some(FileKind::TypeScript));
+        }
+        assert_eq!(file_kind("build"), None);
+        assert!(should_restart(std::path::Path::new("src/server.mjs")));
+        assert!(!should_restart(std::path::Path::new("node_modules/x/index.cjs")));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_stop_script_reaps_before_returning() {
+        let child = spawn_command("sleep 30", None, None, None).unwrap();
+        let proc_entry = std::path::PathBuf::from(format!("/proc/{}", child.id()));
+        assert!(proc_entry.exists());
+
+        let status = stop_script(child).unwrap();
+        assert!(!status.success());
+        // A killed but unreaped child would linger as a zombie in /proc
+        assert!(!proc_entry.exists());
+    }
+
+    #[test]
+    fn test_missing_script_is_skipped_with_if_present() {
+        let scripts = std::collections::HashMap::from([("build".to_string(), "tsc".to_string())]);
+        assert_eq!(script_command(&scripts, "build", false).unwrap().as_deref(), Some("tsc"));
+        assert_eq!(script_command(&scripts, "lint", true).unwrap(), None);
+        let err = script_command(&scripts, "lint", false).unwrap_err();
+        assert!(err.to_string().contains("Available scripts: build"), "{}", err);
+        assert_eq!(crate::error::classify(&err).exit_code(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_missing_test_script_passes_only_when_asked() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("package.json"), r#"{"name": "app", "version": "1.0.0", "scripts": {"build": "true"}}"#).unwrap();
+        assert!(!run_package_script(tmp.path(), "test", true).unwrap());
+        let err = run_package_script(tmp.path(), "test", false).unwrap_err();
+        assert!(err.to_string().contains("Script 'test' not found"), "{}", err);
+
+        std::fs::write(tmp.path().join("package.json"), r#"{"name": "app", "version": "1.0.0", "scripts": {"test": "false"}}"#).unwrap();
+        // A failing test script fails either way
+        assert!(run_package_script(tmp.path(), "test", true).is_err());
+    }
+
+    #[test]
+    fn test_module_format() {
+        assert_eq!(module_format("a.mts", None), ModuleFormat::Esm);
+        assert_eq!(module_format("a.cts", Some("module")), ModuleFormat::CommonJs);
+        assert_eq!(module_format("a.ts", Some("module")), ModuleFormat::Esm);
+        assert_eq!(module_format("a.ts", Some("commonjs")), ModuleFormat::CommonJs);
+        assert_eq!(module_format("a.js", None), ModuleFormat::CommonJs);
+    }
+
+    #[tokio::test]
+    async fn test_runs_mjs_entry_point() {
+        let Ok(node) = which::which("node") else {
+            return;
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("main.mjs"),
+            "import { writeFileSync } from 'node:fs';\nwriteFileSync('out.txt', import.meta.url.endsWith('.mjs') ? 'esm' : 'cjs');\n",
+        ).unwrap();
+
+        let command = file_command("main.mjs", &node.to_string_lossy(), false).await.unwrap().unwrap();
+        run_script(&command, Some(tmp.path())).unwrap();
+        assert_eq!(std::fs::read_to_string(tmp.path().join("out.txt")).unwrap(), "esm");
+    }
+}
@@ -1,86 +1,91 @@
 use anyhow::{Context, Result};
 use sha1::{Sha1, Digest};
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
 
-/// Verify file integrity using SHA-1 checksum (npm registry format)
-pub fn verify_checksum(file_path: &Path, expected_checksum: Option<&str>) -> Result<bool> {
-    if expected_checksum.is_none() {
-        // No checksum provided, skip verification
-        return Ok(true);
-    }
-    
-    let expected = expected_checksum.unwrap();
-    
-    let mut file = File::open(file_path)
-        .context("Failed to open file for checksum verification")?;
-    
-    let mut hasher = Sha1::new();
-    let mut buffer = [0; 8192];
-    
-    loop {
-        let bytes_read = file.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+/// Whether `data` matches `expected`: a hex SHA-1 like the registry's
+/// `dist.shasum`, or a Subresource Integrity string like lockfiles record
+/// (`sha512-<base64>`, several separated by spaces). Fails when `expected`
+/// uses no algorithm crabby knows.
+pub fn matches_integrity(data: &[u8], expected: &str) -> Result<bool> {
+    use base64::Engine;
+    let mut known = false;
+    for hash in expected.split_whitespace() {
+        let Some((algorithm, digest)) = hash.split_once('-') else {
+            if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                known = true;
+                if format!("{:x}", Sha1::digest(data)).eq_ignore_ascii_case(hash) {
+                    return Ok(true);
+                }
+            }
+            continue;
+        };
+        let actual = match algorithm {
+            "sha512" => sha2::Sha512::digest(data).to_vec(),
+            "sha256" => sha2::Sha256::digest(data).to_vec(),
+            "sha1" => Sha1::digest(data).to_vec(),
+            _ => continue,
+        };
+        known = true;
+        // SRI allows options after a `?`
+        let digest = digest.split('?').next().unwrap_or_default();
+        if base64::engine::general_purpose::STANDARD.decode(digest).is_ok_and(|d| d == actual) {
+            return Ok(true);
         }
-        hasher.update(&buffer[..bytes_read]);
     }
-    
-    let result = hasher.finalize();
-    let actual = format!("{:x}", result);
-    
-    Ok(actual == expected)
+    if !known {
+        anyhow::bail!("Unsupported integrity value '{}'", expected);
+    }
+    Ok(false)
 }
 
+/// The `sha512-<base64>` integrity string of `data`, as crabby.lock records it
+pub fn integrity_of(data: &[u8]) -> String {
+    use base64::Engine;
+    format!("sha512-{}", base64::engine::general_purpose::STANDARD.encode(sha2::Sha512::digest(data)))
+}
 
-/// Create a backup of a file or directory
-pub fn create_backup(path: &Path) -> Result<std::path::PathBuf> {
-    use std::time::SystemTime;
-    
-    let timestamp = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)?
-        .as_secs();
-    
-    let file_name = path.file_name()
-        .map(|s| s.to_string_lossy())
-        .unwrap_or_else(|| std::borrow::Cow::from("backup"));
+/// Longest package name the npm registry accepts
+const MAX_PACKAGE_NAME_LENGTH: usize = 214;
 
-    let backup_name = format!("{}.backup.{}", 
-        file_name,
-        timestamp
-    );
-    
-    let backup_path = path.parent()
-        .unwrap_or_else(|| Path::new("."))
-        .join(backup_name);
-    
-    if path.is_file() {
-        std::fs::copy(path, &backup_path)?;
-    } else if path.is_dir() {
-        copy_dir_recursive(path, &backup_path)?;
+/// Check `name` against npm's package naming rules before it becomes a path
+/// under node_modules or part of a shim: `name` or `@scope/name`, each part
+/// made of URL-safe ASCII (`a-z`, `A-Z` for old packages, digits, `-`, `.`, `_`)
+/// and not starting with `.` or `_`.
+pub fn validate_package_name(name: &str) -> Result<()> {
+    let invalid = |reason: &str| anyhow::anyhow!("Invalid package name '{}': {}", name.escape_debug(), reason);
+
+    if name.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    if name.len() > MAX_PACKAGE_NAME_LENGTH {
+        return Err(invalid("it is longer than 214 characters"));
+    }
+    if matches!(name.to_lowercase().as_str(), "node_modules" | "favicon.ico") {
+        return Err(invalid("it is a reserved name"));
     }
-    
-    Ok(backup_path)
-}
 
-/// Recursively copy a directory
-fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
-    std::fs::create_dir_all(dst)?;
-    
-    for entry in std::fs::read_dir(src)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        
-        if file_type.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            std::fs::copy(&src_path, &dst_path)?;
+    let segments: Vec<&str> = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, package)) => vec![scope, package],
+            None => return Err(invalid("a scoped name needs the form @scope/name")),
+        },
+        None => vec![name],
+    };
+    for segment in segments {
+        if segment.is_empty() {
+            return Err(invalid("a scoped name needs the form @scope/name"));
+        }
+        if segment.starts_with('.') || segment.starts_with('_') {
+            return Err(invalid("it can't start with '.' or '_'"));
+        }
+        if let Some(c) = segment.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))) {
+            return Err(match c {
+                '/' | '\\' => invalid("only a scoped name can contain one '/'"),
+                c if c.is_whitespace() => invalid("it can't contain spaces"),
+                c if !c.is_ascii() => invalid("only ASCII letters, digits, '-', '.' and '_' are allowed"),
+                c => invalid(&format!("'{}' is not allowed", c)),
+            });
         }
     }
-    
     Ok(())
 }
 
@@ -94,31 +99,39 @@ pub fn validate_package_json(content: &str) -> Result<()> {
     Ok(())
 }
 
-/// Validate lock file integrity
-#[allow(dead_code)]
-pub fn validate_lockfile(lockfile: &crate::manifest::CrabbyLock) -> Result<()> {
-    // Check for circular dependencies
-    // Check for missing packages
-    // Validate version strings
+#[cfg(test)]
+mod tests {
+    use super::*;
     
-    for (name, info) in &lockfile.dependencies {
-        if name.is_empty() {
-            anyhow::bail!("Empty package name in lock file");
+    #[test]
+    fn test_validate_package_name() {
+        for valid in ["express", "@types/node", "lodash.merge", "JSONStream", "left-pad", "a", "@babel/core", "react-dom_2"] {
+            assert!(validate_package_name(valid).is_ok(), "{} should be valid", valid);
         }
-        
-        if info.version.is_empty() {
-            anyhow::bail!("Empty version for package: {}", name);
+
+        let traversal = ["../../evil", "..", "@scope/../evil", "@../evil", "foo/bar", "foo\\bar", "@scope/a/b", "/etc/passwd"];
+        let shell = ["a;rm -rf", "$(whoami)", "a`b`", "a\"b", "a b", "a|b", "a&b", "a\nb", "a'b"];
+        let unicode = ["ëxpress", "expreѕѕ", "pkg\u{200b}", "😀", "ｅxpress"];
+        let long = "a".repeat(215);
+        let other = ["", ".hidden", "_private", "@scope", "@/name", "@scope/", "node_modules", long.as_str()];
+        for invalid in traversal.iter().chain(&shell).chain(&unicode).chain(&other) {
+            assert!(validate_package_name(invalid).is_err(), "{:?} should be rejected", invalid);
         }
+        assert!(validate_package_name("foo/bar").unwrap_err().to_string().contains("'/'"));
+    }
+
+    #[test]
+    fn test_matches_integrity() {
+        let data = b"tarball bytes";
+        let shasum = format!("{:x}", Sha1::digest(data));
+        assert!(matches_integrity(data, &shasum).unwrap());
+        assert!(matches_integrity(data, &integrity_of(data)).unwrap());
+        assert!(matches_integrity(data, &format!("sha1-bm9wZQ== {}", integrity_of(data))).unwrap());
+        assert!(!matches_integrity(b"other bytes", &integrity_of(data)).unwrap());
+        assert!(!matches_integrity(b"other bytes", &shasum).unwrap());
+        assert!(matches_integrity(data, "md5-abc").is_err());
     }
-    
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    
     #[test]
     fn test_validate_package_json() {
         let valid_json = r#"{"name": "test", "version": "1.0.0"}"#;
@@ -18,7 +18,6 @@ struct PackageInfo {
     name: String,
     version: String,
     description: Option<String>,
-    keywords: Option<Vec<String>>,
     #[serde(rename = "links")]
     _links: Option<serde_json::Value>,
 }
@@ -34,11 +33,16 @@ pub async fn search_packages(query: &str, limit: usize) -> Result<()> {
         limit
     );
     
-    let response = reqwest::get(&url)
-        .await?
-        .error_for_status()?
-        .json::<SearchResponse>()
-        .await?;
+    let spinner = crate::ui::create_spinner("Waiting for registry.npmjs.org...");
+    let response = async {
+        reqwest::get(&url)
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await
+    }.await;
+    spinner.finish_and_clear();
+    let response = response?;
     
     if response.objects.is_empty() {
         crate::ui::print_error(&format!("No packages found for '{}'", query));
@@ -66,3 +70,70 @@ pub async fn search_packages(query: &str, limit: usize) -> Result<()> {
     
     Ok(())
 }
+
+/// Number of single-character edits that turn `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Up to `limit` of `candidates` nearest to `name` by edit distance, leaving
+/// out `name` itself and names that have little in common with it
+pub fn closest_names<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>, limit: usize) -> Vec<String> {
+    let max_distance = (name.chars().count() / 2).max(2);
+    let mut ranked: Vec<(usize, &str)> = candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    ranked.sort();
+    ranked.dedup();
+    ranked.into_iter().take(limit).map(|(_, candidate)| candidate.to_string()).collect()
+}
+
+/// Packages on the registry with names like `name`, for "did you mean" hints.
+/// Empty when the registry can't be searched.
+pub async fn similar_packages(name: &str, registry_url: &str, client: &reqwest::Client) -> Vec<String> {
+    let url = format!("{}/-/v1/search", registry_url.trim_end_matches('/'));
+    let response = async {
+        client.get(&url)
+            .query(&[("text", name), ("size", "20")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await
+    }.await;
+    let Ok(response) = response else {
+        return Vec::new();
+    };
+    let names = response["objects"].as_array().into_iter().flatten()
+        .filter_map(|object| object["package"]["name"].as_str());
+    closest_names(name, names, 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_names() {
+        assert_eq!(levenshtein("expresss", "express"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("lodash", "lodahs"), 2);
+
+        let candidates = ["express", "expressjs", "express-session", "expresss", "koa", "exprest"];
+        assert_eq!(closest_names("expresss", candidates, 3), ["express", "expressjs", "exprest"]);
+        assert!(closest_names("react", ["vue", "angular"], 3).is_empty());
+    }
+}
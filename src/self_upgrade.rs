@@ -3,45 +3,365 @@ use console::style;
 use std::process::Command;
 use std::env;
 use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+const GITHUB_LATEST_RELEASE: &str = "https://api.github.com/repos/AqwozTheDeveloper/crabby/releases/latest";
+const GITHUB_RELEASES: &str = "https://api.github.com/repos/AqwozTheDeveloper/crabby/releases";
+const GITHUB_RELEASES_PAGE: &str = "https://github.com/AqwozTheDeveloper/crabby/releases";
 const GITHUB_CARGO_TOML: &str = "https://raw.githubusercontent.com/AqwozTheDeveloper/crabby/main/Cargo.toml";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// How often the background update check asks GitHub
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
 
-pub async fn check_and_upgrade() -> Result<()> {
-    println!("{} Checking for updates...", style("🔍").bold().cyan());
+/// How `crabby upgrade --self` treats a newer version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Only report it (`--check`)
+    Check,
+    /// Ask before upgrading
+    Prompt,
+    /// Upgrade without asking (`--yes`)
+    Yes,
+}
 
-    let latest_version = fetch_latest_version().await?;
-    
-    if is_newer(&latest_version, CURRENT_VERSION) {
-        println!("{} New version available: {} (current: {})", 
-            style("✨").bold().green(), 
-            style(&latest_version).bold().yellow(),
-            style(CURRENT_VERSION).dim()
-        );
-        
-        print!("\n{} Would you like to upgrade now? (y/n): ", style("❓").bold().yellow());
-        io::stdout().flush()?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        
-        if input.trim().to_lowercase() == "y" {
-            perform_upgrade(&latest_version).await?;
-        } else {
-            println!("{} Upgrade cancelled.", style("❌").red());
+/// What to do after comparing versions
+#[derive(Debug, PartialEq, Eq)]
+enum Decision {
+    UpToDate,
+    /// Tell the user about the newer version, and leave it at that
+    Report,
+    Ask,
+    Upgrade,
+}
+
+/// Without a terminal to answer on, a prompt would block, so only report
+fn decide(latest: &str, current: &str, mode: Mode, interactive: bool) -> Decision {
+    if !is_newer(latest, current) {
+        return Decision::UpToDate;
+    }
+    match mode {
+        Mode::Check => Decision::Report,
+        Mode::Yes => Decision::Upgrade,
+        Mode::Prompt if interactive => Decision::Ask,
+        Mode::Prompt => Decision::Report,
+    }
+}
+
+/// Check for a newer crabby and upgrade as `mode` says. Returns whether a
+/// newer version exists that was not installed.
+pub async fn check_and_upgrade(mode: Mode) -> Result<bool> {
+    use std::io::IsTerminal;
+
+    println!("{} Checking for updates...", style(crate::ui::icon("🔍")).bold().cyan());
+
+    let LatestRelease { version: latest_version, tag, assets } = fetch_latest_version().await?;
+
+    let decision = decide(&latest_version, CURRENT_VERSION, mode, io::stdin().is_terminal());
+    if decision == Decision::UpToDate {
+        println!("{} Crabby is already up to date! (v{})", style(crate::ui::icon("✅")).bold().green(), CURRENT_VERSION);
+        return Ok(false);
+    }
+
+    println!("{} New version available: {} (current: {})", 
+        style(crate::ui::icon("✨")).bold().green(), 
+        style(&latest_version).bold().yellow(),
+        style(CURRENT_VERSION).dim()
+    );
+    if mode != Mode::Check {
+        match fetch_release_notes(&latest_version).await {
+            Ok(notes) => print_release_notes(&notes),
+            Err(e) => println!("{} Could not fetch the release notes: {}", style(crate::ui::icon("⚠️")).yellow(), e),
+        }
+    }
+
+    let upgrade = match decision {
+        Decision::Report => {
+            if mode == Mode::Prompt {
+                println!("{} Run {} to install it", style(crate::ui::icon("💡")).dim(), style("crabby upgrade --yes").cyan());
+            }
+            false
+        }
+        Decision::Ask => {
+            print!("\n{} Would you like to upgrade now? (y/n): ", style(crate::ui::icon("❓")).bold().yellow());
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let accepted = input.trim().to_lowercase() == "y";
+            if !accepted {
+                println!("{} Upgrade cancelled.", style(crate::ui::icon("❌")).red());
+            }
+            accepted
+        }
+        _ => true,
+    };
+    if !upgrade {
+        return Ok(true);
+    }
+
+    match pick_asset(&assets, std::env::consts::OS, std::env::consts::ARCH) {
+        Some(asset) => match checksum_asset(&assets, asset) {
+            Some(checksum) => install_prebuilt(&latest_version, asset, checksum).await?,
+            None => {
+                crate::ui::print_info(&format!("{} has no published checksum, building from source instead", asset.name));
+                perform_upgrade(&latest_version, tag.as_deref()).await?
+            }
+        },
+        None => perform_upgrade(&latest_version, tag.as_deref()).await?,
+    }
+    Ok(false)
+}
+
+/// A file attached to a GitHub release
+struct Asset {
+    name: String,
+    url: String,
+}
+
+struct LatestRelease {
+    version: String,
+    /// The release's git tag; `None` for the version on `main`
+    tag: Option<String>,
+    assets: Vec<Asset>,
+}
+
+/// The newest release. Only when GitHub can't be reached at all, the version
+/// on `main` is used instead (with no tag and no prebuilt binaries).
+async fn fetch_latest_version() -> Result<LatestRelease> {
+    let client = reqwest::Client::builder()
+        .user_agent("crabby-self-upgrade")
+        .build()?;
+
+    let response = match client.get(GITHUB_LATEST_RELEASE)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            println!("{} Could not reach the GitHub releases API ({}), checking main instead", style(crate::ui::icon("⚠️")).yellow(), e);
+            let version = fetch_main_version(&client).await?;
+            return Ok(LatestRelease { version, tag: None, assets: Vec::new() });
+        }
+    };
+    if !response.status().is_success() {
+        bail!("GitHub releases API returned {}", response.status());
+    }
+
+    let release: serde_json::Value = response.json().await?;
+    let tag = release["tag_name"].as_str()
+        .context("Latest GitHub release has no tag")?;
+    let version = parse_release_tag(tag)
+        .with_context(|| format!("Latest release tag '{}' is not a version", tag))?;
+    let assets = release["assets"].as_array().into_iter().flatten()
+        .filter_map(|asset| Some(Asset {
+            name: asset["name"].as_str()?.to_string(),
+            url: asset["browser_download_url"].as_str()?.to_string(),
+        }))
+        .collect();
+    Ok(LatestRelease { version, tag: Some(tag.to_string()), assets })
+}
+
+/// The release binary for `os` and `arch` (as in `std::env::consts`), going by
+/// the usual spellings in asset names: `crabby-x86_64-unknown-linux-gnu.tar.gz`,
+/// `crabby-macos-arm64`, `crabby-windows-x64.zip`, ...
+fn pick_asset<'a>(assets: &'a [Asset], os: &str, arch: &str) -> Option<&'a Asset> {
+    let os_names: &[&str] = match os {
+        "linux" => &["linux"],
+        "macos" => &["macos", "darwin", "apple"],
+        "windows" => &["windows", "win64", "msvc"],
+        _ => return None,
+    };
+    let arch_names: &[&str] = match arch {
+        "x86_64" => &["x86_64", "amd64", "x64"],
+        "aarch64" => &["aarch64", "arm64"],
+        _ => return None,
+    };
+    assets.iter().find(|asset| {
+        let name = asset.name.to_lowercase();
+        let is_binary = !name.ends_with(".sha256") && !name.ends_with(".txt");
+        is_binary
+            && os_names.iter().any(|os| name.contains(os))
+            && arch_names.iter().any(|arch| name.contains(arch))
+    })
+}
+
+/// The `<asset>.sha256` file published next to `binary`
+fn checksum_asset<'a>(assets: &'a [Asset], binary: &Asset) -> Option<&'a Asset> {
+    let name = format!("{}.sha256", binary.name).to_lowercase();
+    assets.iter().find(|asset| asset.name.to_lowercase() == name)
+}
+
+/// Check `bytes` against a `.sha256` file, which holds the hex digest,
+/// optionally followed by the file name (`<sha256>  <file>`)
+fn verify_checksum(bytes: &[u8], checksum_file: &str, name: &str) -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let expected = checksum_file.split_whitespace().next()
+        .with_context(|| format!("The checksum file for {} is empty", name))?
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(bytes));
+    if actual != expected {
+        return Err(crate::error::failure(
+            crate::error::ErrorKind::Integrity,
+            format!("Checksum mismatch for {}: expected {}, got {}", name, expected, actual),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+const EXE_NAME: &str = "crabby.exe";
+#[cfg(not(target_os = "windows"))]
+const EXE_NAME: &str = "crabby";
+
+/// Download a release binary (bare, `.tar.gz` or `.zip`), check it against
+/// its published `checksum` and install it
+async fn install_prebuilt(latest_version: &str, asset: &Asset, checksum: &Asset) -> Result<()> {
+    println!("{} Downloading {}...", style(crate::ui::icon("📥")).bold().blue(), asset.name);
+    let client = reqwest::Client::builder()
+        .user_agent("crabby-self-upgrade")
+        .build()?;
+    let download = |asset: &Asset| {
+        let request = client.get(&asset.url);
+        let name = asset.name.clone();
+        async move {
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                bail!("Downloading {} failed with {}", name, response.status());
+            }
+            Ok(response.bytes().await?)
         }
+    };
+    let checksum_file = download(checksum).await?;
+    let bytes = download(asset).await?;
+    verify_checksum(&bytes, &String::from_utf8_lossy(&checksum_file), &asset.name)?;
+
+    let binary = if asset.name.ends_with(".tar.gz") || asset.name.ends_with(".tgz") {
+        binary_from_tarball(&bytes)?
+    } else if asset.name.ends_with(".zip") {
+        binary_from_zip(&bytes)?
     } else {
-        println!("{} Crabby is already up to date! (v{})", style("✅").bold().green(), CURRENT_VERSION);
+        bytes.to_vec()
+    };
+
+    let bin_dir = dirs::home_dir().context("Could not find home directory")?.join(".crabby").join("bin");
+    std::fs::create_dir_all(&bin_dir)?;
+    let download = bin_dir.join(format!("{}.download", EXE_NAME));
+    std::fs::write(&download, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&download, std::fs::Permissions::from_mode(0o755))?;
     }
 
+    install_binary(&download, &bin_dir)?;
+    let _ = std::fs::remove_file(&download);
+    print_upgraded(latest_version);
     Ok(())
 }
 
-async fn fetch_latest_version() -> Result<String> {
+fn binary_from_tarball(bytes: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bytes));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name().is_some_and(|name| name == EXE_NAME) {
+            let mut binary = Vec::new();
+            entry.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
+    }
+    bail!("The release archive has no {}", EXE_NAME)
+}
+
+fn binary_from_zip(bytes: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if std::path::Path::new(file.name()).file_name().is_some_and(|name| name == EXE_NAME) {
+            let mut binary = Vec::new();
+            file.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
+    }
+    bail!("The release archive has no {}", EXE_NAME)
+}
+
+/// What a release says about itself
+#[derive(Debug, PartialEq, Eq)]
+struct ReleaseNote {
+    version: semver::Version,
+    title: String,
+    body: String,
+}
+
+/// The notes of the releases after this crabby, up to and including `latest`
+async fn fetch_release_notes(latest: &str) -> Result<Vec<ReleaseNote>> {
     let client = reqwest::Client::builder()
         .user_agent("crabby-self-upgrade")
         .build()?;
-        
+    let releases: serde_json::Value = client.get(GITHUB_RELEASES)
+        .query(&[("per_page", "100")])
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(notes_between(&releases, CURRENT_VERSION, latest))
+}
+
+/// From a GitHub `releases` listing, the ones newer than `current` and not
+/// newer than `latest`, oldest first. Drafts and untagged releases are skipped.
+fn notes_between(releases: &serde_json::Value, current: &str, latest: &str) -> Vec<ReleaseNote> {
+    let (Ok(current), Ok(latest)) = (semver::Version::parse(current), semver::Version::parse(latest)) else {
+        return Vec::new();
+    };
+    let mut notes: Vec<ReleaseNote> = releases.as_array().into_iter().flatten()
+        .filter(|release| !release["draft"].as_bool().unwrap_or(false))
+        .filter_map(|release| {
+            let tag = release["tag_name"].as_str()?;
+            let version = semver::Version::parse(&parse_release_tag(tag)?).ok()?;
+            let title = release["name"].as_str().filter(|n| !n.trim().is_empty()).unwrap_or(tag);
+            Some(ReleaseNote {
+                version,
+                title: title.trim().to_string(),
+                body: release["body"].as_str().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .filter(|note| note.version > current && note.version <= latest)
+        .collect();
+    notes.sort_by(|a, b| a.version.cmp(&b.version));
+    notes
+}
+
+/// Longer notes are cut off after this many lines, with a pointer to GitHub
+const MAX_NOTE_LINES: usize = 20;
+
+fn print_release_notes(notes: &[ReleaseNote]) {
+    for note in notes {
+        println!("\n{} {}", style(format!("v{}", note.version)).bold().cyan(), style(&note.title).bold());
+        let lines: Vec<&str> = note.body.lines().collect();
+        for line in lines.iter().take(MAX_NOTE_LINES) {
+            println!("   {}", line);
+        }
+        if lines.len() > MAX_NOTE_LINES {
+            println!("   {}", style(format!("... see {}/tag/v{}", GITHUB_RELEASES_PAGE, note.version)).dim());
+        }
+    }
+}
+
+/// The version a release tag names: `v2.10.0` and `crabby-v2.10.0` are both `2.10.0`
+fn parse_release_tag(tag: &str) -> Option<String> {
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    let version = semver::Version::parse(&tag[start..]).ok()?;
+    Some(version.to_string())
+}
+
+async fn fetch_main_version(client: &reqwest::Client) -> Result<String> {
     let content = client.get(GITHUB_CARGO_TOML)
         .send()
         .await?
@@ -69,7 +389,103 @@ fn is_newer(latest: &str, current: &str) -> bool {
     v_latest > v_current
 }
 
-async fn perform_upgrade(latest_version: &str) -> Result<()> {
+/// A background look for a newer crabby, started with the command and
+/// reported after it
+pub struct UpdateCheck(tokio::task::JoinHandle<Option<String>>);
+
+impl UpdateCheck {
+    /// Print a hint if the check found a newer version. Never waits: a check
+    /// that hasn't finished yet is dropped and retried by the next command.
+    pub async fn finish(self) {
+        if !self.0.is_finished() {
+            self.0.abort();
+            return;
+        }
+        if let Ok(Some(latest)) = self.0.await {
+            eprintln!(
+                "\n{} crabby {} available, run {}",
+                style(crate::ui::icon("✨")).yellow(),
+                style(&latest).bold(),
+                style("crabby upgrade --self").cyan()
+            );
+        }
+    }
+}
+
+/// Start the update check if it's on and the last one is more than a day old
+pub fn start_update_check() -> Option<UpdateCheck> {
+    if !update_check_enabled() {
+        return None;
+    }
+    let state_path = dirs::home_dir()?.join(".crabby").join("update-check.json");
+    let state = std::fs::read_to_string(&state_path).ok();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    if !update_check_due(state.as_deref(), now) {
+        return None;
+    }
+
+    Some(UpdateCheck(tokio::spawn(async move {
+        let latest = latest_release_version().await.ok()?;
+        // Only a check that got an answer counts toward the interval
+        let _ = save_update_check(&state_path, now, &latest);
+        is_newer(&latest, CURRENT_VERSION).then_some(latest)
+    })))
+}
+
+/// Off with CRABBY_NO_UPDATE_CHECK=1, `"updateCheck": false` in the project's
+/// or ~/.crabby/config.json, and on CI
+fn update_check_enabled() -> bool {
+    let env_flag = |name: &str| std::env::var(name).is_ok_and(|v| !matches!(v.as_str(), "" | "0" | "false"));
+    if env_flag("CRABBY_NO_UPDATE_CHECK") {
+        return false;
+    }
+    if ["CI", "CONTINUOUS_INTEGRATION", "BUILD_NUMBER", "RUN_ID", "GITHUB_ACTIONS", "GITLAB_CI", "TF_BUILD"].iter().any(|v| env_flag(v)) {
+        return false;
+    }
+    crate::config::load_config().ok().and_then(|c| c.update_check).unwrap_or(true)
+}
+
+/// Whether the last check recorded in update-check.json (`state`) is at least
+/// `UPDATE_CHECK_INTERVAL` before `now` (in seconds since the epoch)
+fn update_check_due(state: Option<&str>, now: u64) -> bool {
+    let last_check = state
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|json| json["lastCheck"].as_u64());
+    match last_check {
+        Some(last) => now.saturating_sub(last) >= UPDATE_CHECK_INTERVAL.as_secs(),
+        None => true,
+    }
+}
+
+fn save_update_check(path: &Path, now: u64, latest: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let state = serde_json::json!({ "lastCheck": now, "latest": latest });
+    std::fs::write(path, serde_json::to_string_pretty(&state)?)?;
+    Ok(())
+}
+
+/// The latest release's version, quietly and without the fallback to `main`
+async fn latest_release_version() -> Result<String> {
+    let client = reqwest::Client::builder()
+        .user_agent("crabby-self-upgrade")
+        .timeout(Duration::from_secs(5))
+        .build()?;
+    let release: serde_json::Value = client.get(GITHUB_LATEST_RELEASE)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    release["tag_name"].as_str()
+        .and_then(parse_release_tag)
+        .context("Latest GitHub release has no version tag")
+}
+
+/// Build and install `tag` (or `main` when there is none) from source
+async fn perform_upgrade(latest_version: &str, tag: Option<&str>) -> Result<()> {
     // Determine target location (same as installer)
     let home = dirs::home_dir().context("Could not find home directory")?;
     let crabby_dir = home.join(".crabby");
@@ -78,13 +494,13 @@ async fn perform_upgrade(latest_version: &str) -> Result<()> {
     
     std::fs::create_dir_all(&source_dir)?;
     
-    println!("{} Preparing source directory at {:?}...", style("📂").bold().blue(), source_dir);
+    println!("{} Preparing source directory at {:?}...", style(crate::ui::icon("📂")).bold().blue(), source_dir);
 
     // If .git doesn't exist in source_dir, clone. Otherwise, pull.
     if !source_dir.join(".git").exists() {
-        println!("{} Cloning Crabby repository...", style("📥").bold().blue());
+        println!("{} Cloning Crabby repository...", style(crate::ui::icon("📥")).bold().blue());
         let status = Command::new("git")
-            .args(&["clone", "https://github.com/AqwozTheDeveloper/crabby", "."])
+            .args(["clone", "https://github.com/AqwozTheDeveloper/crabby", "."])
             .current_dir(&source_dir)
             .status()
             .context("Failed to run 'git clone'. Ensure Git is installed.")?;
@@ -93,29 +509,29 @@ async fn perform_upgrade(latest_version: &str) -> Result<()> {
             bail!("Git clone failed. Please check your internet connection.");
         }
     } else {
-        println!("{} Updating source code...", style("📂").bold().blue());
+        println!("{} Updating source code...", style(crate::ui::icon("📂")).bold().blue());
         
-        // Reset local changes if any, to ensure clean pull
         let _ = Command::new("git")
-            .args(&["fetch", "--all"])
+            .args(["fetch", "--all", "--tags"])
             .current_dir(&source_dir)
             .status();
-            
-        let status = Command::new("git")
-            .args(&["reset", "--hard", "origin/main"])
-            .current_dir(&source_dir)
-            .status()
-            .context("Failed to run 'git reset'.")?;
-            
-        if !status.success() {
-            bail!("Failed to update source code via git reset.");
-        }
+    }
+
+    // Reset local changes if any, to build exactly the release
+    let status = Command::new("git")
+        .args(["reset", "--hard", tag.unwrap_or("origin/main")])
+        .current_dir(&source_dir)
+        .status()
+        .context("Failed to run 'git reset'.")?;
+
+    if !status.success() {
+        bail!("Failed to update source code via git reset.");
     }
     
-    println!("{} Rebuilding Crabby (this may take a minute)...", style("🔨").bold().yellow());
+    println!("{} Rebuilding Crabby (this may take a minute)...", style(crate::ui::icon("🔨")).bold().yellow());
     
     let status = Command::new("cargo")
-        .args(&["build", "--release"])
+        .args(["build", "--release"])
         .current_dir(&source_dir)
         .status()
         .context("Failed to run 'cargo build'. Ensure Rust is installed (https://rustup.rs).")?;
@@ -124,21 +540,23 @@ async fn perform_upgrade(latest_version: &str) -> Result<()> {
         bail!("Build failed. There might be a compilation error in the latest version.");
     }
     
-    println!("{} Installing new binary...", style("📦").bold().magenta());
-    
-    #[cfg(target_os = "windows")]
-    let exe_name = "crabby.exe";
-    #[cfg(not(target_os = "windows"))]
-    let exe_name = "crabby";
-    
-    let target_path = bin_dir.join(exe_name);
-    let source_path = source_dir.join("target").join("release").join(exe_name);
+    let source_path = source_dir.join("target").join("release").join(EXE_NAME);
     
     if !source_path.exists() {
         bail!("Source binary not found at {:?}. Build might have skipped the release target.", source_path);
     }
     
-    std::fs::create_dir_all(&bin_dir)?;
+    install_binary(&source_path, &bin_dir)?;
+    print_upgraded(latest_version);
+    Ok(())
+}
+
+/// Put the binary at `source_path` in place of the installed crabby
+fn install_binary(source_path: &std::path::Path, bin_dir: &std::path::Path) -> Result<()> {
+    println!("{} Installing new binary...", style(crate::ui::icon("📦")).bold().magenta());
+    
+    let target_path = bin_dir.join(EXE_NAME);
+    std::fs::create_dir_all(bin_dir)?;
     
     #[cfg(target_os = "windows")]
     {
@@ -152,10 +570,98 @@ async fn perform_upgrade(latest_version: &str) -> Result<()> {
         }
     }
     
-    std::fs::copy(&source_path, &target_path).context("Failed to copy new binary to installation directory.")?;
-    
-    println!("\n{} Crabby upgraded successfully to v{}!", style("🎉").bold().green(), latest_version);
-    println!("{} Run {} to verify the new version.", style("💡").dim(), style("crabby --version").cyan());
-    
+    std::fs::copy(source_path, &target_path).context("Failed to copy new binary to installation directory.")?;
     Ok(())
 }
+
+fn print_upgraded(latest_version: &str) {
+    println!("\n{} Crabby upgraded successfully to v{}!", style(crate::ui::icon("🎉")).bold().green(), latest_version);
+    println!("{} Run {} to verify the new version.", style(crate::ui::icon("💡")).dim(), style("crabby --version").cyan());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_release_tag() {
+        assert_eq!(parse_release_tag("v2.10.0").as_deref(), Some("2.10.0"));
+        assert_eq!(parse_release_tag("2.9.7").as_deref(), Some("2.9.7"));
+        assert_eq!(parse_release_tag("crabby-v3.0.0-beta.1").as_deref(), Some("3.0.0-beta.1"));
+        assert_eq!(parse_release_tag("nightly"), None);
+        assert!(is_newer(&parse_release_tag("v2.10.0").unwrap(), "2.9.7"));
+    }
+
+    #[test]
+    fn test_upgrade_decision() {
+        assert_eq!(decide("2.9.7", "2.9.7", Mode::Yes, true), Decision::UpToDate);
+        assert_eq!(decide("2.9.6", "2.9.7", Mode::Check, true), Decision::UpToDate);
+        assert_eq!(decide("2.10.0", "2.9.7", Mode::Check, true), Decision::Report);
+        assert_eq!(decide("2.10.0", "2.9.7", Mode::Prompt, true), Decision::Ask);
+        assert_eq!(decide("2.10.0", "2.9.7", Mode::Prompt, false), Decision::Report);
+        assert_eq!(decide("2.10.0", "2.9.7", Mode::Yes, false), Decision::Upgrade);
+        assert_eq!(decide("3.0.0-beta.1", "2.9.7", Mode::Yes, false), Decision::Upgrade);
+    }
+
+    #[test]
+    fn test_release_notes_between_versions() {
+        let releases = serde_json::json!([
+            {"tag_name": "v2.11.0", "name": "Faster installs", "body": "- Parallel extraction\n"},
+            {"tag_name": "v2.12.0", "name": "", "body": "Next one", "draft": true},
+            {"tag_name": "v2.10.0", "name": "v2.10.0", "body": "- Node version manager"},
+            {"tag_name": "v2.9.7", "name": "Current", "body": ""},
+            {"tag_name": "nightly", "name": "Nightly", "body": ""},
+        ]);
+
+        let notes = notes_between(&releases, "2.9.7", "2.11.0");
+        let versions: Vec<String> = notes.iter().map(|n| n.version.to_string()).collect();
+        assert_eq!(versions, ["2.10.0", "2.11.0"]);
+        assert_eq!(notes[1].title, "Faster installs");
+        assert_eq!(notes[1].body, "- Parallel extraction");
+        assert!(notes_between(&releases, "2.11.0", "2.11.0").is_empty());
+    }
+
+    #[test]
+    fn test_update_check_runs_once_a_day() {
+        let now = 1_700_000_000;
+        assert!(update_check_due(None, now));
+        assert!(update_check_due(Some("not json"), now));
+        assert!(!update_check_due(Some(r#"{"lastCheck": 1699990000, "latest": "2.9.7"}"#), now));
+        assert!(update_check_due(Some(r#"{"lastCheck": 1699900000, "latest": "2.9.7"}"#), now));
+    }
+
+    #[test]
+    fn test_pick_asset_for_platform() {
+        let assets: Vec<Asset> = [
+            "crabby-x86_64-unknown-linux-gnu.tar.gz",
+            "crabby-x86_64-unknown-linux-gnu.tar.gz.sha256",
+            "crabby-aarch64-apple-darwin.tar.gz",
+            "crabby-windows-x64.zip",
+        ].iter().map(|name| Asset { name: name.to_string(), url: format!("https://example.com/{}", name) }).collect();
+
+        let name = |os, arch| pick_asset(&assets, os, arch).map(|a| a.name.as_str());
+        assert_eq!(name("linux", "x86_64"), Some("crabby-x86_64-unknown-linux-gnu.tar.gz"));
+        assert_eq!(name("macos", "aarch64"), Some("crabby-aarch64-apple-darwin.tar.gz"));
+        assert_eq!(name("windows", "x86_64"), Some("crabby-windows-x64.zip"));
+        assert_eq!(name("linux", "aarch64"), None);
+        assert_eq!(name("freebsd", "x86_64"), None);
+
+        let checksum = |os, arch| checksum_asset(&assets, pick_asset(&assets, os, arch)?).map(|a| a.name.as_str());
+        assert_eq!(checksum("linux", "x86_64"), Some("crabby-x86_64-unknown-linux-gnu.tar.gz.sha256"));
+        assert_eq!(checksum("windows", "x86_64"), None);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        use sha2::{Digest, Sha256};
+
+        let binary = b"crabby binary";
+        let actual = format!("{:x}", Sha256::digest(binary));
+        assert!(verify_checksum(binary, &actual, "crabby").is_ok());
+        assert!(verify_checksum(binary, &format!("{}  crabby-x86_64-unknown-linux-gnu.tar.gz\n", actual.to_uppercase()), "crabby").is_ok());
+
+        let err = verify_checksum(binary, &"0".repeat(64), "crabby").unwrap_err();
+        assert_eq!(crate::error::classify(&err), crate::error::ErrorKind::Integrity);
+        assert!(verify_checksum(binary, "", "crabby").is_err());
+    }
+}
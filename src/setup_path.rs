@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{global, ui};
+
+/// Shells whose startup file `crabby setup-path` knows how to edit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(alias = "pwsh")]
+    Powershell,
+    /// Any other POSIX shell, configured through ~/.profile
+    Sh,
+}
+
+impl Shell {
+    /// Guess the user's shell from $SHELL, or PowerShell on Windows
+    pub fn detect() -> Self {
+        let from_env = std::env::var("SHELL").ok().and_then(|shell| {
+            let name = Path::new(&shell).file_name()?.to_str()?.to_string();
+            match name.as_str() {
+                "bash" => Some(Shell::Bash),
+                "zsh" => Some(Shell::Zsh),
+                "fish" => Some(Shell::Fish),
+                "pwsh" | "powershell" => Some(Shell::Powershell),
+                _ => None,
+            }
+        });
+        match from_env {
+            Some(shell) => shell,
+            None if cfg!(target_os = "windows") => Shell::Powershell,
+            None => Shell::Sh,
+        }
+    }
+
+    /// The startup file this shell reads for interactive sessions
+    pub fn profile(self, home: &Path) -> PathBuf {
+        match self {
+            // Terminal.app opens login shells, which read .bash_profile and not .bashrc
+            Shell::Bash if cfg!(target_os = "macos") => home.join(".bash_profile"),
+            Shell::Bash => home.join(".bashrc"),
+            Shell::Zsh => std::env::var_os("ZDOTDIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.to_path_buf())
+                .join(".zshrc"),
+            Shell::Fish => home.join(".config").join("fish").join("config.fish"),
+            Shell::Powershell if cfg!(target_os = "windows") => {
+                home.join("Documents").join("PowerShell").join("Microsoft.PowerShell_profile.ps1")
+            }
+            Shell::Powershell => home.join(".config").join("powershell").join("Microsoft.PowerShell_profile.ps1"),
+            Shell::Sh => home.join(".profile"),
+        }
+    }
+
+    /// The line that puts `bin_dir` first on PATH in this shell
+    pub fn path_line(self, bin_dir: &Path) -> String {
+        let dir = bin_dir.display();
+        match self {
+            Shell::Bash | Shell::Zsh | Shell::Sh => format!("export PATH=\"{}:$PATH\"", dir),
+            Shell::Fish => format!("fish_add_path \"{}\"", dir),
+            Shell::Powershell => format!("$env:Path = \"{}\" + [IO.Path]::PathSeparator + $env:Path", dir),
+        }
+    }
+}
+
+/// Whether `dir` is one of the entries of the current PATH
+pub fn is_on_path(dir: &Path) -> bool {
+    std::env::var_os("PATH")
+        .map(|p| std::env::split_paths(&p).any(|entry| entry == dir))
+        .unwrap_or(false)
+}
+
+/// Append `line` to `profile` unless it already mentions `bin_dir`.
+/// Returns whether the file was changed.
+pub fn add_to_profile(profile: &Path, bin_dir: &Path, line: &str) -> Result<bool> {
+    let existing = fs::read_to_string(profile).unwrap_or_default();
+    if existing.contains(&bin_dir.display().to_string()) {
+        return Ok(false);
+    }
+
+    if let Some(parent) = profile.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut content = existing;
+    if !content.is_empty() {
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+    content.push_str(&format!("# Added by crabby setup-path\n{}\n", line));
+    fs::write(profile, content).with_context(|| format!("Failed to write {}", profile.display()))?;
+    Ok(true)
+}
+
+/// Add the global bin directory to the user PATH stored in the registry,
+/// through PowerShell so that the value is not truncated like with `setx`
+fn add_to_windows_user_path(bin_dir: &Path) -> Result<()> {
+    let script = format!(
+        "$dir = '{}'; $path = [Environment]::GetEnvironmentVariable('Path', 'User'); \
+         if (-not (($path -split ';') -contains $dir)) {{ \
+         [Environment]::SetEnvironmentVariable('Path', (@($dir, $path) | Where-Object {{ $_ }}) -join ';', 'User') }}",
+        bin_dir.display().to_string().replace('\'', "''")
+    );
+    let status = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .status()
+        .context("Failed to run powershell")?;
+    if !status.success() {
+        anyhow::bail!("powershell exited with {}", status);
+    }
+    Ok(())
+}
+
+/// `crabby setup-path`: put the global bin directory on PATH for future shells
+pub fn run(shell: Option<Shell>) -> Result<()> {
+    let bin_dir = global::get_global_bin_dir()?;
+    let home = dirs::home_dir().context("Could not find your home directory")?;
+    let shell = shell.unwrap_or_else(Shell::detect);
+
+    if cfg!(target_os = "windows") && shell == Shell::Powershell {
+        use std::io::{self, Write};
+        print!("{} Add {} to your user PATH? [Y/n]: ", style(ui::icon("❓")).bold().yellow(), style(bin_dir.display()).cyan());
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if matches!(input.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+            add_to_windows_user_path(&bin_dir)?;
+            ui::print_success("Updated your user PATH. Open a new terminal to use it.");
+            return Ok(());
+        }
+    }
+
+    let profile = shell.profile(&home);
+    if add_to_profile(&profile, &bin_dir, &shell.path_line(&bin_dir))? {
+        ui::print_success(&format!("Added {} to PATH in {}", bin_dir.display(), profile.display()));
+        println!("   {} Open a new terminal, or run: {}", style(ui::icon("💡")).dim(), style(shell.path_line(&bin_dir)).cyan());
+    } else {
+        ui::print_info(&format!("{} already sets up {}", profile.display(), bin_dir.display()));
+    }
+    Ok(())
+}
+
+/// Tell the user how to get the global bin directory onto PATH, if it isn't yet
+pub fn print_hint(bin_dir: &Path) {
+    if is_on_path(bin_dir) {
+        return;
+    }
+    println!(
+        "   {} {} is not on your PATH. Run {} to add it",
+        style(ui::icon("💡")).dim(),
+        style(bin_dir.display()).cyan(),
+        style("crabby setup-path").cyan()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_to_profile_is_idempotent() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bin_dir = Path::new("/home/me/.crabby/bin");
+        let profile = Shell::Fish.profile(tmp.path());
+        let line = Shell::Fish.path_line(bin_dir);
+
+        assert!(add_to_profile(&profile, bin_dir, &line).unwrap());
+        assert!(!add_to_profile(&profile, bin_dir, &line).unwrap());
+        let content = fs::read_to_string(&profile).unwrap();
+        assert_eq!(content.matches(&line).count(), 1);
+
+        // An existing profile keeps its content and gains the line at the end
+        let bashrc = tmp.path().join(".bashrc");
+        fs::write(&bashrc, "alias ll='ls -l'").unwrap();
+        assert!(add_to_profile(&bashrc, bin_dir, &Shell::Bash.path_line(bin_dir)).unwrap());
+        let content = fs::read_to_string(&bashrc).unwrap();
+        assert!(content.starts_with("alias ll='ls -l'\n"));
+        assert!(content.ends_with("export PATH=\"/home/me/.crabby/bin:$PATH\"\n"));
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use console::style;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::cache::dir_size;
+
+/// What `crabby size` reports, in bytes
+#[derive(Debug, Serialize)]
+pub struct SizeReport {
+    pub node_modules: u64,
+    /// Every installed package, largest first
+    pub packages: Vec<PackageSize>,
+    /// Downloaded tarballs
+    pub cache: u64,
+    /// The shared package store, when `useStore` has filled it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageSize {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Sizes of `node_modules` under `root` and of the cache in `cache_dir`
+pub fn collect(root: &Path, cache_dir: &Path) -> Result<SizeReport> {
+    let node_modules = root.join("node_modules");
+    let mut packages: Vec<PackageSize> = crate::explorer::installed_packages(&node_modules)?
+        .into_iter()
+        .map(|(name, path)| PackageSize { size: dir_size(&path), name })
+        .collect();
+    packages.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+    let store_dir = cache_dir.join("store");
+    let store = dir_size(&store_dir);
+    Ok(SizeReport {
+        node_modules: dir_size(&node_modules),
+        packages,
+        cache: dir_size(cache_dir) - store,
+        store: (store > 0).then_some(store),
+    })
+}
+
+/// `crabby size`: the biggest `top` packages and the totals, as a table or JSON
+pub fn run(top: usize, json: bool) -> Result<()> {
+    let root = std::env::current_dir()?;
+    let root = crate::manifest::find_project_root(&root).unwrap_or(root);
+    let mut report = collect(&root, &crate::config::get_cache_dir()?)?;
+    report.packages.truncate(top);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    crate::ui::print_header(&format!("{} Disk usage", crate::ui::icon(crate::ui::Icons::PACKAGE)));
+    if report.packages.is_empty() {
+        crate::ui::print_info("Nothing installed in node_modules");
+    } else {
+        let share = |size: u64| match report.node_modules {
+            0 => "-".to_string(),
+            total => format!("{:.1}%", size as f64 * 100.0 / total as f64),
+        };
+        let rows: Vec<Vec<String>> = report.packages.iter()
+            .map(|p| vec![p.name.clone(), crate::ui::format_size(p.size), style(share(p.size)).dim().to_string()])
+            .collect();
+        crate::ui::print_table(&["Package", "Size", "Share"], &rows);
+    }
+
+    println!();
+    println!("  {:<13} {}", "node_modules", style(crate::ui::format_size(report.node_modules)).bold());
+    println!("  {:<13} {}", "Cache", crate::ui::format_size(report.cache));
+    if let Some(store) = report.store {
+        // Store files are hard-linked into node_modules, so they're counted there too
+        println!("  {:<13} {} {}", "Store", crate::ui::format_size(store), style("(shared with node_modules)").dim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_sizes_packages_and_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let node_modules = root.path().join("node_modules");
+        for (name, size) in [("small", 10), ("@scope/big", 300), ("medium", 100)] {
+            std::fs::create_dir_all(node_modules.join(name)).unwrap();
+            std::fs::write(node_modules.join(name).join("index.js"), vec![b'x'; size]).unwrap();
+        }
+        std::fs::create_dir_all(node_modules.join(".bin")).unwrap();
+        std::fs::write(node_modules.join(".bin").join("tool"), "#!/bin/sh").unwrap();
+
+        let cache = tempfile::tempdir().unwrap();
+        std::fs::write(cache.path().join("small-1.0.0.tgz"), vec![0; 50]).unwrap();
+
+        let report = collect(root.path(), cache.path()).unwrap();
+        let names: Vec<_> = report.packages.iter().map(|p| (p.name.as_str(), p.size)).collect();
+        assert_eq!(names, [("@scope/big", 300), ("medium", 100), ("small", 10)]);
+        assert_eq!(report.node_modules, 410 + 9);
+        assert_eq!((report.cache, report.store), (50, None));
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// A dependency that comes from a directory or a git repository instead of
+/// the registry. It's installed like a published package: its `dependencies`
+/// (not its `devDependencies`) are installed too, and its `prepare` script
+/// builds it from source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// `file:../some-dir`
+    File(PathBuf),
+    /// `git+https://…`, `git+ssh://…`, `git://…` or `github:user/repo`, with
+    /// an optional `#branch`, `#tag` or `#commit`
+    Git { url: String, committish: Option<String> },
+}
+
+impl Source {
+    /// The source `spec` names, relative paths resolved from `base`; `None` for registry ranges
+    pub fn parse(spec: &str, base: &Path) -> Option<Self> {
+        if let Some(path) = spec.strip_prefix("file:") {
+            return Some(Source::File(base.join(path)));
+        }
+        let (location, committish) = match spec.split_once('#') {
+            Some((location, committish)) => (location, Some(committish.to_string()).filter(|c| !c.is_empty())),
+            None => (spec, None),
+        };
+        let url = if let Some(url) = location.strip_prefix("git+") {
+            url.to_string()
+        } else if location.starts_with("git://") {
+            location.to_string()
+        } else if let Some(repo) = location.strip_prefix("github:") {
+            format!("https://github.com/{}.git", repo.trim_end_matches(".git"))
+        } else {
+            return None;
+        };
+        Some(Source::Git { url, committish })
+    }
+
+    /// How crabby.lock records this source; a git source is pinned to `commit`
+    pub fn lock_spec(&self, commit: Option<&str>) -> String {
+        match self {
+            Source::File(path) => format!("file:{}", path.display()),
+            Source::Git { url, committish } => match commit.or(committish.as_deref()) {
+                Some(commit) => format!("git+{}#{}", url, commit),
+                None => format!("git+{}", url),
+            },
+        }
+    }
+
+    /// The branch, tag or commit asked for, for a git source
+    pub fn committish(&self) -> Option<&str> {
+        match self {
+            Source::File(_) => None,
+            Source::Git { committish, .. } => committish.as_deref(),
+        }
+    }
+
+    /// Copy the package into `dest`, replacing what's there. Returns the commit
+    /// a git source was checked out at.
+    pub fn install_into(&self, dest: &Path) -> Result<Option<String>> {
+        if dest.exists() {
+            fs::remove_dir_all(dest).with_context(|| format!("Failed to remove {}", dest.display()))?;
+        }
+        match self {
+            Source::File(path) => {
+                if !path.join("package.json").is_file() {
+                    anyhow::bail!("{} has no package.json", path.display());
+                }
+                copy_package(path, dest)?;
+                Ok(None)
+            }
+            Source::Git { url, committish } => {
+                // A scratch clone in the cache, gone once the package is copied out
+                let clone = crate::config::get_cache_dir()?.join("git")
+                    .join(format!("{:x}-{}", Sha1::digest(self.lock_spec(None).as_bytes()), std::process::id()));
+                if clone.exists() {
+                    fs::remove_dir_all(&clone)?;
+                }
+                fs::create_dir_all(&clone)?;
+                let result = clone_at(url, committish.as_deref(), &clone)
+                    .and_then(|commit| copy_package(&clone, dest).map(|_| commit));
+                let _ = fs::remove_dir_all(&clone);
+                result.map(Some)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.lock_spec(None))
+    }
+}
+
+/// Clone `url` into `dir` at `committish` (the default branch without one)
+/// and return the commit checked out
+fn clone_at(url: &str, committish: Option<&str>, dir: &Path) -> Result<String> {
+    // Either would be read as an option by git
+    if url.starts_with('-') || committish.is_some_and(|c| c.starts_with('-')) {
+        anyhow::bail!("Refusing git dependency {}#{}: it starts with '-'", url, committish.unwrap_or_default());
+    }
+    git(&["clone", "--quiet", "--", url, "."], dir)
+        .with_context(|| format!("Failed to clone {}", url))?;
+    if let Some(committish) = committish {
+        git(&["checkout", "--quiet", committish, "--"], dir)
+            .with_context(|| format!("{} has no branch, tag or commit '{}'", url, committish))?;
+    }
+    git(&["rev-parse", "HEAD"], dir)
+}
+
+fn git(args: &[&str], dir: &Path) -> Result<String> {
+    let output = Command::new("git").args(args).current_dir(dir).output()
+        .context("git is needed for git dependencies but could not be run")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `path` relative to `base`, working on the paths as written (symlinks and
+/// `..` aren't resolved against the disk). `path` as is when they share no root.
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    fn lexical(path: &Path) -> Vec<Component<'_>> {
+        let mut parts = Vec::new();
+        for part in path.components() {
+            match part {
+                Component::CurDir => {}
+                Component::ParentDir if matches!(parts.last(), Some(Component::Normal(_))) => { parts.pop(); }
+                part => parts.push(part),
+            }
+        }
+        parts
+    }
+    let (target, from) = (lexical(path), lexical(base));
+    let common = target.iter().zip(&from).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path.to_path_buf();
+    }
+    let mut relative: PathBuf = from[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&target[common..]);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+/// Copy the package at `from` to `to`, without its node_modules and .git
+fn copy_package(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "node_modules" || name == ".git" {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_package(&entry.path(), &to.join(&name))?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), to.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources() {
+        let base = Path::new("/work/app");
+        assert_eq!(Source::parse("file:../lib", base), Some(Source::File(base.join("../lib"))));
+        assert_eq!(
+            Source::parse("github:acme/widgets#v2", base),
+            Some(Source::Git { url: "https://github.com/acme/widgets.git".into(), committish: Some("v2".into()) })
+        );
+        let git = Source::parse("git+ssh://git@example.com/acme/widgets.git", base).unwrap();
+        assert_eq!(git.lock_spec(Some("abc123")), "git+ssh://git@example.com/acme/widgets.git#abc123");
+        assert_eq!(git.committish(), None);
+        assert_eq!(Source::parse("^1.2.0", base), None);
+        assert_eq!(Source::parse("workspace:*", base), None);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let root = Path::new("/work/app");
+        assert_eq!(relative_path(Path::new("/work/app/libs/../packages/a"), root), Path::new("packages/a"));
+        assert_eq!(relative_path(Path::new("/work/shared/lib"), root), Path::new("../shared/lib"));
+        assert_eq!(relative_path(Path::new("/work/app/./"), root), Path::new("."));
+    }
+
+    #[test]
+    fn test_clone_refuses_option_like_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(clone_at("--upload-pack=touch /tmp/pwned", None, dir.path()).unwrap_err().to_string().contains("starts with '-'"));
+        assert!(clone_at("https://example.com/repo.git", Some("--orphan=x"), dir.path()).unwrap_err().to_string().contains("starts with '-'"));
+    }
+}
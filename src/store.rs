@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha512};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Global content-addressable store: every package is extracted once under
+/// `<cache>/store/<hash>` and hard-linked into each project's node_modules.
+pub fn store_dir() -> Result<PathBuf> {
+    let dir = crate::config::get_cache_dir()?.join("store");
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+/// How files ended up in node_modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    HardLink,
+    /// Hard links failed (e.g. store and project on different filesystems)
+    Copy,
+}
+
+/// Return the store entry for `tarball`, extracting it first if it isn't there yet.
+/// Entries are keyed by the sha512 of the tarball so identical contents are shared.
+pub fn ensure_extracted(store: &Path, tarball: &[u8]) -> Result<PathBuf> {
+    let hash = format!("{:x}", Sha512::digest(tarball));
+    let entry = store.join(&hash[..2]).join(&hash[2..]);
+    if entry.exists() {
+        return Ok(entry);
+    }
+
+    // Extract next to the final location and rename, so a concurrent install
+    // never sees a half-written entry
+    let parent = entry.parent().context("Invalid store path")?;
+    fs::create_dir_all(parent)?;
+    let staging = parent.join(format!(".tmp-{}-{}", &hash[2..18], std::process::id()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    crate::package_utils::extract_tarball(tarball, &staging)?;
+
+    if fs::rename(&staging, &entry).is_err() {
+        // Someone else finished the same entry first
+        fs::remove_dir_all(&staging)?;
+        if !entry.exists() {
+            anyhow::bail!("Failed to add package to the store at {}", entry.display());
+        }
+    }
+    Ok(entry)
+}
+
+/// Recreate the tree at `src` under `dst` using hard links, falling back to copies
+pub fn link_tree(src: &Path, dst: &Path) -> Result<LinkMode> {
+    let mut mode = LinkMode::HardLink;
+    link_recursive(src, dst, &mut mode)?;
+    Ok(mode)
+}
+
+fn link_recursive(src: &Path, dst: &Path, mode: &mut LinkMode) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            link_recursive(&from, &to, mode)?;
+            continue;
+        }
+        #[cfg(unix)]
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&from)?, &to)?;
+            continue;
+        }
+        if *mode == LinkMode::HardLink && fs::hard_link(&from, &to).is_ok() {
+            continue;
+        }
+        // Once one link fails (cross-device), the rest will too
+        *mode = LinkMode::Copy;
+        fs::copy(&from, &to).with_context(|| format!("Failed to copy {}", from.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::tarball;
+
+    #[test]
+    fn test_store_extracts_once_and_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("store");
+        let data = tarball(&[("package.json", r#"{"name": "demo"}"#), ("lib/index.js", "module.exports = 1;")]);
+
+        let entry = ensure_extracted(&store, &data).unwrap();
+        assert!(entry.join("lib/index.js").exists());
+        assert_eq!(ensure_extracted(&store, &data).unwrap(), entry);
+
+        let project_a = tmp.path().join("a/node_modules/demo");
+        let project_b = tmp.path().join("b/node_modules/demo");
+        assert_eq!(link_tree(&entry, &project_a).unwrap(), LinkMode::HardLink);
+        assert_eq!(link_tree(&entry, &project_b).unwrap(), LinkMode::HardLink);
+        assert_eq!(fs::read_to_string(project_b.join("lib/index.js")).unwrap(), "module.exports = 1;");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let ino = |p: &Path| fs::metadata(p).unwrap().ino();
+            assert_eq!(ino(&project_a.join("package.json")), ino(&entry.join("package.json")));
+            assert_eq!(ino(&project_b.join("package.json")), ino(&entry.join("package.json")));
+        }
+    }
+}
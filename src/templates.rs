@@ -1,5 +1,6 @@
-use anyhow::{Result, Context};
+use anyhow::Result;
 use std::path::Path;
+use std::process::Command;
 use std::fs;
 use crate::ui;
 
@@ -67,6 +68,79 @@ pub fn create_project(template_name: &str, project_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// What every Node project should keep out of git
+const GITIGNORE_ENTRIES: &[&str] = &["node_modules/", "dist/", ".env*", "*.log", ".crabby/"];
+
+/// `node_modules`, `/node_modules` and `node_modules/` ignore the same thing here
+fn gitignore_key(line: &str) -> &str {
+    line.trim().trim_matches('/')
+}
+
+/// Create `dir`/.gitignore, or add the entries it is missing without touching
+/// the rest. Returns the lines that were added.
+pub fn write_gitignore(dir: &Path) -> Result<Vec<String>> {
+    let path = dir.join(".gitignore");
+    let mut content = fs::read_to_string(&path).unwrap_or_default();
+    let present: std::collections::HashSet<&str> = content.lines().map(gitignore_key).collect();
+    let missing: Vec<String> = GITIGNORE_ENTRIES.iter()
+        .filter(|entry| !present.contains(gitignore_key(entry)))
+        .map(|entry| entry.to_string())
+        .collect();
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for line in &missing {
+        content.push_str(line);
+        content.push('\n');
+    }
+    fs::write(&path, content)?;
+    Ok(missing)
+}
+
+/// `crabby gitignore`
+pub fn run_gitignore(dir: &Path) -> Result<()> {
+    let added = write_gitignore(dir)?;
+    if added.is_empty() {
+        ui::print_info(".gitignore already has every entry");
+    } else {
+        ui::print_success(&format!("Added {} to .gitignore", added.join(", ")));
+    }
+    Ok(())
+}
+
+/// Run git with `args` in `dir`, failing when it exits unsuccessfully
+fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Turn the scaffolded `dir` into a git repository with a .gitignore and an
+/// initial commit. Returns `false` without doing anything when git isn't installed.
+pub fn init_git(dir: &Path) -> Result<bool> {
+    if Command::new("git").arg("--version").output().is_err() {
+        ui::print_warning("git is not installed, skipping repository setup");
+        return Ok(false);
+    }
+
+    write_gitignore(dir)?;
+    git(dir, &["init", "--quiet"])?;
+    git(dir, &["add", "--all"])?;
+    // Without a configured name and email the commit fails; the repository is still useful
+    if let Err(e) = git(dir, &["commit", "--quiet", "-m", "Initial commit from crabby create"]) {
+        ui::print_warning(&format!("Created the repository but not the initial commit: {}", e));
+        return Ok(true);
+    }
+    ui::print_success("Initialized a git repository with an initial commit");
+    Ok(true)
+}
+
 fn scaffold_express_ts(dir: &Path, name: &str) -> Result<()> {
     ui::print_step("🏗️", "Scaffolding Express TypeScript project...");
     
@@ -798,9 +872,52 @@ button:hover {
 
     Ok(())
 }
-fn scaffold_express(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'express-ts' for now.") }
-fn scaffold_vite_react(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-react-ts' for now.") }
-fn scaffold_vite_vue(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-vue-ts' for now.") }
-fn scaffold_vite_svelte(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-svelte-ts' for now.") }
-fn scaffold_vite_vanilla(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-vanilla-ts' for now.") }
-fn scaffold_simple_js(dir: &Path, name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'simple-ts' for now.") }
+fn scaffold_express(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'express-ts' for now.") }
+fn scaffold_vite_react(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-react-ts' for now.") }
+fn scaffold_vite_vue(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-vue-ts' for now.") }
+fn scaffold_vite_svelte(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-svelte-ts' for now.") }
+fn scaffold_vite_vanilla(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'vite-vanilla-ts' for now.") }
+fn scaffold_simple_js(_dir: &Path, _name: &str) -> Result<()> { anyhow::bail!("JS templates coming soon! Use 'simple-ts' for now.") }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_git_commits_scaffold() {
+        if Command::new("git").arg("--version").output().is_err() {
+            return;
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("app");
+        fs::create_dir(&dir).unwrap();
+        scaffold_simple_ts(&dir, "app").unwrap();
+
+        assert!(init_git(&dir).unwrap());
+        assert!(dir.join(".git").exists());
+        assert!(fs::read_to_string(dir.join(".gitignore")).unwrap().contains("node_modules/"));
+
+        // The commit needs an identity, which CI machines don't always have
+        let has_identity = Command::new("git").args(["config", "user.email"]).current_dir(&dir)
+            .output().is_ok_and(|o| o.status.success());
+        if has_identity {
+            let log = Command::new("git").args(["log", "--oneline"]).current_dir(&dir).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&log.stdout).lines().count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_write_gitignore_is_idempotent() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join(".gitignore"), "/node_modules\ncoverage").unwrap();
+
+        let added = write_gitignore(tmp.path()).unwrap();
+        assert!(!added.contains(&"node_modules/".to_string()));
+        assert!(added.contains(&".crabby/".to_string()));
+        let first = fs::read_to_string(tmp.path().join(".gitignore")).unwrap();
+        assert!(first.starts_with("/node_modules\ncoverage\n"));
+
+        assert!(write_gitignore(tmp.path()).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(tmp.path().join(".gitignore")).unwrap(), first);
+    }
+}
@@ -0,0 +1,47 @@
+//! Fixtures shared by the unit tests: a local registry and package tarballs
+
+use flate2::write::GzEncoder;
+use std::io::{BufRead, BufReader, Write};
+
+/// Start a server on a free port that answers each request with
+/// `respond(path)`: `Some(body)` for 200 OK, `None` for 404 Not Found.
+/// Returns its base URL.
+pub fn serve(respond: impl Fn(&str) -> Option<Vec<u8>> + Send + 'static) -> String {
+    serve_with_base(move |_, path| respond(path))
+}
+
+/// Like [`serve`], for responses that link back to the server (such as
+/// tarball URLs in package metadata): `respond` also gets the base URL
+pub fn serve_with_base(respond: impl Fn(&str, &str) -> Option<Vec<u8>> + Send + 'static) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let base = url.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match respond(&base, path) {
+                Some(body) => ("200 OK", body),
+                None => ("404 Not Found", Vec::new()),
+            };
+            let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+    url
+}
+
+/// A gzipped package tarball with `files` under `package/`
+pub fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, format!("package/{}", path), content.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use reqwest::StatusCode;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Give up after this many `429 Too Many Requests` in a row for one request
+const MAX_RATE_LIMIT_RETRIES: u32 = 6;
+/// Tries for a download that keeps getting cut off
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Concurrency limit for registry requests that shrinks when the registry
+/// answers `429 Too Many Requests` and grows back as requests succeed
+pub struct Throttle {
+    semaphore: Semaphore,
+    max: usize,
+    /// Current effective concurrency
+    limit: AtomicUsize,
+    /// Permits to retire as they are released, to bring concurrency down to `limit`
+    debt: AtomicUsize,
+    /// Successes since the last change of `limit`
+    streak: AtomicUsize,
+    /// Nobody sends a request before this
+    paused_until: Mutex<Option<Instant>>,
+}
+
+/// Held while a throttled response is being read
+pub struct Permit<'a> {
+    throttle: &'a Throttle,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else { return };
+        let repaid = self.throttle.debt
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |debt| debt.checked_sub(1))
+            .is_ok();
+        if repaid {
+            permit.forget();
+        }
+    }
+}
+
+/// The throttle every registry request of this process goes through
+pub fn global() -> &'static Throttle {
+    static THROTTLE: OnceLock<Throttle> = OnceLock::new();
+    THROTTLE.get_or_init(|| Throttle::new(crate::MAX_CONCURRENT_DOWNLOADS))
+}
+
+impl Throttle {
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(max),
+            max,
+            limit: AtomicUsize::new(max),
+            debt: AtomicUsize::new(0),
+            streak: AtomicUsize::new(0),
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// Send the request `build` makes, waiting out and retrying 429 responses.
+    /// Keep the returned permit until the body has been read.
+    pub async fn send(&self, build: impl Fn() -> reqwest::RequestBuilder) -> Result<(reqwest::Response, Permit<'_>)> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_pause().await;
+            let permit = Permit { throttle: self, permit: Some(self.semaphore.acquire().await?) };
+            let response = build().send().await?;
+
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                self.succeeded();
+                return Ok((response, permit));
+            }
+
+            attempt += 1;
+            if attempt > MAX_RATE_LIMIT_RETRIES {
+                anyhow::bail!("{} is still rate limiting after {} retries", response.url(), MAX_RATE_LIMIT_RETRIES);
+            }
+            let delay = retry_after(response.headers()).unwrap_or_else(|| backoff(attempt));
+            self.rate_limited(delay);
+            drop(permit);
+        }
+    }
+
+    async fn wait_for_pause(&self) {
+        loop {
+            let until = *self.paused_until.lock().unwrap();
+            match until {
+                Some(until) if until > Instant::now() => tokio::time::sleep_until(until.into()).await,
+                _ => return,
+            }
+        }
+    }
+
+    /// Halve the concurrency and hold every request back for `delay`
+    fn rate_limited(&self, delay: Duration) {
+        {
+            let mut paused = self.paused_until.lock().unwrap();
+            let until = Instant::now() + delay;
+            if paused.is_none_or(|current| current < until) {
+                *paused = Some(until);
+            }
+        }
+        self.streak.store(0, Ordering::SeqCst);
+
+        let limit = self.limit();
+        let reduced = (limit / 2).max(1);
+        if reduced < limit && self.limit.compare_exchange(limit, reduced, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            self.debt.fetch_add(limit - reduced, Ordering::SeqCst);
+        }
+    }
+
+    /// After `limit` successes in a row, allow one more concurrent request
+    fn succeeded(&self) {
+        let limit = self.limit();
+        if limit >= self.max || self.streak.fetch_add(1, Ordering::SeqCst) + 1 < limit {
+            return;
+        }
+        if self.limit.compare_exchange(limit, limit + 1, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            self.streak.store(0, Ordering::SeqCst);
+            // Cancel a permit that is still due to be retired before minting a new one
+            let cancelled = self.debt
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |debt| debt.checked_sub(1))
+                .is_ok();
+            if !cancelled {
+                self.semaphore.add_permits(1);
+            }
+        }
+    }
+}
+
+/// The delay a `Retry-After: <seconds>` header asks for
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let seconds: u64 = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_BACKOFF))
+}
+
+/// 1s, 2s, 4s, ... for the `attempt`th retry without a Retry-After header
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << (attempt - 1).min(6)).min(MAX_BACKOFF)
+}
+
+/// Download `url` into `part`, continuing after whatever an earlier attempt
+/// left there with a `Range` request, and retrying when the connection drops.
+/// Returns once `part` holds the whole body, and whether any of it came from
+/// an earlier attempt.
+pub async fn download_resumable(client: &reqwest::Client, url: &str, part: &Path) -> Result<bool> {
+    let mut resumed = false;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let have = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        resumed |= have > 0;
+        match download_from(client, url, part, have).await {
+            Ok(true) => return Ok(resumed),
+            Ok(false) if attempt < MAX_DOWNLOAD_ATTEMPTS => {}
+            Ok(false) => anyhow::bail!("Download of {} kept getting cut off", url),
+            Err(e) if attempt < MAX_DOWNLOAD_ATTEMPTS && is_interruption(&e) => {}
+            Err(e) => return Err(e),
+        }
+        tokio::time::sleep(Duration::from_millis(250) * attempt).await;
+    }
+}
+
+/// One attempt of `download_resumable`, asking for the bytes after the first
+/// `have`. Returns whether the body is complete.
+async fn download_from(client: &reqwest::Client, url: &str, part: &Path, have: u64) -> Result<bool> {
+    use std::io::Write;
+
+    let (mut response, _permit) = global().send(|| {
+        let request = client.get(url);
+        if have > 0 {
+            request.header(reqwest::header::RANGE, format!("bytes={}-", have))
+        } else {
+            request
+        }
+    }).await?;
+
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(part)?;
+    let total = match response.status() {
+        // `have` is already all there is
+        StatusCode::RANGE_NOT_SATISFIABLE if have > 0 => return Ok(true),
+        StatusCode::PARTIAL_CONTENT => content_range_total(response.headers())
+            .or_else(|| response.content_length().map(|len| have + len)),
+        _ => {
+            // The server sends everything (again); start the file over
+            response.error_for_status_ref()?;
+            file.set_len(0)?;
+            response.content_length()
+        }
+    };
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+    }
+    file.flush()?;
+
+    let len = file.metadata()?.len();
+    Ok(total.is_none_or(|total| len >= total))
+}
+
+/// The full length from a `Content-Range: bytes 0-99/1234` header
+fn content_range_total(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let value = headers.get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?;
+    value.rsplit('/').next()?.trim().parse().ok()
+}
+
+/// Errors worth another attempt: the connection failed or dropped mid-body
+pub fn is_interruption(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_timeout() || e.is_connect() || e.is_body() || e.is_request() || e.is_decode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer each connection with the next canned response
+    fn serve(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/pkg", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_retries_after_429_and_backs_off() {
+        let url = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]);
+        let throttle = Throttle::new(8);
+        let client = reqwest::Client::new();
+
+        let (response, permit) = throttle.send(|| client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ok");
+        drop(permit);
+
+        assert_eq!(throttle.limit(), 4);
+        // The retired permits are gone once they have been returned
+        assert_eq!(throttle.semaphore.available_permits() - throttle.debt.load(Ordering::SeqCst), 4);
+
+        for _ in 0..4 {
+            throttle.succeeded();
+        }
+        assert_eq!(throttle.limit(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_resumes_truncated_part_file() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/big.tgz", listener.local_addr().unwrap());
+        let (ranges_tx, ranges) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let responses = [
+                // Promises the rest of the file but the connection drops after "llo"
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 2-10/11\r\nContent-Length: 9\r\nConnection: close\r\n\r\nllo",
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-10/11\r\nContent-Length: 6\r\nConnection: close\r\n\r\n world",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let range = request.lines().find_map(|l| l.strip_prefix("range: ")).map(str::to_string);
+                ranges_tx.send(range).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let tmp = tempfile::tempdir().unwrap();
+        let part = tmp.path().join("big.tgz.part");
+        std::fs::write(&part, "he").unwrap();
+
+        let resumed = download_resumable(&reqwest::Client::new(), &url, &part).await.unwrap();
+        assert!(resumed);
+        assert_eq!(std::fs::read_to_string(&part).unwrap(), "hello world");
+        assert_eq!(ranges.recv().unwrap().as_deref(), Some("bytes=2-"));
+        assert_eq!(ranges.recv().unwrap().as_deref(), Some("bytes=5-"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+}
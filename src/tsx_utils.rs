@@ -1,6 +1,9 @@
 use anyhow::Result;
+use console::style;
 use std::path::{Path, PathBuf};
-use crate::global;
+use std::sync::OnceLock;
+use crate::runner::ModuleFormat;
+use crate::{global, manifest, node_runtime, package_utils, registry, ui};
 
 #[derive(Debug, Clone)]
 pub enum TsxCommand {
@@ -8,16 +11,69 @@ pub enum TsxCommand {
     Executable(PathBuf),
 }
 
+/// The first Node.js with `module.register`, which `--import tsx` relies on
+const IMPORT_SINCE: semver::Version = semver::Version::new(20, 6, 0);
+
+impl TsxCommand {
+    /// Command line that runs `file` through this tsx on `node`.
+    ///
+    /// With a tsx package and a known Node.js version, tsx is loaded into `node`
+    /// itself: `--import` from Node.js 20.6, before that `--loader` for ESM and
+    /// `--require` for CommonJS. Otherwise the tsx CLI is run and picks for us.
+    pub fn command_for(&self, file: &str, node: &str, node_version: Option<&semver::Version>, format: ModuleFormat) -> String {
+        match (self, node_version) {
+            (TsxCommand::NodeMjs(cli), Some(version)) => {
+                let dist = cli.parent().unwrap_or(Path::new("."));
+                let flag = if *version >= IMPORT_SINCE {
+                    format!("--import \"{}\"", file_url(&dist.join("loader.mjs")))
+                } else if format == ModuleFormat::Esm {
+                    format!("--loader \"{}\"", file_url(&dist.join("loader.mjs")))
+                } else {
+                    format!("--require \"{}\"", dist.join("cjs").join("index.cjs").to_string_lossy())
+                };
+                format!("\"{}\" {} {}", node, flag, file)
+            }
+            (TsxCommand::NodeMjs(cli), None) => format!("\"{}\" \"{}\" {}", node, cli.to_string_lossy(), file),
+            (TsxCommand::Executable(bin), _) => format!("\"{}\" {}", bin.to_string_lossy(), file),
+        }
+    }
+}
+
+/// `file://` URL for an absolute path; `--import` and `--loader` take URLs, and
+/// a bare Windows path like `C:\...` would be read as a URL with a `c:` scheme
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/").replace(' ', "%20");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+/// The tsx found by `get_tsx_command`, so that watch-mode restarts don't probe again
+static RESOLVED: OnceLock<TsxCommand> = OnceLock::new();
+
 /// Get the path to the tsx entry point
 pub fn get_tsx_command() -> Result<TsxCommand> {
+    if let Some(tsx) = RESOLVED.get() {
+        return Ok(tsx.clone());
+    }
+    let tsx = find_tsx()?;
+    Ok(RESOLVED.get_or_init(|| tsx).clone())
+}
+
+fn find_tsx() -> Result<TsxCommand> {
+    let cwd = std::env::current_dir()?;
+    let local = manifest::find_project_root(&cwd).unwrap_or(cwd).join("node_modules");
+
     // 1. Check local node_modules dist (new versions)
-    let local_mjs = Path::new("node_modules").join("tsx").join("dist").join("cli.mjs");
+    let local_mjs = local.join("tsx").join("dist").join("cli.mjs");
     if local_mjs.exists() {
         return Ok(TsxCommand::NodeMjs(local_mjs));
     }
     
     // 2. Check local node_modules bin
-    let local_bin = Path::new("node_modules").join(".bin").join("tsx");
+    let local_bin = local.join(".bin").join("tsx");
     #[cfg(target_os = "windows")]
     let local_bin = local_bin.with_extension("cmd");
     if local_bin.exists() {
@@ -26,9 +82,11 @@ pub fn get_tsx_command() -> Result<TsxCommand> {
 
     // 3. Check global .crabby/global
     if let Ok(global_dir) = global::get_global_dir() {
-        let global_mjs = global_dir.join("node_modules").join("tsx").join("dist").join("cli.mjs");
-        if global_mjs.exists() {
-            return Ok(TsxCommand::NodeMjs(global_mjs));
+        for node_modules in [global::package_prefix(&global_dir, "tsx").join("node_modules"), global_dir.join("node_modules")] {
+            let global_mjs = node_modules.join("tsx").join("dist").join("cli.mjs");
+            if global_mjs.exists() {
+                return Ok(TsxCommand::NodeMjs(global_mjs));
+            }
         }
     }
 
@@ -50,22 +108,133 @@ pub fn get_tsx_command() -> Result<TsxCommand> {
     anyhow::bail!("tsx not found locally or globally")
 }
 
-/// Check if tsx is available
-pub fn is_tsx_globally_available() -> bool {
-    get_tsx_command().is_ok()
+/// Command line for running a TypeScript `file`, or `None` when tsx isn't
+/// installed and the user didn't want to install it (they have been told how).
+/// With `assume_yes`, tsx is installed without asking.
+pub async fn typescript_command(file: &str, node: &str, assume_yes: bool) -> Result<Option<String>> {
+    let tsx = match get_tsx_command() {
+        Ok(tsx) => tsx,
+        Err(_) if offer_install(assume_yes).await? => get_tsx_command()?,
+        Err(_) => return Ok(None),
+    };
+
+    let format = file_module_format(&std::env::current_dir()?.join(file))?;
+    let node_version = node_runtime::node_version(Path::new(node));
+    Ok(Some(tsx.command_for(file, node, node_version.as_ref(), format)))
 }
 
-/// Prompt user to install tsx if not available
-pub fn ensure_tsx_available() -> Result<bool> {
-    if is_tsx_globally_available() {
-        return Ok(true);
+/// Whether Node.js loads `file` as ESM or CommonJS. Like Node.js, this goes
+/// by the package.json nearest to the file.
+fn file_module_format(file: &Path) -> Result<ModuleFormat> {
+    let package_type = match file.parent().and_then(manifest::find_project_root) {
+        Some(root) => manifest::PackageJson::load_from(&root.join("package.json"))?.module_type,
+        None => None,
+    };
+    Ok(crate::runner::module_format(&file.to_string_lossy(), package_type.as_deref()))
+}
+
+/// Ask to install tsx (as a devDependency of the current project, or globally
+/// outside of one) and install it. Returns whether tsx was installed.
+async fn offer_install(assume_yes: bool) -> Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    println!("\n{} TypeScript execution requires 'tsx'", style(ui::icon("⚠️")).yellow());
+    let accepted = if assume_yes {
+        true
+    } else if std::io::stdin().is_terminal() {
+        print!("{} tsx is required to run TypeScript — install it now? (Y/n): ", style(ui::icon("❓")).bold().yellow());
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        matches!(input.trim().to_lowercase().as_str(), "" | "y" | "yes")
+    } else {
+        false
+    };
+    if !accepted {
+        println!("{} Install it: {}", style(ui::icon("💡")).cyan(), style("crabby install tsx -D").bold());
+        return Ok(false);
+    }
+
+    match manifest::find_project_root(&std::env::current_dir()?) {
+        Some(root) => install_dev_dependency(&root).await?,
+        None => {
+            global::install_global("tsx").await?;
+        }
+    }
+    Ok(true)
+}
+
+/// Install tsx into the project at `root` and save it as a devDependency
+async fn install_dev_dependency(root: &Path) -> Result<()> {
+    let config = crate::config::load_config()?;
+    let client = registry::get_client()?;
+    let lock_path = root.join("crabby.lock");
+    let lockfile = manifest::CrabbyLock::load_from(&lock_path)?;
+
+    let (version, _, lockfile) = package_utils::install_package(
+        root, "tsx", &config.registry, &client, lockfile, &package_utils::InstallStats::new()
+    ).await?;
+    lockfile.save_to(&lock_path)?;
+
+    let pkg_path = root.join("package.json");
+    let mut pkg = manifest::PackageJson::load_from(&pkg_path)?;
+    pkg.add_dev_dependency("tsx".to_string(), format!("^{}", version));
+    pkg.save_to(&pkg_path)?;
+    ui::print_success(&format!("Installed tsx v{} as a devDependency", version));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_uses_resolved_tsx_path() {
+        let cli = PathBuf::from("/project/node_modules/tsx/dist/cli.mjs");
+        assert_eq!(
+            TsxCommand::NodeMjs(cli).command_for("src/index.ts", "/opt/node/bin/node", None, ModuleFormat::CommonJs),
+            "\"/opt/node/bin/node\" \"/project/node_modules/tsx/dist/cli.mjs\" src/index.ts"
+        );
+
+        let bin = PathBuf::from("/home/me/.crabby/bin/tsx");
+        let v22 = semver::Version::new(22, 1, 0);
+        let command = TsxCommand::Executable(bin).command_for("src/index.ts", "node", Some(&v22), ModuleFormat::Esm);
+        assert_eq!(command, "\"/home/me/.crabby/bin/tsx\" src/index.ts");
+        assert!(!command.contains("--loader") && !command.contains("--import"));
+    }
+
+    #[test]
+    fn test_loader_flag_follows_node_version_and_module_format() {
+        let tsx = TsxCommand::NodeMjs(PathBuf::from("/my app/node_modules/tsx/dist/cli.mjs"));
+        let command = |major, minor, format| {
+            tsx.command_for("src/index.ts", "node", Some(&semver::Version::new(major, minor, 0)), format)
+        };
+
+        let import = "\"node\" --import \"file:///my%20app/node_modules/tsx/dist/loader.mjs\" src/index.ts";
+        assert_eq!(command(20, 6, ModuleFormat::Esm), import);
+        assert_eq!(command(22, 0, ModuleFormat::CommonJs), import);
+        assert_eq!(
+            command(20, 5, ModuleFormat::Esm),
+            "\"node\" --loader \"file:///my%20app/node_modules/tsx/dist/loader.mjs\" src/index.ts"
+        );
+        assert_eq!(
+            command(18, 19, ModuleFormat::CommonJs),
+            "\"node\" --require \"/my app/node_modules/tsx/dist/cjs/index.cjs\" src/index.ts"
+        );
+    }
+
+    #[test]
+    fn test_module_format_follows_nearest_package_json() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("package.json"), r#"{"name":"app","version":"1.0.0","type":"module"}"#).unwrap();
+        let nested = root.path().join("legacy");
+        std::fs::create_dir_all(nested.join("src")).unwrap();
+        std::fs::write(nested.join("package.json"), r#"{"name":"legacy","version":"1.0.0"}"#).unwrap();
+
+        let format = |file: &str| file_module_format(&root.path().join(file)).unwrap();
+        assert_eq!(format("src/index.ts"), ModuleFormat::Esm);
+        assert_eq!(format("src/config.cts"), ModuleFormat::CommonJs);
+        assert_eq!(format("legacy/src/index.ts"), ModuleFormat::CommonJs);
+        assert_eq!(format("legacy/src/index.mts"), ModuleFormat::Esm);
     }
-    
-    println!("\n{} TypeScript execution requires 'tsx'", console::style("⚠️").yellow());
-    println!("{} Install it: {}", 
-        console::style("💡").cyan(),
-        console::style("crabby install tsx").bold()
-    );
-    
-    Ok(false)
 }
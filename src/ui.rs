@@ -1,7 +1,9 @@
-use dialoguer::{theme::ColorfulTheme, Select, FuzzySelect};
+use dialoguer::{theme::ColorfulTheme, Select, FuzzySelect, MultiSelect};
 use anyhow::Result;
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::reporter;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 // ========== Icon Constants ==========
 
@@ -46,48 +48,170 @@ impl Icons {
     pub const TREE_LAST: &'static str = "└─";
 }
 
+// ========== Color ==========
+
+/// Set when icons, boxes and tables are drawn with ASCII only
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// Decide once, at startup, how output looks. Color is off with `--no-color`,
+/// with `NO_COLOR` set to anything, on a `TERM=dumb` terminal, and when stdout
+/// isn't a terminal (piped or redirected to a file). Emoji and box drawing give
+/// way to ASCII with `ascii` (`--ascii` or the `ascii` setting), on terminals
+/// without them, and whenever color is off, which covers CI logs.
+pub fn init_color(no_color: bool, ascii: bool) {
+    let term = std::env::var("TERM").unwrap_or_default();
+    let enabled = color_enabled(
+        no_color,
+        std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+        term == "dumb",
+        console::Term::stdout().is_term(),
+    );
+    set_color(enabled);
+    set_ascii(ascii || !enabled || ascii_terminal(&term));
+}
+
+fn color_enabled(no_color_flag: bool, no_color_env: bool, dumb_terminal: bool, stdout_is_term: bool) -> bool {
+    // CLICOLOR_FORCE is console's way to keep colors in a pipe
+    let forced = std::env::var("CLICOLOR_FORCE").is_ok_and(|v| v != "0");
+    !no_color_flag && !no_color_env && !dumb_terminal && (stdout_is_term || forced)
+}
+
+/// Terminals whose fonts have no emoji: the Linux console and serial terminals
+fn ascii_terminal(term: &str) -> bool {
+    term == "dumb" || term == "linux" || term.starts_with("vt")
+}
+
+fn set_color(enabled: bool) {
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+fn set_ascii(ascii: bool) {
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+/// Whether output is limited to ASCII
+pub fn ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+/// `text` with every emoji and symbol in it swapped for an ASCII stand-in when
+/// output is ASCII only, e.g. `🍳 Cooking:` becomes `[run] Cooking:`
+pub fn icon(text: &str) -> std::borrow::Cow<'_, str> {
+    if !ascii() || text.is_ascii() {
+        return text.into();
+    }
+    text.split(' ')
+        .map(|word| if word.is_ascii() { word } else { ascii_icon(word) })
+        .collect::<Vec<_>>()
+        .join(" ")
+        .into()
+}
+
+fn ascii_icon(symbol: &str) -> &'static str {
+    match symbol.trim_end_matches('\u{FE0F}') {
+        "✅" | "✓" | "✔" | "🎉" | "✨" | "🍽" => "[ok]",
+        "❌" | "✗" | "🔥" => "[x]",
+        "⚠" | "🚨" => "[!]",
+        "ℹ" => "[i]",
+        "💡" => "[tip]",
+        "❓" => "[?]",
+        "📦" => "[pkg]",
+        "📥" => "[+]",
+        "🗑" => "[-]",
+        "⬆" | "🔄" => "[^]",
+        "⬇" => "[v]",
+        "🔍" | "👀" => "[?]",
+        "📋" => "[list]",
+        "🔒" | "🔐" => "[lock]",
+        "🚀" | "🍳" => "[run]",
+        "🏗" => "[build]",
+        "🧹" => "[clean]",
+        "🎯" => "[>]",
+        "📂" | "📁" => "[dir]",
+        "📄" | "📝" => "[file]",
+        "⏱" => "[time]",
+        "🔗" => "[link]",
+        "🌐" => "[net]",
+        "💾" => "[cache]",
+        "🔧" | "⚙" => "[config]",
+        "▸" | "→" => "->",
+        "↷" => "[=]",
+        "▶" => ">",
+        "•" => "-",
+        "├─" => "|-",
+        "└─" => "`-",
+        _ => "*",
+    }
+}
+
 // ========== Basic Output Functions ==========
 
+// With `--reporter json` stdout carries only events: progress lines are dropped
+// and warnings/errors go to stderr
+
 pub fn print_step(emoji: &str, message: &str) {
-    println!("{} {}", style(emoji).bold(), style(message).bold());
+    if !reporter::human() { return; }
+    println!("{} {}", style(icon(emoji)).bold(), style(message).bold());
+}
+
+fn success_line(message: &str) -> String {
+    format!("{} {}", style(icon(Icons::SUCCESS)).green(), style(message).green())
 }
 
 pub fn print_success(message: &str) {
-    println!("{} {}", style(Icons::SUCCESS).green(), style(message).green());
+    if !reporter::human() { return; }
+    println!("{}", success_line(message));
 }
 
 pub fn print_error(message: &str) {
-    println!("{} {}", style(Icons::ERROR).red(), style(message).red());
+    if !reporter::human() {
+        eprintln!("{} {}", icon(Icons::ERROR), message);
+        return;
+    }
+    println!("{} {}", style(icon(Icons::ERROR)).red(), style(message).red());
 }
 
 pub fn print_info(message: &str) {
-    println!("{} {}", style(Icons::TIP).dim(), style(message).dim());
+    if !reporter::human() { return; }
+    println!("{} {}", style(icon(Icons::TIP)).dim(), style(message).dim());
 }
 
 pub fn print_warning(message: &str) {
-    println!("{} {}", style(Icons::WARNING).yellow(), style(message).yellow());
+    if !reporter::human() {
+        eprintln!("{} {}", icon(Icons::WARNING), message);
+        return;
+    }
+    println!("{} {}", style(icon(Icons::WARNING)).yellow(), style(message).yellow());
 }
 
 // ========== Formatted Output ==========
 
+fn header_line(title: &str) -> String {
+    style(icon(title)).bold().cyan().underlined().to_string()
+}
+
 pub fn print_header(title: &str) {
-    println!("\n{}", style(title).bold().cyan().underlined());
+    println!("\n{}", header_line(title));
 }
 
+#[allow(dead_code)]
 pub fn print_section(title: &str) {
     println!("\n{}", style(title).bold());
 }
 
+#[allow(dead_code)]
 pub fn print_item(icon: &str, name: &str, version: &str) {
     println!("  {} {}  {}", 
-        style(icon).dim(),
+        style(self::icon(icon)).dim(),
         style(name).cyan().bold(),
         style(version).dim()
     );
 }
 
+#[allow(dead_code)]
 pub fn print_tree_item(is_last: bool, name: &str, version: Option<&str>) {
-    let branch = if is_last { Icons::TREE_LAST } else { Icons::TREE_BRANCH };
+    let branch = icon(if is_last { Icons::TREE_LAST } else { Icons::TREE_BRANCH });
     if let Some(v) = version {
         println!("  {} {} {}", 
             style(branch).dim(),
@@ -104,19 +228,58 @@ pub fn print_tree_item(is_last: bool, name: &str, version: Option<&str>) {
 
 // ========== Progress Bars ==========
 
+/// Progress bars and spinners are only drawn on an interactive terminal
+fn is_interactive() -> bool {
+    reporter::human() && console::Term::stdout().is_term()
+}
+
+/// Install bars, drawn together so that workspaces installing at the same
+/// time each get a line instead of overwriting each other
+fn install_bars() -> &'static MultiProgress {
+    static BARS: std::sync::OnceLock<MultiProgress> = std::sync::OnceLock::new();
+    BARS.get_or_init(MultiProgress::new)
+}
+
+/// Run `f` with the install bars cleared, so that what it prints isn't drawn over
+pub fn suspend_bars<R>(f: impl FnOnce() -> R) -> R {
+    install_bars().suspend(f)
+}
+
 pub fn create_progress_bar(total: u64, message: &str) -> ProgressBar {
-    let pb = ProgressBar::new(total);
+    if !is_interactive() {
+        return ProgressBar::hidden();
+    }
+    let pb = install_bars().add(ProgressBar::new(total));
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan} {msg} [{bar:40.cyan/blue}] {pos}/{len}")
             .unwrap()
-            .progress_chars("█▓░")
+            .progress_chars(if ascii() { "#>-" } else { "█▓░" })
+    );
+    pb.set_message(message.to_string());
+    pb
+}
+
+/// A progress bar counting bytes, for downloads of `total` bytes (0 if unknown)
+pub fn create_download_progress_bar(total: u64, message: &str) -> ProgressBar {
+    if !is_interactive() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(total);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan} {msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")
+            .unwrap()
+            .progress_chars(if ascii() { "#>-" } else { "█▓░" })
     );
     pb.set_message(message.to_string());
     pb
 }
 
 pub fn create_spinner(message: &str) -> ProgressBar {
+    if !is_interactive() {
+        return ProgressBar::hidden();
+    }
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -135,25 +298,34 @@ pub fn print_box(content: &[String]) {
         return;
     }
     
-    let max_width = content.iter().map(|s| s.len()).max().unwrap_or(0);
-    let top = format!("╭─{}─╮", "─".repeat(max_width));
-    let bottom = format!("╰─{}─╯", "─".repeat(max_width));
-    
-    println!("{}", style(top).dim());
+    for line in box_lines(content) {
+        println!("{}", line);
+    }
+}
+
+fn box_lines(content: &[String]) -> Vec<String> {
+    let max_width = content.iter().map(|s| console::measure_text_width(s)).max().unwrap_or(0);
+    let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] = if ascii() {
+        ["+", "+", "+", "+", "-", "|"]
+    } else {
+        ["╭", "╮", "╰", "╯", "─", "│"]
+    };
+    let rule = horizontal.repeat(max_width + 2);
+    let mut lines = vec![style(format!("{}{}{}", top_left, rule, top_right)).dim().to_string()];
     for line in content {
-        println!("{} {:<width$} {}", 
-            style("│").dim(),
-            line,
-            style("│").dim(),
-            width = max_width
-        );
+        lines.push(format!("{} {} {}",
+            style(vertical).dim(),
+            console::pad_str(line, max_width, console::Alignment::Left, None),
+            style(vertical).dim(),
+        ));
     }
-    println!("{}", style(bottom).dim());
+    lines.push(style(format!("{}{}{}", bottom_left, rule, bottom_right)).dim().to_string());
+    lines
 }
 
 pub fn print_package_card(name: &str, version: &str, description: Option<&str>, downloads: Option<&str>) {
     let mut lines = vec![
-        format!("{} {}  {}", Icons::PACKAGE, style(name).bold().cyan(), style(version).dim()),
+        format!("{} {}  {}", icon(Icons::PACKAGE), style(name).bold().cyan(), style(version).dim()),
     ];
     
     if let Some(desc) = description {
@@ -161,7 +333,7 @@ pub fn print_package_card(name: &str, version: &str, description: Option<&str>,
     }
     
     if let Some(dl) = downloads {
-        lines.push(format!("   {} {}", Icons::DOWNLOAD, style(dl).dim()));
+        lines.push(format!("   {} {}", icon(Icons::DOWNLOAD), style(dl).dim()));
     }
     
     print_box(&lines);
@@ -170,48 +342,45 @@ pub fn print_package_card(name: &str, version: &str, description: Option<&str>,
 // ========== Table Formatting ==========
 
 pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    for line in table_lines(headers, rows) {
+        println!("{}", line);
+    }
+}
+
+fn table_lines(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
     if rows.is_empty() {
-        return;
+        return Vec::new();
     }
     
-    // Calculate column widths
-    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    // Calculate column widths; cells may be styled, so escapes don't count
+    let mut widths: Vec<usize> = headers.iter().map(|h| console::measure_text_width(h)).collect();
     for row in rows {
         for (i, cell) in row.iter().enumerate() {
             if i < widths.len() {
-                widths[i] = widths[i].max(cell.len());
+                widths[i] = widths[i].max(console::measure_text_width(cell));
             }
         }
     }
+    let pad = |text: &str, width: usize| console::pad_str(text, width, console::Alignment::Left, None).into_owned();
     
-    // Print header
-    print!("  ");
-    for (i, header) in headers.iter().enumerate() {
-        print!("{:<width$}  ", style(header).bold(), width = widths[i]);
-    }
-    println!();
-    
-    // Print separator
-    print!("  ");
-    for width in &widths {
-        print!("{}  ", "─".repeat(*width));
-    }
-    println!();
-    
-    // Print rows
+    let header = headers.iter().enumerate()
+        .map(|(i, header)| pad(&style(header).bold().to_string(), widths[i]));
+    let separator = widths.iter().map(|width| if ascii() { "-" } else { "─" }.repeat(*width));
+    let mut lines = vec![
+        format!("  {}  ", header.collect::<Vec<_>>().join("  ")),
+        format!("  {}  ", separator.collect::<Vec<_>>().join("  ")),
+    ];
     for row in rows {
-        print!("  ");
-        for (i, cell) in row.iter().enumerate() {
-            if i < widths.len() {
-                if i == 0 {
-                    print!("{:<width$}  ", style(cell).cyan(), width = widths[i]);
-                } else {
-                    print!("{:<width$}  ", cell, width = widths[i]);
-                }
+        let cells = row.iter().take(widths.len()).enumerate().map(|(i, cell)| {
+            if i == 0 {
+                pad(&style(cell).cyan().to_string(), widths[i])
+            } else {
+                pad(cell, widths[i])
             }
-        }
-        println!();
+        });
+        lines.push(format!("  {}  ", cells.collect::<Vec<_>>().join("  ")));
     }
+    lines
 }
 
 // ========== Formatting Helpers ==========
@@ -245,6 +414,7 @@ pub fn format_duration(ms: u64) -> String {
     }
 }
 
+#[allow(dead_code)]
 pub fn format_number(num: u64) -> String {
     let s = num.to_string();
     let mut result = String::new();
@@ -273,6 +443,26 @@ pub fn prompt_selection(items: &[String], prompt: &str) -> Result<Option<usize>>
     Ok(selection)
 }
 
+/// Let the user tick any number of `items`, all ticked to begin with
+pub fn prompt_multi_selection(items: &[String], prompt: &str) -> Result<Option<Vec<usize>>> {
+    prompt_multi_selection_with(items, &vec![true; items.len()], prompt)
+}
+
+/// `prompt_multi_selection` with the items in `defaults` ticked to begin with
+pub fn prompt_multi_selection_with(items: &[String], defaults: &[bool], prompt: &str) -> Result<Option<Vec<usize>>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    let selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .defaults(defaults)
+        .interact_opt()?;
+
+    Ok(selection)
+}
+
 pub fn prompt_fuzzy_selection(items: &[String], prompt: &str) -> Result<Option<usize>> {
     if items.is_empty() {
         return Ok(None);
@@ -286,3 +476,88 @@ pub fn prompt_fuzzy_selection(items: &[String], prompt: &str) -> Result<Option<u
 
     Ok(selection)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    static MODE: Mutex<()> = Mutex::new(());
+
+    /// Color and ASCII output for one test, put back when dropped. The mode is
+    /// global, so tests that set it take turns.
+    struct OutputMode {
+        color: bool,
+        ascii: bool,
+        _turn: MutexGuard<'static, ()>,
+    }
+
+    fn output_mode(color: bool, ascii: bool) -> OutputMode {
+        let turn = MODE.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = OutputMode { color: console::colors_enabled(), ascii: super::ascii(), _turn: turn };
+        set_color(color);
+        set_ascii(ascii);
+        previous
+    }
+
+    impl Drop for OutputMode {
+        fn drop(&mut self) {
+            set_color(self.color);
+            set_ascii(self.ascii);
+        }
+    }
+
+    #[test]
+    fn test_plain_output_has_no_escapes() {
+        let _mode = output_mode(false, true);
+        let line = success_line("done");
+        assert!(!line.contains('\x1b'), "{:?}", line);
+        assert_eq!(line, "[ok] done");
+        assert_eq!(style("x").red().bold().to_string(), "x");
+        assert_eq!(icon(Icons::TREE_LAST), "`-");
+        assert_eq!(icon("warn"), "warn");
+
+        assert!(!color_enabled(true, false, false, true));
+        assert!(!color_enabled(false, true, false, true));
+        assert!(!color_enabled(false, false, true, true));
+        assert!(color_enabled(false, false, false, true));
+    }
+
+    #[test]
+    fn test_ascii_mode_is_ascii_only() {
+        let _mode = output_mode(false, true);
+        let card = [format!("{} left-pad  1.0.0", icon(Icons::PACKAGE)), format!("   {} 12k/week", icon(Icons::DOWNLOAD))];
+        let table = [vec!["left-pad".to_string(), format!("1.0.0 {} 2.0.0", icon("→"))]];
+        let mut output = box_lines(&card);
+        output.extend(table_lines(&["Package", "Update"], &table));
+        output.push(icon("🍽️  Served! Done in").into_owned());
+        output.push(header_line(&format!("{} Updates available", Icons::UPDATE)));
+        for line in &output {
+            assert!(line.is_ascii(), "{:?}", line);
+        }
+        assert_eq!(console::strip_ansi_codes(&output[0]), "+-----------------------+");
+        assert_eq!(console::strip_ansi_codes(&output[1]), "| [pkg] left-pad  1.0.0 |");
+        assert_eq!(console::strip_ansi_codes(&output[2]), "|    [v] 12k/week       |");
+        assert!(output[output.len() - 2].starts_with("[ok]  Served!"));
+        assert_eq!(console::strip_ansi_codes(output.last().unwrap()), "[^] Updates available");
+        assert!(ascii_terminal("linux") && !ascii_terminal("xterm-256color"));
+    }
+
+    #[test]
+    fn test_box_and_table_pad_by_display_width() {
+        let _mode = output_mode(false, false);
+        // Two columns per CJK character and emoji, one byte-wise
+        let card = ["📦 left-pad  1.0.0".to_string(), "   左寄せのための文字列パディング".to_string(), "   plain".to_string()];
+        let lines = box_lines(&card);
+        let widths: Vec<usize> = lines.iter().map(|l| console::measure_text_width(l)).collect();
+        assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+        assert_eq!(widths[0], 2 + 3 + 30 + 2);
+
+        let rows = [vec!["左パッド".to_string(), "1.0.0".to_string()], vec!["pad".to_string(), "2.0.0".to_string()]];
+        let lines = table_lines(&["名前", "Version"], &rows);
+        let column = |line: &str| console::strip_ansi_codes(line).find(['1', '2', 'V', '-']).map(|i| console::measure_text_width(&console::strip_ansi_codes(line)[..i]));
+        assert_eq!(column(&lines[0]), Some(12));
+        assert_eq!(column(&lines[2]), Some(12));
+        assert_eq!(column(&lines[3]), Some(12));
+    }
+}
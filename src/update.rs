@@ -12,57 +12,470 @@ struct RegistryPackage {
 
 #[derive(Debug, Deserialize)]
 struct VersionInfo {
-    version: String,
     description: Option<String>,
 }
 
 pub async fn update_package(name: &str, registry: &str) -> Result<(String, String)> {
-    println!("{} Checking for updates to {}...", style("🔍").dim(), name);
-    
+    let spinner = crate::ui::create_spinner(&format!("Checking for updates to {}...", name));
     let client = crate::registry::get_client()?;
-    let (latest, tarball, _) = crate::package_utils::fetch_package_version(name, registry, None, &client).await?;
+    let result = crate::package_utils::fetch_package_version(name, registry, None, &client).await;
+    spinner.finish_and_clear();
+    let (latest, tarball, _) = result?;
     
-    println!("{} Latest version: {}", style("📌").dim(), latest);
+    println!("{} Latest version: {}", style(crate::ui::icon("📌")).dim(), latest);
     
     Ok((latest, tarball))
 }
 
-/// Check which packages are outdated
-pub async fn check_outdated(registry: &str) -> Result<Vec<(String, String, String)>> {
+/// One row of `crabby update --dry-run`
+pub struct PlannedUpdate {
+    pub name: String,
+    /// Locked version, or the declared range when nothing is locked yet
+    pub current: String,
+    pub new: String,
+}
+
+/// Resolve what `crabby update` would move each package to, without changing
+/// anything: the newest version within its declared range, or with `latest`
+/// the newest release
+pub async fn plan_updates(names: &[String], registry: &str, latest: bool) -> Result<Vec<PlannedUpdate>> {
     let pkg_json = crate::manifest::PackageJson::load()?;
-    let mut outdated = Vec::new();
-    
-    for (name, current_version) in &pkg_json.dependencies {
-        let current = current_version.trim_start_matches('^');
-        
-        match update_package(name, registry).await {
-            Ok((latest, _)) => {
-                if latest != current {
-                    outdated.push((name.clone(), current.to_string(), latest));
-                }
+    let lock = crate::manifest::CrabbyLock::load()?;
+    plan_updates_in(&pkg_json, &lock, &std::env::current_dir()?, names, registry, latest).await
+}
+
+/// `plan_updates` for a given package.json and lock in `root`. Packages are
+/// resolved concurrently; `file:`, git and workspace dependencies are skipped.
+async fn plan_updates_in(pkg_json: &crate::manifest::PackageJson, lock: &crate::manifest::CrabbyLock, root: &std::path::Path, names: &[String], registry: &str, latest: bool) -> Result<Vec<PlannedUpdate>> {
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    let declared = pkg_json.get_all_dependencies();
+    let mut wanted = Vec::new();
+    for name in names {
+        let range = declared.get(name);
+        let current = match (lock.dependencies.get(name), range) {
+            (Some(locked), _) => locked.version.clone(),
+            (None, Some(range)) => range.clone(),
+            (None, None) => anyhow::bail!("{} is not a dependency of this project", name),
+        };
+        if range.is_some_and(|r| r.starts_with(crate::manifest::LockDependency::WORKSPACE_PREFIX) || crate::source::Source::parse(r, root).is_some()) {
+            continue;
+        }
+        let req = if latest { None } else { range.cloned() };
+        wanted.push((name.clone(), current, req));
+    }
+
+    let client = crate::registry::get_client()?;
+    let spinner = crate::ui::create_spinner("Resolving new versions...");
+    let result: Result<Vec<PlannedUpdate>> = stream::iter(wanted)
+        .map(|(name, current, req)| {
+            let client = &client;
+            async move {
+                let (new, _, _) = crate::package_utils::fetch_package_version(&name, registry, req.as_deref(), client).await?;
+                Ok(PlannedUpdate { name, current, new })
+            }
+        })
+        .buffer_unordered(crate::MAX_CONCURRENT_DOWNLOADS)
+        .try_collect()
+        .await;
+    spinner.finish_and_clear();
+
+    let mut planned = result?;
+    planned.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(planned)
+}
+
+/// Classify a version change as `major`, `minor`, `patch` or `prerelease`
+pub fn bump_kind(current: &str, new: &str) -> &'static str {
+    let parse = |v: &str| semver::Version::parse(v.trim_start_matches(['^', '~', '=', 'v'])).ok();
+    match (parse(current), parse(new)) {
+        (Some(a), Some(b)) if a == b => "none",
+        (Some(a), Some(b)) if a.major != b.major => "major",
+        (Some(a), Some(b)) if a.minor != b.minor => "minor",
+        (Some(a), Some(b)) if a.patch != b.patch => "patch",
+        (Some(_), Some(_)) => "prerelease",
+        _ => "unknown",
+    }
+}
+
+/// Packages to check for updates with their current versions: what
+/// package.json declares, at the version crabby.lock has for it, and with
+/// `transitive` the other packages in crabby.lock too
+fn outdated_candidates(pkg_json: &crate::manifest::PackageJson, lock: &crate::manifest::CrabbyLock, transitive: bool) -> Vec<(String, String)> {
+    let declared = pkg_json.get_all_dependencies();
+    let mut candidates: Vec<(String, String)> = declared.iter()
+        .filter(|(_, range)| !range.starts_with(crate::manifest::LockDependency::WORKSPACE_PREFIX))
+        .filter(|(_, range)| crate::source::Source::parse(range, std::path::Path::new("")).is_none())
+        .map(|(name, range)| {
+            let current = match lock.dependencies.get(name) {
+                Some(locked) => locked.version.clone(),
+                None => range.trim_start_matches(['^', '~']).to_string(),
+            };
+            (name.clone(), current)
+        })
+        .collect();
+    if transitive {
+        candidates.extend(lock.dependencies.iter()
+            .filter(|(name, dep)| !declared.contains_key(*name) && !dep.is_workspace_link() && !dep.is_source())
+            .map(|(name, dep)| (name.clone(), dep.version.clone())));
+    }
+    candidates.sort();
+    candidates
+}
+
+/// A dependency declared in package.json that has a newer version, for
+/// `crabby outdated --interactive`
+#[derive(Debug, Clone)]
+pub struct OutdatedChoice {
+    pub name: String,
+    /// Locked version, or the declared range without its operator
+    pub current: String,
+    /// The range package.json declares
+    pub range: String,
+    /// Newest version `range` allows
+    pub wanted: Option<String>,
+    pub latest: String,
+}
+
+/// Which version a picked row updates to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The newest version the declared range allows; package.json stays as is
+    Wanted,
+    /// The latest version, saved as `^latest` when the range doesn't allow it
+    Latest,
+}
+
+/// An update the user picked
+#[derive(Debug, PartialEq)]
+pub struct ChosenUpdate {
+    pub name: String,
+    pub version: String,
+    /// Range to save in package.json, `None` to keep the declared one
+    pub range: Option<String>,
+}
+
+/// The direct dependencies with a newer wanted or latest version
+pub async fn outdated_choices(registry: &str) -> Result<Vec<OutdatedChoice>> {
+    let pkg_json = crate::manifest::PackageJson::load()?;
+    let lock = crate::manifest::CrabbyLock::load()?;
+    let client = crate::registry::get_client()?;
+    let declared = pkg_json.get_all_dependencies();
+
+    let spinner = crate::ui::create_spinner("Checking for updates...");
+    let mut choices = Vec::new();
+    for (name, current) in outdated_candidates(&pkg_json, &lock, false) {
+        let Some(range) = declared.get(&name).cloned() else { continue };
+        let Ok((latest, _, _)) = crate::package_utils::fetch_package_version(&name, registry, None, &client).await else {
+            continue;
+        };
+        let wanted = match semver::VersionReq::parse(&range) {
+            Ok(_) => crate::package_utils::fetch_package_version(&name, registry, Some(&range), &client).await.ok().map(|(v, _, _)| v),
+            Err(_) => None,
+        };
+        if latest != current || wanted.as_ref().is_some_and(|w| *w != current) {
+            choices.push(OutdatedChoice { name, current, range, wanted, latest });
+        }
+    }
+    spinner.finish_and_clear();
+    Ok(choices)
+}
+
+/// The rows to pick from: per package the wanted version when it's newer and
+/// not the latest, and the latest version when it's newer. Each row is the
+/// index into `choices`, its target, and whether it starts ticked (updates
+/// that keep the declared range).
+pub fn selection_rows(choices: &[OutdatedChoice]) -> Vec<(usize, Target, bool)> {
+    let mut rows = Vec::new();
+    for (i, choice) in choices.iter().enumerate() {
+        if let Some(wanted) = &choice.wanted {
+            if *wanted != choice.current && *wanted != choice.latest {
+                rows.push((i, Target::Wanted, true));
             }
-            Err(_) => continue,
+        }
+        if choice.latest != choice.current {
+            let in_range = crate::package_utils::satisfies(&choice.range, &choice.latest);
+            rows.push((i, Target::Latest, in_range));
         }
     }
-    
+    rows
+}
+
+/// The updates for the `picked` indexes into `rows`. When both rows of a
+/// package are picked, latest wins.
+pub fn chosen_updates(choices: &[OutdatedChoice], rows: &[(usize, Target, bool)], picked: &[usize]) -> Vec<ChosenUpdate> {
+    let mut targets: Vec<(usize, Target)> = Vec::new();
+    for (index, target, _) in picked.iter().filter_map(|p| rows.get(*p)) {
+        match targets.iter_mut().find(|(i, _)| i == index) {
+            Some(existing) if *target == Target::Latest => existing.1 = Target::Latest,
+            Some(_) => {}
+            None => targets.push((*index, *target)),
+        }
+    }
+    targets.into_iter().map(|(index, target)| {
+        let choice = &choices[index];
+        match target {
+            Target::Wanted => ChosenUpdate {
+                name: choice.name.clone(),
+                version: choice.wanted.clone().unwrap_or_else(|| choice.latest.clone()),
+                range: None,
+            },
+            Target::Latest => ChosenUpdate {
+                name: choice.name.clone(),
+                version: choice.latest.clone(),
+                range: (!crate::package_utils::satisfies(&choice.range, &choice.latest))
+                    .then(|| format!("^{}", choice.latest)),
+            },
+        }
+    }).collect()
+}
+
+/// `crabby outdated --interactive`: pick outdated packages and update them
+pub async fn update_interactively(registry: &str) -> Result<()> {
+    let choices = outdated_choices(registry).await?;
+    if choices.is_empty() {
+        crate::ui::print_success("All packages are up to date!");
+        return Ok(());
+    }
+
+    let rows = selection_rows(&choices);
+    let items: Vec<String> = rows.iter().map(|(index, target, _)| {
+        let choice = &choices[*index];
+        match target {
+            Target::Wanted => format!("{} {} {} {} (newest in {})", choice.name, choice.current, crate::ui::icon("→"), choice.wanted.as_deref().unwrap_or_default(), choice.range),
+            Target::Latest if crate::package_utils::satisfies(&choice.range, &choice.latest) => {
+                format!("{} {} {} {} (latest)", choice.name, choice.current, crate::ui::icon("→"), choice.latest)
+            }
+            Target::Latest => format!("{} {} {} {} (latest, saves ^{})", choice.name, choice.current, crate::ui::icon("→"), choice.latest, choice.latest),
+        }
+    }).collect();
+    let defaults: Vec<bool> = rows.iter().map(|(_, _, ticked)| *ticked).collect();
+    let Some(picked) = crate::ui::prompt_multi_selection_with(&items, &defaults, "Select updates (space to toggle)")? else {
+        return Ok(());
+    };
+    let updates = chosen_updates(&choices, &rows, &picked);
+    if updates.is_empty() {
+        crate::ui::print_info("Nothing selected");
+        return Ok(());
+    }
+
+    crate::backup::create("outdated --interactive", crate::config::load_config()?.backup_limit())?;
+    let root = std::env::current_dir()?;
+    let client = crate::registry::get_client()?;
+    let stats = crate::package_utils::InstallStats::new();
+    let mut lockfile = crate::manifest::CrabbyLock::load()?;
+    let mut pkg_json = crate::manifest::PackageJson::load()?;
+    for update in &updates {
+        let (version, _, updated) = crate::package_utils::install_package_matching(
+            &root, &update.name, Some(&format!("={}", update.version)), registry, &client, lockfile, &stats
+        ).await.with_context(|| format!("Failed to update {}", update.name))?;
+        lockfile = updated;
+        if let Some(range) = &update.range {
+            pkg_json.set_range(&update.name, range);
+        }
+        crate::ui::print_success(&format!("Updated {} to {}", update.name, version));
+    }
+    lockfile.save()?;
+    pkg_json.save()?;
+    Ok(())
+}
+
+/// Where in package.json an outdated package is declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyType {
+    Prod,
+    Optional,
+    Dev,
+    /// Only in crabby.lock, as a dependency of a dependency
+    Transitive,
+}
+
+impl DependencyType {
+    fn of(pkg_json: &crate::manifest::PackageJson, name: &str) -> Self {
+        if pkg_json.dependencies.contains_key(name) {
+            Self::Prod
+        } else if pkg_json.optional_dependencies.contains_key(name) {
+            Self::Optional
+        } else if pkg_json.dev_dependencies.contains_key(name) {
+            Self::Dev
+        } else {
+            Self::Transitive
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Prod => "production",
+            Self::Optional => "optional",
+            Self::Dev => "dev",
+            Self::Transitive => "transitive",
+        }
+    }
+}
+
+/// Which declared packages `crabby outdated` looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutdatedFilter {
+    All,
+    /// dependencies and optionalDependencies (--prod)
+    Prod,
+    /// devDependencies (--dev)
+    Dev,
+}
+
+/// A package with a newer version than the one installed
+#[derive(Debug, PartialEq)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub current: String,
+    pub latest: String,
+    pub kind: DependencyType,
+}
+
+/// Check which packages are outdated: the direct dependencies, and with
+/// `transitive` every other package in crabby.lock
+pub async fn check_outdated(registry: &str, transitive: bool, filter: OutdatedFilter) -> Result<Vec<OutdatedPackage>> {
+    let pkg_json = crate::manifest::PackageJson::load()?;
+    let lock = crate::manifest::CrabbyLock::load()?;
+    outdated_in(&pkg_json, &lock, transitive, filter, registry).await
+}
+
+async fn outdated_in(pkg_json: &crate::manifest::PackageJson, lock: &crate::manifest::CrabbyLock, transitive: bool, filter: OutdatedFilter, registry: &str) -> Result<Vec<OutdatedPackage>> {
+    let candidates = outdated_candidates(pkg_json, lock, transitive).into_iter()
+        .filter(|(name, _)| match (filter, DependencyType::of(pkg_json, name)) {
+            (OutdatedFilter::All, _) => true,
+            (OutdatedFilter::Prod, kind) => matches!(kind, DependencyType::Prod | DependencyType::Optional),
+            (OutdatedFilter::Dev, kind) => kind == DependencyType::Dev,
+        })
+        .collect();
+    Ok(outdated_among(candidates, registry).await?
+        .into_iter()
+        .map(|(name, current, latest)| OutdatedPackage { kind: DependencyType::of(pkg_json, &name), name, current, latest })
+        .collect())
+}
+
+/// The `(name, current)` pairs whose latest version in the registry differs,
+/// as `(name, current, latest)`, by name. Packages are checked concurrently;
+/// those the registry can't resolve are skipped.
+pub async fn outdated_among(candidates: Vec<(String, String)>, registry: &str) -> Result<Vec<(String, String, String)>> {
+    use futures::stream::{self, StreamExt};
+
+    let client = crate::registry::get_client()?;
+    let spinner = crate::ui::create_spinner(&format!("Checking {} packages for updates...", candidates.len()));
+    let mut outdated: Vec<(String, String, String)> = stream::iter(candidates)
+        .map(|(name, current)| {
+            let client = &client;
+            async move {
+                let (latest, _, _) = crate::package_utils::fetch_package_version(&name, registry, None, client).await.ok()?;
+                (latest != current).then_some((name, current, latest))
+            }
+        })
+        .buffer_unordered(crate::MAX_CONCURRENT_DOWNLOADS)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await;
+    spinner.finish_and_clear();
+
+    outdated.sort();
     Ok(outdated)
 }
 
-/// Get package information from registry
-pub async fn get_package_info(name: &str, registry: &str) -> Result<()> {
+/// Split `name@range` (including `@scope/name@range`) into the name and the range
+pub fn split_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.get(1..).and_then(|rest| rest.find('@')) {
+        Some(i) => (&spec[..=i], Some(&spec[i + 2..]).filter(|r| !r.is_empty())),
+        None => (spec, None),
+    }
+}
+
+/// The version `spec` picks: a dist-tag, an exact version, or the highest
+/// version matching a range. No spec means `latest`.
+fn select_version(name: &str, pkg: &RegistryPackage, spec: Option<&str>) -> Result<String> {
+    let spec = spec.unwrap_or("latest");
+    if let Some(tagged) = pkg.dist_tags.get(spec) {
+        return Ok(tagged.clone());
+    }
+    if pkg.versions.contains_key(spec) {
+        return Ok(spec.to_string());
+    }
+    let req = semver::VersionReq::parse(spec)
+        .with_context(|| format!("\"{}\" is not a version, range or dist-tag of {}", spec, name))?;
+    pkg.versions.keys()
+        .filter_map(|v| semver::Version::parse(v).ok())
+        .filter(|v| req.matches(v))
+        .max()
+        .map(|v| v.to_string())
+        .with_context(|| format!("No version of {} matches {}", name, spec))
+}
+
+/// Get package information from registry for `spec` (`name`, `name@version` or `name@range`)
+pub async fn get_package_info(spec: &str, registry: &str) -> Result<()> {
+    let (name, range) = split_spec(spec);
+    let spinner = crate::ui::create_spinner(&format!("Fetching {}...", name));
+    let pkg = fetch_registry_package(name, registry).await;
+    spinner.finish_and_clear();
+    let pkg = pkg?;
+
+    let version = select_version(name, &pkg, range)?;
+    let version_info = pkg.versions.get(&version)
+        .context("Version info not found")?;
+    
+    println!("\n{}", style(format!("{} {}", crate::ui::icon("📦"), name)).bold().cyan());
+    println!("{}", "=".repeat(50));
+    println!("{}: {}", style("Version").bold(), version);
+    if let Some(latest) = pkg.dist_tags.get("latest").filter(|latest| **latest != version) {
+        println!("{}: {}", style("Latest").bold(), latest);
+    }
+    
+    if let Some(desc) = &version_info.description {
+        println!("{}: {}", style("Description").bold(), desc);
+    }
+    
+    println!("{}: {}/{}", style("Registry").bold(), registry, name);
+    print_local_usage(name)?;
+    println!();
+    
+    Ok(())
+}
+
+/// When the current project has `name` installed: its version and the
+/// dependency chains that pull it in, as `crabby why` would show them
+fn print_local_usage(name: &str) -> Result<()> {
+    let Some(installed) = crate::explorer::installed_version(std::path::Path::new("node_modules"), name) else {
+        return Ok(());
+    };
+    println!("{}: {}", style("Installed").bold(), installed);
+
+    let Ok(pkg) = crate::manifest::PackageJson::load() else { return Ok(()) };
+    let lock = crate::manifest::CrabbyLock::load()?;
+    let paths = crate::explorer::find_dependency_paths(&lock, &pkg, name);
+    if !paths.is_empty() {
+        println!("{}:", style("Required by").bold());
+        let arrow = style(format!(" {} ", crate::ui::icon("→"))).dim().to_string();
+        for path in paths {
+            println!("  {} package.json{}{}", style(crate::ui::icon(crate::ui::Icons::CHECKMARK)).green(), arrow, path.join(&arrow));
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_registry_package(name: &str, registry: &str) -> Result<RegistryPackage> {
     let client = crate::registry::get_client()?;
     let url = format!("{}/{}", registry, name);
     
     let mut attempt = 0;
     let max_retries = 3;
     
-    let pkg: RegistryPackage = loop {
+    loop {
         attempt += 1;
         match client.get(&url).send().await {
             Ok(resp) => {
+                if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Err(crate::package_utils::package_not_found(name, registry, &client).await);
+                }
                 let resp = resp.error_for_status()?;
                 match resp.json().await {
-                    Ok(p) => break p,
+                    Ok(p) => return Ok(p),
                     Err(e) => return Err(anyhow::anyhow!("Failed to parse JSON for {}: {}", name, e)),
                 }
             },
@@ -73,24 +486,172 @@ pub async fn get_package_info(name: &str, registry: &str) -> Result<()> {
                 tokio::time::sleep(std::time::Duration::from_secs(2u64.pow(attempt - 1))).await;
             }
         }
-    };
+    }
+}
 
-    let latest = pkg.dist_tags.get("latest")
-        .context("No latest version found")?;
-    
-    let version_info = pkg.versions.get(latest)
-        .context("Version info not found")?;
-    
-    println!("\n{}", style(format!("📦 {}", name)).bold().cyan());
-    println!("{}", "=".repeat(50));
-    println!("{}: {}", style("Version").bold(), latest);
-    
-    if let Some(desc) = &version_info.description {
-        println!("{}: {}", style("Description").bold(), desc);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outdated_candidates_direct_only() {
+        let pkg_json: crate::manifest::PackageJson = serde_json::from_str(r#"{
+            "name": "app", "version": "1.0.0",
+            "dependencies": {"express": "^4.18.0"},
+            "devDependencies": {"typescript": "~5.3.0"}
+        }"#).unwrap();
+        let mut lock = crate::manifest::CrabbyLock::default();
+        for (name, version) in [("express", "4.18.2"), ("accepts", "1.3.8")] {
+            lock.dependencies.insert(name.to_string(), crate::manifest::LockDependency {
+                version: version.to_string(),
+                tarball: String::new(),
+                integrity: None,
+                dependencies: HashMap::new(),
+                registry: None,
+                committish: None,
+            });
+        }
+
+        let pairs = |transitive| outdated_candidates(&pkg_json, &lock, transitive);
+        assert_eq!(pairs(false), vec![
+            ("express".to_string(), "4.18.2".to_string()),
+            ("typescript".to_string(), "5.3.0".to_string()),
+        ]);
+        assert_eq!(pairs(true), vec![
+            ("accepts".to_string(), "1.3.8".to_string()),
+            ("express".to_string(), "4.18.2".to_string()),
+            ("typescript".to_string(), "5.3.0".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_outdated_includes_dev_dependencies() {
+        let registry = crate::test_utils::serve(|path| {
+            let name = path.trim_start_matches('/');
+            let latest = match name {
+                "outdated-express" => "4.18.2",
+                "outdated-typescript" => "5.4.5",
+                _ => return None,
+            };
+            Some(format!(r#"{{"name": "{name}", "dist-tags": {{"latest": "{latest}"}}, "versions": {{"{latest}": {{"version": "{latest}", "dist": {{"tarball": "t", "shasum": ""}}}}}}}}"#).into_bytes())
+        });
+
+        let pkg_json: crate::manifest::PackageJson = serde_json::from_str(r#"{
+            "name": "app", "version": "1.0.0",
+            "dependencies": {"outdated-express": "^4.18.2", "outdated-gone": "^1.0.0"},
+            "devDependencies": {"outdated-typescript": "~5.3.0"}
+        }"#).unwrap();
+        let lock = crate::manifest::CrabbyLock::default();
+        let outdated = |filter| outdated_in(&pkg_json, &lock, false, filter, &registry);
+        let typescript = OutdatedPackage {
+            name: "outdated-typescript".to_string(),
+            current: "5.3.0".to_string(),
+            latest: "5.4.5".to_string(),
+            kind: DependencyType::Dev,
+        };
+        assert_eq!(outdated(OutdatedFilter::Dev).await.unwrap(), [typescript]);
+        assert_eq!(outdated(OutdatedFilter::All).await.unwrap().len(), 1);
+        assert!(outdated(OutdatedFilter::Prod).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_planned_updates_keep_to_declared_ranges() {
+        let registry = crate::test_utils::serve(|_| {
+            let version = |v: &str| format!(r#""{v}": {{"version": "{v}", "dist": {{"tarball": "t", "shasum": ""}}}}"#);
+            Some(format!(r#"{{"name": "planned-dep", "dist-tags": {{"latest": "2.0.0"}}, "versions": {{{}, {}, {}}}}}"#,
+                version("1.0.0"), version("1.4.0"), version("2.0.0")).into_bytes())
+        });
+
+        let root = tempfile::tempdir().unwrap();
+        let pkg_json: crate::manifest::PackageJson = serde_json::from_str(r#"{
+            "name": "app", "version": "1.0.0",
+            "dependencies": {"planned-dep": "^1.0.0", "planned-local": "file:../local"}
+        }"#).unwrap();
+        let mut lock = crate::manifest::CrabbyLock::default();
+        lock.add_package("planned-dep".to_string(), "1.0.0".to_string(), "t".to_string(), HashMap::new());
+        let names = ["planned-dep".to_string(), "planned-local".to_string()];
+        let plan = |latest| plan_updates_in(&pkg_json, &lock, root.path(), &names, &registry, latest);
+
+        let within = plan(false).await.unwrap();
+        assert_eq!(within.iter().map(|p| (p.name.as_str(), p.current.as_str(), p.new.as_str())).collect::<Vec<_>>(),
+            [("planned-dep", "1.0.0", "1.4.0")]);
+        assert_eq!(plan(true).await.unwrap()[0].new, "2.0.0");
+        assert!(plan_updates_in(&pkg_json, &lock, root.path(), &["other".to_string()], &registry, false).await.is_err());
+    }
+
+    #[test]
+    fn test_info_selects_requested_version() {
+        let pkg: RegistryPackage = serde_json::from_str(r#"{
+            "dist-tags": {"latest": "2.1.0", "next": "3.0.0-rc.1"},
+            "versions": {
+                "1.4.0": {"version": "1.4.0", "description": "old"},
+                "1.5.2": {"version": "1.5.2"},
+                "2.1.0": {"version": "2.1.0"},
+                "3.0.0-rc.1": {"version": "3.0.0-rc.1"}
+            }
+        }"#).unwrap();
+
+        assert_eq!(split_spec("@types/node@^18"), ("@types/node", Some("^18")));
+        assert_eq!(split_spec("@types/node"), ("@types/node", None));
+        assert_eq!(split_spec("lodash@"), ("lodash", None));
+
+        assert_eq!(select_version("x", &pkg, None).unwrap(), "2.1.0");
+        assert_eq!(select_version("x", &pkg, Some("1.4.0")).unwrap(), "1.4.0");
+        assert_eq!(select_version("x", &pkg, Some("^1")).unwrap(), "1.5.2");
+        assert_eq!(select_version("x", &pkg, Some("next")).unwrap(), "3.0.0-rc.1");
+        assert!(select_version("x", &pkg, Some("^4")).is_err());
+    }
+
+    #[test]
+    fn test_bump_kind() {
+        assert_eq!(bump_kind("1.2.3", "2.0.0"), "major");
+        assert_eq!(bump_kind("1.2.3", "1.3.0"), "minor");
+        assert_eq!(bump_kind("^1.2.3", "1.2.4"), "patch");
+        assert_eq!(bump_kind("1.2.4-beta.0", "1.2.4"), "prerelease");
+        assert_eq!(bump_kind("1.2.3", "1.2.3"), "none");
+        assert_eq!(bump_kind("latest", "1.0.0"), "unknown");
+    }
+
+    fn choice(name: &str, current: &str, range: &str, wanted: Option<&str>, latest: &str) -> OutdatedChoice {
+        OutdatedChoice {
+            name: name.to_string(),
+            current: current.to_string(),
+            range: range.to_string(),
+            wanted: wanted.map(str::to_string),
+            latest: latest.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_selection_maps_to_updates() {
+        let choices = [
+            choice("react", "18.2.0", "^18.2.0", Some("18.3.1"), "19.0.0"),
+            choice("lodash", "4.17.20", "^4.17.0", Some("4.17.21"), "4.17.21"),
+            choice("left-pad", "1.0.0", "~1.0.0", Some("1.0.0"), "1.3.0"),
+        ];
+        let rows = selection_rows(&choices);
+        assert_eq!(rows, [
+            (0, Target::Wanted, true),
+            (0, Target::Latest, false),
+            (1, Target::Latest, true),
+            (2, Target::Latest, false),
+        ]);
+
+        let update = |name: &str, version: &str, range: Option<&str>| ChosenUpdate {
+            name: name.to_string(),
+            version: version.to_string(),
+            range: range.map(str::to_string),
+        };
+        // The ticked defaults stay within the declared ranges
+        assert_eq!(chosen_updates(&choices, &rows, &[0, 2]), [
+            update("react", "18.3.1", None),
+            update("lodash", "4.17.21", None),
+        ]);
+        // Latest outside the range saves a new one, and wins over wanted
+        assert_eq!(chosen_updates(&choices, &rows, &[0, 1, 3]), [
+            update("react", "19.0.0", Some("^19.0.0")),
+            update("left-pad", "1.3.0", Some("^1.3.0")),
+        ]);
+        assert!(chosen_updates(&choices, &rows, &[]).is_empty());
     }
-    
-    println!("{}: {}/{}", style("Registry").bold(), registry, name);
-    println!();
-    
-    Ok(())
 }
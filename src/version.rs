@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use console::style;
+use semver::{Prerelease, Version};
+use std::process::Command;
+use crate::{manifest, runner, ui};
+
+pub struct VersionOptions {
+    /// `patch`, `minor`, `major`, `prerelease` or an explicit version
+    pub target: String,
+    /// Identifier for prereleases, e.g. `beta` -> `1.2.4-beta.0`
+    pub preid: Option<String>,
+    pub no_git_tag: bool,
+    pub force: bool,
+}
+
+/// Compute the next version the way `npm version` does
+pub fn bump(current: &Version, target: &str, preid: Option<&str>) -> Result<Version> {
+    let mut next = current.clone();
+    let is_pre = !current.pre.is_empty();
+
+    match target {
+        "major" => {
+            // 2.0.0-rc.1 -> 2.0.0 rather than 3.0.0
+            if !(is_pre && current.minor == 0 && current.patch == 0) {
+                next.major += 1;
+            }
+            next.minor = 0;
+            next.patch = 0;
+            next.pre = Prerelease::EMPTY;
+        }
+        "minor" => {
+            if !(is_pre && current.patch == 0) {
+                next.minor += 1;
+            }
+            next.patch = 0;
+            next.pre = Prerelease::EMPTY;
+        }
+        "patch" => {
+            if !is_pre {
+                next.patch += 1;
+            }
+            next.pre = Prerelease::EMPTY;
+        }
+        "prerelease" => {
+            next.pre = next_prerelease(current, preid)?;
+            if !is_pre {
+                next.patch += 1;
+            }
+        }
+        explicit => {
+            next = Version::parse(explicit.trim_start_matches('v'))
+                .with_context(|| format!("'{}' is not patch, minor, major, prerelease or a valid version", explicit))?;
+        }
+    }
+    next.build = semver::BuildMetadata::EMPTY;
+
+    if next == *current {
+        anyhow::bail!("Version is already {}", current);
+    }
+    Ok(next)
+}
+
+/// `1.0.0` -> `0` (or `<preid>.0`), `1.0.1-beta.3` -> `beta.4`, `1.0.1-beta` -> `beta.0`
+fn next_prerelease(current: &Version, preid: Option<&str>) -> Result<Prerelease> {
+    let fresh = match preid {
+        Some(id) => format!("{}.0", id),
+        None => "0".to_string(),
+    };
+    if current.pre.is_empty() {
+        return Ok(Prerelease::new(&fresh)?);
+    }
+
+    let mut parts: Vec<String> = current.pre.as_str().split('.').map(String::from).collect();
+    if preid.is_some_and(|id| parts[0] != id) {
+        return Ok(Prerelease::new(&fresh)?);
+    }
+    match parts.last().and_then(|p| p.parse::<u64>().ok()) {
+        Some(n) => *parts.last_mut().unwrap() = (n + 1).to_string(),
+        None => parts.push("0".to_string()),
+    }
+    Ok(Prerelease::new(&parts.join("."))?)
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn in_git_repo() -> bool {
+    git(&["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.trim() == "true")
+}
+
+fn run_lifecycle(pkg: &manifest::PackageJson, script: &str) -> Result<()> {
+    if let Some(command) = pkg.scripts.get(script) {
+        runner::run_script(command, None).with_context(|| format!("{} script failed", script))?;
+    }
+    Ok(())
+}
+
+/// `crabby version`: bump package.json and, in a git repo, commit and tag the
+/// release. crabby.lock has no entry for the project itself, so only a
+/// monorepo's lockfiles, which record the version of each workspace they
+/// link, change along with it.
+pub fn run(options: VersionOptions) -> Result<()> {
+    let mut pkg = manifest::PackageJson::load()?;
+    if pkg.version.is_empty() {
+        anyhow::bail!("package.json has no version to bump");
+    }
+    let current = Version::parse(&pkg.version)
+        .with_context(|| format!("package.json version '{}' is not valid semver", pkg.version))?;
+    let next = bump(&current, &options.target, options.preid.as_deref())?;
+
+    let use_git = !options.no_git_tag && in_git_repo();
+    if use_git && !options.force && !git(&["status", "--porcelain"])?.trim().is_empty() {
+        anyhow::bail!("Git working directory not clean. Commit or stash your changes first, or pass --force.");
+    }
+
+    run_lifecycle(&pkg, "preversion")?;
+
+    pkg.version = next.to_string();
+    pkg.save()?;
+    let linked_locks = match crate::workspace::find_monorepo_root(&std::env::current_dir()?)? {
+        Some((root, workspaces)) => crate::workspace::update_linked_version(&root, &workspaces, &pkg.name, &pkg.version)?,
+        None => Vec::new(),
+    };
+    ui::print_success(&format!("{} {} {}", style(&current).dim(), ui::icon(ui::Icons::ARROW_RIGHT), style(&next).bold().green()));
+
+    // The `version` script runs after the bump but before the commit, so it can stage extra files
+    run_lifecycle(&pkg, "version")?;
+
+    if use_git {
+        let tag = format!("v{}", next);
+        git(&["add", "package.json"])?;
+        for lock in &linked_locks {
+            git(&["add", &lock.to_string_lossy()])?;
+        }
+        git(&["commit", "-m", &next.to_string()])?;
+        git(&["tag", "-a", &tag, "-m", &tag])?;
+        ui::print_step(ui::Icons::TARGET, &format!("Committed and tagged {}", style(&tag).cyan()));
+    }
+
+    run_lifecycle(&pkg, "postversion")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumped(current: &str, target: &str, preid: Option<&str>) -> String {
+        bump(&Version::parse(current).unwrap(), target, preid).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_bump_release_types() {
+        assert_eq!(bumped("1.2.3", "patch", None), "1.2.4");
+        assert_eq!(bumped("1.2.3", "minor", None), "1.3.0");
+        assert_eq!(bumped("1.2.3", "major", None), "2.0.0");
+        assert_eq!(bumped("1.2.3", "4.0.0", None), "4.0.0");
+
+        // Prereleases graduate to their release instead of skipping it
+        assert_eq!(bumped("1.2.4-0", "patch", None), "1.2.4");
+        assert_eq!(bumped("1.3.0-beta.1", "minor", None), "1.3.0");
+        assert_eq!(bumped("2.0.0-rc.2", "major", None), "2.0.0");
+    }
+
+    #[test]
+    fn test_bump_prerelease() {
+        assert_eq!(bumped("1.2.3", "prerelease", None), "1.2.4-0");
+        assert_eq!(bumped("1.2.3", "prerelease", Some("beta")), "1.2.4-beta.0");
+        assert_eq!(bumped("1.2.4-beta.0", "prerelease", Some("beta")), "1.2.4-beta.1");
+        assert_eq!(bumped("1.2.4-beta.3", "prerelease", Some("rc")), "1.2.4-rc.0");
+        assert_eq!(bumped("1.2.4-alpha", "prerelease", None), "1.2.4-alpha.0");
+    }
+
+    #[test]
+    fn test_bump_rejects_same_or_invalid_version() {
+        let current = Version::parse("1.0.0").unwrap();
+        assert!(bump(&current, "1.0.0", None).is_err());
+        assert!(bump(&current, "banana", None).is_err());
+    }
+}
@@ -1,77 +1,834 @@
 use anyhow::{Context, Result};
 use console::style;
 use glob::glob;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::manifest::PackageJson;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use crate::manifest::{CrabbyLock, LockDependency, PackageJson};
+use crate::{link, runner};
 
 #[derive(Debug, Clone)]
 pub struct Workspace {
     pub name: String,
     pub path: PathBuf,
-    pub _package_json: PackageJson,
+    pub package_json: PackageJson,
 }
 
-/// Find all workspaces based on the patterns in root package.json
-pub fn find_workspaces(root: &Path) -> Result<Vec<Workspace>> {
+/// Something wrong with the workspace setup, reported by `crabby workspaces list`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum WorkspaceProblem {
+    /// Several directories declare the same package name
+    DuplicateName { name: String, paths: Vec<String> },
+    /// A matched directory whose package.json can't be parsed
+    InvalidManifest { path: String, error: String },
+    /// A `workspaces` pattern that matched no package.json
+    EmptyPattern { pattern: String },
+}
+
+impl std::fmt::Display for WorkspaceProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateName { name, paths } => write!(f, "{} is the name of {} workspaces: {}", name, paths.len(), paths.join(", ")),
+            Self::InvalidManifest { path, error } => write!(f, "Failed to parse {}: {}", path, error),
+            Self::EmptyPattern { pattern } => write!(f, "Pattern \"{}\" matched no package.json", pattern),
+        }
+    }
+}
+
+/// The workspaces found under a root, and what went wrong finding them
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pub workspaces: Vec<Workspace>,
+    pub problems: Vec<WorkspaceProblem>,
+}
+
+/// Expand the `workspaces` patterns of the root package.json
+pub fn discover_workspaces(root: &Path) -> Result<Discovery> {
     let root_pkg_path = root.join("package.json");
-    let mut content = fs::read_to_string(&root_pkg_path)
+    let content = fs::read_to_string(&root_pkg_path)
         .context("Failed to read root package.json")?;
-        
-    if content.starts_with('\u{FEFF}') {
-        content = content.trim_start_matches('\u{FEFF}').to_string();
-    }
-    
-    let pkg: PackageJson = serde_json::from_str(&content)?;
-
-    let mut workspaces = Vec::new();
-
-    if let Some(patterns) = pkg.workspaces {
-        for pattern in patterns {
-            // Pattern e.g. "packages/*"
-            // We need to look for package.json inside matches
-            let full_pattern = root.join(&pattern).join("package.json");
-            let pattern_str = full_pattern.to_string_lossy();
-
-            for entry in glob(&pattern_str).expect("Failed to read glob pattern") {
-                match entry {
-                    Ok(path) => {
-                        // path is .../packages/a/package.json
-                        let pkg_dir = path.parent().unwrap().to_path_buf();
-                        
-                        // Load the workspace package.json
-                        let mut content = fs::read_to_string(&path)?;
-                        
-                        // Debug prints
-                        // println!("DEBUG: Reading {}", path.display());
-                        // println!("DEBUG: First bytes: {:?}", content.as_bytes().iter().take(5).collect::<Vec<_>>());
-                        
-                        if content.starts_with('\u{FEFF}') {
-                            content = content.trim_start_matches('\u{FEFF}').to_string();
+    let pkg: PackageJson = crate::manifest::parse_json(&content, &root_pkg_path)?;
+
+    let mut discovery = Discovery::default();
+    let patterns = pkg.workspaces.as_ref().map(|w| w.patterns()).unwrap_or_default();
+    let (negated, included): (Vec<&String>, Vec<&String>) = patterns.iter().partition(|p| p.starts_with('!'));
+    let excludes = negated.iter()
+        .map(|p| glob::Pattern::new(p.trim_start_matches('!').trim_end_matches('/'))
+            .with_context(|| format!("Invalid workspace pattern \"{}\"", p)))
+        .collect::<Result<Vec<_>>>()?;
+    let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+
+    // Expand every pattern first; negations apply regardless of where they appear
+    let mut manifests: Vec<PathBuf> = Vec::new();
+    for pattern in included {
+        // "packages/*" -> packages/*/package.json, "apps/**" -> apps/**/package.json
+        let full_pattern = root.join(pattern.trim_end_matches('/')).join("package.json");
+        let pattern_str = full_pattern.to_string_lossy();
+        let mut matched = false;
+
+        let entries = glob(&pattern_str).with_context(|| format!("Invalid workspace pattern \"{}\"", pattern))?;
+        for entry in entries {
+            match entry {
+                Ok(path) => {
+                    let pkg_dir = path.parent().unwrap_or(root);
+                    let relative = relative_path(root, pkg_dir);
+                    let in_node_modules = pkg_dir.strip_prefix(root).unwrap_or(pkg_dir)
+                        .components().any(|c| c.as_os_str() == "node_modules");
+                    if pkg_dir == root || in_node_modules {
+                        continue;
+                    }
+                    matched = true;
+                    if excludes.iter().any(|ex| ex.matches_with(&relative, options)) || manifests.contains(&path) {
+                        continue;
+                    }
+                    manifests.push(path);
+                }
+                Err(e) => println!("{} Error reading workspace glob: {}", style(crate::ui::icon("⚠️")).yellow(), e),
+            }
+        }
+        if !matched {
+            discovery.problems.push(WorkspaceProblem::EmptyPattern { pattern: pattern.clone() });
+        }
+    }
+
+    for path in manifests {
+        // path is .../packages/a/package.json
+        let pkg_dir = path.parent().unwrap().to_path_buf();
+        let content = fs::read_to_string(&path)?;
+        match crate::manifest::parse_json::<PackageJson>(&content, &path) {
+            Ok(ws_pkg) => discovery.workspaces.push(Workspace {
+                name: ws_pkg.name.clone(),
+                path: pkg_dir,
+                package_json: ws_pkg,
+            }),
+            Err(e) => discovery.problems.push(WorkspaceProblem::InvalidManifest {
+                path: relative_path(root, &path),
+                error: e.root_cause().to_string(),
+            }),
+        }
+    }
+
+    let mut by_name: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for ws in &discovery.workspaces {
+        by_name.entry(&ws.name).or_default().push(relative_path(root, &ws.path));
+    }
+    let duplicates: Vec<WorkspaceProblem> = by_name.into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(name, paths)| WorkspaceProblem::DuplicateName { name: name.to_string(), paths })
+        .collect();
+    discovery.problems.extend(duplicates);
+
+    Ok(discovery)
+}
+
+/// Find all workspaces based on the patterns in root package.json
+pub fn find_workspaces(root: &Path) -> Result<Vec<Workspace>> {
+    let discovery = discover_workspaces(root)?;
+    for problem in &discovery.problems {
+        if let WorkspaceProblem::InvalidManifest { .. } = problem {
+            println!("{} {}", style(crate::ui::icon("❌")).red(), problem);
+        }
+    }
+    Ok(discovery.workspaces)
+}
+
+/// The root of the monorepo `dir` is a workspace of, and its workspaces
+pub fn find_monorepo_root(dir: &Path) -> Result<Option<(PathBuf, Vec<Workspace>)>> {
+    let dir = dir.canonicalize()?;
+    for ancestor in dir.ancestors().skip(1) {
+        let declares_workspaces = fs::read_to_string(ancestor.join("package.json")).ok()
+            .and_then(|content| serde_json::from_str::<PackageJson>(&crate::manifest::clean_json_content(content)).ok())
+            .is_some_and(|pkg| pkg.workspaces.is_some());
+        if !declares_workspaces {
+            continue;
+        }
+        let workspaces = discover_workspaces(ancestor)?.workspaces;
+        if workspaces.iter().any(|ws| ws.path.canonicalize().is_ok_and(|path| path == dir)) {
+            return Ok(Some((ancestor.to_path_buf(), workspaces)));
+        }
+    }
+    Ok(None)
+}
+
+/// Record `version` for the workspace `name` wherever a crabby.lock of the
+/// monorepo at `root` links it: the root's and those of its `workspaces`.
+/// Returns the lockfiles that changed.
+pub fn update_linked_version(root: &Path, workspaces: &[Workspace], name: &str, version: &str) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    let lock_paths = std::iter::once(root.join("crabby.lock")).chain(workspaces.iter().map(|ws| ws.path.join("crabby.lock")));
+    for path in lock_paths {
+        if !path.exists() {
+            continue;
+        }
+        let mut lock = CrabbyLock::load_from(&path)?;
+        match lock.dependencies.get_mut(name) {
+            Some(dep) if dep.is_workspace_link() && dep.version != version => {
+                dep.version = version.to_string();
+                lock.save_to(&path)?;
+                changed.push(path);
+            }
+            _ => {}
+        }
+    }
+    Ok(changed)
+}
+
+/// `path` relative to `root`, with forward slashes
+fn relative_path(root: &Path, path: &Path) -> String {
+    crate::source::relative_path(path, root).to_string_lossy().replace('\\', "/")
+}
+
+/// One row of `crabby workspaces list`
+#[derive(Debug, Serialize)]
+struct WorkspaceEntry {
+    name: String,
+    path: String,
+    version: String,
+    /// Whether root/node_modules/<name> is a link to this workspace
+    linked: bool,
+}
+
+/// `crabby workspaces list`
+pub fn run_list(root: &Path, json: bool) -> Result<()> {
+    let discovery = discover_workspaces(root)?;
+    let entries: Vec<WorkspaceEntry> = discovery.workspaces.iter()
+        .map(|ws| {
+            let link = root.join("node_modules").join(&ws.name);
+            let linked = link::is_link(&link)
+                && link.canonicalize().ok() == ws.path.canonicalize().ok();
+            WorkspaceEntry {
+                name: ws.name.clone(),
+                path: relative_path(root, &ws.path),
+                version: ws.package_json.version.clone(),
+                linked,
+            }
+        })
+        .collect();
+
+    if json {
+        let document = serde_json::json!({ "workspaces": entries, "problems": discovery.problems });
+        println!("{}", serde_json::to_string_pretty(&document)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        crate::ui::print_info("No workspaces found");
+    } else {
+        let rows: Vec<Vec<String>> = entries.iter()
+            .map(|e| vec![
+                e.name.clone(),
+                e.path.clone(),
+                e.version.clone(),
+                if e.linked { style("yes").green().to_string() } else { style("no").dim().to_string() },
+            ])
+            .collect();
+        crate::ui::print_table(&["Name", "Path", "Version", "Linked"], &rows);
+    }
+    for problem in &discovery.problems {
+        crate::ui::print_warning(&problem.to_string());
+    }
+    Ok(())
+}
+
+/// Pull `workspace:` ranges (`workspace:*`, `workspace:^`, `workspace:^1.2.0`)
+/// out of `deps` so they never reach the registry, and record each one in
+/// `lock` as a link to the sibling workspace. The package itself is served by
+/// the symlink `link_workspaces` puts in the root node_modules.
+pub fn resolve_protocol_deps(
+    deps: &mut HashMap<String, String>,
+    workspaces: &[Workspace],
+    root: &Path,
+    lock: &mut CrabbyLock,
+) -> Result<()> {
+    let mut local: Vec<(String, String)> = deps.iter()
+        .filter_map(|(name, range)| {
+            range.strip_prefix(LockDependency::WORKSPACE_PREFIX).map(|r| (name.clone(), r.to_string()))
+        })
+        .collect();
+    local.sort();
+
+    for (name, range) in local {
+        let ws = workspaces.iter().find(|ws| ws.name == name).with_context(|| {
+            format!("{} is declared as \"workspace:{}\" but no workspace in this repo is named {}", name, range, name)
+        })?;
+        let version = &ws.package_json.version;
+        // `*`, `^` and `~` accept whatever version the workspace is at
+        let pinned = !matches!(range.as_str(), "" | "*" | "^" | "~");
+        if pinned && !crate::package_utils::satisfies(&range, version) {
+            anyhow::bail!("Workspace {} is at {}, which does not satisfy \"workspace:{}\"", name, version, range);
+        }
+
+        let relative = relative_path(root, &ws.path);
+        lock.dependencies.insert(name.clone(), LockDependency {
+            version: version.clone(),
+            tarball: format!("{}{}", LockDependency::WORKSPACE_PREFIX, relative),
+            integrity: None,
+            dependencies: HashMap::new(),
+            registry: None,
+            committish: None,
+        });
+        deps.remove(&name);
+    }
+    Ok(())
+}
+
+/// One declaration of a dependency somewhere in the monorepo
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    /// Declaring workspace, or `None` for the root package.json
+    pub workspace: Option<String>,
+    pub range: String,
+}
+
+/// Where each external dependency of a monorepo gets installed
+#[derive(Debug, Default)]
+pub struct HoistPlan {
+    /// Installed once in the root node_modules: name -> exact range (`=1.2.3`)
+    /// or, for ranges crabby can't compare, the root's own range
+    pub root: BTreeMap<String, String>,
+    /// Conflicting versions kept in a workspace's own node_modules: workspace -> name -> range
+    pub local: BTreeMap<String, HashMap<String, String>>,
+}
+
+/// Every registry dependency declared by the root package.json and the
+/// workspaces, skipping `workspace:` ranges and sibling workspaces
+pub fn collect_requirements(root_pkg: &PackageJson, workspaces: &[Workspace]) -> BTreeMap<String, Vec<Requirement>> {
+    let mut requirements: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+    let declarers = std::iter::once((None, root_pkg))
+        .chain(workspaces.iter().map(|ws| (Some(ws.name.clone()), &ws.package_json)));
+
+    for (workspace, pkg) in declarers {
+        for (name, range) in pkg.get_all_dependencies() {
+            if range.starts_with(LockDependency::WORKSPACE_PREFIX) || workspaces.iter().any(|ws| ws.name == name) {
+                continue;
+            }
+            requirements.entry(name).or_default().push(Requirement { workspace: workspace.clone(), range });
+        }
+    }
+    requirements
+}
+
+/// Decide what goes in the root node_modules. A package is hoisted when one
+/// version satisfies every declaration of it (preferring the version already
+/// in the root `lock`, so repeat installs don't move). Otherwise the root's own
+/// range, or else the range most workspaces agree on, is hoisted and the
+/// workspaces that disagree get their own copy.
+pub fn plan_hoisting(
+    requirements: &BTreeMap<String, Vec<Requirement>>,
+    lock: &CrabbyLock,
+    available: &HashMap<String, Vec<semver::Version>>,
+) -> HoistPlan {
+    let mut plan = HoistPlan::default();
+
+    for (name, reqs) in requirements {
+        let best = |ranges: &[&semver::VersionReq]| -> Option<String> {
+            let fits = |v: &semver::Version| ranges.iter().all(|r| r.matches(v));
+            let locked = lock.dependencies.get(name)
+                .filter(|dep| !dep.is_workspace_link())
+                .and_then(|dep| semver::Version::parse(&dep.version).ok())
+                .filter(|v| fits(v));
+            locked
+                .or_else(|| available.get(name)?.iter().rev().find(|v| fits(v)).cloned())
+                .map(|v| v.to_string())
+        };
+        let parsed: Vec<Option<semver::VersionReq>> = reqs.iter()
+            .map(|r| semver::VersionReq::parse(&r.range).ok())
+            .collect();
+
+        if parsed.iter().all(Option::is_some) {
+            let ranges: Vec<&semver::VersionReq> = parsed.iter().flatten().collect();
+            if let Some(version) = best(&ranges) {
+                plan.root.insert(name.clone(), format!("={}", version));
+                continue;
+            }
+        }
+
+        // No common version: the root's declaration wins, then the most popular range
+        let mut popularity: BTreeMap<&str, usize> = BTreeMap::new();
+        for (req, parsed) in reqs.iter().zip(&parsed) {
+            if parsed.is_some() {
+                *popularity.entry(req.range.as_str()).or_default() += 1;
+            }
+        }
+        let root_range = reqs.iter().find(|r| r.workspace.is_none()).map(|r| r.range.as_str())
+            .or_else(|| popularity.iter().max_by_key(|(_, count)| **count).map(|(range, _)| *range));
+
+        let hoisted = root_range.and_then(|range| match semver::VersionReq::parse(range) {
+            Ok(req) => best(&[&req]).map(|v| (format!("={}", v), semver::Version::parse(&v).ok())),
+            Err(_) => Some((range.to_string(), None)),
+        });
+        if let Some((root_spec, _)) = &hoisted {
+            plan.root.insert(name.clone(), root_spec.clone());
+        }
+        let hoisted_version = hoisted.and_then(|(_, version)| version);
+
+        for (req, parsed) in reqs.iter().zip(&parsed) {
+            let Some(workspace) = &req.workspace else { continue };
+            let satisfied = match (parsed, &hoisted_version) {
+                (Some(parsed), Some(version)) => parsed.matches(version),
+                _ => root_range == Some(req.range.as_str()),
+            };
+            if !satisfied {
+                plan.local.entry(workspace.clone()).or_default().insert(name.clone(), req.range.clone());
+            }
+        }
+    }
+    plan
+}
+
+/// Packages whose version in the root `lock` doesn't satisfy every
+/// declaration, so `plan_hoisting` needs their published versions
+pub fn unlocked_requirements(requirements: &BTreeMap<String, Vec<Requirement>>, lock: &CrabbyLock) -> Vec<String> {
+    requirements.iter()
+        .filter(|(name, reqs)| {
+            let locked = lock.dependencies.get(*name).and_then(|dep| semver::Version::parse(&dep.version).ok());
+            !reqs.iter().all(|r| match (&locked, semver::VersionReq::parse(&r.range)) {
+                (Some(version), Ok(req)) => req.matches(version),
+                _ => false,
+            })
+        })
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Delete the workspace's own copies of packages that are now hoisted, so
+/// Node resolves them from the root node_modules
+pub fn remove_hoisted_copies(ws: &Workspace, local: &HashMap<String, String>, lock: &mut CrabbyLock) -> Result<()> {
+    for (name, range) in ws.package_json.get_all_dependencies() {
+        if local.contains_key(&name) || range.starts_with(LockDependency::WORKSPACE_PREFIX) {
+            continue;
+        }
+        let path = ws.path.join("node_modules").join(&name);
+        if path.is_dir() && !link::is_link(&path) {
+            crate::fs_utils::remove_dir_all(&path)?;
+        }
+        lock.dependencies.remove(&name);
+    }
+    Ok(())
+}
+
+/// Most workspaces installing their own dependencies at the same time
+const PARALLEL_INSTALLS: usize = 4;
+
+/// How installing one workspace's own dependencies went
+pub struct WorkspaceInstall {
+    pub name: String,
+    /// Packages in the workspace's crabby.lock afterwards
+    pub packages: usize,
+    pub elapsed_ms: u64,
+    pub error: Option<anyhow::Error>,
+}
+
+/// Install the versions each workspace keeps for itself (`local`: workspace ->
+/// name -> range) into its own node_modules, several workspaces at a time.
+/// They share the HTTP client, metadata and tarball cache; a failing workspace
+/// doesn't stop the others, its error is in the result.
+pub async fn install_workspaces(
+    root: &Path,
+    workspaces: &[Workspace],
+    mut local: BTreeMap<String, HashMap<String, String>>,
+    registry_url: &str,
+    client: &reqwest::Client,
+    stats: &std::sync::Arc<crate::package_utils::InstallStats>,
+) -> Result<Vec<WorkspaceInstall>> {
+    let all = std::sync::Arc::new(workspaces.to_vec());
+    let parallel = if crate::package_utils::is_sequential() { 1 } else { PARALLEL_INSTALLS };
+    let slots = std::sync::Arc::new(tokio::sync::Semaphore::new(parallel));
+    let mut tasks = tokio::task::JoinSet::new();
+
+    // Dependencies first, so that with --sequential the order stays the same
+    for ws in sort_workspaces(workspaces.to_vec())? {
+        let local = local.remove(&ws.name).unwrap_or_default();
+        if !local.is_empty() && crate::reporter::human() {
+            println!("   {} keeps its own {}", style(&ws.name).cyan(), local.keys().cloned().collect::<Vec<_>>().join(", "));
+        }
+        let (all, slots, root) = (all.clone(), slots.clone(), root.to_path_buf());
+        let (registry_url, client, stats) = (registry_url.to_string(), client.clone(), stats.clone());
+        tasks.spawn(async move {
+            let _slot = slots.acquire_owned().await;
+            let started = std::time::Instant::now();
+            let result = install_workspace(&ws, &all, &root, local, &registry_url, &client, &stats).await;
+            WorkspaceInstall {
+                name: ws.name,
+                packages: *result.as_ref().unwrap_or(&0),
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                error: result.err(),
+            }
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        results.push(result?);
+    }
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(results)
+}
+
+/// Install `local` into the workspace and save its crabby.lock, returning how
+/// many packages that has
+async fn install_workspace(
+    ws: &Workspace,
+    all: &[Workspace],
+    root: &Path,
+    local: HashMap<String, String>,
+    registry_url: &str,
+    client: &reqwest::Client,
+    stats: &std::sync::Arc<crate::package_utils::InstallStats>,
+) -> Result<usize> {
+    let lock_path = ws.path.join("crabby.lock");
+    let mut lockfile = CrabbyLock::load_from(&lock_path)?;
+    let mut links = ws.package_json.get_all_dependencies();
+    links.retain(|_, range| range.starts_with(LockDependency::WORKSPACE_PREFIX));
+    resolve_protocol_deps(&mut links, all, root, &mut lockfile)?;
+
+    remove_hoisted_copies(ws, &local, &mut lockfile)?;
+    let lockfile = crate::package_utils::install_all_packages(&ws.path, &local, registry_url, client, lockfile, stats).await?;
+    if !lockfile.dependencies.is_empty() || lock_path.exists() {
+        lockfile.save_to(&lock_path)?;
+    }
+    Ok(lockfile.dependencies.len())
+}
+
+/// One line per workspace install, then every failure at once as the error
+pub fn report_installs(results: &[WorkspaceInstall]) -> Result<()> {
+    // Workspaces that only use hoisted packages have nothing to report
+    let shown: Vec<&WorkspaceInstall> = results.iter().filter(|r| r.packages > 0 || r.error.is_some()).collect();
+    if crate::reporter::human() && !shown.is_empty() {
+        let rows: Vec<Vec<String>> = shown.iter().map(|r| vec![
+            r.name.clone(),
+            r.packages.to_string(),
+            crate::ui::format_duration(r.elapsed_ms),
+            match &r.error {
+                Some(_) => style("failed").red().to_string(),
+                None => style("ok").green().to_string(),
+            },
+        ]).collect();
+        crate::ui::print_table(&["Workspace", "Packages", "Time", "Status"], &rows);
+    }
+
+    let failed: Vec<String> = results.iter()
+        .filter_map(|r| Some(format!("{}: {:#}", r.name, r.error.as_ref()?)))
+        .collect();
+    if !failed.is_empty() {
+        anyhow::bail!("{} of {} workspaces failed to install:\n  {}", failed.len(), results.len(), failed.join("\n  "));
+    }
+    Ok(())
+}
+
+/// For `crabby list --tree` at a monorepo root: which workspaces use each
+/// package that lives in the root node_modules
+pub fn hoisted_requirers(workspaces: &[Workspace]) -> BTreeMap<String, Vec<String>> {
+    let mut requirers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for ws in workspaces {
+        for (name, range) in ws.package_json.get_all_dependencies() {
+            let local = ws.path.join("node_modules").join(&name).exists();
+            let sibling = workspaces.iter().any(|other| other.name == name);
+            if !local && !sibling && !range.starts_with(LockDependency::WORKSPACE_PREFIX) {
+                requirers.entry(name).or_default().push(ws.name.clone());
+            }
+        }
+    }
+    for names in requirers.values_mut() {
+        names.sort();
+    }
+    requirers
+}
+
+/// Look up a workspace by its package name, its name without the scope
+/// (`api` for `@acme/api`) or its directory name
+pub fn find_workspace<'a>(workspaces: &'a [Workspace], query: &str) -> Result<&'a Workspace> {
+    if let Some(ws) = workspaces.iter().find(|ws| ws.name == query) {
+        return Ok(ws);
+    }
+    let matches: Vec<&Workspace> = workspaces.iter()
+        .filter(|ws| {
+            ws.name.rsplit('/').next() == Some(query)
+                || ws.path.file_name().is_some_and(|dir| dir == query)
+        })
+        .collect();
+    match matches.as_slice() {
+        [ws] => Ok(ws),
+        [] => {
+            let names: Vec<&str> = workspaces.iter().map(|ws| ws.name.as_str()).collect();
+            anyhow::bail!("No workspace named {}. Available: {}", query, names.join(", "))
+        }
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|ws| ws.name.as_str()).collect();
+            anyhow::bail!("{} matches several workspaces ({}), use the full name", query, names.join(", "))
+        }
+    }
+}
+
+/// Keep the workspaces whose name matches one of `filters` (exact names or globs
+/// like `@org/*`). No filters keeps everything.
+pub fn filter_workspaces(workspaces: Vec<Workspace>, filters: &[String]) -> Result<Vec<Workspace>> {
+    if filters.is_empty() {
+        return Ok(workspaces);
+    }
+    let patterns = filters.iter()
+        .map(|f| glob::Pattern::new(f).with_context(|| format!("Invalid --filter pattern '{}'", f)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let selected: Vec<Workspace> = workspaces.into_iter()
+        .filter(|ws| patterns.iter().any(|p| p.as_str() == ws.name || p.matches(&ws.name)))
+        .collect();
+    if selected.is_empty() {
+        anyhow::bail!("No workspace matches --filter {}", filters.join(", "));
+    }
+    Ok(selected)
+}
+
+/// For each workspace (by index), the sibling workspaces it depends on through
+/// any of its dependency maps
+pub fn dependency_graph(workspaces: &[Workspace]) -> Vec<Vec<usize>> {
+    let index: HashMap<&str, usize> = workspaces.iter()
+        .enumerate()
+        .map(|(i, ws)| (ws.name.as_str(), i))
+        .collect();
+
+    workspaces.iter()
+        .map(|ws| {
+            let pkg = &ws.package_json;
+            let mut deps: Vec<usize> = [&pkg.dependencies, &pkg.dev_dependencies, &pkg.optional_dependencies, &pkg.peer_dependencies]
+                .into_iter()
+                .flat_map(|map| map.keys())
+                .filter_map(|name| index.get(name.as_str()).copied())
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            deps
+        })
+        .collect()
+}
+
+/// Group workspaces into levels: every workspace comes after the ones it
+/// depends on, and workspaces within a level don't depend on each other.
+/// Fails with the offending chain when the dependencies form a cycle.
+pub fn dependency_levels(workspaces: Vec<Workspace>) -> Result<Vec<Vec<Workspace>>> {
+    let graph = dependency_graph(&workspaces);
+    let mut remaining: Vec<usize> = graph.iter().map(Vec::len).collect();
+    let mut placed = vec![false; workspaces.len()];
+    let mut levels: Vec<Vec<usize>> = Vec::new();
+
+    while placed.iter().any(|p| !p) {
+        let level: Vec<usize> = (0..workspaces.len())
+            .filter(|&i| !placed[i] && remaining[i] == 0)
+            .collect();
+        if level.is_empty() {
+            let cycle = find_cycle(&graph, &placed)
+                .into_iter()
+                .map(|i| workspaces[i].name.as_str())
+                .collect::<Vec<_>>();
+            anyhow::bail!("Workspace dependency cycle: {}", cycle.join(" -> "));
+        }
+        for &i in &level {
+            placed[i] = true;
+        }
+        for (i, deps) in graph.iter().enumerate() {
+            remaining[i] -= deps.iter().filter(|d| level.contains(d)).count();
+        }
+        levels.push(level);
+    }
+
+    let mut slots: Vec<Option<Workspace>> = workspaces.into_iter().map(Some).collect();
+    Ok(levels.into_iter()
+        .map(|level| level.into_iter().filter_map(|i| slots[i].take()).collect())
+        .collect())
+}
+
+/// Workspaces in an order where dependencies come first
+pub fn sort_workspaces(workspaces: Vec<Workspace>) -> Result<Vec<Workspace>> {
+    Ok(dependency_levels(workspaces)?.into_iter().flatten().collect())
+}
+
+/// The script that produces a workspace's build output: `prepare`, else `build`
+fn build_script(ws: &Workspace) -> Option<(&'static str, &str)> {
+    ["prepare", "build"].into_iter()
+        .find_map(|name| Some((name, ws.package_json.scripts.get(name)?.as_str())))
+}
+
+/// Workspaces that another workspace depends on and that have a build
+/// script, dependencies first
+pub fn build_order(workspaces: &[Workspace]) -> Result<Vec<Workspace>> {
+    let depended_on: std::collections::HashSet<&str> = dependency_graph(workspaces).iter()
+        .flatten()
+        .map(|&i| workspaces[i].name.as_str())
+        .collect();
+    let targets: Vec<String> = workspaces.iter()
+        .filter(|ws| depended_on.contains(ws.name.as_str()) && build_script(ws).is_some())
+        .map(|ws| ws.name.clone())
+        .collect();
+    Ok(sort_workspaces(workspaces.to_vec())?
+        .into_iter()
+        .filter(|ws| targets.contains(&ws.name))
+        .collect())
+}
+
+/// What a build writes: the directories in `files`, or `dist`
+fn build_outputs(ws: &Workspace) -> Vec<PathBuf> {
+    let listed: Vec<PathBuf> = ws.package_json.files.iter()
+        .flatten()
+        .filter(|entry| !entry.contains(['*', '?', '[', '!']))
+        .map(|entry| ws.path.join(entry.trim_start_matches("./")))
+        .filter(|path| path.is_dir())
+        .collect();
+    if listed.is_empty() { vec![ws.path.join("dist")] } else { listed }
+}
+
+/// Latest modification time of the files under `path`, ignoring
+/// node_modules, crabby.lock, dotfiles and `skip`
+fn newest_mtime(path: &Path, skip: &[PathBuf]) -> Option<std::time::SystemTime> {
+    let meta = fs::metadata(path).ok()?;
+    if !meta.is_dir() {
+        return meta.modified().ok();
+    }
+    fs::read_dir(path).ok()?
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            !name.starts_with('.') && name != "node_modules" && name != "crabby.lock" && !skip.contains(&entry.path())
+        })
+        .filter_map(|entry| newest_mtime(&entry.path(), skip))
+        .max()
+}
+
+/// Whether every build output exists and is newer than the workspace's sources
+pub fn build_is_fresh(ws: &Workspace) -> bool {
+    let outputs = build_outputs(ws);
+    let built = outputs.iter().map(|out| newest_mtime(out, &[])).collect::<Option<Vec<_>>>();
+    match (built.and_then(|times| times.into_iter().min()), newest_mtime(&ws.path, &outputs)) {
+        (Some(built), Some(sources)) => built >= sources,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Run `prepare` (or `build`) in every workspace others depend on, so their
+/// build output exists before dependents use it. Up-to-date builds are skipped.
+pub fn build_linked_workspaces(workspaces: &[Workspace]) -> Result<()> {
+    for ws in build_order(workspaces)? {
+        let Some((script, command)) = build_script(&ws) else { continue };
+        if build_is_fresh(&ws) {
+            if crate::reporter::human() {
+                println!("   {} {}", style(&ws.name).cyan(), style("build is up to date").dim());
+            }
+            continue;
+        }
+        crate::ui::print_step(crate::ui::Icons::BUILD, &format!("Running {} in {}", script, ws.name));
+        runner::run_script(command, Some(&ws.path))
+            .with_context(|| format!("\"{}\" failed in workspace {}", script, ws.name))?;
+    }
+    Ok(())
+}
+
+/// Walk dependencies from an unplaced workspace until one repeats, returning
+/// the cycle with its first member repeated at the end
+fn find_cycle(graph: &[Vec<usize>], placed: &[bool]) -> Vec<usize> {
+    let Some(start) = (0..graph.len()).find(|&i| !placed[i]) else {
+        return Vec::new();
+    };
+    let mut path = vec![start];
+    let mut current = start;
+    loop {
+        // Every unplaced workspace still waits on another unplaced one
+        let Some(&next) = graph[current].iter().find(|&&d| !placed[d]) else {
+            return path;
+        };
+        if let Some(pos) = path.iter().position(|&i| i == next) {
+            let mut cycle = path.split_off(pos);
+            cycle.push(next);
+            return cycle;
+        }
+        path.push(next);
+        current = next;
+    }
+}
+
+pub struct RunOptions<'a> {
+    pub script: &'a str,
+    pub filters: &'a [String],
+    pub parallel: bool,
+    /// Upper bound on scripts running at once with `parallel`
+    pub concurrency: usize,
+}
+
+/// `crabby run <script> --workspaces`: run the script in every (filtered)
+/// workspace that defines it, stopping at the first failure
+pub fn run_script_in_workspaces(root: &Path, options: &RunOptions) -> Result<()> {
+    let workspaces = filter_workspaces(find_workspaces(root)?, options.filters)?;
+    if workspaces.is_empty() {
+        anyhow::bail!("No workspaces found. Add a \"workspaces\" field to package.json");
+    }
+
+    // Order before dropping workspaces without the script, so a chain through
+    // one of them still runs in dependency order
+    let levels: Vec<Vec<Workspace>> = dependency_levels(workspaces)?
+        .into_iter()
+        .map(|level| {
+            let (with, without): (Vec<Workspace>, Vec<Workspace>) = level.into_iter()
+                .partition(|ws| ws.package_json.scripts.contains_key(options.script));
+            for ws in &without {
+                println!("{}", style(format!("   Skipping {} (no \"{}\" script)", ws.name, options.script)).dim());
+            }
+            with
+        })
+        .filter(|level| !level.is_empty())
+        .collect();
+    let targets: Vec<&Workspace> = levels.iter().flatten().collect();
+    if targets.is_empty() {
+        crate::ui::print_warning(&format!("No workspace defines a \"{}\" script", options.script));
+        return Ok(());
+    }
+
+    let width = targets.iter().map(|ws| ws.name.len()).max().unwrap_or(0);
+    let prefix = |ws: &Workspace| style(format!("[{:<width$}]", ws.name, width = width)).cyan().to_string();
+    let cancel = AtomicBool::new(false);
+
+    if !options.parallel {
+        for ws in &targets {
+            let command = &ws.package_json.scripts[options.script];
+            runner::run_script_prefixed(command, &ws.path, &prefix(ws), &cancel)
+                .with_context(|| format!("\"{}\" failed in {}", options.script, ws.name))?;
+        }
+    } else {
+        // Levels run one after another; the workspaces inside a level run concurrently
+        for level in &levels {
+            let queue = Mutex::new(level.iter());
+            let failures = Mutex::new(Vec::new());
+            std::thread::scope(|scope| {
+                for _ in 0..options.concurrency.clamp(1, level.len()) {
+                    scope.spawn(|| loop {
+                        if cancel.load(Ordering::Relaxed) {
+                            break;
                         }
-                        
-                        let ws_pkg: PackageJson = match serde_json::from_str(&content) {
-                            Ok(p) => p,
-                            Err(e) => {
-                                println!("{} Failed to parse {}: {}", style("❌").red(), path.display(), e);
-                                println!("First 10 chars: {:?}", content.chars().take(10).collect::<String>());
-                                continue;
+                        let Some(ws) = queue.lock().unwrap().next() else { break };
+                        let command = &ws.package_json.scripts[options.script];
+                        if let Err(e) = runner::run_script_prefixed(command, &ws.path, &prefix(ws), &cancel) {
+                            if !e.is::<runner::Cancelled>() {
+                                // Fail fast: stop the scripts still running and start no more
+                                cancel.store(true, Ordering::Relaxed);
+                                failures.lock().unwrap().push(ws.name.clone());
                             }
-                        };
-                        
-                        workspaces.push(Workspace {
-                            name: ws_pkg.name.clone(),
-                            path: pkg_dir,
-                            _package_json: ws_pkg,
-                        });
-                    }
-                    Err(e) => println!("{} Error reading workspace glob: {}", style("⚠️").yellow(), e),
+                        }
+                    });
                 }
+            });
+
+            let failures = failures.into_inner().unwrap();
+            if !failures.is_empty() {
+                anyhow::bail!("\"{}\" failed in {}", options.script, failures.join(", "));
             }
         }
     }
 
-    Ok(workspaces)
+    crate::ui::print_success(&format!("Ran \"{}\" in {} workspace(s)", options.script, targets.len()));
+    Ok(())
 }
 
 /// Link all workspaces to the root node_modules so they can resolve each other
@@ -114,30 +871,369 @@ pub fn link_workspaces(root: &Path, workspaces: &[Workspace]) -> Result<()> {
         }
         
         println!("   Linking workspace {} -> {}", style(&ws.name).cyan(), ws.path.display());
+        link_dir(&ws.path, &target_link)
+            .context(format!("Failed to link workspace {}", ws.name))?;
+        // Its bins too, so scripts anywhere in the monorepo can call them
+        crate::package_utils::link_workspace_binaries(&node_modules, &ws.name, &ws.package_json.bin)?;
+    }
+
+    Ok(())
+}
 
-        #[cfg(target_os = "windows")]
-        {
-            if let Err(e) = std::os::windows::fs::symlink_dir(&ws.path, &target_link) {
-                 // Error 1314: A required privilege is not held by the client.
-                 if e.raw_os_error() == Some(1314) {
-                     println!("   {} Symlink failed, trying junction...", style("⚠️").yellow());
-                     let status = std::process::Command::new("cmd")
-                        .args(&["/C", "mklink", "/J", target_link.to_str().unwrap(), ws.path.to_str().unwrap()])
-                        .output()?; // Use output to suppress "Junction created for..." message or handle stdout
-                     
-                     if !status.status.success() {
-                         return Err(anyhow::anyhow!("Failed to create junction for {}: {}", ws.name, String::from_utf8_lossy(&status.stderr)));
-                     }
-                 } else {
-                     return Err(anyhow::anyhow!("Failed to link workspace {}: {}", ws.name, e));
+/// Create a directory symlink at `link` pointing to `target`. On Windows,
+/// where symlinks need Developer Mode or admin rights, fall back to a junction.
+pub fn link_dir(target: &Path, link: &Path) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        if let Err(e) = std::os::windows::fs::symlink_dir(target, link) {
+             // Error 1314: A required privilege is not held by the client.
+             if e.raw_os_error() == Some(1314) {
+                 println!("   {} Symlink failed, trying junction...", style(crate::ui::icon("⚠️")).yellow());
+                 let status = std::process::Command::new("cmd")
+                    .args(["/C", "mklink", "/J", link.to_str().unwrap(), target.to_str().unwrap()])
+                    .output()?; // Use output to suppress "Junction created for..." message or handle stdout
+                 
+                 if !status.status.success() {
+                     return Err(anyhow::anyhow!("Failed to create junction: {}", String::from_utf8_lossy(&status.stderr)));
                  }
-            }
+             } else {
+                 return Err(e.into());
+             }
         }
-
-        #[cfg(not(target_os = "windows"))]
-        std::os::unix::fs::symlink(&ws.path, &target_link)
-             .context(format!("Failed to link workspace {}", ws.name))?;
     }
 
+    #[cfg(not(target_os = "windows"))]
+    std::os::unix::fs::symlink(target, link)?;
+
+    Ok(())
+}
+
+/// Remove a symlink or junction created by `link_dir` without touching its target
+pub fn remove_link(link: &Path) -> Result<()> {
+    // Directory symlinks and junctions are directories on Windows, files elsewhere
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str) -> Workspace {
+        depending(name, &[])
+    }
+
+    fn depending(name: &str, deps: &[&str]) -> Workspace {
+        let mut ws = Workspace {
+            name: name.to_string(),
+            path: PathBuf::from("packages").join(name),
+            package_json: PackageJson { name: name.to_string(), ..Default::default() },
+        };
+        for dep in deps {
+            ws.package_json.dependencies.insert(dep.to_string(), "workspace:*".to_string());
+        }
+        ws
+    }
+
+    fn level_names(levels: Vec<Vec<Workspace>>) -> Vec<Vec<String>> {
+        levels.into_iter().map(|l| l.into_iter().map(|w| w.name).collect()).collect()
+    }
+
+    #[test]
+    fn test_failed_workspace_installs_are_reported_together() {
+        let install = |name: &str, error: Option<&str>| WorkspaceInstall {
+            name: name.to_string(),
+            packages: if error.is_some() { 0 } else { 2 },
+            elapsed_ms: 10,
+            error: error.map(|e| anyhow::anyhow!(e.to_string())),
+        };
+        assert!(report_installs(&[install("@acme/api", None), install("@acme/web", None)]).is_ok());
+
+        let results = [
+            install("@acme/api", Some("Package 'left-padd' not found")),
+            install("@acme/cli", None),
+            install("@acme/web", Some("Failed to download tarball")),
+        ];
+        let err = report_installs(&results).unwrap_err().to_string();
+        assert_eq!(err, "2 of 3 workspaces failed to install:\n  @acme/api: Package 'left-padd' not found\n  @acme/web: Failed to download tarball");
+    }
+
+    #[test]
+    fn test_workspace_protocol_resolves_locally() {
+        let root = PathBuf::from("/repo");
+        let mut core = workspace("@acme/core");
+        core.path = root.join("packages/core");
+        core.package_json.version = "1.4.0".into();
+        let workspaces = vec![core];
+
+        let mut deps = HashMap::from([
+            ("@acme/core".to_string(), "workspace:^".to_string()),
+            ("zod".to_string(), "^3.0.0".to_string()),
+        ]);
+        let mut lock = CrabbyLock::default();
+        resolve_protocol_deps(&mut deps, &workspaces, &root, &mut lock).unwrap();
+
+        assert_eq!(deps.keys().collect::<Vec<_>>(), ["zod"]);
+        let entry = &lock.dependencies["@acme/core"];
+        assert_eq!(entry.version, "1.4.0");
+        assert_eq!(entry.tarball, "workspace:packages/core");
+        assert!(entry.is_workspace_link());
+
+        let mut unknown = HashMap::from([("@acme/nope".to_string(), "workspace:*".to_string())]);
+        let err = resolve_protocol_deps(&mut unknown, &workspaces, &root, &mut lock).unwrap_err();
+        assert!(err.to_string().contains("no workspace in this repo is named @acme/nope"));
+
+        let mut too_new = HashMap::from([("@acme/core".to_string(), "workspace:^2.0.0".to_string())]);
+        assert!(resolve_protocol_deps(&mut too_new, &workspaces, &root, &mut lock).is_err());
+    }
+
+    #[test]
+    fn test_dependency_levels_diamond() {
+        let mut app = depending("app", &["ui", "api", "react"]);
+        app.package_json.dev_dependencies.insert("core".into(), "workspace:*".into());
+        let workspaces = vec![app, depending("ui", &["core"]), depending("api", &["core"]), depending("core", &[])];
+
+        assert_eq!(level_names(dependency_levels(workspaces).unwrap()), vec![
+            vec!["core".to_string()],
+            vec!["ui".to_string(), "api".to_string()],
+            vec!["app".to_string()],
+        ]);
+    }
+
+    #[test]
+    fn test_dependency_levels_reports_cycle() {
+        let workspaces = vec![
+            depending("standalone", &[]),
+            depending("a", &["b"]),
+            depending("b", &["c"]),
+            depending("c", &["a"]),
+        ];
+        let err = dependency_levels(workspaces).unwrap_err().to_string();
+        assert_eq!(err, "Workspace dependency cycle: a -> b -> c -> a");
+    }
+
+    fn versions(list: &[&str]) -> Vec<semver::Version> {
+        list.iter().map(|v| semver::Version::parse(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_plan_hoisting_shares_compatible_versions() {
+        let mut web = depending("web", &[]);
+        web.package_json.dependencies.insert("react".into(), "^18.0.0".into());
+        web.package_json.dependencies.insert("lodash".into(), "^4.17.0".into());
+        let mut api = depending("api", &["web"]);
+        api.package_json.dependencies.insert("react".into(), "^18.2.0".into());
+        api.package_json.dependencies.insert("lodash".into(), "^3.10.0".into());
+        let mut docs = depending("docs", &[]);
+        docs.package_json.dev_dependencies.insert("lodash".into(), "^4.0.0".into());
+        let workspaces = vec![web, api, docs];
+
+        let requirements = collect_requirements(&PackageJson::default(), &workspaces);
+        assert!(!requirements.contains_key("web"));
+
+        let available = HashMap::from([
+            ("react".to_string(), versions(&["17.0.2", "18.0.0", "18.2.0", "18.3.1"])),
+            ("lodash".to_string(), versions(&["3.10.1", "4.17.20", "4.17.21"])),
+        ]);
+        let plan = plan_hoisting(&requirements, &CrabbyLock::default(), &available);
+        assert_eq!(plan.root["react"], "=18.3.1");
+        assert_eq!(plan.root["lodash"], "=4.17.21");
+        assert_eq!(plan.local.len(), 1);
+        assert_eq!(plan.local["api"]["lodash"], "^3.10.0");
+
+        // A locked version that still fits is kept, so repeat installs are stable
+        let mut lock = CrabbyLock::default();
+        lock.dependencies.insert("react".into(), LockDependency {
+            version: "18.2.0".into(),
+            tarball: String::new(),
+            integrity: None,
+            dependencies: HashMap::new(),
+            registry: None,
+            committish: None,
+        });
+        assert_eq!(plan_hoisting(&requirements, &lock, &available).root["react"], "=18.2.0");
+    }
+
+    #[test]
+    fn test_plan_hoisting_root_declaration_wins_conflicts() {
+        let mut root = PackageJson::default();
+        root.dev_dependencies.insert("typescript".into(), "~5.3.0".into());
+        let mut a = depending("a", &[]);
+        a.package_json.dev_dependencies.insert("typescript".into(), "^4.9.0".into());
+        let mut b = depending("b", &[]);
+        b.package_json.dev_dependencies.insert("typescript".into(), "^4.9.0".into());
+
+        let requirements = collect_requirements(&root, &[a, b]);
+        let available = HashMap::from([("typescript".to_string(), versions(&["4.9.5", "5.3.3", "5.4.2"]))]);
+        let plan = plan_hoisting(&requirements, &CrabbyLock::default(), &available);
+
+        assert_eq!(plan.root["typescript"], "=5.3.3");
+        assert_eq!(plan.local["a"]["typescript"], "^4.9.0");
+        assert_eq!(plan.local["b"]["typescript"], "^4.9.0");
+    }
+
+    #[test]
+    fn test_discover_reports_problems() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("package.json"), r#"{"name": "mono", "version": "1.0.0", "workspaces": ["packages/*", "apps/*"]}"#).unwrap();
+        for (dir, manifest) in [
+            ("packages/a", r#"{"name": "shared", "version": "1.0.0"}"#),
+            ("packages/b", r#"{"name": "shared", "version": "2.0.0"}"#),
+            ("packages/c", r#"{"name": "c", "version": "1.0.0""#),
+            ("packages/d", r#"{"name": "d", "version": "0.1.0"}"#),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("package.json"), manifest).unwrap();
+        }
+
+        let discovery = discover_workspaces(root).unwrap();
+        let mut names: Vec<&str> = discovery.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["d", "shared", "shared"]);
+
+        assert_eq!(discovery.problems.len(), 3);
+        assert_eq!(discovery.problems[0], WorkspaceProblem::EmptyPattern { pattern: "apps/*".to_string() });
+        assert!(matches!(&discovery.problems[1], WorkspaceProblem::InvalidManifest { path, .. } if path == "packages/c/package.json"));
+        assert!(matches!(&discovery.problems[2], WorkspaceProblem::DuplicateName { name, paths } if name == "shared" && paths.len() == 2));
+    }
+
+    /// packages/{a,b,legacy}, apps/web, apps/tools/cli and a stray package in node_modules
+    fn fixture_monorepo(workspaces: &str) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("package.json"), format!(r#"{{"name": "mono", "version": "1.0.0", "workspaces": {}}}"#, workspaces)).unwrap();
+        for dir in ["packages/a", "packages/b", "packages/legacy", "apps/web", "apps/tools/cli", "apps/web/node_modules/dep"] {
+            let name = dir.rsplit('/').next().unwrap();
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("package.json"), format!(r#"{{"name": "{}", "version": "1.0.0"}}"#, name)).unwrap();
+        }
+        tmp
+    }
+
+    fn discovered_names(root: &Path) -> Vec<String> {
+        let discovery = discover_workspaces(root).unwrap();
+        assert!(discovery.problems.is_empty(), "{:?}", discovery.problems);
+        let mut names: Vec<String> = discovery.workspaces.into_iter().map(|ws| ws.name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_member_finds_its_monorepo() {
+        let mono = fixture_monorepo(r#"["packages/*"]"#);
+        let member = mono.path().join("packages/a");
+        let (root, workspaces) = find_monorepo_root(&member).unwrap().unwrap();
+        assert_eq!(root, mono.path().canonicalize().unwrap());
+
+        // A member's workspace: deps are locked relative to the member
+        let mut deps = HashMap::from([("b".to_string(), "workspace:*".to_string())]);
+        let mut lock = CrabbyLock::default();
+        resolve_protocol_deps(&mut deps, &workspaces, &member, &mut lock).unwrap();
+        assert!(deps.is_empty());
+        assert_eq!(lock.dependencies["b"].tarball, "workspace:../b");
+
+        // apps/ isn't one of its workspaces
+        assert!(find_monorepo_root(&mono.path().join("apps/web")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bumped_workspace_version_is_locked() {
+        let mono = fixture_monorepo(r#"["packages/*"]"#);
+        let (root, workspaces) = find_monorepo_root(&mono.path().join("packages/a")).unwrap().unwrap();
+        let mut deps = HashMap::from([("a".to_string(), "workspace:*".to_string())]);
+        let mut root_lock = CrabbyLock::default();
+        resolve_protocol_deps(&mut deps, &workspaces, &root, &mut root_lock).unwrap();
+        root_lock.add_package("left-pad".into(), "1.0.0".into(), "t".into(), HashMap::new());
+        root_lock.save_to(&root.join("crabby.lock")).unwrap();
+
+        let changed = update_linked_version(&root, &workspaces, "a", "2.0.0").unwrap();
+        assert_eq!(changed, [root.join("crabby.lock")]);
+        let lock = CrabbyLock::load_from(&root.join("crabby.lock")).unwrap();
+        assert_eq!(lock.dependencies["a"].version, "2.0.0");
+        assert_eq!(lock.dependencies["left-pad"].version, "1.0.0");
+        // Nothing else links it, and the same version again changes nothing
+        assert!(update_linked_version(&root, &workspaces, "left-pad", "2.0.0").unwrap().is_empty());
+        assert!(update_linked_version(&root, &workspaces, "a", "2.0.0").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_workspace_pattern_shapes() {
+        let simple = fixture_monorepo(r#"["packages/*", "apps/*"]"#);
+        assert_eq!(discovered_names(simple.path()), ["a", "b", "legacy", "web"]);
+
+        let negated_and_nested = fixture_monorepo(r#"["packages/*", "!packages/legacy", "apps/**"]"#);
+        assert_eq!(discovered_names(negated_and_nested.path()), ["a", "b", "cli", "web"]);
+
+        let object_form = fixture_monorepo(r#"{"packages": ["packages/*", "!packages/legacy"], "nohoist": ["**/react-native"]}"#);
+        assert_eq!(discovered_names(object_form.path()), ["a", "b"]);
+
+        // The object form survives a load/save round trip
+        let manifest = object_form.path().join("package.json");
+        PackageJson::load_from(&manifest).unwrap().save_to(&manifest).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+        assert_eq!(saved["workspaces"]["nohoist"][0], "**/react-native");
+    }
+
+    #[test]
+    fn test_builds_depended_on_workspaces_when_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let with_build = |ws: &mut Workspace| {
+            ws.package_json.scripts.insert("build".into(), "tsc".into());
+            ws.path = tmp.path().join(&ws.name);
+        };
+        let mut core = depending("core", &[]);
+        with_build(&mut core);
+        let mut ui = depending("ui", &["core"]);
+        with_build(&mut ui);
+        ui.package_json.scripts.insert("prepare".into(), "vite build".into());
+        let mut app = depending("app", &["ui"]);
+        with_build(&mut app);
+        let order: Vec<String> = build_order(&[app, ui.clone(), core.clone()]).unwrap()
+            .into_iter().map(|ws| ws.name).collect();
+        assert_eq!(order, ["core", "ui"]);
+        assert_eq!(build_script(&ui), Some(("prepare", "vite build")));
+
+        let src = core.path.join("src").join("index.ts");
+        let out = core.path.join("dist").join("index.js");
+        fs::create_dir_all(src.parent().unwrap()).unwrap();
+        fs::write(&src, "export {}").unwrap();
+        assert!(!build_is_fresh(&core));
+
+        fs::create_dir_all(out.parent().unwrap()).unwrap();
+        fs::write(&out, "").unwrap();
+        let touch = |path: &Path, secs: u64| {
+            let time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+        };
+        touch(&src, 1_000);
+        touch(&out, 2_000);
+        assert!(build_is_fresh(&core));
+        touch(&src, 3_000);
+        assert!(!build_is_fresh(&core));
+    }
+
+    #[test]
+    fn test_find_workspace_by_name_or_directory() {
+        let mut api = workspace("@acme/api");
+        api.path = PathBuf::from("packages/server");
+        let workspaces = vec![api, workspace("@acme/web"), workspace("@other/web")];
+
+        assert_eq!(find_workspace(&workspaces, "@acme/api").unwrap().name, "@acme/api");
+        assert_eq!(find_workspace(&workspaces, "api").unwrap().name, "@acme/api");
+        assert_eq!(find_workspace(&workspaces, "server").unwrap().name, "@acme/api");
+        assert!(find_workspace(&workspaces, "web").unwrap_err().to_string().contains("several"));
+        assert!(find_workspace(&workspaces, "docs").is_err());
+    }
+
+    #[test]
+    fn test_filter_workspaces_by_name_and_glob() {
+        let all = || vec![workspace("api"), workspace("web"), workspace("@org/api-client")];
+        let names = |ws: Vec<Workspace>| ws.into_iter().map(|w| w.name).collect::<Vec<_>>();
+
+        assert_eq!(names(filter_workspaces(all(), &[]).unwrap()), ["api", "web", "@org/api-client"]);
+        assert_eq!(names(filter_workspaces(all(), &["api".into()]).unwrap()), ["api"]);
+        assert_eq!(names(filter_workspaces(all(), &["@org/*".into(), "web".into()]).unwrap()), ["web", "@org/api-client"]);
+        assert!(filter_workspaces(all(), &["nope".into()]).is_err());
+    }
+}